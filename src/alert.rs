@@ -0,0 +1,160 @@
+//! Audible alerts when samples breach the latency threshold.
+
+use crate::ping::PingResult;
+use crate::stats::Thresholds;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "beep")]
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two alert beeps.
+pub const BEEP_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// Debounces threshold alerts so a sustained breach beeps once per
+/// [`BEEP_COOLDOWN`] rather than on every sample.
+pub struct Beeper {
+    last_beep: Option<Instant>,
+}
+
+impl Default for Beeper {
+    fn default() -> Self {
+        Beeper::new()
+    }
+}
+
+impl Beeper {
+    pub fn new() -> Self {
+        Beeper { last_beep: None }
+    }
+
+    /// Whether `sample` should trigger a beep at `now`, recording it if so.
+    pub fn should_beep(
+        &mut self,
+        sample: &PingResult,
+        thresholds: &Thresholds,
+        now: Instant,
+    ) -> bool {
+        let breached = thresholds
+            .latency
+            .is_some_and(|limit| sample.average > limit);
+        let cooled_down = self
+            .last_beep
+            .is_none_or(|last| now.duration_since(last) >= BEEP_COOLDOWN);
+
+        if breached && cooled_down {
+            self.last_beep = Some(now);
+        }
+        breached && cooled_down
+    }
+}
+
+/// Snooze lengths offered in the GUI.
+pub const SNOOZE_OPTIONS: [Duration; 3] = [
+    Duration::from_secs(15 * 60),
+    Duration::from_secs(60 * 60),
+    Duration::from_secs(4 * 60 * 60),
+];
+
+/// Alerts muted until a point in time, so a known noisy period doesn't
+/// beep or page without turning alerts off. Clones share the snooze, which
+/// lasts only as long as the process.
+#[derive(Debug, Clone, Default)]
+pub struct Snooze {
+    until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Snooze {
+    fn until(&self) -> std::sync::MutexGuard<'_, Option<Instant>> {
+        self.until.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Mutes alerts for `duration` from `now`, replacing any snooze.
+    pub fn snooze(&self, now: Instant, duration: Duration) {
+        *self.until() = Some(now + duration);
+    }
+
+    pub fn cancel(&self) {
+        *self.until() = None;
+    }
+
+    /// How long alerts stay muted after `now`, `None` once they aren't.
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        let until = (*self.until())?;
+        until
+            .checked_duration_since(now)
+            .filter(|left| !left.is_zero())
+    }
+
+    pub fn is_active(&self, now: Instant) -> bool {
+        self.remaining(now).is_some()
+    }
+}
+
+pub fn terminal_bell() {
+    eprint!("\x07");
+}
+
+/// Plays a short tone on the default output device, quietly giving up if
+/// there isn't one.
+#[cfg(feature = "beep")]
+pub fn play_beep() {
+    use rodio::Source;
+
+    thread::spawn(|| {
+        let Ok((_stream, handle)) = rodio::OutputStream::try_default() else {
+            return;
+        };
+        let Ok(sink) = rodio::Sink::try_new(&handle) else {
+            return;
+        };
+        sink.append(
+            rodio::source::SineWave::new(880.0)
+                .take_duration(Duration::from_millis(200))
+                .amplify(0.2),
+        );
+        sink.sleep_until_end();
+    });
+}
+
+#[cfg(not(feature = "beep"))]
+pub fn play_beep() {
+    terminal_bell();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn snoozed_alerts_resume_once_it_expires() {
+        let thresholds = Thresholds {
+            latency: Some(50.0),
+            loss: 5.0,
+            loss_cutoff: None,
+        };
+        let slow = PingResult::new("example.com".to_string(), 80.0, Utc::now());
+        let mut beeper = Beeper::new();
+        let snooze = Snooze::default();
+        // As the GUI checks before beeping
+        let mut alerts =
+            |now| !snooze.is_active(now) && beeper.should_beep(&slow, &thresholds, now);
+
+        let start = Instant::now();
+        // Snoozed from the GUI, which holds a clone
+        snooze.clone().snooze(start, SNOOZE_OPTIONS[0]);
+        assert_eq!(snooze.remaining(start), Some(SNOOZE_OPTIONS[0]));
+        let during = start + Duration::from_secs(14 * 60);
+        assert_eq!(snooze.remaining(during), Some(Duration::from_secs(60)));
+        assert!(!alerts(start));
+        assert!(!alerts(during));
+
+        let after = start + SNOOZE_OPTIONS[0];
+        assert_eq!(snooze.remaining(after), None);
+        assert!(alerts(after));
+
+        snooze.snooze(after, SNOOZE_OPTIONS[1]);
+        snooze.cancel();
+        assert!(alerts(after + BEEP_COOLDOWN));
+    }
+}
@@ -0,0 +1,2444 @@
+//! The egui window plotting samples as they arrive.
+
+use crate::alert::{play_beep, Beeper, Snooze, SNOOZE_OPTIONS};
+use crate::ascii::to_ascii;
+use crate::color::{Palette, Rgb};
+use crate::config::Config;
+use crate::events::{EventLog, Note};
+use crate::export::{
+    export_chart, export_metadata, export_notes, export_rollups_to_csv, export_samples,
+    metadata_path, notes_path, render_chart_rgb, SampleWriter, CHART_SIZE,
+};
+use crate::http::HttpTimings;
+use crate::netwatch::NetworkChange;
+use crate::ping::{IpFamily, PingResult};
+use crate::replay::{Replay, REPLAY_SPEEDS};
+use crate::resolve::ResolutionChange;
+use crate::sampler::SamplerRestart;
+use crate::stats::{
+    align_time_of_day, crossing_rate, deviation_scores, downsample, estimated_loss,
+    expected_samples, inter_arrival, jitter_scatter, latency_rate, losses, moving, outage_rate,
+    outlier_bounds, rollups, trim_older_than, trim_to_memory, Aggregate, ExtremesTracker,
+    HostComparison, LastSeen, LogHistogram, Outage, RegimeChange, RegimeDetector, Rollup,
+    SequenceTracker, TtlTracker, Warmup, DEVIATION_WINDOW, DOWNSAMPLE_TIERS, JITTER_WINDOW,
+};
+use crate::suspend::Suspension;
+use crate::timezone::format_in;
+use chrono::{DateTime, Local, TimeZone, Utc};
+use eframe::egui::{self};
+use egui_plot::{
+    uniform_grid_spacer, AxisHints, Bar, BarChart, BoxElem, BoxPlot, BoxSpread, GridInput,
+    GridMark, HLine, Legend, Line, LineStyle, MarkerShape, Plot, PlotPoint, PlotPoints, Points,
+    Polygon, Text, VLine,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+/// Bucket growth of the histogram view, resolving latencies to within 5%.
+const HISTOGRAM_GROWTH: f64 = 1.1;
+
+/// Samples kept per host with `--downsample`, about two and a half days once
+/// averaged into minutes.
+const DOWNSAMPLED_MAX_POINTS: usize = 5000;
+
+/// Slot the outage sparkline counts outages in.
+const OUTAGE_BUCKET: Duration = Duration::from_secs(5 * 60);
+
+/// Rough width of a legend character, used to fit host labels to a plot.
+const LEGEND_CHAR_WIDTH: f32 = 7.0;
+
+/// Share of a plot's width its legend may take up.
+const LEGEND_WIDTH_SHARE: f32 = 0.3;
+
+/// Host labels are never cut shorter than this many characters.
+const MIN_LABEL_CHARS: usize = 12;
+
+pub fn color32(Rgb(r, g, b): Rgb) -> egui::Color32 {
+    egui::Color32::from_rgb(r, g, b)
+}
+
+/// A column of the host comparison table, which it can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CompareColumn {
+    #[default]
+    Host,
+    Avg,
+    Min,
+    Max,
+    Loss,
+    Jitter,
+}
+
+impl CompareColumn {
+    pub const ALL: [CompareColumn; 6] = [
+        CompareColumn::Host,
+        CompareColumn::Avg,
+        CompareColumn::Min,
+        CompareColumn::Max,
+        CompareColumn::Loss,
+        CompareColumn::Jitter,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CompareColumn::Host => "Host",
+            CompareColumn::Avg => "Avg",
+            CompareColumn::Min => "Min",
+            CompareColumn::Max => "Max",
+            CompareColumn::Loss => "Loss",
+            CompareColumn::Jitter => "Jitter",
+        }
+    }
+
+    /// Sorts `rows` by this column, ascending unless `descending`. Hosts
+    /// without a jitter yet sort before the rest.
+    pub fn sort(self, rows: &mut [HostComparison], descending: bool) {
+        rows.sort_by(|a, b| {
+            let order = match self {
+                CompareColumn::Host => a.host.cmp(&b.host),
+                CompareColumn::Avg => a.avg.total_cmp(&b.avg),
+                CompareColumn::Min => a.min.total_cmp(&b.min),
+                CompareColumn::Max => a.max.total_cmp(&b.max),
+                CompareColumn::Loss => a.loss.total_cmp(&b.loss),
+                CompareColumn::Jitter => a
+                    .jitter
+                    .partial_cmp(&b.jitter)
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            };
+            if descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+    }
+}
+
+/// GUI choices remembered between runs.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Draw a marker at every sample on top of the line.
+    pub show_points: bool,
+    /// Color the markers by how far each sample strays from the recent
+    /// ones rather than by host.
+    pub deviation_colors: bool,
+    /// Which of each sample's round-trip times are plotted.
+    pub show_min: bool,
+    pub show_avg: bool,
+    pub show_max: bool,
+    /// Overlay each host with this aggregate of its last `moving_window`
+    /// samples, or nothing when `None`.
+    pub moving_aggregate: Option<Aggregate>,
+    pub moving_window: usize,
+    /// Show the latency histogram below the plot.
+    pub show_histogram: bool,
+    /// Show each sample's latency against its local jitter below the plot.
+    pub show_jitter: bool,
+    /// Show how fast each host's latency changes below the plot.
+    pub show_rate: bool,
+    /// Show the host comparison table below the plot, sorted by
+    /// `compare_by`.
+    pub show_comparison: bool,
+    pub compare_by: CompareColumn,
+    pub compare_descending: bool,
+    /// Show latencies in seconds once they reach [`SECONDS_FROM`].
+    pub auto_units: bool,
+    /// Seconds between vertical and ms between horizontal gridlines, chosen
+    /// by the plot when `None`.
+    pub x_grid: Option<f64>,
+    pub y_grid: Option<f64>,
+    /// How the latency plot spreads time along its x axis.
+    pub time_scale: TimeScale,
+    /// Seconds per bucket when each host is plotted as min-max whiskers
+    /// around its bucket means, or `None` for a line through every sample.
+    pub error_bars: Option<f64>,
+    /// The host whose stats are shown in full and whose line stands out,
+    /// dimming the others. All hosts are shown alike when `None`.
+    pub active_host: Option<String>,
+    pub palette: Palette,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            show_points: false,
+            deviation_colors: false,
+            show_min: false,
+            show_avg: true,
+            show_max: false,
+            moving_aggregate: None,
+            moving_window: 10,
+            show_histogram: false,
+            show_jitter: false,
+            show_rate: false,
+            show_comparison: false,
+            compare_by: CompareColumn::default(),
+            compare_descending: false,
+            auto_units: false,
+            x_grid: None,
+            y_grid: None,
+            time_scale: TimeScale::default(),
+            error_bars: None,
+            active_host: None,
+            palette: Palette::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// These settings with the gridline spacing, error bar bucket, moving
+    /// overlay and palette given on the command line, which win over what was
+    /// remembered.
+    pub fn overridden_by(mut self, config: &Config) -> Settings {
+        if let Some(spacing) = config.x_grid {
+            self.x_grid = Some(spacing.as_secs_f64());
+        }
+        if let Some(spacing) = config.y_grid {
+            self.y_grid = Some(spacing);
+        }
+        if let Some(bucket) = config.error_bars {
+            self.error_bars = Some(bucket.as_secs_f64());
+        }
+        if let Some(aggregate) = config.moving_aggregate {
+            self.moving_aggregate = Some(aggregate);
+        }
+        if let Some(window) = config.moving_window {
+            self.moving_window = window;
+        }
+        if let Some(palette) = config.palette {
+            self.palette = palette;
+        }
+        self
+    }
+}
+
+/// The live monitoring window.
+pub struct PingApp {
+    ping_receiver: mpsc::Receiver<PingResult>,
+    ping_data: Vec<PingResult>,
+    /// Samples excluded from stats by `--warmup`, kept only for plotting.
+    warmup_data: Vec<PingResult>,
+    warmup: Warmup,
+    hosts: Vec<String>,
+    /// When each host last answered, warm-up included.
+    last_seen: HashMap<String, DateTime<Utc>>,
+    max_points: usize,
+    config: Config,
+    /// Reference samples from a previous run, never updated.
+    baseline: Vec<PingResult>,
+    /// Notes saved with the baseline, shifted along with it.
+    baseline_notes: Vec<Note>,
+    /// Samples of earlier days, one export each.
+    history: Vec<Vec<PingResult>>,
+    /// Notes added during this run, exported next to the samples.
+    notes: Vec<Note>,
+    /// Text of the note being typed.
+    note_text: String,
+    started: DateTime<Utc>,
+    beep: bool,
+    beeper: Beeper,
+    settings: Settings,
+    regimes: RegimeDetector,
+    /// Detected regime changes, annotated on the plot.
+    regime_changes: Vec<RegimeChange>,
+    /// Silences past the grace period, counted in the outage sparkline.
+    outages: Vec<Outage>,
+    /// Changes found by `--reresolve`, annotated on the plot.
+    resolutions: Option<mpsc::Receiver<ResolutionChange>>,
+    resolution_changes: Vec<ResolutionChange>,
+    /// Default route changes found by `--watch-network`.
+    network: Option<mpsc::Receiver<NetworkChange>>,
+    network_changes: Vec<NetworkChange>,
+    suspend: Option<mpsc::Receiver<Suspension>>,
+    suspensions: Vec<Suspension>,
+    /// Samplers the watchdog had to start again, logged as events.
+    restarts: Option<mpsc::Receiver<SamplerRestart>>,
+    events: EventLog,
+    sequences: SequenceTracker,
+    ttls: TtlTracker,
+    /// All-time best and worst samples, surviving trimming.
+    extremes: ExtremesTracker,
+    /// Samples the samplers had to drop because the display fell behind.
+    dropped: Option<Arc<AtomicUsize>>,
+    /// Kept open because on X11 copied data only lasts as long as the
+    /// clipboard handle that owns it.
+    clipboard: Option<arboard::Clipboard>,
+    /// Outcome of the last "Copy chart", shown under the button.
+    clipboard_status: Option<String>,
+    /// Streams samples to stdout off the GUI thread with `--stream-stdout`.
+    stdout_writer: Option<SampleWriter>,
+    /// Why streaming to stdout stopped, shown in the status bar.
+    writer_error: Option<String>,
+    /// Time range, in plot x coordinates, the stats are limited to.
+    selection: Option<(f64, f64)>,
+    /// Where the selection drag in progress started.
+    selecting_from: Option<f64>,
+    /// Playback of an export, shown instead of live samples.
+    replay: Option<Replay>,
+    /// How many replayed samples have been taken in so far.
+    replayed: usize,
+    /// Mutes beeps, and webhooks sharing it, until it runs out.
+    snooze: Snooze,
+}
+
+impl PingApp {
+    pub fn new(
+        ping_receiver: mpsc::Receiver<PingResult>,
+        config: Config,
+        baseline: Vec<PingResult>,
+    ) -> Self {
+        PingApp {
+            ping_receiver,
+            ping_data: vec![],
+            warmup_data: vec![],
+            warmup: Warmup::new(config.warmup),
+            hosts: vec![],
+            last_seen: HashMap::new(),
+            max_points: 1000,
+            baseline,
+            baseline_notes: vec![],
+            history: vec![],
+            notes: vec![],
+            note_text: String::new(),
+            started: Utc::now(),
+            beep: config.beep,
+            beeper: Beeper::new(),
+            settings: Settings::default().overridden_by(&config),
+            regimes: RegimeDetector::new(config.regime_sensitivity),
+            regime_changes: vec![],
+            outages: vec![],
+            resolutions: None,
+            resolution_changes: vec![],
+            network: None,
+            network_changes: vec![],
+            suspend: None,
+            suspensions: vec![],
+            restarts: None,
+            events: EventLog::new(),
+            sequences: SequenceTracker::new(),
+            ttls: TtlTracker::new(),
+            extremes: ExtremesTracker::new(),
+            dropped: None,
+            clipboard: None,
+            clipboard_status: None,
+            stdout_writer: if config.stream_stdout {
+                Some(SampleWriter::spawn(io::stdout()))
+            } else {
+                None
+            },
+            writer_error: None,
+            selection: None,
+            selecting_from: None,
+            replay: None,
+            replayed: 0,
+            snooze: Snooze::default(),
+            config,
+        }
+    }
+
+    pub fn with_baseline_notes(mut self, notes: Vec<Note>) -> Self {
+        self.baseline_notes = notes;
+        self
+    }
+
+    /// Overlays each of `days` on today by time of day.
+    pub fn with_history(mut self, days: Vec<Vec<PingResult>>) -> Self {
+        self.history = days;
+        self
+    }
+
+    /// Logs and annotates the address changes `--reresolve` sends on
+    /// `resolutions`.
+    pub fn with_resolutions(mut self, resolutions: mpsc::Receiver<ResolutionChange>) -> Self {
+        self.resolutions = Some(resolutions);
+        self
+    }
+
+    /// Logs and annotates the route changes `--watch-network` sends on
+    /// `network`.
+    pub fn with_network_changes(mut self, network: mpsc::Receiver<NetworkChange>) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Annotates the times the machine slept that `suspend` sends, instead
+    /// of drawing lines across them.
+    pub fn with_suspensions(mut self, suspend: mpsc::Receiver<Suspension>) -> Self {
+        self.suspend = Some(suspend);
+        self
+    }
+
+    /// Logs the sampler restarts the watchdog sends on `restarts`.
+    pub fn with_restarts(mut self, restarts: mpsc::Receiver<SamplerRestart>) -> Self {
+        self.restarts = Some(restarts);
+        self
+    }
+
+    /// Shares `snooze` with whatever else should be muted along with the
+    /// beeps, such as the webhook.
+    pub fn with_snooze(mut self, snooze: Snooze) -> Self {
+        self.snooze = snooze;
+        self
+    }
+
+    /// Plays back `replay` with play/pause, seek and speed controls, in
+    /// place of live samples.
+    pub fn with_replay(mut self, replay: Replay) -> Self {
+        // Known up front so colors stay put while seeking
+        self.hosts = replay.hosts();
+        self.replay = Some(replay);
+        self
+    }
+
+    /// Takes in a sample for plotting and stats.
+    fn record(&mut self, ping_value: PingResult) {
+        if !self.hosts.contains(&ping_value.host) {
+            self.hosts.push(ping_value.host.clone());
+        }
+        if let Some(&last) = self.last_seen.get(&ping_value.host) {
+            let grace = self.config.grace_for(&ping_value.host);
+            if let Some(outage) =
+                Outage::between(&ping_value.host, last, ping_value.datetime_recv, grace)
+                    .filter(|outage| !self.slept_through(outage))
+            {
+                self.events.push(outage.event());
+                self.outages.push(outage);
+            }
+        }
+        self.last_seen
+            .insert(ping_value.host.clone(), ping_value.datetime_recv);
+        self.sequences.observe(&ping_value);
+        self.ttls.observe(&ping_value);
+        if self.warmup.is_warming_up(&ping_value) {
+            if self.config.plot_warmup {
+                self.warmup_data.push(ping_value);
+            }
+            return;
+        }
+        if let Some(change) = self.regimes.observe(&ping_value) {
+            self.events.push(change.event());
+            self.regime_changes.push(change);
+        }
+        self.extremes.observe(&ping_value);
+        self.ping_data.push(ping_value);
+    }
+
+    /// Advances the replay by `elapsed` and takes in the samples it reveals.
+    /// Seeking backwards starts over from the beginning of the recording.
+    fn step_replay(&mut self, elapsed: Duration) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+        replay.advance(elapsed);
+        let revealed = replay.revealed();
+        if revealed.len() < self.replayed {
+            self.replayed = 0;
+            self.ping_data.clear();
+            self.warmup_data.clear();
+            self.last_seen.clear();
+            self.warmup = Warmup::new(self.config.warmup);
+            self.regimes = RegimeDetector::new(self.config.regime_sensitivity);
+            self.regime_changes.clear();
+            self.outages.clear();
+            self.events = EventLog::new();
+            self.sequences = SequenceTracker::new();
+            self.ttls = TtlTracker::new();
+            self.extremes = ExtremesTracker::new();
+        }
+        let fresh = revealed[self.replayed..].to_vec();
+        self.replayed += fresh.len();
+        for sample in fresh {
+            self.record(sample);
+        }
+    }
+
+    /// Play/pause, the timeline slider and the speed picker.
+    fn replay_controls(&mut self, ui: &mut egui::Ui) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            let label = if replay.playing { "Pause" } else { "Play" };
+            if ui.button(label).clicked() {
+                if !replay.playing && replay.cursor() == replay.end() {
+                    replay.seek(replay.start());
+                }
+                replay.playing = !replay.playing;
+            }
+
+            let start = replay.start();
+            let length = (replay.end() - start).num_milliseconds() as f64 / 1000.0;
+            let mut offset = (replay.cursor() - start).num_milliseconds() as f64 / 1000.0;
+            ui.spacing_mut().slider_width = (ui.available_width() - 200.0).max(100.0);
+            let slider = egui::Slider::new(&mut offset, 0.0..=length)
+                .show_value(false)
+                .custom_formatter(|secs, _| format_time(plot_x(start) + secs));
+            if ui.add(slider).changed() {
+                replay.seek(start + chrono::Duration::milliseconds((offset * 1000.0) as i64));
+            }
+            ui.label(format_time(plot_x(replay.cursor())));
+
+            egui::ComboBox::from_id_salt("replay_speed")
+                .selected_text(format!("{}x", replay.speed))
+                .show_ui(ui, |ui| {
+                    for speed in REPLAY_SPEEDS {
+                        ui.selectable_value(&mut replay.speed, speed, format!("{speed}x"));
+                    }
+                });
+        });
+        if let Some(metadata) = &replay.metadata {
+            ui.label(metadata.to_string());
+        }
+    }
+
+    /// Shows the count of samples dropped on the way here, see
+    /// [`SampleSender::dropped`](crate::sampler::SampleSender::dropped).
+    pub fn with_dropped(mut self, dropped: Arc<AtomicUsize>) -> Self {
+        self.dropped = Some(dropped);
+        self
+    }
+
+    /// Each host's label cut to fit the legend of a plot `width` wide, paired
+    /// with its full name.
+    fn labels(&self, width: f32) -> Vec<(String, String)> {
+        let max_chars = label_chars(width);
+        self.hosts
+            .iter()
+            .map(|host| (self.plain(truncate_label(host, max_chars)), host.clone()))
+            .collect()
+    }
+
+    /// `text` as shown, cut down to ASCII under `--ascii`.
+    fn plain(&self, text: String) -> String {
+        if self.config.ascii {
+            to_ascii(&text)
+        } else {
+            text
+        }
+    }
+
+    /// The unit latencies are shown in, going by the largest sample held.
+    fn latency_unit(&self) -> LatencyUnit {
+        if !self.settings.auto_units {
+            return LatencyUnit::Millis;
+        }
+        LatencyUnit::for_max(
+            self.ping_data
+                .iter()
+                .map(|data| data.average)
+                .fold(0.0, f64::max),
+        )
+    }
+
+    /// The line color of the host that reported `index`th.
+    fn host_color(&self, index: usize) -> Rgb {
+        self.config.host_color_in(self.settings.palette, index)
+    }
+
+    /// `color` from the palette in use.
+    fn palette_color(&self, color: fn(Palette) -> Rgb) -> egui::Color32 {
+        color32(color(self.settings.palette))
+    }
+
+    /// The host focused on, if it is one of this session's.
+    fn active_host(&self) -> Option<&String> {
+        self.settings
+            .active_host
+            .as_ref()
+            .filter(|host| self.hosts.contains(host))
+    }
+
+    /// Whether `host` is shown in full, being focused on or with no host
+    /// focused on.
+    fn emphasized(&self, host: &str) -> bool {
+        self.active_host().is_none_or(|active| active == host)
+    }
+
+    /// Picks the host to focus on.
+    fn active_host_picker(&mut self, ui: &mut egui::Ui) {
+        let selected = self
+            .active_host()
+            .map_or("All hosts".to_string(), |host| self.plain(host.clone()));
+        egui::ComboBox::from_label("Focus")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.settings.active_host, None, "All hosts");
+                for host in &self.hosts {
+                    let text = if self.config.ascii {
+                        to_ascii(host)
+                    } else {
+                        host.clone()
+                    };
+                    ui.selectable_value(&mut self.settings.active_host, Some(host.clone()), text);
+                }
+            });
+    }
+
+    /// Whether `outage` is explained by the machine sleeping and left out
+    /// under `--ignore-suspended`.
+    fn slept_through(&self, outage: &Outage) -> bool {
+        self.config.ignore_suspended
+            && self
+                .suspensions
+                .iter()
+                .any(|suspension| suspension.overlaps(outage.start, outage.end))
+    }
+
+    /// Whether `sample` falls within the selected time range, if there is one.
+    fn in_selection(&self, sample: &PingResult) -> bool {
+        let x = plot_x(sample.sent_at());
+        self.selection
+            .is_none_or(|(from, to)| (from..=to).contains(&x))
+    }
+
+    /// Draws each host's latency distribution on a log scale, over the
+    /// selected time range if there is one.
+    fn histogram(&self, ui: &mut egui::Ui) {
+        let labels = self.labels(ui.available_width());
+        let charts: Vec<BarChart> = self
+            .hosts
+            .iter()
+            .enumerate()
+            .map(|(index, host)| {
+                let samples = self
+                    .ping_data
+                    .iter()
+                    .filter(|data| &data.host == host && self.in_selection(data));
+                let histogram =
+                    LogHistogram::from_values(HISTOGRAM_GROWTH, samples.map(|data| data.average));
+                let bars = histogram
+                    .buckets()
+                    .map(|(low, high, count)| {
+                        let (low, high) = (low.log10(), high.log10());
+                        Bar::new((low + high) / 2.0, count as f64).width((high - low) * 0.9)
+                    })
+                    .collect();
+                BarChart::new(bars)
+                    .name(&labels[index].0)
+                    .color(color32(self.host_color(index)).gamma_multiply(0.6))
+            })
+            .collect();
+
+        Plot::new("histogram")
+            .height(150.0)
+            .legend(Legend::default())
+            .x_axis_label("Ping (ms, log scale)")
+            .y_axis_label("Samples")
+            .custom_x_axes(vec![
+                AxisHints::new_x().formatter(|x, _range| format!("{:.3}", 10f64.powf(x.value)))
+            ])
+            .show(ui, |plot_ui| {
+                for chart in charts {
+                    plot_ui.bar_chart(chart);
+                }
+            });
+    }
+
+    /// Scatter of each sample's latency against the jitter around it, to
+    /// show whether slow periods are also unstable ones.
+    fn jitter(&self, ui: &mut egui::Ui) {
+        let labels = self.labels(ui.available_width());
+        Plot::new("jitter_scatter")
+            .height(150.0)
+            .label_formatter(full_name_formatter(labels.clone(), |point| {
+                format!("{:.2} ms, jitter {:.2} ms", point.x, point.y)
+            }))
+            .legend(Legend::default())
+            .x_axis_label("Ping (ms)")
+            .y_axis_label("Jitter (ms)")
+            .show(ui, |plot_ui| {
+                for (index, host) in self.hosts.iter().enumerate() {
+                    let values: Vec<f64> = self
+                        .ping_data
+                        .iter()
+                        .filter(|data| &data.host == host && self.in_selection(data))
+                        .map(|data| data.average)
+                        .collect();
+                    let points: Vec<[f64; 2]> = jitter_scatter(&values, JITTER_WINDOW)
+                        .into_iter()
+                        .map(|(latency, jitter)| [latency, jitter])
+                        .collect();
+                    plot_ui.points(
+                        Points::new(PlotPoints::new(points))
+                            .name(&labels[index].0)
+                            .color(color32(self.host_color(index)))
+                            .radius(2.0),
+                    );
+                }
+            });
+    }
+
+    /// A row of stats over the selection per host, sorted as the comparison
+    /// table is.
+    fn comparison_rows(&self) -> Vec<HostComparison> {
+        let mut rows: Vec<HostComparison> = self
+            .hosts
+            .iter()
+            .filter_map(|host| {
+                let samples: Vec<&PingResult> = self
+                    .ping_data
+                    .iter()
+                    .filter(|data| &data.host == host && self.in_selection(data))
+                    .collect();
+                HostComparison::of(host, &samples, self.config.interval_for(host))
+            })
+            .collect();
+        self.settings
+            .compare_by
+            .sort(&mut rows, self.settings.compare_descending);
+        rows
+    }
+
+    /// The hosts' stats over the selection side by side, sorted by the
+    /// column whose header was clicked last, and reversed when clicked again.
+    fn comparison(&mut self, ui: &mut egui::Ui, unit: LatencyUnit) {
+        let rows = self.comparison_rows();
+
+        egui::Grid::new("host_comparison")
+            .striped(true)
+            .show(ui, |ui| {
+                for column in CompareColumn::ALL {
+                    let sorted = self.settings.compare_by == column;
+                    let arrow = match (sorted, self.settings.compare_descending) {
+                        (false, _) => "",
+                        (true, false) => " ^",
+                        (true, true) => " v",
+                    };
+                    let header = ui.selectable_label(sorted, format!("{}{arrow}", column.name()));
+                    if header.clicked() {
+                        self.settings.compare_descending =
+                            sorted && !self.settings.compare_descending;
+                        self.settings.compare_by = column;
+                    }
+                }
+                ui.end_row();
+                for row in &rows {
+                    ui.label(self.plain(row.host.clone()));
+                    for value in [row.avg, row.min, row.max] {
+                        ui.label(unit.format(value));
+                    }
+                    ui.label(format!("{:.1}%", row.loss));
+                    ui.label(
+                        row.jitter
+                            .map_or("-".to_string(), |jitter| unit.format(jitter)),
+                    );
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// Marks each of `points`, the plotted averages of `host`, green, yellow
+    /// or red by its z-score against the samples before it.
+    fn deviation_points(
+        &self,
+        plot_ui: &mut egui_plot::PlotUi,
+        host: &str,
+        label: &str,
+        points: &[[f64; 2]],
+    ) {
+        let values: Vec<f64> = self
+            .ping_data
+            .iter()
+            .filter(|data| data.host == host)
+            .map(|data| data.average)
+            .collect();
+        // Scored over every retained sample, though only the plotted ones
+        // are drawn
+        let scores = deviation_scores(&values, DEVIATION_WINDOW);
+        let unplotted = self.config.unplotted(values.len());
+        let palette = self.settings.palette;
+        let thresholds = self.config.deviation_thresholds;
+        let mut groups: Vec<(Rgb, Vec<[f64; 2]>)> = vec![];
+        for (point, score) in points.iter().zip(scores.into_iter().skip(unplotted)) {
+            let color = palette.deviation(score, thresholds);
+            match groups.iter_mut().find(|(group, _)| *group == color) {
+                Some((_, group)) => group.push(*point),
+                None => groups.push((color, vec![*point])),
+            }
+        }
+        for (color, group) in groups {
+            plot_ui.points(
+                Points::new(PlotPoints::new(group))
+                    .name(label)
+                    .color(color32(color))
+                    .radius(3.0),
+            );
+        }
+    }
+
+    /// Each host's change in latency per second over time, on the main
+    /// plot's time axis, with 0 marked so climbs and drops stand apart.
+    fn rate(&self, ui: &mut egui::Ui) {
+        let labels = self.labels(ui.available_width());
+        Plot::new("rate_plot")
+            .height(150.0)
+            .link_axis("time", [true, false])
+            .label_formatter(full_name_formatter(labels.clone(), |point| {
+                format!("{}  {:+.1} ms/s", format_time(point.x), point.y)
+            }))
+            .legend(Legend::default())
+            .x_axis_label("Time")
+            .y_axis_label("Change (ms/s)")
+            .custom_x_axes(vec![
+                AxisHints::new_x().formatter(|x, _range| format_time(x.value))
+            ])
+            .show(ui, |plot_ui| {
+                plot_ui.hline(HLine::new(0.0).color(egui::Color32::GRAY));
+                for (index, host) in self.hosts.iter().enumerate() {
+                    let samples: Vec<(DateTime<Utc>, f64)> = self
+                        .ping_data
+                        .iter()
+                        .filter(|data| &data.host == host)
+                        .map(|data| (data.sent_at(), data.average))
+                        .collect();
+                    let points: Vec<[f64; 2]> = latency_rate(&samples)
+                        .into_iter()
+                        .map(|(at, rate)| [plot_x(at), rate])
+                        .collect();
+                    plot_ui.line(
+                        Line::new(PlotPoints::new(points))
+                            .name(&labels[index].0)
+                            .color(color32(self.host_color(index))),
+                    );
+                }
+            });
+    }
+
+    /// Draws the bandwidth iperf measured, on a time axis linked to the
+    /// latency plot.
+    fn bandwidth(&self, ui: &mut egui::Ui) {
+        let labels = self.labels(ui.available_width());
+        Plot::new("bandwidth_plot")
+            .height(150.0)
+            .link_axis("time", [true, false])
+            .label_formatter(full_name_formatter(labels.clone(), |point| {
+                format!("{}  {:.1} Mbit/s", format_time(point.x), point.y)
+            }))
+            .legend(Legend::default())
+            .x_axis_label("Time")
+            .y_axis_label("Bandwidth (Mbit/s)")
+            .custom_x_axes(vec![
+                AxisHints::new_x().formatter(|x, _range| format_time(x.value))
+            ])
+            .show(ui, |plot_ui| {
+                for (index, host) in self.hosts.iter().enumerate() {
+                    let points: Vec<[f64; 2]> = self
+                        .ping_data
+                        .iter()
+                        .filter(|data| &data.host == host)
+                        .filter_map(|data| Some([plot_x(data.sent_at()), data.bandwidth?]))
+                        .collect();
+                    plot_ui.line(
+                        Line::new(PlotPoints::new(points))
+                            .name(&labels[index].0)
+                            .color(color32(self.host_color(index))),
+                    );
+                }
+            });
+    }
+
+    /// A small chart of how many outages started in each [`OUTAGE_BUCKET`]
+    /// since the oldest sample held.
+    fn outage_sparkline(&self, ui: &mut egui::Ui, now: DateTime<Utc>) {
+        let start = self
+            .ping_data
+            .first()
+            .map_or(now, |data| data.datetime_recv);
+        let points: Vec<[f64; 2]> = outage_rate(&self.outages, start, now, OUTAGE_BUCKET)
+            .into_iter()
+            .map(|(at, count)| [plot_x(at), count as f64])
+            .collect();
+        Plot::new("outage_sparkline")
+            .height(40.0)
+            .show_axes(false)
+            .show_grid(false)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .allow_boxed_zoom(false)
+            .include_y(0.0)
+            .include_y(1.0)
+            .label_formatter(|_, point| format!("{}  {:.0} outages", format_time(point.x), point.y))
+            .show(ui, |plot_ui| {
+                plot_ui.line(
+                    Line::new(PlotPoints::new(points)).color(self.palette_color(Palette::bad)),
+                );
+            });
+    }
+
+    /// Each host's latencies with its color, as the chart images draw them.
+    fn chart_series(&self) -> Vec<(String, Vec<f64>, Rgb)> {
+        self.hosts
+            .iter()
+            .enumerate()
+            .map(|(index, host)| {
+                let values = self
+                    .ping_data
+                    .iter()
+                    .filter(|data| &data.host == host)
+                    .map(|data| data.average)
+                    .collect();
+                (host.clone(), values, self.host_color(index))
+            })
+            .collect()
+    }
+
+    /// Plots `host` as a line through its mean in each `bucket` seconds long
+    /// window, with whiskers from the window's min to its max, which stays
+    /// readable over sessions too long for a line through every sample.
+    fn error_bars(
+        &self,
+        plot_ui: &mut egui_plot::PlotUi,
+        host: &str,
+        label: &str,
+        color: egui::Color32,
+        bucket: f64,
+        x_of: impl Fn(DateTime<Utc>) -> f64,
+    ) {
+        // Saved settings may hold a bucket no Duration can represent
+        let Some(bucket) = Duration::try_from_secs_f64(bucket)
+            .ok()
+            .filter(|bucket| !bucket.is_zero())
+        else {
+            return;
+        };
+        let half = chrono::Duration::from_std(bucket / 2).unwrap_or_default();
+        let rollups = self.error_bar_rollups(host, bucket);
+        let whiskers = rollups
+            .iter()
+            .map(|rollup| {
+                let spread =
+                    BoxSpread::new(rollup.min, rollup.avg, rollup.avg, rollup.avg, rollup.max);
+                // Half the bucket's width on the plot, which varies with the
+                // time scale
+                let width = (x_of(rollup.start + half + half) - x_of(rollup.start)) * 0.5;
+                BoxElem::new(x_of(rollup.start + half), spread)
+                    .box_width(width)
+                    .whisker_width(width)
+            })
+            .collect();
+        let means: Vec<[f64; 2]> = rollups
+            .iter()
+            .map(|rollup| [x_of(rollup.start + half), rollup.avg])
+            .collect();
+        plot_ui.box_plot(BoxPlot::new(whiskers).name(label).color(color));
+        plot_ui.line(Line::new(PlotPoints::new(means)).name(label).color(color));
+    }
+
+    /// The min, mean and max of each `bucket` long window of the plotted
+    /// samples of `host`, which [`PingApp::error_bars`] draws.
+    fn error_bar_rollups(&self, host: &str, bucket: Duration) -> Vec<Rollup> {
+        let samples: Vec<PingResult> = self.plotted_samples(host).into_iter().cloned().collect();
+        rollups(&samples, bucket, |host| self.config.interval_for(host))
+    }
+
+    /// The retained samples of `host` the plot draws, the newest
+    /// `--plot-points` of them. Stats still go by every retained sample.
+    fn plotted_samples(&self, host: &str) -> Vec<&PingResult> {
+        let mut samples: Vec<&PingResult> = self
+            .ping_data
+            .iter()
+            .filter(|data| data.host == host)
+            .collect();
+        samples.drain(..self.config.unplotted(samples.len()));
+        samples
+    }
+
+    /// Bars of how many times latency rose above its threshold in each
+    /// `--crossing-bucket` since the oldest sample held, to size up unstable
+    /// periods.
+    fn crossing_bars(&self, ui: &mut egui::Ui, now: DateTime<Utc>) {
+        let start = self
+            .ping_data
+            .first()
+            .map_or(now, |data| data.datetime_recv);
+        let bucket = self.config.crossing_bucket;
+        let threshold = |data: &PingResult| {
+            self.config
+                .thresholds_for(&data.host, data.datetime_recv.with_timezone(&Local).time())
+                .latency
+        };
+        let width = bucket.as_secs_f64() * 0.9;
+        let bars: Vec<Bar> = crossing_rate(&self.ping_data, threshold, start, now, bucket)
+            .into_iter()
+            .map(|(at, count)| {
+                Bar::new(plot_x(at) + bucket.as_secs_f64() / 2.0, count as f64).width(width)
+            })
+            .collect();
+        Plot::new("crossing_bars")
+            .height(40.0)
+            .show_axes(false)
+            .show_grid(false)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .allow_boxed_zoom(false)
+            .include_y(0.0)
+            .include_y(1.0)
+            .label_formatter(|_, point| {
+                format!("{}  {:.0} crossings", format_time(point.x), point.y)
+            })
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(BarChart::new(bars).color(self.palette_color(Palette::warning)));
+            });
+    }
+
+    /// Renders each host's samples like the PNG export and puts the image on
+    /// the clipboard.
+    fn copy_chart(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let rgb = render_chart_rgb(&self.chart_series(), self.config.chart_smooth)?;
+
+        let (width, height) = CHART_SIZE;
+        let rgba: Vec<u8> = rgb
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect();
+        let clipboard = match &mut self.clipboard {
+            Some(clipboard) => clipboard,
+            None => self.clipboard.insert(arboard::Clipboard::new()?),
+        };
+        clipboard.set_image(arboard::ImageData {
+            width: width as usize,
+            height: height as usize,
+            bytes: rgba.into(),
+        })?;
+        Ok(())
+    }
+
+    /// Gives back the receiver and config, e.g. to continue headless.
+    pub fn into_parts(self) -> (mpsc::Receiver<PingResult>, Config) {
+        (self.ping_receiver, self.config)
+    }
+}
+
+impl eframe::App for PingApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Suspensions come first so the gaps they leave aren't outages
+        if let Some(suspend) = &self.suspend {
+            for suspension in suspend.try_iter() {
+                // Also for outages already recorded from samples that came first
+                if self.config.ignore_suspended {
+                    self.outages
+                        .retain(|outage| !suspension.overlaps(outage.start, outage.end));
+                }
+                self.events.push(suspension.event());
+                self.suspensions.push(suspension);
+            }
+        }
+        // Try to receive new ping data without blocking
+        while let Ok(ping_value) = self.ping_receiver.try_recv() {
+            if let Some(writer) = &self.stdout_writer {
+                writer.write(&ping_value);
+            }
+            if self.beep
+                && !self.config.schedule.in_maintenance(Local::now().time())
+                && !self.snooze.is_active(Instant::now())
+                && self.beeper.should_beep(
+                    &ping_value,
+                    &self
+                        .config
+                        .thresholds_for(&ping_value.host, Local::now().time()),
+                    Instant::now(),
+                )
+            {
+                play_beep();
+            }
+            self.record(ping_value);
+        }
+        if let Some(resolutions) = &self.resolutions {
+            for change in resolutions.try_iter() {
+                self.events.push(change.event());
+                self.resolution_changes.push(change);
+            }
+        }
+        if let Some(network) = &self.network {
+            for change in network.try_iter() {
+                self.events.push(change.event());
+                self.network_changes.push(change);
+            }
+        }
+        if let Some(restarts) = &self.restarts {
+            for restart in restarts.try_iter() {
+                self.events.push(restart.event());
+            }
+        }
+        self.step_replay(Duration::from_secs_f32(ctx.input(|i| i.stable_dt)));
+        // A replay is as far along as its cursor
+        let now = self.replay.as_ref().map_or(Utc::now(), Replay::cursor);
+
+        let max_points = if self.config.downsample {
+            downsample(&mut self.ping_data, now, &DOWNSAMPLE_TIERS);
+            DOWNSAMPLED_MAX_POINTS
+        } else {
+            self.max_points
+        };
+        // Limit the number of points, by age when a retention window is set
+        if let Some(retain) = self.config.retain {
+            trim_older_than(&mut self.ping_data, now, retain);
+        } else if self.ping_data.len() > max_points * self.hosts.len().max(1) {
+            self.ping_data.remove(0);
+        }
+        let memory_used = match self.config.max_memory {
+            Some(budget) => trim_to_memory(&mut self.ping_data, budget),
+            None => self.ping_data.iter().map(PingResult::estimated_size).sum(),
+        };
+        // Only annotate changes within the remaining data
+        if let Some(first) = self.ping_data.first() {
+            self.regime_changes
+                .retain(|change| change.at >= first.datetime_recv);
+            self.outages
+                .retain(|outage| outage.start >= first.datetime_recv);
+            self.resolution_changes
+                .retain(|change| change.at >= first.datetime_recv);
+            self.network_changes
+                .retain(|change| change.at >= first.datetime_recv);
+            self.suspensions
+                .retain(|suspension| suspension.end >= first.datetime_recv);
+        }
+
+        let selecting = ctx.input(|i| i.modifiers.shift);
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.selection = None;
+        }
+
+        if self.replay.is_some() {
+            egui::TopBottomPanel::top("replay_controls").show(ctx, |ui| self.replay_controls(ui));
+        }
+
+        let unit = self.latency_unit();
+        if let Some(err) = self.stdout_writer.as_ref().and_then(SampleWriter::error) {
+            eprintln!("Error streaming to stdout: {err}");
+            self.writer_error = Some(format!("Stopped streaming to stdout: {err}"));
+        }
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if let Some(err) = &self.writer_error {
+                    ui.colored_label(self.palette_color(Palette::bad), err);
+                    ui.separator();
+                }
+                if let Some(left) = self.snooze.remaining(Instant::now()) {
+                    let secs = left.as_secs();
+                    ui.label(format!(
+                        "Alerts muted for {:02}:{:02}:{:02}",
+                        secs / 3600,
+                        secs / 60 % 60,
+                        secs % 60
+                    ));
+                    if ui.small_button("Unmute").clicked() {
+                        self.snooze.cancel();
+                    }
+                    ui.separator();
+                }
+                for host in &self.hosts {
+                    let Some(&last) = self.last_seen.get(host) else {
+                        continue;
+                    };
+                    let seen = LastSeen::new(last, now, self.config.grace_for(host));
+                    let secs = seen.elapsed.as_secs();
+                    let text = format!("{host}: last seen {:02}:{:02} ago", secs / 60, secs % 60);
+                    if seen.overdue {
+                        ui.colored_label(self.palette_color(Palette::bad), text);
+                    } else {
+                        ui.label(text);
+                    }
+                    ui.separator();
+                }
+            });
+        });
+
+        egui::SidePanel::right("stats_panel").show(ctx, |ui| {
+            ui.heading("Stats");
+            if self.hosts.len() > 1 {
+                self.active_host_picker(ui);
+            }
+            ui.checkbox(&mut self.beep, "Beep above threshold");
+            ui.horizontal(|ui| {
+                ui.label("Mute alerts for");
+                for duration in SNOOZE_OPTIONS {
+                    let secs = duration.as_secs();
+                    let text = if secs >= 3600 {
+                        format!("{}h", secs / 3600)
+                    } else {
+                        format!("{}m", secs / 60)
+                    };
+                    if ui.button(text).clicked() {
+                        self.snooze.snooze(Instant::now(), duration);
+                    }
+                }
+            });
+            ui.checkbox(&mut self.settings.show_points, "Show samples");
+            ui.checkbox(&mut self.settings.deviation_colors, "Color by deviation");
+            ui.horizontal(|ui| {
+                let selected = self
+                    .settings
+                    .moving_aggregate
+                    .map_or("off", Aggregate::name);
+                egui::ComboBox::from_label("Moving")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.settings.moving_aggregate, None, "off");
+                        for aggregate in Aggregate::ALL {
+                            ui.selectable_value(
+                                &mut self.settings.moving_aggregate,
+                                Some(aggregate),
+                                aggregate.name(),
+                            );
+                        }
+                    });
+                if self.settings.moving_aggregate.is_some() {
+                    ui.add(
+                        egui::DragValue::new(&mut self.settings.moving_window)
+                            .range(1..=1000)
+                            .suffix(" samples"),
+                    );
+                }
+            });
+            ui.horizontal(|ui| {
+                let field = ui.text_edit_singleline(&mut self.note_text);
+                let submitted = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if (ui.button("Add note").clicked() || submitted) && !self.note_text.is_empty() {
+                    self.notes.push(Note {
+                        at: now,
+                        text: std::mem::take(&mut self.note_text),
+                    });
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.settings.show_min, "Min");
+                ui.checkbox(&mut self.settings.show_avg, "Avg");
+                ui.checkbox(&mut self.settings.show_max, "Max");
+            });
+            ui.checkbox(&mut self.settings.show_histogram, "Show histogram");
+            ui.checkbox(&mut self.settings.show_jitter, "Show jitter vs latency");
+            ui.checkbox(&mut self.settings.show_rate, "Show rate of change");
+            ui.checkbox(&mut self.settings.show_comparison, "Compare hosts");
+            ui.checkbox(&mut self.settings.auto_units, "Seconds for long pings");
+            egui::ComboBox::from_label("Palette")
+                .selected_text(self.settings.palette.name())
+                .show_ui(ui, |ui| {
+                    for palette in Palette::ALL {
+                        ui.selectable_value(&mut self.settings.palette, palette, palette.name());
+                    }
+                });
+            egui::ComboBox::from_label("Time scale")
+                .selected_text(self.settings.time_scale.name())
+                .show_ui(ui, |ui| {
+                    for scale in TimeScale::ALL {
+                        ui.selectable_value(&mut self.settings.time_scale, scale, scale.name());
+                    }
+                });
+            grid_control(
+                ui,
+                "X grid every",
+                &mut self.settings.x_grid,
+                10.0,
+                DAY,
+                " s",
+            );
+            grid_control(
+                ui,
+                "Y grid every",
+                &mut self.settings.y_grid,
+                10.0,
+                1e5,
+                " ms",
+            );
+            grid_control(
+                ui,
+                "Error bars every",
+                &mut self.settings.error_bars,
+                60.0,
+                DAY,
+                " s",
+            );
+            if ui.button("Copy chart").clicked() {
+                self.clipboard_status = Some(match self.copy_chart() {
+                    Ok(()) => "Chart copied to the clipboard".to_string(),
+                    Err(err) => format!("Could not copy the chart: {err}"),
+                });
+            }
+            if let Some(status) = &self.clipboard_status {
+                ui.weak(status);
+            }
+            let megabytes = |bytes: usize| bytes as f64 / (1 << 20) as f64;
+            match self.config.max_memory {
+                Some(budget) => ui.label(format!(
+                    "Memory: {:.1} of {:.1} MB",
+                    megabytes(memory_used),
+                    megabytes(budget)
+                )),
+                None => ui.label(format!("Memory: {:.1} MB", megabytes(memory_used))),
+            };
+            let dropped = self
+                .dropped
+                .as_ref()
+                .map_or(0, |d| d.load(Ordering::Relaxed));
+            if dropped > 0 {
+                ui.colored_label(
+                    self.palette_color(Palette::warning),
+                    format!("{dropped} samples dropped, display falling behind"),
+                );
+            }
+            match self.selection {
+                Some((from, to)) => {
+                    ui.label(format!(
+                        "Selection: {} - {}",
+                        format_time(from),
+                        format_time(to)
+                    ));
+                    if ui.button("Clear selection (Esc)").clicked() {
+                        self.selection = None;
+                    }
+                }
+                None => {
+                    ui.weak("Shift+drag on the plot to select a time range");
+                }
+            }
+            for host in &self.hosts {
+                let samples: Vec<&PingResult> = self
+                    .ping_data
+                    .iter()
+                    .filter(|data| &data.host == host && self.in_selection(data))
+                    .collect();
+                let Some(last) = samples.last() else {
+                    continue;
+                };
+
+                ui.separator();
+                // Cut to the panel's width, with the full name on hover
+                let heading = egui::RichText::new(self.plain(host.clone())).strong();
+                ui.add(egui::Label::new(heading).truncate());
+                ui.label(format!("Last: {}", unit.format(last.average)));
+                if !self.emphasized(host) {
+                    continue;
+                }
+                let thresholds = self.config.thresholds_for(host, Local::now().time());
+                // Replies over the loss cutoff are plotted but count as lost
+                let on_time: Vec<&PingResult> = samples
+                    .iter()
+                    .copied()
+                    .filter(|data| !thresholds.counts_as_lost(data.average))
+                    .collect();
+                // As are outliers, when rejecting them
+                let mut filtered = on_time.clone();
+                let bounds = self.config.reject_outliers.and_then(|mads| {
+                    let values: Vec<f64> = on_time.iter().map(|data| data.average).collect();
+                    outlier_bounds(&values, mads)
+                });
+                if let Some((low, high)) = bounds {
+                    filtered.retain(|data| (low..=high).contains(&data.average));
+                }
+                if !filtered.is_empty() {
+                    let average = filtered.iter().map(|data| data.average).sum::<f64>()
+                        / filtered.len() as f64;
+                    ui.label(format!("Average: {}", unit.format(average)));
+                }
+                if filtered.len() < on_time.len() {
+                    ui.weak(format!(
+                        "{} outliers left out of the stats",
+                        on_time.len() - filtered.len()
+                    ));
+                }
+                if let Some(threshold) = thresholds.latency {
+                    ui.label(format!("Threshold: {threshold:.0} ms"));
+                }
+                if let Some(cutoff) = thresholds.loss_cutoff {
+                    let interval = self.config.interval_for(host);
+                    let times: Vec<DateTime<Utc>> =
+                        samples.iter().map(|data| data.datetime_recv).collect();
+                    let expected = expected_samples(&times, interval);
+                    ui.label(format!(
+                        "Loss: {:.1}% counting {} over {cutoff:.0} ms",
+                        100.0 * (expected - on_time.len()) as f64 / expected as f64,
+                        samples.len() - on_time.len()
+                    ));
+                }
+                if let Some(bandwidth) = last.bandwidth {
+                    ui.label(format!("Bandwidth: {bandwidth:.1} Mbit/s"));
+                }
+                let histogram =
+                    LogHistogram::from_values(HISTOGRAM_GROWTH, filtered.iter().map(|d| d.average));
+                if let [Some(p50), Some(p95), Some(p99)] =
+                    [50.0, 95.0, 99.0].map(|percent| histogram.percentile(percent))
+                {
+                    ui.label(format!(
+                        "p50/p95/p99: {} / {} / {}",
+                        unit.format(p50),
+                        unit.format(p95),
+                        unit.format(p99)
+                    ));
+                }
+
+                if let Some(budget) = self.config.budget {
+                    let values: Vec<f64> = samples.iter().map(|data| data.average).collect();
+                    if let Some(met) = budget.met_percent(&values) {
+                        let color = if budget.is_met(met) {
+                            self.palette_color(Palette::met)
+                        } else {
+                            self.palette_color(Palette::missed)
+                        };
+                        ui.add(
+                            egui::ProgressBar::new((met / 100.0) as f32)
+                                .fill(color)
+                                .text(format!(
+                                    "{met:.1}% within {} ms (target {}%)",
+                                    budget.latency, budget.target
+                                )),
+                        );
+                    }
+                }
+
+                let times: Vec<DateTime<Utc>> =
+                    samples.iter().map(|data| data.datetime_recv).collect();
+                if let Some(gaps) = inter_arrival(&times) {
+                    ui.label(format!(
+                        "Interval: {:.2} s (avg {:.2} s, {:.2}/s)",
+                        gaps.last,
+                        gaps.average,
+                        1.0 / gaps.average
+                    ));
+                    let interval = self.config.interval_at(host, Local::now().time());
+                    if gaps.deviates_from(interval) {
+                        ui.colored_label(
+                            self.palette_color(Palette::warning),
+                            format!("Sampling drifting from configured {interval:?}"),
+                        );
+                    }
+                }
+
+                if let Some(extremes) = self.extremes.get(host) {
+                    for (name, extreme) in [("Best", extremes.min), ("Worst", extremes.max)] {
+                        ui.label(format!(
+                            "{name}: {} at {}",
+                            unit.format(extreme.value),
+                            format_in(extreme.at, &Utc, "%H:%M:%S")
+                        ));
+                    }
+                }
+
+                if let Some(counts) = self.sequences.counts(host) {
+                    ui.label(format!(
+                        "Sequence: {} dropped, {} out of order, {} duplicate",
+                        counts.dropped, counts.out_of_order, counts.duplicates
+                    ));
+                }
+
+                if let Some(ttl) = self.ttls.stats(host) {
+                    if ttl.unstable() {
+                        ui.colored_label(
+                            self.palette_color(Palette::warning),
+                            format!(
+                                "Path instability: TTL changed {} times ({}-{}, mostly {})",
+                                ttl.changes, ttl.min, ttl.max, ttl.mode
+                            ),
+                        );
+                    } else {
+                        ui.label(format!("TTL: {} (variance {:.1})", ttl.mode, ttl.variance));
+                    }
+                }
+
+                if let Some(timings) = last.http_timings {
+                    http_breakdown(ui, host, &timings);
+                }
+            }
+
+            if self.config.dual_stack {
+                for host in &self.config.hosts {
+                    let [v4, v6] = IpFamily::BOTH.map(|family| {
+                        let label = family.label(&host.host);
+                        let samples: Vec<&PingResult> = self
+                            .ping_data
+                            .iter()
+                            .filter(|data| data.host == label)
+                            .collect();
+                        if samples.is_empty() {
+                            return None;
+                        }
+                        let times: Vec<DateTime<Utc>> =
+                            samples.iter().map(|data| data.datetime_recv).collect();
+                        let average = samples.iter().map(|data| data.average).sum::<f64>()
+                            / samples.len() as f64;
+                        Some((
+                            average,
+                            estimated_loss(&times, self.config.interval_for(&label)),
+                        ))
+                    });
+
+                    ui.separator();
+                    ui.strong(format!("{}: IPv6 vs IPv4", host.host));
+                    match (v6, v4) {
+                        (Some((v6_average, v6_loss)), Some((v4_average, v4_loss))) => {
+                            ui.label(format!(
+                                "Latency: {v6_average:.2} vs {v4_average:.2} ms ({:+.2})",
+                                v6_average - v4_average
+                            ));
+                            ui.label(format!(
+                                "Loss: {v6_loss:.1} vs {v4_loss:.1} % ({:+.1})",
+                                v6_loss - v4_loss
+                            ));
+                        }
+                        (None, _) => {
+                            ui.weak("No IPv6 replies yet");
+                        }
+                        (_, None) => {
+                            ui.weak("No IPv4 replies yet");
+                        }
+                    }
+                }
+            }
+
+            ui.separator();
+            ui.label(format!("Outages per {} min", OUTAGE_BUCKET.as_secs() / 60));
+            self.outage_sparkline(ui, now);
+            if self.hosts.iter().any(|host| {
+                self.config
+                    .thresholds_for(host, Local::now().time())
+                    .latency
+                    .is_some()
+            }) {
+                ui.label(format!(
+                    "Threshold crossings per {:?}",
+                    self.config.crossing_bucket
+                ));
+                self.crossing_bars(ui, now);
+            }
+
+            ui.separator();
+            egui::CollapsingHeader::new("Events")
+                .default_open(true)
+                .show(ui, |ui| {
+                    for event in self.events.iter().rev() {
+                        ui.label(self.plain(event.to_string()));
+                    }
+                });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let max_chars = label_chars(ui.available_width());
+            let labels = self.labels(ui.available_width());
+            let (scale, origin) = (self.settings.time_scale, plot_x(now));
+            let x_of = move |time| scale.forward(plot_x(time), origin);
+            let mut plot = Plot::new("ping_plot")
+                .label_formatter(full_name_formatter(labels.clone(), move |point| {
+                    format!(
+                        "{}  {}",
+                        format_time(scale.inverse(point.x, origin)),
+                        unit.format(point.y)
+                    )
+                }))
+                .view_aspect(2.0)
+                // Shift+drag selects a time range instead of panning
+                .allow_drag(!selecting)
+                // The plots below stay linear, so only line up with this one
+                // when it is too
+                .link_axis(
+                    match scale {
+                        TimeScale::Linear => "time",
+                        TimeScale::Recent => "time_recent",
+                    },
+                    [true, false],
+                )
+                .legend(Legend::default())
+                .x_axis_label("Time")
+                .custom_x_axes(vec![AxisHints::new_x().formatter(move |x, _range| {
+                    format_time(scale.inverse(x.value, origin))
+                })])
+                .custom_y_axes(vec![AxisHints::new_y()
+                    .label(format!("Ping ({})", unit.suffix()))
+                    .formatter(move |y, _range| unit.format_tick(y.value, y.step_size))]);
+            if let Some(spacing) = self.settings.x_grid.filter(|_| scale == TimeScale::Linear) {
+                plot = plot.x_grid_spacer(fixed_grid_spacer(spacing));
+            }
+            if let Some(spacing) = self.settings.y_grid {
+                plot = plot.y_grid_spacer(fixed_grid_spacer(spacing));
+            }
+
+            plot.show(ui, |plot_ui| {
+                let response = plot_ui.response();
+                let (started, dragged, stopped, hovered) = (
+                    response.drag_started(),
+                    response.dragged(),
+                    response.drag_stopped(),
+                    response.hovered(),
+                );
+                // The selection is kept in real time, independent of the scale
+                if let Some(pointer) = plot_ui.pointer_coordinate().filter(|_| selecting) {
+                    let at = scale.inverse(pointer.x, origin);
+                    if started {
+                        self.selecting_from = Some(at);
+                    }
+                    if let Some(from) = self.selecting_from.filter(|_| dragged) {
+                        self.selection = Some((from.min(at), from.max(at)));
+                    }
+                }
+                if stopped {
+                    self.selecting_from = None;
+                }
+                if let Some((from, to)) = self.selection {
+                    for x in [from, to] {
+                        let x = scale.forward(x, origin);
+                        plot_ui.vline(VLine::new(x).color(egui::Color32::GRAY));
+                    }
+                }
+
+                // Earlier days are moved onto today and drawn faintest, behind
+                // the baseline and the live data
+                let today = Local::now().date_naive();
+                for day in &self.history {
+                    let Some(first) = day.first() else {
+                        continue;
+                    };
+                    let date = first.datetime_recv.with_timezone(&Local).date_naive();
+                    let mut hosts: Vec<&String> = vec![];
+                    for data in day {
+                        if !hosts.contains(&&data.host) {
+                            hosts.push(&data.host);
+                        }
+                    }
+                    for host in hosts {
+                        let color = match self.hosts.iter().position(|known| known == host) {
+                            Some(index) => color32(self.host_color(index)),
+                            None => egui::Color32::from_gray(128),
+                        };
+                        let aligned = align_time_of_day(
+                            day.iter().filter(|data| &data.host == host),
+                            today,
+                            &Local,
+                        );
+                        let points = aligned
+                            .into_iter()
+                            .map(|(at, value)| [x_of(at), value])
+                            .collect();
+                        plot_ui.line(
+                            Line::new(PlotPoints::new(points))
+                                .name(
+                                    self.plain(format!(
+                                        "{} {date}",
+                                        truncate_label(host, max_chars)
+                                    )),
+                                )
+                                .color(color.gamma_multiply(0.25)),
+                        );
+                    }
+                }
+
+                // The baseline is shifted so its first sample lines up with the
+                // start of this run, and drawn first so live data sits on top
+                if let Some(first) = self.baseline.first() {
+                    let offset = self.started - first.datetime_recv;
+                    for note in &self.baseline_notes {
+                        let color = egui::Color32::from_gray(128).gamma_multiply(0.5);
+                        draw_note(plot_ui, x_of(note.at + offset), &note.text, color);
+                    }
+                    let points = PlotPoints::new(
+                        self.baseline
+                            .iter()
+                            .map(|data| [x_of(data.sent_at() + offset), data.average])
+                            .collect(),
+                    );
+                    plot_ui.line(
+                        Line::new(points)
+                            .name("baseline")
+                            .color(egui::Color32::from_gray(128).gamma_multiply(0.5)),
+                    );
+                }
+
+                // Maintenance windows and outages are shaded behind the lines
+                // up to the highest sample; the plot's own bounds would grow
+                // with them
+                let top = self
+                    .ping_data
+                    .iter()
+                    .map(|data| data.max.unwrap_or(data.average))
+                    .fold(0.0, f64::max);
+                if let Some(first) = self.ping_data.first() {
+                    for (start, end) in self.config.schedule.maintenance_spans(first.sent_at(), now)
+                    {
+                        let start = x_of(start);
+                        shade(plot_ui, start, x_of(end), top, egui::Color32::GRAY);
+                        draw_note(plot_ui, start, "maintenance", egui::Color32::GRAY);
+                    }
+                }
+
+                // One line per host, positioned by the time each sample
+                // was sent and broken where the machine slept
+                let asleep: Vec<(f64, f64)> = self
+                    .suspensions
+                    .iter()
+                    .map(|suspension| (x_of(suspension.start), x_of(suspension.end)))
+                    .collect();
+                for (index, host) in self.hosts.iter().enumerate() {
+                    let label = &labels[index].0;
+                    let warmup: Vec<[f64; 2]> = self
+                        .warmup_data
+                        .iter()
+                        .filter(|data| &data.host == host)
+                        .map(|data| [x_of(data.sent_at()), data.average])
+                        .collect();
+                    if !warmup.is_empty() {
+                        plot_ui.line(
+                            Line::new(PlotPoints::new(warmup))
+                                .color(color32(self.host_color(index)).gamma_multiply(0.3)),
+                        );
+                    }
+
+                    let emphasized = self.emphasized(host);
+                    let color = color32(self.host_color(index));
+                    let color = if emphasized {
+                        color
+                    } else {
+                        color.gamma_multiply(0.3)
+                    };
+                    let plotted = self.plotted_samples(host);
+
+                    // Samples missed in a row are shaded as one outage, and
+                    // lone misses marked where the sample was due
+                    let times: Vec<DateTime<Utc>> =
+                        plotted.iter().map(|data| data.sent_at()).collect();
+                    let losses = losses(host, &times, self.config.interval_for(host));
+                    for run in losses.runs.iter().filter(|run| !self.slept_through(run)) {
+                        let start = x_of(run.start);
+                        shade(plot_ui, start, x_of(run.end), top, color);
+                        plot_ui.text(
+                            Text::new(
+                                PlotPoint::new(start, top),
+                                format!("{label} down {}s", (run.end - run.start).num_seconds()),
+                            )
+                            .color(color)
+                            .anchor(egui::Align2::LEFT_TOP),
+                        );
+                    }
+                    if !losses.single.is_empty() {
+                        let missed = losses.single.iter().map(|&at| [x_of(at), 0.0]).collect();
+                        plot_ui.points(
+                            Points::new(PlotPoints::new(missed))
+                                .name(format!("{label} lost"))
+                                .color(color)
+                                .shape(MarkerShape::Cross)
+                                .radius(4.0),
+                        );
+                    }
+                    let series = |value: fn(&PingResult) -> Option<f64>| -> Vec<[f64; 2]> {
+                        let points = plotted
+                            .iter()
+                            .filter_map(|data| Some([x_of(data.sent_at()), value(data)?]))
+                            .collect();
+                        match self.config.plot_delay {
+                            Some(delay) => reveal_until(points, x_of(now - delay)),
+                            None => points,
+                        }
+                    };
+
+                    if let Some(bucket) = self.settings.error_bars {
+                        self.error_bars(plot_ui, host, label, color, bucket, x_of);
+                    } else {
+                        // Min and max are lighter and dotted/dashed to tell them apart
+                        if self.settings.show_min {
+                            for segment in split_at_gaps(series(|data| data.min), &asleep) {
+                                plot_ui.line(
+                                    Line::new(PlotPoints::new(segment))
+                                        .name(format!("{label} min"))
+                                        .color(color.linear_multiply(0.6))
+                                        .style(LineStyle::dotted_dense()),
+                                );
+                            }
+                        }
+                        if self.settings.show_max {
+                            for segment in split_at_gaps(series(|data| data.max), &asleep) {
+                                plot_ui.line(
+                                    Line::new(PlotPoints::new(segment))
+                                        .name(format!("{label} max"))
+                                        .color(color.linear_multiply(0.6))
+                                        .style(LineStyle::dashed_dense()),
+                                );
+                            }
+                        }
+                        if self.settings.show_avg {
+                            let points = series(|data| Some(data.average));
+                            if self.settings.deviation_colors && emphasized {
+                                self.deviation_points(plot_ui, host, label, &points);
+                            } else if self.settings.show_points {
+                                plot_ui.points(
+                                    Points::new(PlotPoints::new(points.clone()))
+                                        .name(label)
+                                        .color(color)
+                                        .radius(2.5),
+                                );
+                            }
+                            for segment in split_at_gaps(points, &asleep) {
+                                plot_ui.line(
+                                    Line::new(PlotPoints::new(segment)).name(label).color(color),
+                                );
+                            }
+                        }
+                    }
+
+                    if let Some(aggregate) = self.settings.moving_aggregate {
+                        let points = series(|data| Some(data.average));
+                        let values: Vec<f64> = points.iter().map(|point| point[1]).collect();
+                        let overlay = points
+                            .iter()
+                            .zip(moving(&values, self.settings.moving_window, aggregate))
+                            .map(|(point, value)| [point[0], value])
+                            .collect();
+                        for segment in split_at_gaps(overlay, &asleep) {
+                            plot_ui.line(
+                                Line::new(PlotPoints::new(segment))
+                                    .name(format!("{label} moving {}", aggregate.name()))
+                                    .color(color.linear_multiply(0.8))
+                                    .width(2.5),
+                            );
+                        }
+                    }
+
+                    let thresholds = self.config.thresholds_for(host, Local::now().time());
+                    if let Some(threshold) = thresholds.latency.filter(|_| emphasized) {
+                        plot_ui.hline(
+                            HLine::new(threshold)
+                                .name(format!("{label} threshold"))
+                                .color(color.gamma_multiply(0.5))
+                                .style(LineStyle::dashed_loose()),
+                        );
+                    }
+                }
+
+                for note in &self.notes {
+                    draw_note(plot_ui, x_of(note.at), &note.text, egui::Color32::GRAY);
+                }
+
+                for change in &self.regime_changes {
+                    let index = self.hosts.iter().position(|host| host == &change.host);
+                    let color = color32(self.host_color(index.unwrap_or_default()));
+                    let x = x_of(change.at);
+                    plot_ui.vline(VLine::new(x).color(color).style(LineStyle::dashed_loose()));
+                    plot_ui.text(
+                        Text::new(
+                            PlotPoint::new(x, change.after),
+                            self.plain(format!("{:.0} → {:.0} ms", change.before, change.after)),
+                        )
+                        .color(color)
+                        .anchor(egui::Align2::LEFT_BOTTOM),
+                    );
+                }
+
+                for change in &self.resolution_changes {
+                    let index = self
+                        .hosts
+                        .iter()
+                        .position(|host| IpFamily::host_of(host) == change.host);
+                    let color = color32(self.host_color(index.unwrap_or_default()));
+                    let after: Vec<String> = change.after.iter().map(|ip| ip.to_string()).collect();
+                    draw_note(plot_ui, x_of(change.at), &after.join(", "), color);
+                }
+
+                for change in &self.network_changes {
+                    let text = self.plain(change.summary());
+                    draw_note(plot_ui, x_of(change.at), &text, egui::Color32::GRAY);
+                }
+
+                for &(start, end) in &asleep {
+                    draw_note(plot_ui, start, "suspended", egui::Color32::GRAY);
+                    plot_ui.vline(VLine::new(end).color(egui::Color32::GRAY));
+                }
+
+                // Crosshair with a readout of the position under the cursor
+                if let Some(pointer) = plot_ui.pointer_coordinate().filter(|_| hovered) {
+                    let color = egui::Color32::from_gray(160).gamma_multiply(0.6);
+                    plot_ui.vline(VLine::new(pointer.x).color(color));
+                    plot_ui.hline(HLine::new(pointer.y).color(color));
+                    plot_ui.text(
+                        Text::new(
+                            pointer,
+                            format!(
+                                "{}  {}",
+                                format_time(scale.inverse(pointer.x, origin)),
+                                unit.format(pointer.y)
+                            ),
+                        )
+                        .anchor(egui::Align2::LEFT_BOTTOM),
+                    );
+                }
+            });
+
+            if self.ping_data.iter().any(|data| data.bandwidth.is_some()) {
+                self.bandwidth(ui);
+            }
+            if self.settings.show_histogram {
+                self.histogram(ui);
+            }
+            if self.settings.show_jitter {
+                self.jitter(ui);
+            }
+            if self.settings.show_rate {
+                self.rate(ui);
+            }
+            if self.settings.show_comparison {
+                self.comparison(ui, unit);
+            }
+        });
+
+        // Request a repaint to ensure continuous updates
+        ctx.request_repaint();
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if let Some(forensics) = &self.config.forensics {
+            forensics.flush();
+        }
+        if let Some(file_path) = &self.config.export_path {
+            if let Err(err) = export_samples(file_path, &self.ping_data, &self.config.csv_options) {
+                eprintln!("Error exporting to {file_path}: {err}");
+            }
+            let file_path = metadata_path(file_path);
+            let metadata = self.config.run_metadata(self.started);
+            if let Err(err) = export_metadata(&file_path, &metadata) {
+                eprintln!("Error exporting metadata to {file_path}: {err}");
+            }
+        }
+        let notes_export = self
+            .config
+            .export_path
+            .as_ref()
+            .filter(|_| !self.notes.is_empty());
+        if let Some(file_path) = notes_export {
+            let file_path = notes_path(file_path);
+            if let Err(err) = export_notes(&file_path, &self.notes) {
+                eprintln!("Error exporting notes to {file_path}: {err}");
+            }
+        }
+        if let Some(file_path) = &self.config.rollup_path {
+            let rollups = rollups(&self.ping_data, self.config.rollup_bucket, |host| {
+                self.config.interval_for(host)
+            });
+            if let Err(err) = export_rollups_to_csv(file_path, &rollups, &self.config.csv_options) {
+                eprintln!("Error exporting rollups to {file_path}: {err}");
+            }
+        }
+        if let Some(file_path) = &self.config.chart_path {
+            if let Err(err) =
+                export_chart(file_path, &self.chart_series(), self.config.chart_smooth)
+            {
+                eprintln!("Error drawing the chart to {file_path}: {err}");
+            }
+        }
+    }
+}
+
+/// How many characters of a host label fit the legend of a plot `width` wide.
+fn label_chars(width: f32) -> usize {
+    ((width * LEGEND_WIDTH_SHARE / LEGEND_CHAR_WIDTH) as usize).max(MIN_LABEL_CHARS)
+}
+
+/// Shortens `label` to `max_chars` by putting an ellipsis in its middle,
+/// keeping the start and end that tell FQDNs and IPv6 addresses apart.
+pub fn truncate_label(label: &str, max_chars: usize) -> String {
+    let count = label.chars().count();
+    if count <= max_chars {
+        return label.to_string();
+    }
+    let head = max_chars.saturating_sub(1).div_ceil(2);
+    let tail = max_chars.saturating_sub(1) - head;
+    label
+        .chars()
+        .take(head)
+        .chain(std::iter::once('…'))
+        .chain(label.chars().skip(count - tail))
+        .collect()
+}
+
+/// Hover text for plot items named after [`PingApp::labels`], giving the
+/// host's full name followed by `describe` of the point.
+fn full_name_formatter(
+    labels: Vec<(String, String)>,
+    describe: impl Fn(&PlotPoint) -> String,
+) -> impl Fn(&str, &PlotPoint) -> String {
+    move |name, point| {
+        if name.is_empty() {
+            return String::new();
+        }
+        let name = labels
+            .iter()
+            .filter(|(label, host)| label != host)
+            .find_map(|(label, host)| Some(format!("{host}{}", name.strip_prefix(label.as_str())?)))
+            .unwrap_or_else(|| name.to_string());
+        format!("{name}\n{}", describe(point))
+    }
+}
+
+/// Shades the plot from `start` to `end` on the x axis, up from 0 to `top`.
+fn shade(plot_ui: &mut egui_plot::PlotUi, start: f64, end: f64, top: f64, color: egui::Color32) {
+    plot_ui.polygon(
+        Polygon::new(PlotPoints::new(vec![
+            [start, 0.0],
+            [end, 0.0],
+            [end, top],
+            [start, top],
+        ]))
+        .fill_color(color.gamma_multiply(0.15))
+        .stroke(egui::Stroke::NONE)
+        .allow_hover(false),
+    );
+}
+
+/// Draws a note as a vertical line labelled at the top of the plot.
+fn draw_note(plot_ui: &mut egui_plot::PlotUi, x: f64, text: &str, color: egui::Color32) {
+    let top = plot_ui.plot_bounds().max()[1];
+    plot_ui.vline(VLine::new(x).color(color));
+    plot_ui.text(
+        Text::new(PlotPoint::new(x, top), text)
+            .color(color)
+            .anchor(egui::Align2::LEFT_TOP),
+    );
+}
+
+/// Cuts `points`, sorted by x, off at `x` and ends them with a point
+/// interpolated towards the next one, so that a line drawn from them grows
+/// continuously as `x` moves rather than a sample at a time.
+fn reveal_until(mut points: Vec<[f64; 2]>, x: f64) -> Vec<[f64; 2]> {
+    let shown = points.partition_point(|point| point[0] <= x);
+    let next = points.get(shown).copied();
+    points.truncate(shown);
+    if let (Some(&[x0, y0]), Some([x1, y1])) = (points.last(), next) {
+        points.push([x, y0 + (y1 - y0) * (x - x0) / (x1 - x0)]);
+    }
+    points
+}
+
+/// Gridlines every `spacing` plot units, every fifth and tenth drawn
+/// stronger. When zoomed out so far that they would crowd together, the
+/// spacing doubles until they don't.
+fn fixed_grid_spacer(spacing: f64) -> Box<dyn Fn(GridInput) -> Vec<GridMark>> {
+    uniform_grid_spacer(move |input| {
+        let mut spacing = spacing;
+        while spacing < input.base_step_size {
+            spacing *= 2.0;
+        }
+        [spacing, spacing * 5.0, spacing * 10.0]
+    })
+}
+
+/// Seconds in a day, the widest spacing offered along the time axis.
+const DAY: f64 = 86_400.0;
+
+/// A checkbox for fixing a gridline spacing, starting at `initial`, and a
+/// field for it up to `max` while it is fixed.
+fn grid_control(
+    ui: &mut egui::Ui,
+    label: &str,
+    spacing: &mut Option<f64>,
+    initial: f64,
+    max: f64,
+    suffix: &str,
+) {
+    ui.horizontal(|ui| {
+        let mut fixed = spacing.is_some();
+        if ui.checkbox(&mut fixed, label).changed() {
+            *spacing = fixed.then_some(initial);
+        }
+        if let Some(spacing) = spacing {
+            ui.add(
+                egui::DragValue::new(spacing)
+                    .range(0.001..=max)
+                    .suffix(suffix),
+            );
+        }
+    });
+}
+
+/// Splits `points`, sorted by x, into the runs between the `gaps`, so that
+/// no line is drawn across any part of a gap.
+fn split_at_gaps(points: Vec<[f64; 2]>, gaps: &[(f64, f64)]) -> Vec<Vec<[f64; 2]>> {
+    let mut segments: Vec<Vec<[f64; 2]>> = vec![];
+    for point in points {
+        let crosses = segments
+            .last()
+            .and_then(|segment| segment.last())
+            .is_some_and(|last| {
+                gaps.iter()
+                    .any(|&(start, end)| start < point[0] && last[0] < end)
+            });
+        match segments.last_mut() {
+            Some(segment) if !crosses => segment.push(point),
+            _ => segments.push(vec![point]),
+        }
+    }
+    segments
+}
+
+/// Position of `time` on the plot's x axis, in seconds since the epoch.
+pub fn plot_x(time: DateTime<Utc>) -> f64 {
+    time.timestamp_millis() as f64 / 1000.0
+}
+
+/// Age in seconds up to which [`TimeScale::Recent`] stays close to linear,
+/// compressing older time more and more.
+pub const RECENT_SCALE: f64 = 60.0;
+
+/// How time is spread along the latency plot's x axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TimeScale {
+    #[default]
+    Linear,
+    /// Logarithmic in the age of a point, so the last minutes take most of
+    /// the width while earlier history stays in view.
+    Recent,
+}
+
+impl TimeScale {
+    pub const ALL: [TimeScale; 2] = [TimeScale::Linear, TimeScale::Recent];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TimeScale::Linear => "Linear",
+            TimeScale::Recent => "Recent first",
+        }
+    }
+
+    /// Where `x`, in seconds since the epoch as from [`plot_x`], is drawn
+    /// when the newest point is at `now`. Times after `now` are spread the
+    /// same way as times before it.
+    pub fn forward(self, x: f64, now: f64) -> f64 {
+        match self {
+            TimeScale::Linear => x,
+            TimeScale::Recent => {
+                let age = now - x;
+                now - age.signum() * RECENT_SCALE * (age.abs() / RECENT_SCALE).ln_1p()
+            }
+        }
+    }
+
+    /// The time, in seconds since the epoch, drawn at position `x`: the
+    /// inverse of [`TimeScale::forward`].
+    pub fn inverse(self, x: f64, now: f64) -> f64 {
+        match self {
+            TimeScale::Linear => x,
+            TimeScale::Recent => {
+                let distance = now - x;
+                now - distance.signum() * RECENT_SCALE * (distance.abs() / RECENT_SCALE).exp_m1()
+            }
+        }
+    }
+}
+
+/// Latencies from this many ms up are shown in seconds with
+/// [`Settings::auto_units`].
+pub const SECONDS_FROM: f64 = 1000.0;
+
+/// Unit latencies are displayed in. Samples are always kept in ms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyUnit {
+    Millis,
+    Seconds,
+}
+
+impl LatencyUnit {
+    /// The unit that reads best for latencies up to `max` ms.
+    pub fn for_max(max: f64) -> LatencyUnit {
+        if max >= SECONDS_FROM {
+            LatencyUnit::Seconds
+        } else {
+            LatencyUnit::Millis
+        }
+    }
+
+    pub fn suffix(self) -> &'static str {
+        match self {
+            LatencyUnit::Millis => "ms",
+            LatencyUnit::Seconds => "s",
+        }
+    }
+
+    /// `ms` converted to this unit.
+    pub fn scale(self, ms: f64) -> f64 {
+        match self {
+            LatencyUnit::Millis => ms,
+            LatencyUnit::Seconds => ms / 1000.0,
+        }
+    }
+
+    /// `ms` in this unit with the suffix, like `2500.00 ms` or `2.50 s`.
+    pub fn format(self, ms: f64) -> String {
+        format!("{:.2} {}", self.scale(ms), self.suffix())
+    }
+
+    /// An axis tick at `ms` on a grid `step` ms apart, with just enough
+    /// decimals to tell the ticks apart.
+    pub fn format_tick(self, ms: f64, step: f64) -> String {
+        let decimals = (-self.scale(step).log10()).ceil().clamp(0.0, 6.0) as usize;
+        format!("{:.*}", decimals, self.scale(ms))
+    }
+}
+
+/// Formats an x axis value back into a time of day.
+pub fn format_time(x: f64) -> String {
+    match Utc.timestamp_millis_opt((x * 1000.0) as i64).single() {
+        Some(dt) => format_in(dt, &Utc, "%H:%M:%S"),
+        None => String::new(),
+    }
+}
+
+/// Shows the phases of the last HTTP request as one horizontal stacked bar.
+fn http_breakdown(ui: &mut egui::Ui, host: &str, timings: &HttpTimings) {
+    let phases = [
+        ("DNS", timings.dns),
+        ("Connect", timings.connect),
+        ("TLS", timings.tls.unwrap_or(0.0)),
+        ("TTFB", timings.ttfb),
+    ];
+
+    for (name, value) in phases {
+        ui.label(format!("{name}: {value:.2} ms"));
+    }
+
+    let mut charts: Vec<BarChart> = vec![];
+    for (name, value) in phases {
+        let chart = BarChart::new(vec![Bar::new(0.0, value).width(0.6)])
+            .horizontal()
+            .name(name);
+        let below: Vec<&BarChart> = charts.iter().collect();
+        let chart = chart.stack_on(&below);
+        charts.push(chart);
+    }
+
+    Plot::new(("http_breakdown", host))
+        .height(60.0)
+        .show_axes([true, false])
+        .show_grid(false)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .legend(Legend::default())
+        .show(ui, |plot_ui| {
+            for chart in charts {
+                plot_ui.bar_chart(chart);
+            }
+        });
+}
+
+/// Runs the GUI, taking the app out of `app` once the window is created.
+pub fn draw_chart_realtime(app: &mut Option<PingApp>) -> Result<(), Box<dyn std::error::Error>> {
+    // Set up eframe options
+    let options = eframe::NativeOptions {
+        ..Default::default()
+    };
+
+    // Run the application
+    eframe::run_native(
+        "Network Ping Monitor",
+        options,
+        Box::new(|cc| {
+            let mut app = app.take().expect("the app is only created once");
+            if let Some(storage) = cc.storage {
+                let stored: Settings =
+                    eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+                app.settings = stored.overridden_by(&app.config);
+            }
+            Ok(Box::new(app))
+        }),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switches_to_seconds_from_a_second_up() {
+        assert_eq!(LatencyUnit::for_max(999.99), LatencyUnit::Millis);
+        assert_eq!(LatencyUnit::for_max(SECONDS_FROM), LatencyUnit::Seconds);
+        assert_eq!(LatencyUnit::Millis.format(999.994), "999.99 ms");
+        assert_eq!(LatencyUnit::Seconds.format(1000.0), "1.00 s");
+        assert_eq!(LatencyUnit::Seconds.format(2500.0), "2.50 s");
+        assert_eq!(LatencyUnit::Seconds.format(0.0), "0.00 s");
+        assert_eq!(LatencyUnit::Millis.format_tick(250.0, 50.0), "250");
+        assert_eq!(LatencyUnit::Seconds.format_tick(1200.0, 100.0), "1.2");
+        assert_eq!(LatencyUnit::Seconds.format_tick(1010.0, 10.0), "1.01");
+        assert_eq!(LatencyUnit::Seconds.format_tick(2000.0, 1000.0), "2");
+    }
+
+    fn app() -> PingApp {
+        let (_tx, rx) = mpsc::channel();
+        let config = Config::from_args(["a".to_string()]).unwrap();
+        PingApp::new(rx, config, vec![])
+    }
+
+    #[test]
+    fn focusing_a_host_dims_the_others() {
+        let mut app = app();
+        for host in ["a", "b"] {
+            app.record(PingResult::new(host.to_string(), 10.0, Utc::now()));
+        }
+        assert_eq!(app.active_host(), None);
+        assert!(app.emphasized("a") && app.emphasized("b"));
+
+        app.settings.active_host = Some("b".to_string());
+        assert_eq!(app.active_host().map(String::as_str), Some("b"));
+        assert!(!app.emphasized("a") && app.emphasized("b"));
+
+        // A focus remembered from a session with other hosts is let go
+        app.settings.active_host = Some("c".to_string());
+        assert_eq!(app.active_host(), None);
+        assert!(app.emphasized("a"));
+
+        app.settings.active_host = Some("b".to_string());
+        let saved = serde_json::to_string(&app.settings).unwrap();
+        let restored: Settings = serde_json::from_str(&saved).unwrap();
+        assert_eq!(restored.active_host.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn trimming_keeps_the_all_time_extremes() {
+        let mut app = app();
+        let start = Utc::now() - chrono::Duration::seconds(100);
+        let at = |seconds| start + chrono::Duration::seconds(seconds);
+        for seconds in 0..100 {
+            let average = match seconds {
+                3 => 1.0,
+                7 => 500.0,
+                _ => 20.0 + (seconds % 5) as f64,
+            };
+            app.record(PingResult::new("a".to_string(), average, at(seconds)));
+        }
+        trim_older_than(&mut app.ping_data, at(100), Duration::from_secs(30));
+        assert!(app.ping_data.iter().all(|sample| sample.average < 25.0));
+
+        let extremes = app.extremes.get("a").unwrap();
+        assert_eq!((extremes.min.value, extremes.min.at), (1.0, at(3)));
+        assert_eq!((extremes.max.value, extremes.max.at), (500.0, at(7)));
+    }
+
+    #[test]
+    fn comparison_rows_match_each_hosts_stats() {
+        let mut app = app();
+        let start = Utc::now() - chrono::Duration::seconds(10);
+        let at = |seconds| start + chrono::Duration::seconds(seconds);
+        for (seconds, average) in [(0, 10.0), (1, 30.0), (2, 20.0)] {
+            app.record(PingResult::new("b".to_string(), average, at(seconds)));
+        }
+        for seconds in [0, 2, 3] {
+            app.record(PingResult::new("a".to_string(), 50.0, at(seconds)));
+        }
+        app.settings.compare_by = CompareColumn::Host;
+        app.settings.compare_descending = false;
+        let rows = app.comparison_rows();
+        let interval = app.config.interval_for("a");
+        assert_eq!(interval, Duration::from_secs(1));
+        assert_eq!(
+            rows,
+            [
+                HostComparison {
+                    host: "a".to_string(),
+                    avg: 50.0,
+                    min: 50.0,
+                    max: 50.0,
+                    loss: 25.0,
+                    jitter: Some(0.0),
+                },
+                HostComparison {
+                    host: "b".to_string(),
+                    avg: 20.0,
+                    min: 10.0,
+                    max: 30.0,
+                    loss: 0.0,
+                    jitter: Some(15.0),
+                },
+            ]
+        );
+
+        app.settings.compare_by = CompareColumn::Jitter;
+        app.settings.compare_descending = true;
+        let hosts: Vec<String> = app
+            .comparison_rows()
+            .into_iter()
+            .map(|row| row.host)
+            .collect();
+        assert_eq!(hosts, ["b", "a"]);
+    }
+
+    #[test]
+    fn error_bars_span_each_buckets_min_to_max() {
+        let mut app = app();
+        let at = |seconds: i64| DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap();
+        let series = [(0, 10.0), (20, 30.0), (40, 20.0), (60, 50.0), (80, 70.0)];
+        for (seconds, latency) in series {
+            app.record(PingResult::new("a".to_string(), latency, at(seconds)));
+            app.record(PingResult::new("b".to_string(), 500.0, at(seconds)));
+        }
+        let bars = |app: &PingApp| {
+            app.error_bar_rollups("a", Duration::from_secs(60))
+                .iter()
+                .map(|rollup| (rollup.start, rollup.min, rollup.avg, rollup.max))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            bars(&app),
+            [(at(0), 10.0, 20.0, 30.0), (at(60), 50.0, 60.0, 70.0)]
+        );
+
+        // Only the plotted samples are bucketed
+        app.config.plot_points = Some(3);
+        assert_eq!(
+            bars(&app),
+            [(at(0), 20.0, 20.0, 20.0), (at(60), 50.0, 60.0, 70.0)]
+        );
+    }
+
+    #[test]
+    fn recent_time_scale_inverts_and_favors_recent_time() {
+        let now = 1_704_067_200.0;
+        for scale in TimeScale::ALL {
+            for age in [-30.0, 0.0, 0.5, 59.0, 3600.0, 86_400.0 * 7.0] {
+                let x = now - age;
+                let back = scale.inverse(scale.forward(x, now), now);
+                // To the millisecond timestamps are exported to, even a week back
+                assert!((back - x).abs() < 1e-3, "{scale:?} at age {age}: {back}");
+            }
+        }
+
+        let recent = TimeScale::Recent;
+        assert_eq!(recent.forward(now, now), now);
+        // A second of the last minute gets more width than one an hour ago,
+        // and a second of yesterday less still
+        let density = |from: f64, to: f64| {
+            (recent.forward(now - to, now) - recent.forward(now - from, now)) / (from - to)
+        };
+        assert!(density(60.0, 0.0) > 50.0 * density(7200.0, 3600.0));
+        assert!(density(7200.0, 3600.0) > 10.0 * density(90_000.0, 86_400.0));
+        // Older points are still further left
+        assert!(recent.forward(now - 7200.0, now) < recent.forward(now - 3600.0, now));
+        assert_eq!(TimeScale::Linear.forward(now - 3600.0, now), now - 3600.0);
+    }
+
+    #[test]
+    fn plots_only_the_newest_points_of_the_retained_ones() {
+        let (_tx, rx) = mpsc::channel();
+        let config = Config::from_args(["a", "--plot-points", "500"].map(String::from)).unwrap();
+        let mut app = PingApp::new(rx, config, vec![]);
+        let at = |seconds: i64| DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap();
+        for i in 0..2000 {
+            app.record(PingResult::new("a".to_string(), i as f64, at(i)));
+        }
+        app.record(PingResult::new("b".to_string(), 5.0, at(2000)));
+
+        let plotted = app.plotted_samples("a");
+        assert_eq!(plotted.len(), 500);
+        assert_eq!(plotted[0].average, 1500.0);
+        assert_eq!(plotted[499].average, 1999.0);
+        assert_eq!(app.plotted_samples("b").len(), 1);
+        // Stats still cover the whole buffer
+        assert_eq!(app.ping_data.len(), 2001);
+        let rows = app.comparison_rows();
+        assert_eq!((rows[0].min, rows[0].max), (0.0, 1999.0));
+    }
+}
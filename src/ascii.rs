@@ -0,0 +1,51 @@
+//! Plain ASCII stand-ins for the symbols in labels and terminal output, for
+//! `--ascii` and terminals whose locale isn't UTF-8.
+
+use std::env;
+
+/// Whether the terminal's locale is UTF-8, going by the first of `LC_ALL`,
+/// `LC_CTYPE` and `LANG` that is set. Without any, UTF-8 is assumed.
+pub fn locale_is_utf8() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .is_none_or(|locale| {
+            let locale = locale.to_ascii_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+/// The ASCII character drawn in place of `c`: lines for box drawing, `*` for
+/// plot markers and `?` for anything else that isn't ASCII already.
+pub fn ascii_char(c: char) -> char {
+    match c {
+        c if c.is_ascii() => c,
+        '─' | '━' | '═' | '╌' | '┄' => '-',
+        '│' | '┃' | '║' | '╎' | '┆' => '|',
+        '\u{2500}'..='\u{257f}' => '+',
+        // An empty braille pattern is a blank plot cell
+        '\u{2800}' => ' ',
+        '\u{2801}'..='\u{28ff}' | '\u{2580}'..='\u{259f}' | '•' | '·' => '*',
+        '…' => '.',
+        '→' => '>',
+        '←' => '<',
+        'µ' => 'u',
+        _ => '?',
+    }
+}
+
+/// `text` with every non-ASCII character replaced, spelling out the common
+/// symbols rather than squeezing them into one character.
+pub fn to_ascii(text: &str) -> String {
+    let mut ascii = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '…' => ascii.push_str("..."),
+            '→' => ascii.push_str("->"),
+            '←' => ascii.push_str("<-"),
+            c => ascii.push(ascii_char(c)),
+        }
+    }
+    ascii
+}
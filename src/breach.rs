@@ -0,0 +1,249 @@
+//! `--on-breach`: runs a command when a host's latency goes over its
+//! threshold, such as a script starting a packet capture.
+//!
+//! The command runs through the shell with the breach described in
+//! `NETWORK_TEST_HOST`, `NETWORK_TEST_LATENCY` and `NETWORK_TEST_THRESHOLD`,
+//! both in ms, and `NETWORK_TEST_AT`.
+
+use crate::alert::Snooze;
+use crate::export::format_timestamp;
+use crate::ping::PingResult;
+use crate::sampler;
+use crate::schedule::Schedule;
+use chrono::{Local, NaiveTime};
+use std::collections::HashMap;
+use std::io;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Least time between two runs of the command for one host, however often
+/// it crosses its threshold.
+pub const BREACH_COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct HostBreach {
+    over: bool,
+    last_run: Option<Instant>,
+}
+
+/// Debounces breaches per host: the command runs when a host goes over its
+/// threshold, not again while it stays over, and at most once per
+/// [`BREACH_COOLDOWN`].
+#[derive(Debug, Default)]
+pub struct BreachTracker {
+    hosts: HashMap<String, HostBreach>,
+}
+
+impl BreachTracker {
+    pub fn new() -> BreachTracker {
+        BreachTracker::default()
+    }
+
+    /// Whether `sample`, held to a latency `limit` in ms, starts a breach the
+    /// command should run for at `now`, recording the run if so.
+    pub fn observe(&mut self, sample: &PingResult, limit: Option<f64>, now: Instant) -> bool {
+        let over = limit.is_some_and(|limit| sample.average > limit);
+        let host = self.hosts.entry(sample.host.clone()).or_default();
+        let starts = over
+            && !host.over
+            && host
+                .last_run
+                .is_none_or(|last| now.duration_since(last) >= BREACH_COOLDOWN);
+        host.over = over;
+        if starts {
+            host.last_run = Some(now);
+        }
+        starts
+    }
+}
+
+/// `command` run through the platform's shell.
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+/// Runs `command` for the breach of `limit` ms by `sample` on its own thread,
+/// logging how it exited. Its output goes to stderr, keeping stdout for
+/// `--stream-stdout`.
+pub fn run(command: &str, sample: &PingResult, limit: f64) {
+    let mut child = shell(command);
+    child
+        .env("NETWORK_TEST_HOST", &sample.host)
+        .env("NETWORK_TEST_LATENCY", format!("{:.3}", sample.average))
+        .env("NETWORK_TEST_THRESHOLD", limit.to_string())
+        .env("NETWORK_TEST_AT", format_timestamp(sample.datetime_recv))
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(io::stderr()));
+    let host = sample.host.clone();
+    thread::spawn(move || match child.status() {
+        Ok(status) => eprintln!("--on-breach command for {host} finished with {status}"),
+        Err(err) => eprintln!("Error running --on-breach command for {host}: {err}"),
+    });
+}
+
+/// Passes every sample from `rx` on to the returned receiver, running
+/// `command` as hosts go over the latency `limit` they are held to at a
+/// local time of day. Breaches during a maintenance window of `schedule` or
+/// while `snooze` is active run nothing.
+pub fn watch_breaches(
+    rx: mpsc::Receiver<PingResult>,
+    command: String,
+    schedule: Schedule,
+    snooze: Snooze,
+    limit: impl Fn(&str, NaiveTime) -> Option<f64> + Send + 'static,
+) -> mpsc::Receiver<PingResult> {
+    let mut breaches = BreachTracker::new();
+    sampler::tee(rx, move |ping_value| {
+        let time = Local::now().time();
+        let limit = limit(&ping_value.host, time);
+        let now = Instant::now();
+        // Suppressed samples aren't observed, so they neither start the
+        // cooldown nor hide a breach still going on once they're over
+        let suppressed = schedule.in_maintenance(time) || snooze.is_active(now);
+        if !suppressed && breaches.observe(ping_value, limit, now) {
+            if let Some(limit) = limit {
+                run(&command, ping_value, limit);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn sample(average: f64) -> PingResult {
+        PingResult::new("example.com".to_string(), average, Utc::now())
+    }
+
+    #[test]
+    fn runs_once_per_breach_and_cooldown() {
+        let mut breaches = BreachTracker::new();
+        let start = Instant::now();
+        assert!(!breaches.observe(&sample(10.0), Some(50.0), start));
+        assert!(breaches.observe(&sample(80.0), Some(50.0), start));
+        assert!(!breaches.observe(&sample(90.0), Some(50.0), start));
+        assert!(!breaches.observe(&sample(10.0), Some(50.0), start));
+        // Over again, but within the cooldown
+        assert!(!breaches.observe(&sample(80.0), Some(50.0), start));
+        assert!(!breaches.observe(&sample(10.0), Some(50.0), start));
+        let later = start + BREACH_COOLDOWN;
+        assert!(breaches.observe(&sample(80.0), Some(50.0), later));
+        assert!(!breaches.observe(&sample(80.0), None, later));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn snoozed_breaches_run_once_the_snooze_is_over() {
+        let out = std::env::temp_dir().join(format!("breach-snooze-{}", std::process::id()));
+        let _ = std::fs::remove_file(&out);
+        let (tx, rx) = mpsc::channel();
+        let snooze = Snooze::default();
+        snooze.snooze(Instant::now(), Duration::from_secs(60));
+        let command = format!("echo $NETWORK_TEST_LATENCY >> {}", out.display());
+        let teed = watch_breaches(rx, command, Schedule::default(), snooze.clone(), |_, _| {
+            Some(50.0)
+        });
+
+        tx.send(sample(80.0)).unwrap();
+        teed.recv().unwrap();
+        snooze.cancel();
+        tx.send(sample(90.0)).unwrap();
+        teed.recv().unwrap();
+
+        let started = Instant::now();
+        while !out.exists() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        thread::sleep(Duration::from_millis(100));
+        let runs = std::fs::read_to_string(&out).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert_eq!(runs, "90.000\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn breaches_in_a_maintenance_window_run_nothing() {
+        use crate::schedule::parse_schedule;
+
+        let window = |from: i64, to: i64| {
+            let now = Local::now().time();
+            let (start, end) = (
+                now + chrono::Duration::hours(from),
+                now + chrono::Duration::hours(to),
+            );
+            parse_schedule(&format!(
+                "{}-{} maintenance",
+                start.format("%H:%M"),
+                end.format("%H:%M")
+            ))
+            .unwrap()
+        };
+        let runs = |schedule: Schedule, name: &str| {
+            let out = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            let _ = std::fs::remove_file(&out);
+            let (tx, rx) = mpsc::channel();
+            let command = format!("echo $NETWORK_TEST_LATENCY >> {}", out.display());
+            let teed = watch_breaches(rx, command, schedule, Snooze::default(), |_, _| Some(50.0));
+            tx.send(sample(80.0)).unwrap();
+            teed.recv().unwrap();
+            let started = Instant::now();
+            while !out.exists() && started.elapsed() < Duration::from_secs(1) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            thread::sleep(Duration::from_millis(100));
+            let runs = std::fs::read_to_string(&out).unwrap_or_default();
+            let _ = std::fs::remove_file(&out);
+            runs
+        };
+
+        assert_eq!(runs(window(-1, 1), "breach-maintenance"), "");
+        assert_eq!(runs(window(2, 4), "breach-outside-maintenance"), "80.000\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn describes_the_breach_in_the_environment() {
+        let out = std::env::temp_dir().join(format!("breach-env-{}", std::process::id()));
+        let _ = std::fs::remove_file(&out);
+        let command = format!(
+            "printf '%s\\n' \"$NETWORK_TEST_HOST\" \"$NETWORK_TEST_LATENCY\" \
+             \"$NETWORK_TEST_THRESHOLD\" \"$NETWORK_TEST_AT\" > {0}.tmp && mv {0}.tmp {0}",
+            out.display()
+        );
+        let at = chrono::DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let teed = watch_breaches(
+            rx,
+            command,
+            Schedule::default(),
+            Snooze::default(),
+            |_, _| Some(62.5),
+        );
+        tx.send(PingResult::new("example.com".to_string(), 123.4567, at))
+            .unwrap();
+        teed.recv().unwrap();
+
+        let started = Instant::now();
+        while !out.exists() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let env = std::fs::read_to_string(&out).unwrap();
+        std::fs::remove_file(&out).unwrap();
+        assert_eq!(
+            env,
+            "example.com\n123.457\n62.5\n2024-01-01T00:00:00.000Z\n"
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! Saving the output of every `ping` run to its own numbered file with
+//! `--record-raw`, and feeding the files back through the parser in place of
+//! running `ping` with `--replay-raw`, so parser bugs can be reproduced.
+//!
+//! A capture directory holds `000001.out`, `000002.out` and so on with the
+//! exact stdout of each run, and an `index.tsv` listing each number with the
+//! host that was pinged, in the order they ran.
+//!
+//! `--forensics` instead keeps only the latest runs of each host and saves
+//! the ones around a failed or slow sample, so there is something to look at
+//! after an incident without logging everything.
+
+use crate::export::TIMESTAMP_FORMAT;
+use crate::ping::PingResult;
+use chrono::{Local, NaiveTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Name of the index in a capture directory.
+pub const CAPTURE_INDEX: &str = "index.tsv";
+
+fn capture_path(dir: &Path, number: usize) -> PathBuf {
+    dir.join(format!("{number:06}.out"))
+}
+
+/// Writes captures to a directory. Clones share the numbering.
+#[derive(Debug, Clone)]
+pub struct RawRecorder {
+    dir: PathBuf,
+    /// The last number used and the open index.
+    state: Arc<Mutex<(usize, File)>>,
+}
+
+impl RawRecorder {
+    /// Records into `dir`, creating it if needed. Numbering carries on after
+    /// any captures already there.
+    pub fn create(dir: impl Into<PathBuf>) -> Result<RawRecorder, io::Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let index_path = dir.join(CAPTURE_INDEX);
+        let last = match fs::read_to_string(&index_path) {
+            Ok(index) => parse_index(&index)?.last().map_or(0, |(number, _)| *number),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(index_path)?;
+        Ok(RawRecorder {
+            dir,
+            state: Arc::new(Mutex::new((last, index))),
+        })
+    }
+
+    /// Saves the stdout of one run against `host`. Failing to write is
+    /// reported on stderr rather than failing the sample.
+    pub fn record(&self, host: &str, stdout: &[u8]) {
+        // A sampler that panicked mid-write shouldn't stop the others recording
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        let number = state.0 + 1;
+        let written = fs::write(capture_path(&self.dir, number), stdout)
+            .and_then(|()| writeln!(state.1, "{number}\t{host}"));
+        match written {
+            Ok(()) => state.0 = number,
+            Err(err) => eprintln!(
+                "Error recording raw output to {}: {err}",
+                self.dir.display()
+            ),
+        }
+    }
+}
+
+/// Reads the `number\thost` lines of an index.
+fn parse_index(index: &str) -> Result<Vec<(usize, String)>, io::Error> {
+    index
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid capture index line {line:?}"),
+                )
+            };
+            let (number, host) = line.split_once('\t').ok_or_else(invalid)?;
+            Ok((number.parse().map_err(|_| invalid())?, host.to_string()))
+        })
+        .collect()
+}
+
+/// The captures in a directory, handed out in recorded order per host.
+/// Clones share what's left.
+#[derive(Debug, Clone)]
+pub struct RawCaptures {
+    dir: PathBuf,
+    /// Hosts in the order they first appear.
+    hosts: Vec<String>,
+    remaining: Arc<Mutex<HashMap<String, VecDeque<usize>>>>,
+}
+
+impl RawCaptures {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<RawCaptures, io::Error> {
+        let dir = dir.into();
+        let index = parse_index(&fs::read_to_string(dir.join(CAPTURE_INDEX))?)?;
+        let mut hosts = vec![];
+        let mut remaining: HashMap<String, VecDeque<usize>> = HashMap::new();
+        for (number, host) in index {
+            if !hosts.contains(&host) {
+                hosts.push(host.clone());
+            }
+            remaining.entry(host).or_default().push_back(number);
+        }
+        Ok(RawCaptures {
+            dir,
+            hosts,
+            remaining: Arc::new(Mutex::new(remaining)),
+        })
+    }
+
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    /// Captures of `host` not yet handed out.
+    pub fn remaining(&self, host: &str) -> usize {
+        let remaining = self.remaining.lock().unwrap_or_else(|err| err.into_inner());
+        remaining.get(host).map_or(0, VecDeque::len)
+    }
+
+    /// The output of the next run recorded against `host`, or an
+    /// [`io::ErrorKind::UnexpectedEof`] error once there are none left.
+    pub fn next(&self, host: &str) -> Result<String, io::Error> {
+        let number = {
+            let mut remaining = self.remaining.lock().unwrap_or_else(|err| err.into_inner());
+            remaining.get_mut(host).and_then(VecDeque::pop_front)
+        };
+        match number {
+            Some(number) => fs::read_to_string(capture_path(&self.dir, number)),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("No captures of {host} left in {}", self.dir.display()),
+            )),
+        }
+    }
+}
+
+/// Samples of raw output saved before and after an anomaly by default.
+pub const FORENSICS_WINDOW: (usize, usize) = (5, 5);
+
+/// Latency in ms above which a host's sample is a spike at a local time of
+/// day, if any.
+type Spike = dyn Fn(&str, NaiveTime) -> Option<f64> + Send + Sync;
+
+/// Saves the raw output of the runs around each failed sample, or one
+/// slower than its host's threshold, to a file named after the host and the
+/// time of the anomaly. Clones share the buffers.
+#[derive(Clone)]
+pub struct Forensics {
+    dir: PathBuf,
+    before: usize,
+    after: usize,
+    spike: Arc<Spike>,
+    windows: Arc<Mutex<HashMap<String, ForensicsWindow>>>,
+}
+
+impl fmt::Debug for Forensics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Forensics")
+            .field("dir", &self.dir)
+            .field("before", &self.before)
+            .field("after", &self.after)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct ForensicsWindow {
+    /// Raw output of the run in progress, taken when its result is observed.
+    latest: Option<Vec<u8>>,
+    /// Entries of the last `before` samples.
+    recent: VecDeque<Vec<u8>>,
+    /// A dump still waiting for the samples after its anomaly.
+    pending: Option<PendingDump>,
+}
+
+impl Drop for ForensicsWindow {
+    /// Saves a dump the samples stopped short of, once the last clone of its
+    /// [`Forensics`] is gone.
+    fn drop(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            pending.write();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PendingDump {
+    path: PathBuf,
+    entries: Vec<Vec<u8>>,
+    remaining: usize,
+}
+
+impl PendingDump {
+    fn write(self) {
+        match fs::write(&self.path, self.entries.concat()) {
+            Ok(()) => eprintln!(
+                "Saved raw output around an anomaly to {}",
+                self.path.display()
+            ),
+            Err(err) => eprintln!("Error saving {}: {err}", self.path.display()),
+        }
+    }
+}
+
+impl Forensics {
+    /// Saves `before` and `after` samples around each anomaly into `dir`,
+    /// creating it if needed. Hosts `spike` has no latency for at a time of
+    /// day only count failures as anomalies then.
+    pub fn create(
+        dir: impl Into<PathBuf>,
+        (before, after): (usize, usize),
+        spike: impl Fn(&str, NaiveTime) -> Option<f64> + Send + Sync + 'static,
+    ) -> Result<Forensics, io::Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Forensics {
+            dir,
+            before,
+            after,
+            spike: Arc::new(spike),
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    fn windows(&self) -> std::sync::MutexGuard<'_, HashMap<String, ForensicsWindow>> {
+        // A sampler that panicked mid-update shouldn't stop the others
+        self.windows.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Holds on to `entry`, the raw output of the run now sampling `host`,
+    /// until its result is observed.
+    pub fn capture(&self, host: &str, entry: Vec<u8>) {
+        self.windows().entry(host.to_string()).or_default().latest = Some(entry);
+    }
+
+    /// Takes in the result of sampling `host`, with the output captured for
+    /// it or a description of the result when there was none. An anomaly
+    /// starts a dump of the samples before it, written once the samples after
+    /// it are in. Anomalies within a dump's window are part of it.
+    pub fn observe(&self, host: &str, result: &Result<PingResult, io::Error>) {
+        let mut windows = self.windows();
+        let window = windows.entry(host.to_string()).or_default();
+        let mut entry = window.latest.take().unwrap_or_else(|| {
+            format!("=== {} {host}\n", Utc::now().format(TIMESTAMP_FORMAT)).into_bytes()
+        });
+        let anomalous = match result {
+            Ok(sample) => {
+                entry.extend(format!("[result] {:.2} ms\n", sample.average).as_bytes());
+                (self.spike)(host, Local::now().time()).is_some_and(|spike| sample.average > spike)
+            }
+            Err(err) => {
+                entry.extend(format!("[error] {err}\n").as_bytes());
+                true
+            }
+        };
+
+        if let Some(mut pending) = window.pending.take() {
+            pending.entries.push(entry.clone());
+            pending.remaining -= 1;
+            if pending.remaining == 0 {
+                pending.write();
+            } else {
+                window.pending = Some(pending);
+            }
+        } else if anomalous {
+            let safe_host: String = host
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            let name = format!("{safe_host}-{}.log", Utc::now().format("%Y%m%dT%H%M%S%.3f"));
+            let mut entries: Vec<Vec<u8>> = window.recent.iter().cloned().collect();
+            entries.push(entry.clone());
+            let pending = PendingDump {
+                path: self.dir.join(name),
+                entries,
+                remaining: self.after,
+            };
+            if self.after == 0 {
+                pending.write();
+            } else {
+                window.pending = Some(pending);
+            }
+        }
+
+        window.recent.push_back(entry);
+        while window.recent.len() > self.before {
+            window.recent.pop_front();
+        }
+    }
+
+    /// Saves the dumps still waiting for samples after their anomaly with
+    /// the samples they have, for when sampling stops.
+    pub fn flush(&self) {
+        for window in self.windows().values_mut() {
+            if let Some(pending) = window.pending.take() {
+                pending.write();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ping::{Mode, Pinger};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn reply(average: f64) -> String {
+        format!("round-trip min/avg/max/stddev = {average}/{average}/{average}/0.000 ms\n")
+    }
+
+    #[test]
+    fn replays_recorded_captures_in_order() {
+        let dir = temp_dir("raw-captures");
+        let recorder = RawRecorder::create(&dir).unwrap();
+        recorder.record("a", reply(10.5).as_bytes());
+        recorder.record("b", b"Request timeout for icmp_seq 0\n");
+        // Recording again carries on the numbering
+        RawRecorder::create(&dir)
+            .unwrap()
+            .record("a", reply(11.25).as_bytes());
+        assert_eq!(
+            fs::read_to_string(dir.join(CAPTURE_INDEX)).unwrap(),
+            "1\ta\n2\tb\n3\ta\n"
+        );
+        assert_eq!(
+            fs::read_to_string(capture_path(&dir, 3)).unwrap(),
+            reply(11.25)
+        );
+
+        let captures = RawCaptures::open(&dir).unwrap();
+        assert_eq!(captures.hosts(), ["a", "b"]);
+        assert_eq!(captures.remaining("a"), 2);
+        let pinger = Pinger::new(Mode::Icmp).with_raw_captures(captures.clone());
+        assert_eq!(pinger.ping("a").unwrap().average, 10.5);
+        assert!(pinger.ping("b").is_err());
+        assert_eq!(pinger.ping("a").unwrap().average, 11.25);
+        assert_eq!(captures.remaining("a"), 0);
+        let err = pinger.ping("a").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dumps_the_window_around_an_anomaly() {
+        let dir = temp_dir("forensics");
+        let forensics =
+            Forensics::create(&dir, (2, 3), |host, _| (host == "a").then_some(100.0)).unwrap();
+        let dumps = || fs::read_dir(&dir).unwrap().count();
+        for run in 0..10 {
+            let latency = if run == 5 { 500.0 } else { 10.0 };
+            forensics.capture("a", format!("=== run {run}\n").into_bytes());
+            forensics.observe(
+                "a",
+                &Ok(PingResult::new("a".to_string(), latency, Utc::now())),
+            );
+            // Written once the samples after the spike are in
+            assert_eq!(dumps(), usize::from(run >= 8), "after run {run}");
+        }
+
+        let dump = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        assert!(dump
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("a-"));
+        let dump = fs::read_to_string(&dump).unwrap();
+        let runs: Vec<&str> = dump
+            .lines()
+            .filter(|line| line.starts_with("==="))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                "=== run 3",
+                "=== run 4",
+                "=== run 5",
+                "=== run 6",
+                "=== run 7",
+                "=== run 8"
+            ]
+        );
+        assert!(dump.contains("[result] 500.00 ms\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn judges_spikes_when_observed_and_saves_cut_short_dumps() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let dir = temp_dir("forensics-flush");
+        let strict = Arc::new(AtomicBool::new(false));
+        let threshold = Arc::clone(&strict);
+        let forensics = Forensics::create(&dir, (1, 5), move |_, _| {
+            Some(if threshold.load(Ordering::Relaxed) {
+                5.0
+            } else {
+                100.0
+            })
+        })
+        .unwrap();
+        let dumps = || fs::read_dir(&dir).unwrap().count();
+        let observe = |forensics: &Forensics, host: &str| {
+            let sample = PingResult::new(host.to_string(), 10.0, Utc::now());
+            forensics.observe(host, &Ok(sample));
+        };
+
+        observe(&forensics, "a");
+        strict.store(true, Ordering::Relaxed);
+        observe(&forensics, "a");
+        assert_eq!(dumps(), 0);
+        forensics.flush();
+        assert_eq!(dumps(), 1);
+
+        // The last clone going saves what the samples stopped short of
+        observe(&forensics, "b");
+        let clone = forensics.clone();
+        drop(forensics);
+        assert_eq!(dumps(), 1);
+        drop(clone);
+        assert_eq!(dumps(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
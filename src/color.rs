@@ -0,0 +1,257 @@
+//! Line colors, shared by the GUI, the terminal dashboard and chart images.
+
+use serde::{Deserialize, Serialize};
+
+/// An sRGB color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+/// Colors assigned to hosts in the order they first report.
+pub const HOST_COLORS: [Rgb; 6] = [
+    Rgb(31, 119, 180),
+    Rgb(255, 127, 14),
+    Rgb(44, 160, 44),
+    Rgb(214, 39, 40),
+    Rgb(148, 103, 189),
+    Rgb(140, 86, 75),
+];
+
+/// The Okabe-Ito colors, told apart with any common color vision
+/// deficiency: blue, orange, bluish green, vermillion, reddish purple and sky
+/// blue. Yellow is left out as it doesn't show on white.
+pub const COLORBLIND_HOST_COLORS: [Rgb; 6] = [
+    Rgb(0, 114, 178),
+    Rgb(230, 159, 0),
+    Rgb(0, 158, 115),
+    Rgb(213, 94, 0),
+    Rgb(204, 121, 167),
+    Rgb(86, 180, 233),
+];
+
+/// The set of colors lines and status indicators are drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Default,
+    /// Okabe-Ito colors, with blue and vermillion instead of green and red
+    /// for good and bad.
+    Colorblind,
+}
+
+impl Palette {
+    pub const ALL: [Palette; 2] = [Palette::Default, Palette::Colorblind];
+
+    pub fn parse(value: &str) -> Result<Palette, String> {
+        match value {
+            "default" => Ok(Palette::Default),
+            "colorblind" => Ok(Palette::Colorblind),
+            _ => Err(format!(
+                "Unknown palette {value}, expected default or colorblind"
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Default => "default",
+            Palette::Colorblind => "colorblind",
+        }
+    }
+
+    /// Colors assigned to hosts in the order they first report.
+    pub fn host_colors(self) -> &'static [Rgb] {
+        match self {
+            Palette::Default => &HOST_COLORS,
+            Palette::Colorblind => &COLORBLIND_HOST_COLORS,
+        }
+    }
+
+    /// Something being wrong, like an overdue host or an outage.
+    pub fn bad(self) -> Rgb {
+        match self {
+            Palette::Default => Rgb(255, 0, 0),
+            Palette::Colorblind => Rgb(213, 94, 0),
+        }
+    }
+
+    /// A latency budget being missed, darker than [`Palette::bad`] to carry
+    /// text.
+    pub fn missed(self) -> Rgb {
+        match self {
+            Palette::Default => Rgb(139, 0, 0),
+            Palette::Colorblind => Rgb(170, 75, 0),
+        }
+    }
+
+    /// A latency budget being met.
+    pub fn met(self) -> Rgb {
+        match self {
+            Palette::Default => Rgb(0, 100, 0),
+            Palette::Colorblind => Rgb(0, 114, 178),
+        }
+    }
+
+    /// Something worth a look, like drifting sampling.
+    pub fn warning(self) -> Rgb {
+        match self {
+            Palette::Default => Rgb(255, 255, 0),
+            Palette::Colorblind => Rgb(240, 228, 66),
+        }
+    }
+
+    /// The color of a sample `z` standard deviations from normal, either
+    /// way: [`Palette::met`] until `thresholds.warn`, [`Palette::warning`]
+    /// until `thresholds.bad` and [`Palette::bad`] beyond. Samples without a
+    /// score yet count as normal.
+    pub fn deviation(self, z: Option<f64>, thresholds: DeviationThresholds) -> Rgb {
+        match z.map(f64::abs) {
+            Some(z) if z >= thresholds.bad => self.bad(),
+            Some(z) if z >= thresholds.warn => self.warning(),
+            _ => self.met(),
+        }
+    }
+}
+
+/// Z-scores from which a sample counts as unusual and as anomalous when
+/// coloring by deviation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeviationThresholds {
+    pub warn: f64,
+    pub bad: f64,
+}
+
+impl Default for DeviationThresholds {
+    fn default() -> Self {
+        DeviationThresholds {
+            warn: 2.0,
+            bad: 3.0,
+        }
+    }
+}
+
+/// Names accepted by [`Rgb::parse`].
+const NAMED_COLORS: [(&str, Rgb); 12] = [
+    ("black", Rgb(0, 0, 0)),
+    ("white", Rgb(255, 255, 255)),
+    ("gray", Rgb(128, 128, 128)),
+    ("red", Rgb(255, 0, 0)),
+    ("green", Rgb(0, 160, 0)),
+    ("blue", Rgb(0, 0, 255)),
+    ("yellow", Rgb(255, 215, 0)),
+    ("orange", Rgb(255, 127, 14)),
+    ("purple", Rgb(148, 103, 189)),
+    ("brown", Rgb(140, 86, 75)),
+    ("cyan", Rgb(0, 200, 200)),
+    ("magenta", Rgb(220, 0, 220)),
+];
+
+impl Rgb {
+    /// Parses a color name such as `red`, or hex as `#rrggbb` or `#rgb`.
+    pub fn parse(value: &str) -> Result<Rgb, String> {
+        let invalid = || format!("Invalid color {value}, expected a name or #rrggbb");
+
+        let Some(hex) = value.strip_prefix('#') else {
+            return NAMED_COLORS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(value))
+                .map(|&(_, color)| color)
+                .ok_or_else(invalid);
+        };
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| invalid());
+        match hex.len() {
+            6 => Ok(Rgb(
+                channel(&hex[0..2])?,
+                channel(&hex[2..4])?,
+                channel(&hex[4..6])?,
+            )),
+            // Each digit is doubled, so #f80 is #ff8800
+            3 => Ok(Rgb(
+                channel(&hex[0..1].repeat(2))?,
+                channel(&hex[1..2].repeat(2))?,
+                channel(&hex[2..3].repeat(2))?,
+            )),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(Rgb::parse("#1f77b4"), Ok(Rgb(31, 119, 180)));
+        assert_eq!(Rgb::parse("#FF8800"), Ok(Rgb(255, 136, 0)));
+        assert_eq!(Rgb::parse("#f80"), Ok(Rgb(255, 136, 0)));
+        assert_eq!(Rgb::parse("Orange"), Ok(Rgb(255, 127, 14)));
+        for invalid in ["1f77b4", "#1f77b", "#gg0000", "#+1+2+3", "teal"] {
+            assert!(Rgb::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn palettes_have_distinct_colors() {
+        for palette in Palette::ALL {
+            assert_eq!(Palette::parse(palette.name()), Ok(palette));
+            let colors = palette.host_colors();
+            for (i, color) in colors.iter().enumerate() {
+                assert!(
+                    !colors[i + 1..].contains(color),
+                    "{palette:?} repeats {color:?}"
+                );
+            }
+            let status = [
+                palette.bad(),
+                palette.missed(),
+                palette.met(),
+                palette.warning(),
+            ];
+            for (i, color) in status.iter().enumerate() {
+                assert!(
+                    !status[i + 1..].contains(color),
+                    "{palette:?} repeats {color:?}"
+                );
+            }
+        }
+        assert!(Palette::parse("rainbow").is_err());
+
+        // Okabe-Ito blue and vermillion for good and bad
+        let colorblind = Palette::Colorblind;
+        assert_eq!(
+            (colorblind.met(), colorblind.bad()),
+            (Rgb(0, 114, 178), Rgb(213, 94, 0))
+        );
+        assert_ne!(colorblind.host_colors(), Palette::Default.host_colors());
+    }
+
+    #[test]
+    fn colors_z_scores_by_deviation() {
+        let thresholds = DeviationThresholds::default();
+        for palette in [Palette::Default, Palette::Colorblind] {
+            let color = |z| palette.deviation(z, thresholds);
+            assert_eq!(color(None), palette.met());
+            assert_eq!(color(Some(0.0)), palette.met());
+            assert_eq!(color(Some(1.99)), palette.met());
+            assert_eq!(color(Some(2.0)), palette.warning());
+            assert_eq!(color(Some(-2.5)), palette.warning());
+            assert_eq!(color(Some(3.0)), palette.bad());
+            assert_eq!(color(Some(-7.0)), palette.bad());
+        }
+        let strict = DeviationThresholds {
+            warn: 1.0,
+            bad: 1.5,
+        };
+        assert_eq!(
+            Palette::Default.deviation(Some(1.2), strict),
+            Palette::Default.warning()
+        );
+        assert_eq!(
+            Palette::Default.deviation(Some(1.5), strict),
+            Palette::Default.bad()
+        );
+    }
+}
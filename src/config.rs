@@ -0,0 +1,1716 @@
+//! Command line parsing.
+
+use crate::ascii::locale_is_utf8;
+use crate::capture::{Forensics, RawCaptures, RawRecorder, FORENSICS_WINDOW};
+use crate::color::{DeviationThresholds, Palette, Rgb};
+#[cfg(feature = "dns")]
+use crate::dns::{DnsQuery, DnsRecord};
+use crate::export::{CsvOptions, ExportFormat, Invocation, RunMetadata};
+use crate::http::parse_url;
+use crate::ping::{IpFamily, Metric, Mode, Pinger, RawLog, DEFAULT_SAMPLE_TIMEOUT};
+use crate::proxy::Proxy;
+use crate::sampler::Backpressure;
+use crate::schedule::{parse_schedule, Schedule};
+use crate::stats::{Aggregate, LatencyBudget, Thresholds};
+use crate::summary::SummaryTemplate;
+use crate::timezone::parse_zone;
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Runtime options collected from the command line.
+pub struct Config {
+    pub hosts: Vec<HostConfig>,
+    pub interval: Duration,
+    pub mode: Mode,
+    /// Name looked up in DNS mode, and whether AAAA records are asked for
+    /// instead of A.
+    pub query: Option<String>,
+    pub query_aaaa: bool,
+    pub metric: Metric,
+    /// Used instead of the `ping` found on `PATH`.
+    pub ping_binary: Option<PathBuf>,
+    /// Where the raw output of every probe is appended.
+    pub raw_log: Option<RawLog>,
+    /// Save each `ping` run's output to a numbered file.
+    pub record_raw: Option<RawRecorder>,
+    /// Save the raw output around failed and slow samples.
+    pub forensics: Option<Forensics>,
+    /// Parse recorded outputs instead of running `ping`.
+    pub replay_raw: Option<RawCaptures>,
+    /// SOCKS5 or HTTP proxy that HTTP probes go through.
+    pub proxy: Option<Proxy>,
+    /// ToS byte set on echo requests, for QoS testing.
+    pub tos: Option<u8>,
+    /// Extra arguments appended to every `ping` command line, unchecked.
+    pub ping_args: Vec<String>,
+    /// Seed of the samples made up in simulate mode, from entropy when `None`.
+    pub seed: Option<u64>,
+    /// How long a single `ping` may run before it's killed.
+    pub sample_timeout: Duration,
+    /// Keep one `ping` running per host instead of one per sample.
+    pub stream_ping: bool,
+    /// Ping every host over both IPv4 and IPv6 at once.
+    pub dual_stack: bool,
+    /// Look host names up again this often, noting when their addresses change.
+    pub reresolve: Option<Duration>,
+    /// Annotate default route changes and look hosts up again after them.
+    pub watch_network: bool,
+    /// Don't count the gaps the machine slept through as outages.
+    pub ignore_suspended: bool,
+    /// Samples that may wait between the samplers and the display.
+    pub channel_capacity: usize,
+    pub backpressure: Backpressure,
+    pub iterations: Option<u32>,
+    pub export_path: Option<String>,
+    /// Name and notes written to the metadata next to exports.
+    pub run_name: Option<String>,
+    /// Add the invocation to the metadata, from `args`.
+    pub record_invocation: bool,
+    pub args: Vec<String>,
+    pub run_notes: Vec<String>,
+    pub csv_options: CsvOptions,
+    /// A previous export plotted behind the live data for comparison.
+    pub baseline_path: Option<String>,
+    /// Exports of earlier days overlaid by time of day.
+    pub history_paths: Vec<String>,
+    /// An export played back in the GUI instead of sampling live.
+    pub replay_path: Option<String>,
+    /// Exports combined into the `--export` file instead of sampling.
+    pub merge_paths: Vec<String>,
+    /// Write every sample to stdout as a JSON line as soon as it arrives.
+    pub stream_stdout: bool,
+    /// Unix socket to answer stats snapshots on.
+    pub stats_socket: Option<PathBuf>,
+    /// Address to answer `POST /probe` bursts and `GET /healthz` on.
+    pub probe_listen: Option<String>,
+    /// Recent loss in percent from which `/healthz` reports a host as down.
+    pub healthz_loss: f64,
+    /// Prometheus text file rewritten with the current gauges.
+    pub metrics_file: Option<PathBuf>,
+    /// Called when a host stays silent for `outage_alert_after`, and again
+    /// when it answers.
+    pub webhook_url: Option<String>,
+    /// Shell command run when a host goes over its latency threshold.
+    pub on_breach: Option<String>,
+    pub outage_alert_after: Duration,
+    /// `None` picks headless mode automatically when there's no display.
+    pub headless: Option<bool>,
+    /// Show a dashboard in the terminal instead of a window.
+    pub tui: bool,
+    /// Keep samples for this long instead of capping them by count.
+    pub retain: Option<Duration>,
+    /// Approximate bytes the held samples may take up, across all hosts.
+    pub max_memory: Option<usize>,
+    /// Average older samples into coarser buckets, see
+    /// [`DOWNSAMPLE_TIERS`](crate::stats::DOWNSAMPLE_TIERS).
+    pub downsample: bool,
+    /// Sample a fixed number of times headless, export, and exit with a code
+    /// reflecting [`Health`](crate::stats::Health).
+    pub once: bool,
+    /// Sample headless into running statistics without keeping the samples,
+    /// and print a summary table at the end.
+    pub stats_only: bool,
+    /// Print one line per host at the end of a headless run instead of the
+    /// summary table.
+    pub summary_template: Option<SummaryTemplate>,
+    /// Print a compact line of latency, a sparkline and loss for status bars.
+    pub status_line: bool,
+    /// Exit straight away if the first sample of any host fails.
+    pub fail_fast: bool,
+    /// Check the environment and exit instead of monitoring.
+    pub doctor: bool,
+    pub thresholds: Thresholds,
+    /// Time-of-day overrides of the interval and latency threshold.
+    pub schedule: Schedule,
+    /// Median absolute deviations beyond which the stats panel ignores a value.
+    pub reject_outliers: Option<f64>,
+    /// How long a host may go without answering before its last-seen counter
+    /// turns red. `None` allows three of its intervals.
+    pub grace: Option<Duration>,
+    /// Relative latency shift reported as a regime change, e.g. 0.5 for 50%.
+    pub regime_sensitivity: f64,
+    /// Sound an alert when a sample exceeds the latency threshold.
+    pub beep: bool,
+    /// Samples per host left out of statistics at the start of a run.
+    pub warmup: usize,
+    /// Still draw the warm-up samples, dimmed.
+    pub plot_warmup: bool,
+    /// Draw lines this far behind the samples, interpolating their heads, so
+    /// they advance smoothly instead of jumping when samples come in bursts.
+    /// The plot then lags by this much.
+    pub plot_delay: Option<chrono::Duration>,
+    /// Most recent samples of each host drawn, while stats and exports still
+    /// use every retained one.
+    pub plot_points: Option<usize>,
+    /// Time between vertical and latency in ms between horizontal gridlines,
+    /// instead of the plot picking them. The GUI remembers changes to them.
+    pub x_grid: Option<Duration>,
+    pub y_grid: Option<f64>,
+    /// Plot each host as min-max whiskers around the mean of buckets this
+    /// long instead of a line per sample. The GUI remembers changes to it.
+    pub error_bars: Option<Duration>,
+    /// Line colors for the hosts in order, before falling back to the
+    /// palette's.
+    pub colors: Vec<Rgb>,
+    /// Colors for the remaining hosts and status indicators. The GUI
+    /// remembers the last one picked when not given.
+    pub palette: Option<Palette>,
+    /// Overlay each host with this aggregate of its last `moving_window`
+    /// samples. The GUI remembers the last choice when not given.
+    pub moving_aggregate: Option<Aggregate>,
+    pub moving_window: Option<usize>,
+    /// Z-scores at which samples turn yellow and red when the GUI colors them
+    /// by deviation.
+    pub deviation_thresholds: DeviationThresholds,
+    /// Only use ASCII in labels, the TUI and printed output.
+    pub ascii: bool,
+    /// Zone timestamps are displayed and exported in instead of each place's
+    /// default of UTC or local time.
+    pub timezone: Option<Tz>,
+    /// Run headless indefinitely, appending samples to daily files here.
+    pub daemon_dir: Option<String>,
+    pub daemon_format: ExportFormat,
+    /// Number of daily files kept by `--daemon` before the oldest is deleted.
+    pub daemon_keep: usize,
+    /// Size a daemon log file may grow to before the day continues in a
+    /// new numbered part.
+    pub max_file_size: Option<usize>,
+    /// Holds the process ID while running, for service managers.
+    pub pid_file: Option<PathBuf>,
+    /// Shown as a gauge of how many samples meet it.
+    pub budget: Option<LatencyBudget>,
+    /// Write per-bucket aggregates here alongside the raw export.
+    pub rollup_path: Option<String>,
+    /// Chart drawn on exit, as an SVG or a PNG depending on its suffix.
+    pub chart_path: Option<String>,
+    /// Draw chart lines as splines through the samples.
+    pub chart_smooth: bool,
+    pub rollup_bucket: Duration,
+    /// Slot length of the threshold crossing counts in the GUI.
+    pub crossing_bucket: Duration,
+}
+
+const USAGE: &str = "\
+Usage: network-test <host> [iterations] [options]
+
+Options:
+  --host spec                 Monitor another host, as host[=interval] [threshold=ms],
+                              or url [interval=duration] for URLs with a query
+  --hosts-file path           Read hosts from a file, one --host spec per line
+  --stdin                     Read hosts from standard input like --hosts-file
+  --interval duration         Default time between samples (default 1s)
+  --mode name                 How latency is measured: icmp, http, iperf, dns or
+                              simulate for made-up samples (default icmp)
+  --seed n                    Make up the same samples on every run in simulate mode
+  --query name                Name looked up in dns mode, where hosts are the resolvers
+  --query-type a|aaaa         Record type looked up in dns mode (default a)
+  --server host               iperf3 server to measure bandwidth to in iperf mode
+  --metric min|avg|max        Round trip plotted and alerted on (default avg)
+  --ping-binary path          Run this ping executable instead of the one on PATH
+  --raw-log path              Append the raw output of every ping run to a file
+  --record-raw dir            Save the output of every ping run to a numbered file in dir
+  --forensics dir             Save the raw output around each failed or slow sample to dir
+  --forensics-window b,a      Samples saved before and after each of those (default 5,5)
+  --replay-raw dir            Parse the outputs saved by --record-raw instead of pinging,
+                              all hosts recorded unless hosts are given
+  --proxy url                 Send HTTP probes through socks5://host:port or http://host:port
+  --tos value                 ToS byte of echo requests, e.g. 184 or 0xb8 for DSCP EF;
+                              ping -Q on Linux, -z on macOS, not on Windows
+  --ping-args args            Append these arguments to every ping command, split like a
+                              shell would; unchecked, so they may break parsing
+  --sample-timeout duration   Kill a ping that hasn't finished after this long (10s)
+  --schedule path             Read time-of-day interval/threshold overrides and
+                              maintenance windows from a file
+  --stream-ping               Read replies from one long-running ping per host
+  --rapid duration            Stream pings this often, at least 10ms; below 200ms ping
+                              needs root or CAP_NET_RAW and --i-understand is required
+  --i-understand              Confirm a --rapid interval below 200ms
+  --dual-stack                Ping each host over IPv4 and IPv6 side by side
+  --reresolve duration        Look hosts up again this often, noting address changes
+  --watch-network             Note default route changes, like a VPN coming up, and look
+                              hosts up again after them (Linux only)
+  --ignore-suspended          Leave gaps from the machine sleeping out of the outages
+  --channel-capacity n        Samples buffered for the display (default 10000)
+  --backpressure block|drop   When the buffer is full, wait or drop the oldest sample
+                              (default block)
+  --iterations n              Stop each sampler after n samples
+  --export file.csv|file.ntb  Write samples to a CSV or compact binary file on exit,
+                              gzip-compressed when the name ends in .gz
+  --run-name name             Name the run in the metadata written next to exports
+  --record-invocation         Add the command line, settings and environment to the
+                              export metadata, leaving out secrets
+  --note text                 Add a note to the export metadata, repeatable
+  --rollup-export file.csv    Write per-bucket count/avg/min/max/p95/loss on exit
+  --rollup-bucket duration    Bucket length for --rollup-export (default 1m)
+  --crossing-bucket duration  Slot length of the threshold crossing counts (default 1m)
+  --chart file.svg|.png       Draw a chart of every host on exit, as SVG or PNG by suffix
+  --chart-smooth              Draw chart lines as smooth curves through the samples
+  --csv-delimiter c           Field delimiter for CSV exports (default ,)
+  --csv-crlf                  End CSV lines with CRLF
+  --rolling-window n          Add rolling min/avg/max columns over n samples
+  --baseline file.csv|.ntb    Plot a previous export behind the live data
+  --history file.csv|.ntb     Overlay an earlier day's export by time of day, repeatable
+  --replay file.csv|.ntb      Play back an export with seek and speed controls, no pinging
+  --merge file...             Combine exports into one timeline written to --export, then
+                              exit; samples in more than one file are kept once
+  --stream-stdout             Print every sample to stdout as a JSON line
+  --stats-socket path         Answer with a JSON stats snapshot on a Unix socket
+  --probe-listen addr         Serve POST /probe on addr, like 127.0.0.1:9000, taking an
+                              extra burst of samples of a host and answering with its stats,
+                              and GET /healthz, 503 while a host looks down
+  --healthz-loss percent      Recent loss from which /healthz has a host down (default 50)
+  --metrics-file path         Rewrite a Prometheus text file of latency, loss and jitter
+                              every 15s, for node_exporter's textfile collector
+  --webhook-url url           POST a JSON alert when a host stays silent, and when it's back
+  --outage-alert-after dur    Silence that counts as an outage for --webhook-url (default 60s)
+  --on-breach command         Run command through the shell when a host goes over its
+                              latency threshold, at most once a minute per host, with
+                              NETWORK_TEST_HOST, _LATENCY, _THRESHOLD and _AT set
+  --headless | --gui          Force running without or with the GUI
+  --tui                       Show a terminal dashboard instead of the GUI
+  --retain duration           Keep samples by age instead of by count
+  --max-memory size           Also drop the oldest samples past this size, e.g. 50MB
+  --downsample                Keep 10 minutes at full resolution, then 10s averages for
+                              2 hours and minute averages beyond, so more history fits
+  --once                      Sample, export and exit with a health code
+  --stats-only                Print a summary at the end without keeping samples
+  --summary-template str      Print the summary as str per host, with {host}, {avg}, {min},
+                              {max}, {loss}, {p95}, the times of the min and max,
+                              {min_at} and {max_at}, and the strict and loss-weighted
+                              availability per minute, {availability} and {weighted}
+  --status-line               Print a line like host 23ms, a sparkline and loss for status
+                              bars, after every sample or once with --once
+  --doctor                    Check that ping, permissions and the display work, then exit
+  --fail-fast                 Exit with a down code if a host can't be reached at all
+  --threshold ms              Latency above which a host is degraded
+  --loss-threshold percent    Loss above which a host is degraded (default 0)
+  --latency-loss-cutoff ms    Count slower replies as lost in stats, loss and availability,
+                              while still plotting them
+  --reject-outliers n         Leave values over n median absolute deviations from the
+                              median out of the stats panel, while still plotting them
+  --grace duration            Silence after which a host is shown as unreachable
+  --regime-sensitivity f      Latency shift reported as a regime change (default 0.5)
+  --beep                      Beep when a sample exceeds the threshold
+  --warmup n                  Leave the first n samples per host out of stats
+  --plot-warmup               Still plot the warm-up samples, dimmed
+  --smooth-plot delay         Draw lines this far behind the samples so they advance
+                              smoothly rather than in bursts, at the cost of lag
+  --plot-points n             Draw only the latest n samples of each host, keeping the rest
+                              for stats and exports
+  --x-grid duration           Time between vertical gridlines, e.g. 30s
+  --y-grid ms                 Latency between horizontal gridlines
+  --error-bars duration       Plot min-max whiskers around the mean of buckets this long
+  --color name|#rrggbb        Line color of the next host, repeat for more hosts
+  --palette name              Colors for hosts and indicators: default or colorblind
+  --moving aggregate          Overlay each host with a moving mean, median, max or min
+  --moving-window n           Samples the --moving overlay aggregates (default 10)
+  --deviation-thresholds w,b  Standard deviations from recent samples at which samples
+                              colored by deviation turn yellow and red (default 2,3)
+  --ascii                     Draw only ASCII, the default when the locale isn't UTF-8
+  --timezone tz               Show and export timestamps in tz, like America/New_York
+  --daemon dir                Run headless, writing one log file per day to dir
+  --daemon-format csv|ndjson  Format of the daemon log files (default ndjson)
+  --daemon-keep n             Daily log files kept before pruning (default 7)
+  --max-file-size size        Continue a day's log in a numbered file past this size,
+                              e.g. 10MB
+  --pid-file path             Write the process ID here while running, refusing to start
+                              if the process in it is still running
+  --budget ms                 Latency budget shown as a gauge in the stats panel
+  --budget-target percent     Share of samples that must meet the budget (default 95)";
+
+/// The shortest `--rapid` interval accepted at all.
+pub const RAPID_MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// `--rapid` intervals below this need `--i-understand`. It is also where
+/// `ping -i` stops working for unprivileged users, as going below needs root
+/// or `CAP_NET_RAW`.
+pub const RAPID_CONFIRM_BELOW: Duration = Duration::from_millis(200);
+
+/// Number of samples per host taken by `--once` when `--iterations` is not set.
+pub const ONCE_ITERATIONS: u32 = 10;
+
+/// Flags whose values are left out of recorded invocations, as their URLs
+/// can carry credentials or tokens and free-form commands anything at all.
+const REDACTED_FLAGS: &[&str] = &["--proxy", "--webhook-url", "--on-breach", "--ping-args"];
+
+/// Environment variables recorded in invocations: the ones that change what
+/// network-test does.
+const INVOCATION_ENV: &[&str] = &[
+    "PATH",
+    "LC_ALL",
+    "LC_CTYPE",
+    "LANG",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+];
+
+/// A monitored host and any options overriding the global ones.
+pub struct HostConfig {
+    pub host: String,
+    pub interval: Option<Duration>,
+    /// Latency in ms above which this host is degraded, instead of the
+    /// global threshold.
+    pub threshold: Option<f64>,
+}
+
+impl Config {
+    /// Parses the arguments following the program name.
+    ///
+    /// The original `<host> <iterations>` positional form is still accepted.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Config, String> {
+        let mut config = Config {
+            hosts: vec![],
+            interval: Duration::from_secs(1),
+            mode: Mode::Icmp,
+            query: None,
+            query_aaaa: false,
+            metric: Metric::Avg,
+            ping_binary: None,
+            raw_log: None,
+            record_raw: None,
+            forensics: None,
+            replay_raw: None,
+            proxy: None,
+            tos: None,
+            ping_args: vec![],
+            seed: None,
+            sample_timeout: DEFAULT_SAMPLE_TIMEOUT,
+            stream_ping: false,
+            dual_stack: false,
+            reresolve: None,
+            watch_network: false,
+            ignore_suspended: false,
+            channel_capacity: 10_000,
+            backpressure: Backpressure::Block,
+            iterations: None,
+            export_path: None,
+            run_name: None,
+            record_invocation: false,
+            args: vec![],
+            run_notes: vec![],
+            csv_options: CsvOptions::default(),
+            baseline_path: None,
+            history_paths: vec![],
+            merge_paths: vec![],
+            replay_path: None,
+            stream_stdout: false,
+            stats_socket: None,
+            probe_listen: None,
+            healthz_loss: 50.0,
+            metrics_file: None,
+            webhook_url: None,
+            on_breach: None,
+            outage_alert_after: Duration::from_secs(60),
+            headless: None,
+            tui: false,
+            retain: None,
+            max_memory: None,
+            downsample: false,
+            once: false,
+            stats_only: false,
+            summary_template: None,
+            status_line: false,
+            fail_fast: false,
+            doctor: false,
+            thresholds: Thresholds {
+                latency: None,
+                loss: 0.0,
+                loss_cutoff: None,
+            },
+            schedule: Schedule::default(),
+            reject_outliers: None,
+            grace: None,
+            regime_sensitivity: 0.5,
+            beep: false,
+            warmup: 0,
+            plot_warmup: false,
+            plot_delay: None,
+            plot_points: None,
+            x_grid: None,
+            error_bars: None,
+            y_grid: None,
+            colors: vec![],
+            palette: None,
+            moving_aggregate: None,
+            moving_window: None,
+            deviation_thresholds: DeviationThresholds::default(),
+            ascii: false,
+            timezone: None,
+            daemon_dir: None,
+            pid_file: None,
+            daemon_format: ExportFormat::Ndjson,
+            daemon_keep: 7,
+            max_file_size: None,
+            budget: None,
+            rollup_path: None,
+            chart_path: None,
+            chart_smooth: false,
+            rollup_bucket: Duration::from_secs(60),
+            crossing_bucket: Duration::from_secs(60),
+        };
+        let mut positional = vec![];
+        let mut budget_latency = None;
+        let mut budget_target = 95.0;
+        let mut servers = false;
+        let mut rapid = None;
+        let mut forensics_dir = None;
+        let mut forensics_window = FORENSICS_WINDOW;
+        let mut understood = false;
+
+        let args: Vec<String> = args.into_iter().collect();
+        config.args = args.clone();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--host" => config
+                    .hosts
+                    .push(parse_host_spec(&next_value(&mut args, &arg)?)?),
+                "--server" => {
+                    config.hosts.push(HostConfig {
+                        host: next_value(&mut args, &arg)?,
+                        interval: None,
+                        threshold: None,
+                    });
+                    servers = true;
+                }
+                "--hosts-file" => {
+                    let path = next_value(&mut args, &arg)?;
+                    let contents = fs::read_to_string(&path)
+                        .map_err(|err| format!("Error reading hosts file {path}: {err}"))?;
+                    config.hosts.extend(parse_hosts_file(&contents)?);
+                }
+                "--stdin" => config.hosts.extend(read_hosts(io::stdin().lock())?),
+                "--interval" => config.interval = parse_interval(&next_value(&mut args, &arg)?)?,
+                "--mode" => config.mode = Mode::parse(&next_value(&mut args, &arg)?)?,
+                "--seed" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.seed = Some(value.parse().map_err(|_| format!("Invalid seed {value}"))?);
+                }
+                "--query" => config.query = Some(next_value(&mut args, &arg)?),
+                "--query-type" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.query_aaaa = match value.to_ascii_lowercase().as_str() {
+                        "a" => false,
+                        "aaaa" => true,
+                        _ => return Err(format!("Unknown query type {value}, expected a or aaaa")),
+                    };
+                }
+                "--metric" => config.metric = Metric::parse(&next_value(&mut args, &arg)?)?,
+                "--ping-binary" => {
+                    let path = PathBuf::from(next_value(&mut args, &arg)?);
+                    if !path.is_file() {
+                        return Err(format!("Ping binary {} not found", path.display()));
+                    }
+                    config.ping_binary = Some(path);
+                }
+                "--raw-log" => {
+                    let path = next_value(&mut args, &arg)?;
+                    config.raw_log = Some(
+                        RawLog::open(&path)
+                            .map_err(|err| format!("Error opening raw log {path}: {err}"))?,
+                    );
+                }
+                "--record-raw" => {
+                    let dir = next_value(&mut args, &arg)?;
+                    config.record_raw =
+                        Some(RawRecorder::create(&dir).map_err(|err| {
+                            format!("Error opening capture directory {dir}: {err}")
+                        })?);
+                }
+                "--forensics" => forensics_dir = Some(next_value(&mut args, &arg)?),
+                "--forensics-window" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let invalid = || {
+                        format!("Invalid forensics window {value}, expected before,after samples")
+                    };
+                    let (before, after) = value.split_once(',').ok_or_else(invalid)?;
+                    forensics_window = (
+                        before.trim().parse().map_err(|_| invalid())?,
+                        after.trim().parse().map_err(|_| invalid())?,
+                    );
+                }
+                "--replay-raw" => {
+                    let dir = next_value(&mut args, &arg)?;
+                    config.replay_raw = Some(
+                        RawCaptures::open(&dir)
+                            .map_err(|err| format!("Error reading captures in {dir}: {err}"))?,
+                    );
+                }
+                "--proxy" => config.proxy = Some(Proxy::parse(&next_value(&mut args, &arg)?)?),
+                "--tos" => {
+                    if cfg!(windows) {
+                        return Err("--tos isn't supported by ping on Windows".to_string());
+                    }
+                    config.tos = Some(parse_tos(&next_value(&mut args, &arg)?)?);
+                }
+                "--ping-args" => config
+                    .ping_args
+                    .extend(split_args(&next_value(&mut args, &arg)?)?),
+                "--sample-timeout" => {
+                    let timeout = parse_duration(&next_value(&mut args, &arg)?)?;
+                    if timeout.is_zero() {
+                        return Err("--sample-timeout must be longer than 0s".to_string());
+                    }
+                    config.sample_timeout = timeout;
+                }
+                "--schedule" => {
+                    let path = next_value(&mut args, &arg)?;
+                    let contents = fs::read_to_string(&path)
+                        .map_err(|err| format!("Error reading schedule {path}: {err}"))?;
+                    config.schedule = parse_schedule(&contents)?;
+                    if config.schedule.has_maintenance() {
+                        config.csv_options.maintenance = Some(config.schedule.clone());
+                    }
+                }
+                "--stream-ping" => config.stream_ping = true,
+                "--rapid" => rapid = Some(parse_interval(&next_value(&mut args, &arg)?)?),
+                "--i-understand" => understood = true,
+                "--dual-stack" => config.dual_stack = true,
+                "--watch-network" => {
+                    if !cfg!(target_os = "linux") {
+                        return Err("--watch-network is only supported on Linux".to_string());
+                    }
+                    config.watch_network = true;
+                }
+                "--ignore-suspended" => config.ignore_suspended = true,
+                "--reresolve" => {
+                    config.reresolve = Some(parse_duration(&next_value(&mut args, &arg)?)?)
+                }
+                "--channel-capacity" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(capacity) if capacity > 0 => config.channel_capacity = capacity,
+                        _ => return Err(format!("Invalid channel capacity {value}")),
+                    }
+                }
+                "--backpressure" => {
+                    config.backpressure = Backpressure::parse(&next_value(&mut args, &arg)?)?
+                }
+                "--iterations" => {
+                    config.iterations = Some(parse_iterations(&next_value(&mut args, &arg)?)?)
+                }
+                "--export" => config.export_path = Some(next_value(&mut args, &arg)?),
+                "--run-name" => config.run_name = Some(next_value(&mut args, &arg)?),
+                "--record-invocation" => config.record_invocation = true,
+                "--note" => config.run_notes.push(next_value(&mut args, &arg)?),
+                "--rollup-export" => config.rollup_path = Some(next_value(&mut args, &arg)?),
+                "--chart" => config.chart_path = Some(next_value(&mut args, &arg)?),
+                "--chart-smooth" => config.chart_smooth = true,
+                "--rollup-bucket" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.rollup_bucket = parse_duration(&value)?;
+                    if config.rollup_bucket.as_millis() == 0 {
+                        return Err(format!("Invalid rollup bucket {value}"));
+                    }
+                }
+                "--crossing-bucket" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.crossing_bucket = parse_duration(&value)?;
+                    if config.crossing_bucket.as_millis() == 0 {
+                        return Err(format!("Invalid crossing bucket {value}"));
+                    }
+                }
+                "--baseline" => config.baseline_path = Some(next_value(&mut args, &arg)?),
+                "--history" => config.history_paths.push(next_value(&mut args, &arg)?),
+                "--merge" => {
+                    config.merge_paths.push(next_value(&mut args, &arg)?);
+                    while let Some(path) = args.next_if(|next| !next.starts_with("--")) {
+                        config.merge_paths.push(path);
+                    }
+                }
+                "--replay" => config.replay_path = Some(next_value(&mut args, &arg)?),
+                "--stream-stdout" => config.stream_stdout = true,
+                "--webhook-url" => {
+                    let url = next_value(&mut args, &arg)?;
+                    parse_url(&url).map_err(|err| err.to_string())?;
+                    config.webhook_url = Some(url);
+                }
+                "--outage-alert-after" => {
+                    config.outage_alert_after = parse_duration(&next_value(&mut args, &arg)?)?;
+                }
+                "--metrics-file" => {
+                    config.metrics_file = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--stats-socket" => {
+                    if !cfg!(all(unix, feature = "socket")) {
+                        return Err(
+                            "--stats-socket needs a Unix build with the socket feature".to_string()
+                        );
+                    }
+                    config.stats_socket = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--on-breach" => config.on_breach = Some(next_value(&mut args, &arg)?),
+                "--probe-listen" => config.probe_listen = Some(next_value(&mut args, &arg)?),
+                "--healthz-loss" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(loss) if (0.0..=100.0).contains(&loss) => config.healthz_loss = loss,
+                        _ => return Err(format!("Invalid healthz loss {value}")),
+                    }
+                }
+                "--headless" => config.headless = Some(true),
+                "--gui" => config.headless = Some(false),
+                "--tui" => {
+                    if !cfg!(feature = "tui") {
+                        return Err("--tui needs a build with the tui feature".to_string());
+                    }
+                    config.tui = true;
+                }
+                "--retain" => config.retain = Some(parse_duration(&next_value(&mut args, &arg)?)?),
+                "--max-memory" => {
+                    config.max_memory = Some(parse_size(&next_value(&mut args, &arg)?)?)
+                }
+                "--downsample" => config.downsample = true,
+                "--once" => {
+                    config.once = true;
+                    config.headless = Some(true);
+                }
+                "--stats-only" => {
+                    config.stats_only = true;
+                    config.headless = Some(true);
+                }
+                "--summary-template" => {
+                    config.summary_template =
+                        Some(SummaryTemplate::parse(&next_value(&mut args, &arg)?)?);
+                    config.headless = Some(true);
+                }
+                "--status-line" => {
+                    config.status_line = true;
+                    config.headless = Some(true);
+                }
+                "--fail-fast" => config.fail_fast = true,
+                "--doctor" => config.doctor = true,
+                "--threshold" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.thresholds.latency = Some(
+                        value
+                            .parse()
+                            .map_err(|err| format!("Invalid threshold {value}: {err}"))?,
+                    );
+                }
+                "--loss-threshold" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.thresholds.loss = value
+                        .parse()
+                        .map_err(|err| format!("Invalid loss threshold {value}: {err}"))?;
+                }
+                "--latency-loss-cutoff" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let cutoff: f64 = value
+                        .parse()
+                        .map_err(|err| format!("Invalid loss cutoff {value}: {err}"))?;
+                    if cutoff <= 0.0 {
+                        return Err(format!("Invalid loss cutoff {value}, must be above 0"));
+                    }
+                    config.thresholds.loss_cutoff = Some(cutoff);
+                }
+                "--reject-outliers" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let mads: f64 = value
+                        .parse()
+                        .map_err(|err| format!("Invalid outlier limit {value}: {err}"))?;
+                    if mads <= 0.0 {
+                        return Err(format!("Invalid outlier limit {value}, must be above 0"));
+                    }
+                    config.reject_outliers = Some(mads);
+                }
+                "--grace" => config.grace = Some(parse_duration(&next_value(&mut args, &arg)?)?),
+                "--regime-sensitivity" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(sensitivity) if sensitivity > 0.0 => {
+                            config.regime_sensitivity = sensitivity
+                        }
+                        _ => return Err(format!("Invalid regime sensitivity {value}")),
+                    }
+                }
+                "--beep" => config.beep = true,
+                "--warmup" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.warmup = value
+                        .parse()
+                        .map_err(|err| format!("Invalid warmup {value}: {err}"))?;
+                }
+                "--plot-warmup" => config.plot_warmup = true,
+                "--smooth-plot" => {
+                    let delay = parse_duration(&next_value(&mut args, &arg)?)?;
+                    config.plot_delay = Some(
+                        chrono::Duration::from_std(delay)
+                            .map_err(|_| format!("--smooth-plot delay {delay:?} is too long"))?,
+                    );
+                }
+                "--plot-points" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(points) if points > 0 => config.plot_points = Some(points),
+                        _ => return Err(format!("Invalid plot points {value}")),
+                    }
+                }
+                "--x-grid" => {
+                    let spacing = parse_duration(&next_value(&mut args, &arg)?)?;
+                    if spacing.is_zero() {
+                        return Err("--x-grid must be longer than 0s".to_string());
+                    }
+                    config.x_grid = Some(spacing);
+                }
+                "--error-bars" => {
+                    let bucket = parse_duration(&next_value(&mut args, &arg)?)?;
+                    if bucket.is_zero() {
+                        return Err("--error-bars must be longer than 0s".to_string());
+                    }
+                    config.error_bars = Some(bucket);
+                }
+                "--y-grid" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let spacing: f64 = value
+                        .parse()
+                        .map_err(|err| format!("Invalid gridline spacing {value}: {err}"))?;
+                    if spacing <= 0.0 {
+                        return Err(format!("Invalid gridline spacing {value}, must be above 0"));
+                    }
+                    config.y_grid = Some(spacing);
+                }
+                "--color" => config
+                    .colors
+                    .push(Rgb::parse(&next_value(&mut args, &arg)?)?),
+                "--palette" => {
+                    config.palette = Some(Palette::parse(&next_value(&mut args, &arg)?)?)
+                }
+                "--moving" => {
+                    config.moving_aggregate = Some(Aggregate::parse(&next_value(&mut args, &arg)?)?)
+                }
+                "--moving-window" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(window) if window > 0 => config.moving_window = Some(window),
+                        _ => return Err(format!("Invalid moving window {value}")),
+                    }
+                }
+                "--deviation-thresholds" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let invalid = || {
+                        format!("Invalid deviation thresholds {value}, expected warn,bad with 0 < warn < bad")
+                    };
+                    let (warn, bad) = value.split_once(',').ok_or_else(invalid)?;
+                    let (warn, bad): (f64, f64) = (
+                        warn.trim().parse().map_err(|_| invalid())?,
+                        bad.trim().parse().map_err(|_| invalid())?,
+                    );
+                    if !(0.0 < warn && warn < bad) {
+                        return Err(invalid());
+                    }
+                    config.deviation_thresholds = DeviationThresholds { warn, bad };
+                }
+                "--ascii" => config.ascii = true,
+                "--timezone" => config.timezone = Some(parse_zone(&next_value(&mut args, &arg)?)?),
+                "--csv-delimiter" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let mut chars = value.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(delimiter), None) if delimiter != '"' => {
+                            config.csv_options.delimiter = delimiter
+                        }
+                        _ => return Err(format!("Invalid CSV delimiter {value}")),
+                    }
+                }
+                "--csv-crlf" => config.csv_options.crlf = true,
+                "--rolling-window" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(window) if window > 0 => {
+                            config.csv_options.rolling_window = Some(window)
+                        }
+                        _ => return Err(format!("Invalid rolling window {value}")),
+                    }
+                }
+                "--daemon" => {
+                    config.daemon_dir = Some(next_value(&mut args, &arg)?);
+                    config.headless = Some(true);
+                }
+                "--pid-file" => config.pid_file = Some(PathBuf::from(next_value(&mut args, &arg)?)),
+                "--daemon-format" => {
+                    config.daemon_format = ExportFormat::parse(&next_value(&mut args, &arg)?)?
+                }
+                "--daemon-keep" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(keep) if keep > 0 => config.daemon_keep = keep,
+                        _ => return Err(format!("Invalid daemon keep count {value}")),
+                    }
+                }
+                "--max-file-size" => match parse_size(&next_value(&mut args, &arg)?)? {
+                    0 => return Err("--max-file-size must be over 0".to_string()),
+                    size => config.max_file_size = Some(size),
+                },
+                "--budget" => {
+                    let value = next_value(&mut args, &arg)?;
+                    budget_latency = Some(
+                        value
+                            .parse()
+                            .map_err(|err| format!("Invalid budget {value}: {err}"))?,
+                    );
+                }
+                "--budget-target" => {
+                    let value = next_value(&mut args, &arg)?;
+                    match value.parse() {
+                        Ok(target) if (0.0..=100.0).contains(&target) => budget_target = target,
+                        _ => return Err(format!("Invalid budget target {value}")),
+                    }
+                }
+                flag if flag.starts_with("--") => return Err(format!("Unknown option {flag}")),
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        if let Some(host) = positional.next() {
+            config.hosts.insert(0, parse_host_spec(&host)?);
+        }
+        if let Some(iterations) = positional.next() {
+            config.iterations = Some(parse_iterations(&iterations)?);
+        }
+        if let Some(extra) = positional.next() {
+            return Err(format!("Unexpected argument {extra}"));
+        }
+        config.budget = budget_latency.map(|latency| LatencyBudget {
+            latency,
+            target: budget_target,
+        });
+        if config.tui && (config.stream_stdout || config.headless.is_some()) {
+            return Err("--tui can't be combined with --stream-stdout, --headless, --gui, --once, --stats-only, --summary-template, --status-line or --daemon".to_string());
+        }
+        if config.summary_template.is_some() && config.iterations.is_none() && !config.once {
+            return Err("--summary-template needs --iterations to know when to print".to_string());
+        }
+        if config.status_line
+            && (config.stream_stdout
+                || config.stats_only
+                || config.summary_template.is_some()
+                || config.daemon_dir.is_some())
+        {
+            return Err("--status-line can't be combined with --stream-stdout, --stats-only, --summary-template or --daemon".to_string());
+        }
+        if config.stats_only {
+            if config.iterations.is_none() {
+                return Err("--stats-only needs --iterations to know when to print".to_string());
+            }
+            if config.once
+                || config.export_path.is_some()
+                || config.rollup_path.is_some()
+                || config.chart_path.is_some()
+                || config.daemon_dir.is_some()
+            {
+                return Err(
+                    "--stats-only keeps no samples for --export, --rollup-export, --chart, --once or --daemon"
+                        .to_string(),
+                );
+            }
+        }
+        if config.tos.is_some() && config.mode != Mode::Icmp {
+            return Err("--tos only applies to --mode icmp".to_string());
+        }
+        if !config.ping_args.is_empty() && config.mode != Mode::Icmp {
+            return Err("--ping-args only applies to --mode icmp".to_string());
+        }
+        if config.proxy.is_some() && config.mode != Mode::Http {
+            return Err("--proxy only applies to --mode http".to_string());
+        }
+        if servers != (config.mode == Mode::Iperf) {
+            return Err("--server and --mode iperf go together".to_string());
+        }
+        if config.query.is_some() != (config.mode == Mode::Dns) {
+            return Err("--query and --mode dns go together".to_string());
+        }
+        if let Some(interval) = rapid {
+            if interval < RAPID_MIN_INTERVAL {
+                return Err(format!(
+                    "--rapid can't go below {}ms",
+                    RAPID_MIN_INTERVAL.as_millis()
+                ));
+            }
+            if interval < RAPID_CONFIRM_BELOW && !understood {
+                return Err(format!(
+                    "--rapid below {}ms can flood the network, pass --i-understand to go ahead",
+                    RAPID_CONFIRM_BELOW.as_millis()
+                ));
+            }
+            // Rapid pings map to `ping -i`, which only the streaming sampler uses
+            config.interval = interval;
+            config.stream_ping = true;
+        }
+        if config.replay_path.is_some() && (config.tui || config.headless == Some(true)) {
+            return Err("--replay only works in the GUI".to_string());
+        }
+        if config.replay_path.is_some() {
+            config.headless = Some(false);
+        }
+        if config.dual_stack && config.mode != Mode::Icmp {
+            return Err("--dual-stack only works in icmp mode".to_string());
+        }
+        if config.stream_ping && config.mode != Mode::Icmp {
+            return Err("--stream-ping only works in icmp mode".to_string());
+        }
+        if config.record_raw.is_some() || config.replay_raw.is_some() {
+            if config.mode != Mode::Icmp || config.stream_ping {
+                return Err(
+                    "--record-raw and --replay-raw only work in icmp mode without --stream-ping"
+                        .to_string(),
+                );
+            }
+            if config.record_raw.is_some() && config.replay_raw.is_some() {
+                return Err("--record-raw and --replay-raw can't be combined".to_string());
+            }
+        }
+        if let Some(captures) = &config.replay_raw {
+            if config.hosts.is_empty() {
+                config.hosts = captures
+                    .hosts()
+                    .iter()
+                    .map(|host| HostConfig {
+                        host: host.clone(),
+                        interval: None,
+                        threshold: None,
+                    })
+                    .collect();
+            }
+            // Stop once every capture has been used
+            if config.iterations.is_none() {
+                let most = config
+                    .hosts
+                    .iter()
+                    .map(|host| captures.remaining(&host.host))
+                    .max()
+                    .unwrap_or(0);
+                config.iterations = Some(most as u32);
+            }
+        }
+        if let Some(dir) = forensics_dir {
+            let limits: Vec<(String, Option<f64>)> = config
+                .hosts
+                .iter()
+                .map(|host| (host.host.clone(), host.threshold))
+                .collect();
+            let (schedule, default) = (config.schedule.clone(), config.thresholds.latency);
+            let spike = move |host: &str, time| {
+                let host = IpFamily::host_of(host);
+                limits
+                    .iter()
+                    .find(|(name, _)| name == host)
+                    .and_then(|(_, limit)| *limit)
+                    .or(schedule.threshold_at(time))
+                    .or(default)
+            };
+            config.forensics = Some(
+                Forensics::create(&dir, forensics_window, spike)
+                    .map_err(|err| format!("Error opening forensics directory {dir}: {err}"))?,
+            );
+        }
+        if config.once && config.daemon_dir.is_some() {
+            return Err("--once and --daemon can't be combined".to_string());
+        }
+        if config.once && config.iterations.is_none() {
+            config.iterations = Some(ONCE_ITERATIONS);
+        }
+
+        if !config.merge_paths.is_empty() && config.export_path.is_none() {
+            return Err("--merge needs --export to write the merged samples to".to_string());
+        }
+
+        if config.hosts.is_empty()
+            && config.replay_path.is_none()
+            && config.merge_paths.is_empty()
+            && !config.doctor
+        {
+            return Err(USAGE.to_string());
+        }
+
+        Ok(config)
+    }
+
+    /// The pinger every sampler should use.
+    pub fn pinger(&self) -> Pinger {
+        let mut pinger = Pinger::new(self.mode)
+            .with_metric(self.metric)
+            .with_timeout(self.sample_timeout);
+        if let Some(path) = &self.ping_binary {
+            pinger = pinger.with_ping_binary(path);
+        }
+        if let Some(raw_log) = &self.raw_log {
+            pinger = pinger.with_raw_log(raw_log.clone());
+        }
+        if let Some(recorder) = &self.record_raw {
+            pinger = pinger.with_raw_recorder(recorder.clone());
+        }
+        if let Some(captures) = &self.replay_raw {
+            pinger = pinger.with_raw_captures(captures.clone());
+        }
+        if let Some(forensics) = &self.forensics {
+            pinger = pinger.with_forensics(forensics.clone());
+        }
+        if let Some(seed) = self.seed {
+            pinger = pinger.with_seed(seed);
+        }
+        if let Some(proxy) = &self.proxy {
+            pinger = pinger.with_proxy(proxy.clone());
+        }
+        if let Some(tos) = self.tos {
+            pinger = pinger.with_tos(tos);
+        }
+        if !self.ping_args.is_empty() {
+            pinger = pinger.with_extra_args(self.ping_args.clone());
+        }
+        #[cfg(feature = "dns")]
+        if let Some(name) = &self.query {
+            let record = if self.query_aaaa {
+                DnsRecord::Aaaa
+            } else {
+                DnsRecord::A
+            };
+            pinger = pinger.with_dns_query(DnsQuery {
+                name: name.clone(),
+                record,
+            });
+        }
+        pinger
+    }
+
+    /// Each host paired with the interval its sampler should run at.
+    pub fn schedule(&self) -> Vec<(String, Duration)> {
+        self.hosts
+            .iter()
+            .map(|h| (h.host.clone(), h.interval.unwrap_or(self.interval)))
+            .collect()
+    }
+
+    /// Each sampler to start: the host it pings, its interval and the pinger
+    /// to use. With `--dual-stack` every host gets one sampler per family.
+    pub fn samplers(&self) -> Vec<(String, Duration, Pinger)> {
+        let pinger = self.pinger();
+        let mut samplers = vec![];
+        for (host, interval) in self.schedule() {
+            if self.dual_stack {
+                for family in IpFamily::BOTH {
+                    samplers.push((host.clone(), interval, pinger.clone().with_family(family)));
+                }
+            } else {
+                samplers.push((host, interval, pinger.clone()));
+            }
+        }
+        samplers
+    }
+
+    /// Metadata of a run started at `started`, for writing next to exports.
+    pub fn run_metadata(&self, started: DateTime<Utc>) -> RunMetadata {
+        RunMetadata {
+            run_name: self.run_name.clone(),
+            notes: self.run_notes.clone(),
+            hosts: self
+                .samplers()
+                .iter()
+                .map(|(host, _, pinger)| pinger.label(host))
+                .collect(),
+            started,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            invocation: if self.record_invocation {
+                Some(self.invocation())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// The arguments this config was parsed from, with the values of flags
+    /// that may hold credentials redacted, the main settings and the
+    /// environment variables that affect a run.
+    pub fn invocation(&self) -> Invocation {
+        let mut args = self.args.clone();
+        for i in 1..args.len() {
+            if REDACTED_FLAGS.contains(&args[i - 1].as_str()) {
+                args[i] = "<redacted>".to_string();
+            }
+        }
+
+        let mut settings = BTreeMap::new();
+        let mut set = |name: &str, value: String| settings.insert(name.to_string(), value);
+        set("mode", format!("{:?}", self.mode).to_lowercase());
+        set("interval", format!("{:?}", self.interval));
+        set("metric", format!("{:?}", self.metric).to_lowercase());
+        if let Some(iterations) = self.iterations {
+            set("iterations", iterations.to_string());
+        }
+        if let Some(latency) = self.thresholds.latency {
+            set("latency_threshold_ms", latency.to_string());
+        }
+        set("loss_threshold_percent", self.thresholds.loss.to_string());
+        set("sample_timeout", format!("{:?}", self.sample_timeout));
+        if let Some(path) = &self.ping_binary {
+            set("ping_binary", path.display().to_string());
+        }
+        if !self.ping_args.is_empty() {
+            set("ping_args", "<redacted>".to_string());
+        }
+        for host in &self.hosts {
+            set(
+                &format!("host.{}", host.host),
+                format!("{:?}", self.interval_for(&host.host)),
+            );
+        }
+
+        let env = INVOCATION_ENV
+            .iter()
+            .filter_map(|&var| Some((var.to_string(), env::var(var).ok()?)))
+            .collect();
+        Invocation {
+            args,
+            settings,
+            env,
+        }
+    }
+
+    /// How many of a host's `count` retained samples, oldest first, are left
+    /// out of the plot by `--plot-points`.
+    pub fn unplotted(&self, count: usize) -> usize {
+        self.plot_points
+            .map_or(0, |limit| count.saturating_sub(limit))
+    }
+
+    /// The interval `host` is sampled at outside any schedule. Samples
+    /// labelled with an [`IpFamily`] go by their host's interval.
+    pub fn interval_for(&self, host: &str) -> Duration {
+        let host = IpFamily::host_of(host);
+        self.hosts
+            .iter()
+            .find(|h| h.host == host)
+            .and_then(|h| h.interval)
+            .unwrap_or(self.interval)
+    }
+
+    /// The interval `host` is sampled at, local `time` of day.
+    pub fn interval_at(&self, host: &str, time: NaiveTime) -> Duration {
+        self.schedule
+            .interval_at(time)
+            .unwrap_or_else(|| self.interval_for(host))
+    }
+
+    /// How long `host` may go without answering before it's flagged.
+    pub fn grace_for(&self, host: &str) -> Duration {
+        self.grace.unwrap_or_else(|| self.interval_for(host) * 3)
+    }
+
+    /// The line color of the host that reported `index`th.
+    pub fn host_color(&self, index: usize) -> Rgb {
+        self.host_color_in(self.palette.unwrap_or_default(), index)
+    }
+
+    /// The line color of the host that reported `index`th, falling back to
+    /// `palette` past the `--color`s given.
+    pub fn host_color_in(&self, palette: Palette, index: usize) -> Rgb {
+        let colors = palette.host_colors();
+        self.colors
+            .get(index)
+            .copied()
+            .unwrap_or(colors[index % colors.len()])
+    }
+
+    /// Whether terminal output sticks to ASCII, because of `--ascii` or a
+    /// locale that isn't UTF-8. The GUI only follows `--ascii`.
+    pub fn ascii_terminal(&self) -> bool {
+        self.ascii || !locale_is_utf8()
+    }
+
+    /// The latency threshold set for `host` itself, if any. Samples labelled
+    /// with an [`IpFamily`] go by their host's threshold.
+    pub fn host_threshold(&self, host: &str) -> Option<f64> {
+        let host = IpFamily::host_of(host);
+        self.hosts
+            .iter()
+            .find(|h| h.host == host)
+            .and_then(|h| h.threshold)
+    }
+
+    /// The thresholds `host` is held to at local `time` of day: its own
+    /// latency threshold if it has one, and otherwise
+    /// [`thresholds_at`](Config::thresholds_at).
+    pub fn thresholds_for(&self, host: &str, time: NaiveTime) -> Thresholds {
+        let thresholds = self.thresholds_at(time);
+        Thresholds {
+            latency: self.host_threshold(host).or(thresholds.latency),
+            ..thresholds
+        }
+    }
+
+    /// The thresholds in effect at local `time` of day.
+    pub fn thresholds_at(&self, time: NaiveTime) -> Thresholds {
+        Thresholds {
+            latency: self.schedule.threshold_at(time).or(self.thresholds.latency),
+            ..self.thresholds
+        }
+    }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {flag}"))
+}
+
+fn parse_iterations(value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|err| format!("Error parsing iterations {value}: {err}"))
+}
+
+/// Parses `host[=interval] [interval=duration] [threshold=ms]`, as given to
+/// `--host` and in hosts files. The `=` of a URL's query string is part of
+/// the URL, so such URLs take their interval as a separate token.
+pub fn parse_host_spec(spec: &str) -> Result<HostConfig, String> {
+    let mut tokens = spec.split_whitespace();
+    let target = tokens.next().unwrap_or_default();
+    let (host, mut interval) = match target.split_once('=') {
+        Some((host, interval)) if !host.contains('?') => (host, Some(parse_interval(interval)?)),
+        _ => (target, None),
+    };
+
+    if host.is_empty() {
+        return Err(format!("Missing host in {spec}"));
+    }
+
+    let mut threshold = None;
+    for token in tokens {
+        match token.split_once('=') {
+            Some(("interval", value)) => interval = Some(parse_interval(value)?),
+            Some(("threshold", value)) => {
+                threshold = Some(
+                    value
+                        .parse()
+                        .map_err(|err| format!("Invalid threshold {value} in {spec}: {err}"))?,
+                )
+            }
+            _ => return Err(format!("Unknown setting {token} in {spec}")),
+        }
+    }
+
+    Ok(HostConfig {
+        host: host.to_string(),
+        interval,
+        threshold,
+    })
+}
+
+/// Parses a hosts file with one [`parse_host_spec`] spec per line. Blank
+/// lines and lines starting with `#` are ignored.
+pub fn parse_hosts_file(contents: &str) -> Result<Vec<HostConfig>, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_host_spec)
+        .collect()
+}
+
+/// Reads hosts from `reader` until EOF in the format of [`parse_hosts_file`],
+/// for `--stdin`. Reaching EOF without a host is an error so an empty pipe
+/// doesn't go unnoticed.
+pub fn read_hosts(mut reader: impl Read) -> Result<Vec<HostConfig>, String> {
+    let mut contents = String::new();
+    reader
+        .read_to_string(&mut contents)
+        .map_err(|err| format!("Error reading hosts from stdin: {err}"))?;
+    let hosts = parse_hosts_file(&contents)?;
+    if hosts.is_empty() {
+        return Err("--stdin got no hosts before EOF".to_string());
+    }
+    Ok(hosts)
+}
+
+/// Splits `value` into arguments at unquoted whitespace, as a POSIX shell
+/// would: single quotes keep everything, double quotes keep everything but
+/// backslash escapes, and a backslash outside quotes escapes any character.
+/// Nothing is expanded.
+pub fn split_args(value: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(current.take()),
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| format!("Trailing backslash in {value}"))?;
+                current.get_or_insert_with(String::new).push(escaped);
+            }
+            '\'' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(format!("Unclosed ' in {value}")),
+                    }
+                }
+            }
+            '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => arg.push(c),
+                            Some(c) => arg.extend(['\\', c]),
+                            None => return Err(format!("Unclosed \" in {value}")),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err(format!("Unclosed \" in {value}")),
+                    }
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+/// Parses a ToS byte given in decimal or as `0x` hex.
+pub fn parse_tos(value: &str) -> Result<u8, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| format!("Invalid ToS {value}, expected 0-255 or 0x00-0xff"))
+}
+
+/// Parses a size such as `512KB` or `50MB`, in binary units. A bare number is
+/// in bytes.
+pub fn parse_size(value: &str) -> Result<usize, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid size {value}"))?;
+    let scale = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        _ => return Err(format!("Invalid size unit in {value}")),
+    };
+
+    Ok((number * scale as f64) as usize)
+}
+
+/// Parses durations such as `500ms`, `30s`, `5m` or `1h`. A bare number is
+/// taken as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration {value}"))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("Invalid duration unit in {value}")),
+    };
+
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("Duration {value} is too long"))
+}
+
+/// Parses a sampling interval like [`parse_duration`], which has to be
+/// longer than zero so the sampler doesn't spin.
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    match parse_duration(value)? {
+        interval if interval.is_zero() => Err(format!("Interval {value} must be longer than 0s")),
+        interval => Ok(interval),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Config, String> {
+        Config::from_args(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("soon").is_err());
+    }
+
+    #[test]
+    fn rejects_durations_too_long_to_represent() {
+        assert!(parse_duration("99999999999999999999h").is_err());
+        assert!(parse_interval("99999999999999999999h").is_err());
+    }
+
+    #[test]
+    fn rejects_zero_intervals() {
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("0ms").is_err());
+        assert!(parse_host_spec("example.com=0s").is_err());
+        assert_eq!(parse_interval("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("0"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn parses_per_host_intervals() {
+        let host = parse_host_spec("example.com=250ms threshold=80").unwrap();
+        assert_eq!(host.host, "example.com");
+        assert_eq!(host.interval, Some(Duration::from_millis(250)));
+        assert_eq!(host.threshold, Some(80.0));
+        assert_eq!(parse_host_spec("example.com").unwrap().interval, None);
+        assert!(parse_host_spec("=5s").is_err());
+        let host = parse_host_spec("example.com interval=2s").unwrap();
+        assert_eq!(host.interval, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn keeps_url_query_strings_whole() {
+        let host = parse_host_spec("https://example.com/search?q=abc").unwrap();
+        assert_eq!(host.host, "https://example.com/search?q=abc");
+        assert_eq!(host.interval, None);
+
+        let host = parse_host_spec("http://127.0.0.1:9/status?delay=5 interval=250ms").unwrap();
+        assert_eq!(host.host, "http://127.0.0.1:9/status?delay=5");
+        assert_eq!(host.interval, Some(Duration::from_millis(250)));
+
+        let host = parse_host_spec("http://example.com/status=5s").unwrap();
+        assert_eq!(host.host, "http://example.com/status");
+        assert_eq!(host.interval, Some(Duration::from_secs(5)));
+
+        let config = parse("--mode http https://example.com/search?q=abc").unwrap();
+        assert_eq!(config.hosts[0].host, "https://example.com/search?q=abc");
+        let Ok(hosts) = read_hosts("https://example.com/?a=1&b=2 threshold=80\n".as_bytes()) else {
+            panic!("hosts should be read");
+        };
+        assert_eq!(hosts[0].host, "https://example.com/?a=1&b=2");
+    }
+
+    #[test]
+    fn schedules_hosts_at_their_own_interval() {
+        let config =
+            parse("1.1.1.1 --interval 2s --host example.com=500ms --host 8.8.8.8").unwrap();
+        assert_eq!(
+            config.schedule(),
+            vec![
+                ("1.1.1.1".to_string(), Duration::from_secs(2)),
+                ("example.com".to_string(), Duration::from_millis(500)),
+                ("8.8.8.8".to_string(), Duration::from_secs(2)),
+            ]
+        );
+        assert_eq!(
+            config.interval_for("example.com"),
+            Duration::from_millis(500)
+        );
+        assert_eq!(config.interval_for("8.8.8.8"), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn rapid_intervals_below_the_confirmation_need_it() {
+        let config = parse("example.com --rapid 500ms").unwrap();
+        assert_eq!(config.interval, Duration::from_millis(500));
+        assert!(config.stream_ping);
+        assert!(parse("example.com --rapid 50ms").is_err());
+        let config = parse("example.com --rapid 50ms --i-understand").unwrap();
+        assert_eq!(config.interval, Duration::from_millis(50));
+        assert!(parse("example.com --rapid 5ms --i-understand").is_err());
+    }
+
+    #[test]
+    fn dual_stack_samples_each_host_over_both_families() {
+        let labels = |line: &str| {
+            parse(line)
+                .unwrap()
+                .samplers()
+                .iter()
+                .map(|(host, _, pinger)| pinger.label(host))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            labels("example.com --dual-stack"),
+            ["example.com (IPv4)", "example.com (IPv6)"]
+        );
+        assert_eq!(labels("example.com"), ["example.com"]);
+        assert!(parse("example.com --dual-stack --mode http").is_err());
+    }
+
+    #[test]
+    fn resolves_per_host_thresholds() {
+        let config = Config::from_args(
+            [
+                "lan.example",
+                "--host",
+                "far.example threshold=250",
+                "--threshold",
+                "50",
+            ]
+            .map(String::from),
+        )
+        .unwrap();
+        let noon = NaiveTime::from_hms_opt(12, 0, 0).unwrap();
+        assert_eq!(
+            config.thresholds_for("far.example", noon).latency,
+            Some(250.0)
+        );
+        assert_eq!(
+            config.thresholds_for("far.example (IPv6)", noon).latency,
+            Some(250.0)
+        );
+        assert_eq!(
+            config.thresholds_for("lan.example", noon).latency,
+            Some(50.0)
+        );
+        assert_eq!(
+            config.thresholds_for("other.example", noon).latency,
+            Some(50.0)
+        );
+
+        let hosts = parse_hosts_file("# lab\nswitch.lan threshold=2\n\nfar.example=5s\n").unwrap();
+        assert_eq!(hosts[0].threshold, Some(2.0));
+        assert_eq!(hosts[1].threshold, None);
+        assert!(parse_host_spec("far.example threshold=soon").is_err());
+        assert!(parse_host_spec("far.example loss=5").is_err());
+    }
+
+    #[test]
+    fn splits_ping_args_like_a_shell() {
+        assert_eq!(
+            split_args(r#"-s 1200  -p 'ab cd' "x \"y\" \n" a\ b"#),
+            Ok(["-s", "1200", "-p", "ab cd", "x \"y\" \\n", "a b"]
+                .map(String::from)
+                .to_vec())
+        );
+        assert_eq!(split_args("''"), Ok(vec![String::new()]));
+        assert!(split_args("-p 'ab").is_err());
+        assert!(split_args("-p \\").is_err());
+    }
+
+    #[test]
+    fn reads_hosts_from_a_reader_until_eof() {
+        let input = "8.8.8.8\n\n# fallback\n1.1.1.1=250ms threshold=80\n";
+        let Ok(hosts) = read_hosts(input.as_bytes()) else {
+            panic!("hosts should be read");
+        };
+        let hosts: Vec<_> = hosts
+            .iter()
+            .map(|host| (host.host.as_str(), host.interval, host.threshold))
+            .collect();
+        assert_eq!(
+            hosts,
+            [
+                ("8.8.8.8", None, None),
+                ("1.1.1.1", Some(Duration::from_millis(250)), Some(80.0)),
+            ]
+        );
+
+        assert_eq!(
+            read_hosts("# nothing yet\n".as_bytes()).err().as_deref(),
+            Some("--stdin got no hosts before EOF")
+        );
+        assert!(read_hosts("example.com=soon\n".as_bytes()).is_err());
+        assert!(read_hosts(&[0xff, b'\n'][..]).is_err());
+    }
+
+    #[test]
+    fn records_the_invocation_without_secrets() {
+        let args = [
+            "http://example.com/",
+            "--mode",
+            "http",
+            "--interval",
+            "2s",
+            "--proxy",
+            "http://proxy.internal:3128",
+            "--webhook-url",
+            "http://hooks.example.com/secret-token",
+            "--record-invocation",
+        ];
+        let config = Config::from_args(args.map(String::from)).unwrap();
+        let started = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+        let metadata = config.run_metadata(started);
+        let invocation = metadata.invocation.clone().unwrap();
+        assert_eq!(
+            invocation.args,
+            [
+                "http://example.com/",
+                "--mode",
+                "http",
+                "--interval",
+                "2s",
+                "--proxy",
+                "<redacted>",
+                "--webhook-url",
+                "<redacted>",
+                "--record-invocation",
+            ]
+        );
+        assert_eq!(invocation.settings["mode"], "http");
+        assert_eq!(invocation.settings["interval"], "2s");
+        assert!(invocation
+            .env
+            .keys()
+            .all(|var| INVOCATION_ENV.contains(&var.as_str())));
+        assert_eq!(invocation.env.get("PATH"), env::var("PATH").ok().as_ref());
+        assert!(metadata.to_string().ends_with(
+            "\nnetwork-test http://example.com/ --mode http --interval 2s --proxy <redacted> \
+             --webhook-url <redacted> --record-invocation"
+        ));
+
+        let args = [
+            "example.com",
+            "--ping-args",
+            "-p deadbeef",
+            "--on-breach",
+            "curl -H 'Authorization: secret' http://hooks.example.com/",
+        ];
+        let invocation = Config::from_args(args.map(String::from))
+            .unwrap()
+            .invocation();
+        assert_eq!(
+            invocation.args,
+            [
+                "example.com",
+                "--ping-args",
+                "<redacted>",
+                "--on-breach",
+                "<redacted>",
+            ]
+        );
+        assert_eq!(invocation.settings["ping_args"], "<redacted>");
+
+        let config = Config::from_args(["example.com".to_string()]).unwrap();
+        assert_eq!(config.run_metadata(started).invocation, None);
+    }
+
+    #[test]
+    fn rejects_a_healthz_loss_outside_a_percentage() {
+        assert_eq!(
+            parse("example.com --healthz-loss 25").unwrap().healthz_loss,
+            25.0
+        );
+        assert!(parse("example.com --healthz-loss 100").is_ok());
+        assert!(parse("example.com --healthz-loss 150").is_err());
+        assert!(parse("example.com --healthz-loss -1").is_err());
+        assert!(parse("example.com --healthz-loss NaN").is_err());
+    }
+}
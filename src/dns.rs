@@ -0,0 +1,209 @@
+//! DNS resolution latency: one query per sample sent straight to a resolver
+//! over UDP, timed until its answer.
+
+use crate::ping::PingResult;
+use chrono::Utc;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest answer accepted over UDP, as EDNS isn't used.
+const DNS_MAX_ANSWER: usize = 512;
+
+/// The record type asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DnsRecord {
+    A,
+    Aaaa,
+}
+
+impl DnsRecord {
+    fn code(self) -> u16 {
+        match self {
+            DnsRecord::A => 1,
+            DnsRecord::Aaaa => 28,
+        }
+    }
+}
+
+/// A name to look up and the record type asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsQuery {
+    pub name: String,
+    pub record: DnsRecord,
+}
+
+/// Encodes a recursive query for `query` with the given `id`.
+pub fn build_query(id: u16, query: &DnsQuery) -> Result<Vec<u8>, io::Error> {
+    let mut packet = Vec::with_capacity(32 + query.name.len());
+    packet.extend(id.to_be_bytes());
+    // Recursion desired, one question
+    packet.extend([0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in query.name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid DNS name {}", query.name),
+            ));
+        }
+        packet.push(label.len() as u8);
+        packet.extend(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend(query.record.code().to_be_bytes());
+    packet.extend(1u16.to_be_bytes()); // IN
+    Ok(packet)
+}
+
+/// Checks the answer to the query with `id`. SERVFAIL and NXDOMAIN each
+/// give their own error, NXDOMAIN as [`io::ErrorKind::NotFound`]. An answer
+/// holding no records of the type asked for still counts as resolved.
+pub fn check_answer(id: u16, answer: &[u8], query: &DnsQuery) -> Result<(), io::Error> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    if answer.len() < 12 {
+        return Err(invalid(format!("Truncated DNS answer for {}", query.name)));
+    }
+    if u16::from_be_bytes([answer[0], answer[1]]) != id || answer[2] & 0x80 == 0 {
+        return Err(invalid(format!("Unexpected DNS packet for {}", query.name)));
+    }
+    match answer[3] & 0x0f {
+        0 => Ok(()),
+        2 => Err(io::Error::other(format!("SERVFAIL for {}", query.name))),
+        3 => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("NXDOMAIN for {}", query.name),
+        )),
+        rcode => Err(io::Error::other(format!(
+            "DNS error {rcode} for {}",
+            query.name
+        ))),
+    }
+}
+
+/// The address of `resolver`, given as `host` or `host:port` with port 53
+/// by default.
+fn resolver_addr(resolver: &str) -> Result<SocketAddr, io::Error> {
+    let with_port = resolver.to_socket_addrs().or_else(|_| {
+        (resolver.trim_start_matches('[').trim_end_matches(']'), 53).to_socket_addrs()
+    });
+    with_port?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No address for resolver {resolver}"),
+        )
+    })
+}
+
+/// Times looking up `query` at `resolver`, from sending the question to
+/// receiving its answer.
+pub fn get_dns(resolver: &str, query: &DnsQuery) -> Result<PingResult, io::Error> {
+    let addr = resolver_addr(resolver)?;
+    let bind: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(DNS_TIMEOUT))?;
+    socket.connect(addr)?;
+
+    let id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.subsec_nanos() as u16);
+    let packet = build_query(id, query)?;
+    let started = Instant::now();
+    socket.send(&packet)?;
+    let mut answer = [0u8; DNS_MAX_ANSWER];
+    // Stray answers to earlier, timed out queries are skipped
+    let len = loop {
+        let len = socket.recv(&mut answer)?;
+        if len >= 2 && u16::from_be_bytes([answer[0], answer[1]]) == id {
+            break len;
+        }
+    };
+    let elapsed = started.elapsed();
+    check_answer(id, &answer[..len], query)?;
+
+    Ok(PingResult::new(
+        resolver.to_string(),
+        elapsed.as_secs_f64() * 1000.0,
+        Utc::now(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// A resolver on loopback answering one query after `delay` with
+    /// `rcode`, after a stray answer to some other query. Yields the query.
+    fn stub_resolver(delay: Duration, rcode: u8) -> (String, thread::JoinHandle<Vec<u8>>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let mut query = [0u8; DNS_MAX_ANSWER];
+            let (len, from) = socket.recv_from(&mut query).unwrap();
+            let query = query[..len].to_vec();
+            thread::sleep(delay);
+            let mut answer = query.clone();
+            answer[2] |= 0x80;
+            answer[3] = rcode;
+            let mut stray = answer.clone();
+            stray[0] = !stray[0];
+            socket.send_to(&stray, from).unwrap();
+            socket.send_to(&answer, from).unwrap();
+            query
+        });
+        (addr, handle)
+    }
+
+    fn query(record: DnsRecord) -> DnsQuery {
+        DnsQuery {
+            name: "example.com.".to_string(),
+            record,
+        }
+    }
+
+    #[test]
+    fn times_lookups_against_the_resolver() {
+        let (resolver, handle) = stub_resolver(Duration::from_millis(50), 0);
+        let sample = get_dns(&resolver, &query(DnsRecord::Aaaa)).unwrap();
+        let sent = handle.join().unwrap();
+        assert_eq!(sample.host, resolver);
+        assert!(sample.average >= 50.0, "{}", sample.average);
+        // Recursion desired, then example.com AAAA IN
+        assert_eq!(sent[2..6], [0x01, 0x00, 0x00, 0x01]);
+        assert_eq!(sent[12..], *b"\x07example\x03com\x00\x00\x1c\x00\x01");
+    }
+
+    #[test]
+    fn tells_servfail_from_nxdomain() {
+        let (resolver, handle) = stub_resolver(Duration::ZERO, 3);
+        let err = get_dns(&resolver, &query(DnsRecord::A)).unwrap_err();
+        handle.join().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "NXDOMAIN for example.com.");
+
+        let (resolver, handle) = stub_resolver(Duration::ZERO, 2);
+        let err = get_dns(&resolver, &query(DnsRecord::A)).unwrap_err();
+        handle.join().unwrap();
+        assert_eq!(err.to_string(), "SERVFAIL for example.com.");
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let long = DnsQuery {
+            name: format!("{}.com", "a".repeat(64)),
+            record: DnsRecord::A,
+        };
+        assert!(build_query(1, &long).is_err());
+        let empty_label = DnsQuery {
+            name: "example..com".to_string(),
+            record: DnsRecord::A,
+        };
+        assert!(build_query(1, &empty_label).is_err());
+    }
+}
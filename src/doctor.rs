@@ -0,0 +1,204 @@
+//! `--doctor`: checks that the environment can run the monitor.
+
+use crate::config::Config;
+use crate::ping::{Mode, Pinger};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Host pinged to check that `ping` works at all.
+const LOOPBACK: &str = "127.0.0.1";
+
+/// The outcome of one check, with what was found or what went wrong.
+pub struct Check {
+    pub name: &'static str,
+    /// Whether a failure means the monitor can't work, rather than only a
+    /// feature being unavailable.
+    pub critical: bool,
+    pub result: Result<String, String>,
+}
+
+impl Check {
+    pub fn failed_critically(&self) -> bool {
+        self.critical && self.result.is_err()
+    }
+}
+
+/// Runs every check that applies to `config`.
+pub fn run_checks(config: &Config) -> Vec<Check> {
+    let icmp = config.mode == Mode::Icmp;
+    let ping_binary = config
+        .ping_binary
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("ping"));
+    vec![
+        Check {
+            name: "ping binary",
+            critical: icmp,
+            result: check_ping_binary(&ping_binary),
+        },
+        Check {
+            name: "loopback ping",
+            critical: icmp,
+            result: check_loopback(&ping_binary),
+        },
+        Check {
+            name: "raw sockets",
+            critical: false,
+            result: check_raw_sockets(),
+        },
+        Check {
+            name: "display",
+            critical: false,
+            result: check_display(),
+        },
+    ]
+}
+
+/// Prints one line per check and returns whether any critical one failed.
+pub fn print_report(checks: &[Check]) -> bool {
+    for check in checks {
+        let (status, detail) = match &check.result {
+            Ok(detail) => ("ok", detail),
+            Err(detail) if check.critical => ("FAIL", detail),
+            Err(detail) => ("warn", detail),
+        };
+        println!("[{status:^4}] {}: {detail}", check.name);
+    }
+    checks.iter().any(Check::failed_critically)
+}
+
+/// Finds `binary` the way the samplers run it, on `PATH` unless it's a path.
+pub fn check_ping_binary(binary: &Path) -> Result<String, String> {
+    if binary.components().count() > 1 {
+        return if binary.is_file() {
+            Ok(binary.display().to_string())
+        } else {
+            Err(format!("{} not found", binary.display()))
+        };
+    }
+    env::var_os("PATH")
+        .iter()
+        .flat_map(env::split_paths)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| candidate.is_file())
+        .map(|found| found.display().to_string())
+        .ok_or_else(|| format!("{} not found on PATH", binary.display()))
+}
+
+/// Pings the loopback address once with `ping_binary`.
+pub fn check_loopback(ping_binary: &Path) -> Result<String, String> {
+    Pinger::new(Mode::Icmp)
+        .with_ping_binary(ping_binary)
+        .ping(LOOPBACK)
+        .map(|result| format!("{LOOPBACK} answered in {:.2} ms", result.average))
+        .map_err(|err| format!("pinging {LOOPBACK} failed: {err}"))
+}
+
+/// Whether this process may open raw or unprivileged ICMP sockets, as a
+/// native pinger would need. Only checked on Linux.
+pub fn check_raw_sockets() -> Result<String, String> {
+    if !cfg!(target_os = "linux") {
+        return Err("not checked on this platform".to_string());
+    }
+    let status = fs::read_to_string("/proc/self/status").map_err(|err| err.to_string())?;
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(str::trim)
+    };
+
+    // CAP_NET_RAW is capability 13
+    let cap_net_raw = field("CapEff:")
+        .and_then(|caps| u64::from_str_radix(caps, 16).ok())
+        .is_some_and(|caps| caps & (1 << 13) != 0);
+    if cap_net_raw {
+        return Ok("CAP_NET_RAW available".to_string());
+    }
+
+    // Unprivileged ICMP sockets are allowed for the groups in this range
+    let gid: Option<u32> =
+        field("Gid:").and_then(|gids| gids.split_whitespace().nth(1)?.parse().ok());
+    let range = fs::read_to_string("/proc/sys/net/ipv4/ping_group_range").unwrap_or_default();
+    let mut bounds = range.split_whitespace().map(|bound| bound.parse::<u32>());
+    if let (Some(gid), Some(Ok(low)), Some(Ok(high))) = (gid, bounds.next(), bounds.next()) {
+        if (low..=high).contains(&gid) {
+            return Ok(format!("unprivileged ICMP sockets allowed for group {gid}"));
+        }
+    }
+    Err("no CAP_NET_RAW and unprivileged ICMP sockets are off".to_string())
+}
+
+pub fn check_display() -> Result<String, String> {
+    if display_available() {
+        Ok("a window can be opened".to_string())
+    } else {
+        Err("no display found, the monitor will run headless".to_string())
+    }
+}
+
+/// Whether a window can be opened. Only X11/Wayland sessions are checked; other
+/// platforms are assumed to always have a display.
+pub fn display_available() -> bool {
+    if cfg!(all(unix, not(target_os = "macos"))) {
+        ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|var| env::var_os(var).is_some_and(|value| !value.is_empty()))
+    } else {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn finds_ping_binaries_by_path_or_on_path() {
+        assert_eq!(
+            check_ping_binary(Path::new("/bin/sh")),
+            Ok("/bin/sh".to_string())
+        );
+        assert!(check_ping_binary(Path::new("sh")).is_ok());
+        assert!(check_ping_binary(Path::new("/nonexistent/ping")).is_err());
+        assert!(check_ping_binary(Path::new("no-such-ping-binary")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn checks_loopback_with_the_given_binary() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = env::temp_dir().join(format!("doctor-loopback-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let ping = dir.join("ping");
+        fs::write(
+            &ping,
+            "#!/bin/sh\necho 'round-trip min/avg/max/stddev = 0.05/0.05/0.05/0.00 ms'\n",
+        )
+        .unwrap();
+        fs::set_permissions(&ping, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(
+            check_loopback(&ping),
+            Ok("127.0.0.1 answered in 0.05 ms".to_string())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(check_loopback(&ping).is_err());
+    }
+
+    #[test]
+    fn only_critical_failures_fail_the_report() {
+        let check = |critical, result: Result<&str, &str>| Check {
+            name: "check",
+            critical,
+            result: result.map(String::from).map_err(String::from),
+        };
+        assert!(!print_report(&[
+            check(true, Ok("fine")),
+            check(false, Err("missing"))
+        ]));
+        assert!(print_report(&[check(true, Err("missing"))]));
+    }
+}
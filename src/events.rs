@@ -0,0 +1,60 @@
+//! Notable things that happened during a run, shown alongside the samples.
+
+use crate::timezone::format_in;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Number of events kept before the oldest are dropped.
+pub const EVENT_LOG_CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub at: DateTime<Utc>,
+    pub host: String,
+    pub message: String,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {}",
+            format_in(self.at, &Utc, "%H:%M:%S"),
+            self.host,
+            self.message
+        )
+    }
+}
+
+/// The most recent [`EVENT_LOG_CAPACITY`] events, oldest first.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    events: VecDeque<Event>,
+}
+
+impl EventLog {
+    pub fn new() -> EventLog {
+        EventLog::default()
+    }
+
+    pub fn push(&mut self, event: Event) {
+        if self.events.len() == EVENT_LOG_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Event> {
+        self.events.iter()
+    }
+}
+
+/// A remark the user pinned to a point in time, like "started VPN".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Note {
+    #[serde(with = "crate::export::timestamp")]
+    pub at: DateTime<Utc>,
+    pub text: String,
+}
@@ -0,0 +1,1551 @@
+//! Writing samples to files and streams, and reading exports back.
+
+use crate::color::Rgb;
+use crate::events::Note;
+use crate::ping::PingResult;
+use crate::schedule::Schedule;
+use crate::stats::{rolling_stats, RollingStats, Rollup};
+use crate::timezone;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::{
+    chart::ChartBuilder,
+    style::{IntoFont, WHITE},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+/// How every export writes timestamps: RFC 3339 in UTC with milliseconds, to
+/// line up with packet captures.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+/// [`TIMESTAMP_FORMAT`] with the offset of the `--timezone` zone in place of
+/// `Z`.
+pub const ZONED_TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f%:z";
+
+/// `at` as an export writes it, in the `--timezone` zone if one was given.
+pub fn format_timestamp(at: DateTime<Utc>) -> String {
+    match timezone::zone() {
+        Some(zone) => at
+            .with_timezone(&zone)
+            .format(ZONED_TIMESTAMP_FORMAT)
+            .to_string(),
+        None => at.format(TIMESTAMP_FORMAT).to_string(),
+    }
+}
+
+/// Serde adapter writing a timestamp with [`format_timestamp`], for use with
+/// `#[serde(with = "crate::export::timestamp")]`. Any RFC 3339 timestamp is
+/// read back.
+pub mod timestamp {
+    use super::format_timestamp;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(at: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_timestamp(*at))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        DateTime::deserialize(deserializer)
+    }
+}
+
+/// Like [`timestamp`], for a time that may be missing.
+pub mod optional_timestamp {
+    use super::format_timestamp;
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        at: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match at {
+            Some(at) => serializer.collect_str(&format_timestamp(*at)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Option::deserialize(deserializer)
+    }
+}
+
+/// Options controlling the layout of CSV exports.
+#[derive(Clone)]
+pub struct CsvOptions {
+    /// When set, each row also gets `roll_avg,roll_min,roll_max` computed over
+    /// this many samples of the same host, ending at the row itself.
+    pub rolling_window: Option<usize>,
+    pub delimiter: char,
+    /// End lines with `\r\n` instead of `\n`.
+    pub crlf: bool,
+    /// When set, each row also gets a `maintenance` column, 1 for samples
+    /// taken during one of its maintenance windows and 0 otherwise.
+    pub maintenance: Option<Schedule>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            rolling_window: None,
+            delimiter: ',',
+            crlf: false,
+            maintenance: None,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// The header of a CSV export, without the rolling columns only whole
+    /// exports have.
+    fn header(&self) -> Vec<&'static str> {
+        let mut header = vec!["timestamp", "host", "average"];
+        if self.maintenance.is_some() {
+            header.push("maintenance");
+        }
+        header
+    }
+
+    fn line_ending(&self) -> &'static str {
+        if self.crlf {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+}
+
+/// Exports to a path ending in this are gzip-compressed.
+pub const GZIP_SUFFIX: &str = ".gz";
+
+/// Writes a whole export to `file_path`, refusing to overwrite an existing
+/// file, and gzip-compresses it when the path ends in [`GZIP_SUFFIX`].
+fn write_export(file_path: &str, contents: &[u8]) -> Result<(), std::io::Error> {
+    let path = Path::new(file_path);
+    if path.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("File {file_path} already exists."),
+        ));
+    }
+
+    let mut file = File::create(file_path)?;
+    if file_path.ends_with(GZIP_SUFFIX) {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(contents)?;
+        encoder.finish()?;
+        Ok(())
+    } else {
+        file.write_all(contents)
+    }
+}
+
+/// Reads an export written by [`write_export`], decompressing it if needed.
+fn read_export(file_path: &str) -> Result<Vec<u8>, std::io::Error> {
+    let bytes = fs::read(file_path)?;
+    if !file_path.ends_with(GZIP_SUFFIX) {
+        return Ok(bytes);
+    }
+
+    let mut contents = vec![];
+    MultiGzDecoder::new(bytes.as_slice()).read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+pub fn export_to_csv(
+    file_path: &str,
+    values: &[PingResult],
+    options: &CsvOptions,
+) -> Result<(), std::io::Error> {
+    write_export(file_path, format_csv(values, options).as_bytes())
+}
+
+pub fn format_csv(values: &[PingResult], options: &CsvOptions) -> String {
+    let mut header = options.header();
+    // Rolling stats are computed per host so interleaved hosts don't mix
+    let mut rolling: Vec<Option<RollingStats>> = vec![None; values.len()];
+    if let Some(window) = options.rolling_window {
+        header.extend(["roll_avg", "roll_min", "roll_max"]);
+
+        let mut hosts: Vec<&str> = values.iter().map(|v| v.host.as_str()).collect();
+        hosts.sort_unstable();
+        hosts.dedup();
+        for host in hosts {
+            let indices: Vec<usize> = (0..values.len())
+                .filter(|&i| values[i].host == host)
+                .collect();
+            let averages: Vec<f64> = indices.iter().map(|&i| values[i].average).collect();
+            for (&i, stats) in indices.iter().zip(rolling_stats(&averages, window)) {
+                rolling[i] = Some(stats);
+            }
+        }
+    }
+
+    let rows = values
+        .iter()
+        .zip(rolling)
+        .map(|(v, stats)| csv_row(v, stats, options));
+
+    std::iter::once(format_record(&header, options.delimiter))
+        .chain(rows)
+        .collect::<Vec<String>>()
+        .join(options.line_ending())
+}
+
+/// Writes [`Rollup`]s as CSV.
+pub fn export_rollups_to_csv(
+    file_path: &str,
+    rollups: &[Rollup],
+    options: &CsvOptions,
+) -> Result<(), std::io::Error> {
+    let header = ["start", "host", "count", "avg", "min", "max", "p95", "loss"];
+    let rows = rollups.iter().map(|rollup| {
+        let row = [
+            format_timestamp(rollup.start),
+            rollup.host.clone(),
+            rollup.count.to_string(),
+            rollup.avg.to_string(),
+            rollup.min.to_string(),
+            rollup.max.to_string(),
+            rollup.p95.to_string(),
+            rollup.loss.to_string(),
+        ];
+        format_record(&row, options.delimiter)
+    });
+    let contents = std::iter::once(format_record(&header, options.delimiter))
+        .chain(rows)
+        .collect::<Vec<String>>()
+        .join(options.line_ending());
+
+    write_export(file_path, contents.as_bytes())
+}
+
+/// One CSV row for `sample`, with rolling columns when `stats` is given.
+fn csv_row(sample: &PingResult, stats: Option<RollingStats>, options: &CsvOptions) -> String {
+    let mut row = vec![
+        format_timestamp(sample.datetime_recv),
+        sample.host.clone(),
+        sample.average.to_string(),
+    ];
+    if let Some(schedule) = &options.maintenance {
+        let time = sample.datetime_recv.with_timezone(&Local).time();
+        row.push(u8::from(schedule.in_maintenance(time)).to_string());
+    }
+    if let Some(stats) = stats {
+        row.extend([stats.avg, stats.min, stats.max].map(|stat| stat.to_string()));
+    }
+    format_record(&row, options.delimiter)
+}
+
+/// Joins `fields` with `delimiter`, quoting any field that contains the
+/// delimiter, a quote or a line break as RFC 4180 describes.
+fn format_record(fields: &[impl AsRef<str>], delimiter: char) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([delimiter, '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(&delimiter.to_string())
+}
+
+/// Splits a line written by [`format_record`] back into its fields.
+fn split_record(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+pub fn import_csv(file_path: &str) -> Result<Vec<PingResult>, std::io::Error> {
+    let contents = String::from_utf8(read_export(file_path)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    parse_csv(&contents)
+}
+
+/// Reads back the output of [`format_csv`]. Columns are located by name so
+/// optional columns such as the rolling stats are skipped, and the delimiter
+/// is taken from the header so any [`CsvOptions`] can be read.
+pub fn parse_csv(contents: &str) -> Result<Vec<PingResult>, std::io::Error> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut lines = contents.lines();
+    let header_line = lines.next().unwrap_or_default();
+    let delimiter = header_line
+        .strip_prefix("timestamp")
+        .and_then(|rest| rest.chars().next())
+        .unwrap_or(',');
+    let header = split_record(header_line, delimiter);
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| invalid(format!("Missing column {name}")))
+    };
+    let (timestamp, host, average) = (column("timestamp")?, column("host")?, column("average")?);
+
+    lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields = split_record(line, delimiter);
+            let field = |i: usize| {
+                fields
+                    .get(i)
+                    .map(String::as_str)
+                    .ok_or_else(|| invalid(format!("Missing field in row {line}")))
+            };
+
+            let datetime_recv = DateTime::parse_from_rfc3339(field(timestamp)?)
+                .map_err(|err| invalid(format!("Invalid timestamp in row {line}: {err}")))?
+                .with_timezone(&Utc);
+            let average = field(average)?
+                .parse()
+                .map_err(|err| invalid(format!("Invalid average in row {line}: {err}")))?;
+
+            Ok(PingResult::new(
+                field(host)?.to_string(),
+                average,
+                datetime_recv,
+            ))
+        })
+        .collect()
+}
+
+/// Extension of files written in the compact binary format.
+pub const BINARY_EXTENSION: &str = "ntb";
+
+/// Exports as binary when `file_path` ends in [`BINARY_EXTENSION`] and as CSV
+/// otherwise.
+pub fn export_samples(
+    file_path: &str,
+    values: &[PingResult],
+    options: &CsvOptions,
+) -> Result<(), std::io::Error> {
+    if is_binary_path(file_path) {
+        export_to_binary(file_path, values)
+    } else {
+        export_to_csv(file_path, values, options)
+    }
+}
+
+/// Combines the samples of several exports into one timeline ordered by
+/// receive time. A sample of the same host at the same time in more than
+/// one export, as where sessions overlap, is kept once, from the first
+/// export holding it. Gaps between sessions stay as they are.
+pub fn merge_samples(exports: Vec<Vec<PingResult>>) -> Vec<PingResult> {
+    let mut merged: Vec<PingResult> = exports.into_iter().flatten().collect();
+    // Stable, so the first export's copy of a duplicate comes first
+    merged.sort_by(|a, b| {
+        a.datetime_recv
+            .cmp(&b.datetime_recv)
+            .then_with(|| a.host.cmp(&b.host))
+    });
+    merged.dedup_by(|b, a| a.host == b.host && a.datetime_recv == b.datetime_recv);
+    merged
+}
+
+/// Reads back anything written by [`export_samples`].
+pub fn import_samples(file_path: &str) -> Result<Vec<PingResult>, std::io::Error> {
+    if is_binary_path(file_path) {
+        import_binary(file_path)
+    } else {
+        import_csv(file_path)
+    }
+}
+
+fn is_binary_path(file_path: &str) -> bool {
+    let file_path = file_path.strip_suffix(GZIP_SUFFIX).unwrap_or(file_path);
+    Path::new(file_path)
+        .extension()
+        .is_some_and(|extension| extension == BINARY_EXTENSION)
+}
+
+pub fn export_to_binary(file_path: &str, values: &[PingResult]) -> Result<(), std::io::Error> {
+    write_export(file_path, &encode_binary(values)?)
+}
+
+pub fn import_binary(file_path: &str) -> Result<Vec<PingResult>, std::io::Error> {
+    decode_binary(&read_export(file_path)?)
+}
+
+const BINARY_MAGIC: &[u8; 4] = b"NTB1";
+const RECORD_HOST: u8 = 0;
+const RECORD_SAMPLE: u8 = 1;
+
+/// Encodes samples in a compact binary format, about a third of the size of
+/// CSV.
+///
+/// After the `NTB1` magic come tagged records, all integers little-endian:
+/// a host record (`0`, `u16` length, UTF-8 name) the first time a host
+/// appears, and a sample record (`1`, `u16` host index, `i64` microseconds
+/// since the epoch, `f32` average) for every sample. HTTP timings are not
+/// kept.
+pub fn encode_binary(values: &[PingResult]) -> Result<Vec<u8>, std::io::Error> {
+    let too_many = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+
+    let mut out = BINARY_MAGIC.to_vec();
+    let mut hosts: Vec<&str> = vec![];
+    for value in values {
+        let index = match hosts.iter().position(|&host| host == value.host) {
+            Some(index) => index,
+            None => {
+                let length = u16::try_from(value.host.len())
+                    .map_err(|_| too_many("Host name too long for binary export"))?;
+                out.push(RECORD_HOST);
+                out.extend(length.to_le_bytes());
+                out.extend(value.host.as_bytes());
+                hosts.push(&value.host);
+                hosts.len() - 1
+            }
+        };
+        let index =
+            u16::try_from(index).map_err(|_| too_many("Too many hosts for binary export"))?;
+
+        out.push(RECORD_SAMPLE);
+        out.extend(index.to_le_bytes());
+        out.extend(value.datetime_recv.timestamp_micros().to_le_bytes());
+        out.extend((value.average as f32).to_le_bytes());
+    }
+
+    Ok(out)
+}
+
+/// Reads back the output of [`encode_binary`].
+pub fn decode_binary(bytes: &[u8]) -> Result<Vec<PingResult>, std::io::Error> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut rest = bytes
+        .strip_prefix(BINARY_MAGIC)
+        .ok_or_else(|| invalid("Not a binary export".to_string()))?;
+    let mut take = |n: usize| {
+        if rest.len() < n {
+            return Err(invalid("Truncated binary export".to_string()));
+        }
+        let (taken, remaining) = rest.split_at(n);
+        rest = remaining;
+        Ok(taken)
+    };
+
+    let mut hosts: Vec<String> = vec![];
+    let mut values = vec![];
+    while let Ok(&[tag]) = take(1) {
+        match tag {
+            RECORD_HOST => {
+                let length = u16::from_le_bytes(take(2)?.try_into().unwrap());
+                let host = std::str::from_utf8(take(length as usize)?)
+                    .map_err(|err| invalid(format!("Invalid host name: {err}")))?;
+                hosts.push(host.to_string());
+            }
+            RECORD_SAMPLE => {
+                let index = u16::from_le_bytes(take(2)?.try_into().unwrap());
+                let micros = i64::from_le_bytes(take(8)?.try_into().unwrap());
+                let average = f32::from_le_bytes(take(4)?.try_into().unwrap());
+
+                let host = hosts
+                    .get(index as usize)
+                    .ok_or_else(|| invalid(format!("Unknown host index {index}")))?;
+                let datetime_recv = DateTime::from_timestamp_micros(micros)
+                    .ok_or_else(|| invalid(format!("Invalid timestamp {micros}")))?;
+                values.push(PingResult::new(host.clone(), average as f64, datetime_recv));
+            }
+            _ => return Err(invalid(format!("Unknown record type {tag}"))),
+        }
+    }
+
+    Ok(values)
+}
+
+/// Where the notes belonging to the export at `file_path` are kept.
+pub fn notes_path(file_path: &str) -> String {
+    format!("{file_path}.notes.json")
+}
+
+/// Writes notes as a JSON array, e.g. next to an export at [`notes_path`].
+pub fn export_notes(file_path: &str, notes: &[Note]) -> Result<(), std::io::Error> {
+    write_export(file_path, &serde_json::to_vec_pretty(notes)?)
+}
+
+pub fn import_notes(file_path: &str) -> Result<Vec<Note>, std::io::Error> {
+    Ok(serde_json::from_slice(&read_export(file_path)?)?)
+}
+
+/// What a run was, written next to its export at [`metadata_path`] to tell
+/// captures apart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub run_name: Option<String>,
+    #[serde(default)]
+    pub notes: Vec<String>,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(with = "timestamp")]
+    pub started: DateTime<Utc>,
+    /// Version of network-test that made the export.
+    pub version: String,
+    /// How network-test was started, with `--record-invocation`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invocation: Option<Invocation>,
+}
+
+/// The command line, resolved settings and environment a run was started
+/// with, to reproduce a capture.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invocation {
+    /// Arguments after the program name, with secrets redacted.
+    pub args: Vec<String>,
+    /// Settings in effect after defaults and parsing, by name.
+    pub settings: BTreeMap<String, String>,
+    /// The variables network-test reads that were set.
+    pub env: BTreeMap<String, String>,
+}
+
+impl fmt::Display for RunMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(run_name) = &self.run_name {
+            write!(f, "{run_name}: ")?;
+        }
+        write!(
+            f,
+            "{} from {} (network-test {})",
+            self.hosts.join(", "),
+            format_timestamp(self.started),
+            self.version
+        )?;
+        if let Some(invocation) = &self.invocation {
+            write!(f, "\nnetwork-test {}", invocation.args.join(" "))?;
+        }
+        for note in &self.notes {
+            write!(f, "\n{note}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the metadata of the export at `file_path` is kept.
+pub fn metadata_path(file_path: &str) -> String {
+    format!("{file_path}.meta.json")
+}
+
+pub fn export_metadata(file_path: &str, metadata: &RunMetadata) -> Result<(), std::io::Error> {
+    write_export(file_path, &serde_json::to_vec_pretty(metadata)?)
+}
+
+pub fn import_metadata(file_path: &str) -> Result<RunMetadata, std::io::Error> {
+    Ok(serde_json::from_slice(&read_export(file_path)?)?)
+}
+
+/// Size in pixels of rendered chart images.
+pub const CHART_SIZE: (u32, u32) = (800, 600);
+
+/// Points drawn between two samples when a chart is smoothed.
+const SPLINE_STEPS: usize = 8;
+
+/// Draws `ping_data` to `ping_graph.png`, as a Catmull-Rom spline through the
+/// samples when `smooth` is set and with straight segments otherwise.
+pub fn draw_chart_png(
+    ping_data: Vec<f64>,
+    color: Rgb,
+    smooth: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Prepare the drawing area
+    let root = BitMapBackend::new("ping_graph.png", CHART_SIZE).into_drawing_area();
+    draw_chart(&root, &[(String::new(), ping_data, color)], smooth)
+}
+
+/// Draws `ping_data` to `file_path` as an SVG, in vector form but otherwise
+/// the same as [`draw_chart_png`].
+pub fn draw_chart_svg(
+    file_path: &str,
+    ping_data: Vec<f64>,
+    color: Rgb,
+    smooth: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let root = SVGBackend::new(file_path, CHART_SIZE).into_drawing_area();
+    draw_chart(&root, &[(String::new(), ping_data, color)], smooth)
+}
+
+/// Draws one line per `(name, values, color)` series to `file_path`, as an
+/// SVG when it ends in `.svg` and a PNG otherwise, smoothed like
+/// [`draw_chart_png`] when `smooth` is set.
+pub fn export_chart(
+    file_path: &str,
+    series: &[(String, Vec<f64>, Rgb)],
+    smooth: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if file_path.to_ascii_lowercase().ends_with(".svg") {
+        let root = SVGBackend::new(file_path, CHART_SIZE).into_drawing_area();
+        draw_chart(&root, series, smooth)
+    } else {
+        let root = BitMapBackend::new(file_path, CHART_SIZE).into_drawing_area();
+        draw_chart(&root, series, smooth)
+    }
+}
+
+/// The latencies of each host in `ping_data` as chart series, in the order
+/// hosts first appear, colored by `color` of that position.
+pub fn chart_series(
+    ping_data: &[PingResult],
+    color: impl Fn(usize) -> Rgb,
+) -> Vec<(String, Vec<f64>, Rgb)> {
+    let mut series: Vec<(String, Vec<f64>, Rgb)> = vec![];
+    for sample in ping_data {
+        match series.iter_mut().find(|(host, _, _)| *host == sample.host) {
+            Some((_, values, _)) => values.push(sample.average),
+            None => series.push((
+                sample.host.clone(),
+                vec![sample.average],
+                color(series.len()),
+            )),
+        }
+    }
+    series
+}
+
+/// Renders one line per `(name, values, color)` series into [`CHART_SIZE`] RGB
+/// pixels, the same way [`draw_chart_png`] draws its file.
+pub fn render_chart_rgb(
+    series: &[(String, Vec<f64>, Rgb)],
+    smooth: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (width, height) = CHART_SIZE;
+    let mut buffer = vec![0; width as usize * height as usize * 3];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, CHART_SIZE).into_drawing_area();
+        draw_chart(&root, series, smooth)?;
+    }
+    Ok(buffer)
+}
+
+fn draw_chart<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    series: &[(String, Vec<f64>, Rgb)],
+    smooth: bool,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+
+    let len = series
+        .iter()
+        .map(|(_, values, _)| values.len())
+        .max()
+        .unwrap_or(0);
+    let max = series
+        .iter()
+        .flat_map(|(_, values, _)| values.iter().copied())
+        .fold(0.0, f64::max);
+
+    // Prepare a chart context
+    let mut chart = ChartBuilder::on(root)
+        .caption("Network Ping Monitoring", ("Arial", 30).into_font())
+        .margin(5)
+        .x_label_area_size(30)
+        .y_label_area_size(50)
+        .build_cartesian_2d(0.0..len.max(100) as f64, 0.0..(max * 1.1).max(200.0))?;
+
+    chart.configure_mesh().draw()?;
+
+    for (name, values, Rgb(r, g, b)) in series {
+        let color = RGBColor(*r, *g, *b);
+        let mut points: Vec<(f64, f64)> = values
+            .iter()
+            .enumerate()
+            .map(|(x, &y)| (x as f64, y))
+            .collect();
+        if smooth {
+            points = catmull_rom(&points, SPLINE_STEPS);
+        }
+        let line = chart.draw_series(LineSeries::new(points, color.stroke_width(1)))?;
+        if !name.is_empty() {
+            line.label(name.as_str()).legend(move |(x, y)| {
+                PathElement::new([(x, y), (x + 20, y)], color.stroke_width(1))
+            });
+        }
+    }
+
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+
+    Ok(())
+}
+
+/// Samples a uniform Catmull-Rom spline through `points`, `steps` points per
+/// segment. The ends are held by repeating the first and last point, and the
+/// curve is kept at or above zero as latencies can't overshoot below it.
+fn catmull_rom(points: &[(f64, f64)], steps: usize) -> Vec<(f64, f64)> {
+    if points.len() < 3 || steps == 0 {
+        return points.to_vec();
+    }
+    let at = |index: isize| points[index.clamp(0, points.len() as isize - 1) as usize];
+
+    let mut curve = Vec::with_capacity((points.len() - 1) * steps + 1);
+    for segment in 0..points.len() as isize - 1 {
+        let (p0, p1, p2, p3) = (
+            at(segment - 1),
+            at(segment),
+            at(segment + 1),
+            at(segment + 2),
+        );
+        for step in 0..steps {
+            let t = step as f64 / steps as f64;
+            let blend = |a: f64, b: f64, c: f64, d: f64| {
+                0.5 * (2.0 * b
+                    + (c - a) * t
+                    + (2.0 * a - 5.0 * b + 4.0 * c - d) * t * t
+                    + (3.0 * b - a - 3.0 * c + d) * t * t * t)
+            };
+            let y = blend(p0.1, p1.1, p2.1, p3.1).max(0.0);
+            curve.push((blend(p0.0, p1.0, p2.0, p3.0), y));
+        }
+    }
+    curve.push(points[points.len() - 1]);
+    curve
+}
+
+/// `result` as a single JSON line, the way every NDJSON output writes it.
+fn ndjson_line(result: &PingResult) -> Result<Vec<u8>, std::io::Error> {
+    let mut line = serde_json::to_vec(result)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Writes `result` as a single JSON line and flushes so consumers see it
+/// immediately.
+pub fn write_ndjson(out: &mut impl Write, result: &PingResult) -> Result<(), std::io::Error> {
+    out.write_all(&ndjson_line(result)?)?;
+    out.flush()
+}
+
+/// Writes samples as JSON lines on its own thread, so that sending them
+/// never waits on a slow disk or pipe. Each line is flushed as it's written
+/// for consumers reading along, such as a pipe on stdout. The first write
+/// error stops the writer and is kept for [`SampleWriter::error`].
+pub struct SampleWriter {
+    tx: mpsc::Sender<PingResult>,
+    errors: mpsc::Receiver<io::Error>,
+}
+
+impl SampleWriter {
+    pub fn spawn(mut out: impl Write + Send + 'static) -> SampleWriter {
+        let (tx, rx) = mpsc::channel::<PingResult>();
+        let (error_tx, errors) = mpsc::channel();
+        thread::spawn(move || {
+            for sample in rx {
+                if let Err(err) = write_ndjson(&mut out, &sample) {
+                    let _ = error_tx.send(err);
+                    return;
+                }
+            }
+        });
+        SampleWriter { tx, errors }
+    }
+
+    /// Queues `sample` to be written. Samples sent after the writer stopped
+    /// are dropped.
+    pub fn write(&self, sample: &PingResult) {
+        let _ = self.tx.send(sample.clone());
+    }
+
+    /// The error that stopped the writer, once, if it has stopped.
+    pub fn error(&self) -> Option<io::Error> {
+        self.errors.try_recv().ok()
+    }
+}
+
+/// File formats samples can be written in as they arrive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Result<ExportFormat, String> {
+        match value {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            _ => Err(format!("Unknown format {value}, expected csv or ndjson")),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Prefix of the files written by [`RotatingWriter`].
+const ROTATING_PREFIX: &str = "network-test-";
+
+/// The file a [`RotatingWriter`] is appending to.
+struct RotatingFile {
+    date: NaiveDate,
+    part: usize,
+    /// Bytes in the file, including what was there before it was opened.
+    size: u64,
+    /// Whether no sample went into the file yet, so it's never rolled over
+    /// empty however small the limit.
+    fresh: bool,
+    file: BufWriter<File>,
+}
+
+/// Appends samples to one file per UTC day in a directory, named
+/// `network-test-YYYY-MM-DD.<ext>`, and deletes the oldest files once more
+/// than `keep` of them exist. With a size limit a day continues in numbered
+/// parts, `network-test-YYYY-MM-DD.1.<ext>` and so on, each counted against
+/// `keep`.
+pub struct RotatingWriter {
+    dir: PathBuf,
+    format: ExportFormat,
+    csv_options: CsvOptions,
+    keep: usize,
+    max_file_size: Option<u64>,
+    current: Option<RotatingFile>,
+}
+
+impl RotatingWriter {
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        format: ExportFormat,
+        csv_options: CsvOptions,
+        keep: usize,
+    ) -> Result<RotatingWriter, std::io::Error> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(RotatingWriter {
+            dir,
+            format,
+            csv_options,
+            keep,
+            max_file_size: None,
+            current: None,
+        })
+    }
+
+    /// Moves on to the day's next part once a sample would take the current
+    /// file past `bytes`.
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = Some(bytes);
+        self
+    }
+
+    /// The file samples received on `date` are written to.
+    pub fn path_for(&self, date: NaiveDate) -> PathBuf {
+        self.part_path(date, 0)
+    }
+
+    /// The `part`th file of `date`, the first being unnumbered.
+    fn part_path(&self, date: NaiveDate, part: usize) -> PathBuf {
+        let ext = self.format.extension();
+        self.dir.join(match part {
+            0 => format!("{ROTATING_PREFIX}{date}.{ext}"),
+            part => format!("{ROTATING_PREFIX}{date}.{part}.{ext}"),
+        })
+    }
+
+    /// Writes `sample` to the file for the day it was received on, switching
+    /// files when the day changes or the size limit is reached and reopening
+    /// one moved away. Every sample is flushed so a crash loses nothing
+    /// already written.
+    pub fn write(&mut self, sample: &PingResult) -> Result<(), std::io::Error> {
+        let record = match self.format {
+            ExportFormat::Csv => {
+                // Rolling stats need the whole export, so they're left out here
+                let mut row = csv_row(sample, None, &self.csv_options);
+                row.push_str(self.csv_options.line_ending());
+                row.into_bytes()
+            }
+            ExportFormat::Ndjson => ndjson_line(sample)?,
+        };
+
+        let date = sample.datetime_recv.date_naive();
+        match &self.current {
+            Some(current) if current.date == date => {
+                let full = self
+                    .max_file_size
+                    .is_some_and(|max| !current.fresh && current.size + record.len() as u64 > max);
+                if full {
+                    self.open(date, current.part + 1)?;
+                } else if !self.part_path(date, current.part).exists() {
+                    // Moved or deleted from under the writer, as by logrotate
+                    self.open(date, current.part)?;
+                }
+            }
+            _ => {
+                let part = self.last_part(date)?;
+                self.open(date, part)?;
+            }
+        }
+        let Some(current) = &mut self.current else {
+            unreachable!("a file was just opened");
+        };
+        let written = current
+            .file
+            .write_all(&record)
+            .and_then(|()| current.file.flush());
+        if written.is_err() {
+            // Reopened by the next sample, in case the file or directory was
+            // moved away or the disk has room again by then
+            self.current = None;
+            return written;
+        }
+        current.size += record.len() as u64;
+        current.fresh = false;
+        Ok(())
+    }
+
+    /// The latest part of `date` already on disk, so a restart carries on
+    /// appending where the last run stopped. Earlier parts may have been
+    /// pruned.
+    fn last_part(&self, date: NaiveDate) -> Result<usize, std::io::Error> {
+        if self.max_file_size.is_none() {
+            return Ok(0);
+        }
+        let (prefix, suffix) = (
+            format!("{ROTATING_PREFIX}{date}."),
+            format!(".{}", self.format.extension()),
+        );
+        Ok(fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                name.strip_prefix(&prefix)?
+                    .strip_suffix(&suffix)?
+                    .parse()
+                    .ok()
+            })
+            .max()
+            .unwrap_or(0))
+    }
+
+    fn open(&mut self, date: NaiveDate, part: usize) -> Result<(), std::io::Error> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.part_path(date, part);
+        let is_new = !path.exists();
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        let mut size = file.metadata()?.len();
+        let mut file = BufWriter::new(file);
+        if is_new && self.format == ExportFormat::Csv {
+            let header = format_record(&self.csv_options.header(), self.csv_options.delimiter);
+            file.write_all(header.as_bytes())?;
+            file.write_all(self.csv_options.line_ending().as_bytes())?;
+            size += (header.len() + self.csv_options.line_ending().len()) as u64;
+        }
+
+        self.current = Some(RotatingFile {
+            date,
+            part,
+            size,
+            fresh: is_new,
+            file,
+        });
+        self.prune()
+    }
+
+    /// Deletes the oldest rotated files beyond `keep`, ordered by the date
+    /// and part in their names.
+    fn prune(&self) -> Result<(), std::io::Error> {
+        let suffix = format!(".{}", self.format.extension());
+        let mut rotated: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| {
+                        name.starts_with(ROTATING_PREFIX) && name.ends_with(&suffix)
+                    })
+            })
+            .collect();
+        rotated.sort_by_cached_key(|path| {
+            let stem = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default()
+                .trim_start_matches(ROTATING_PREFIX)
+                .trim_end_matches(&suffix)
+                .to_string();
+            match stem.split_once('.') {
+                Some((date, part)) => (date.to_string(), part.parse().unwrap_or(0)),
+                None => (stem, 0),
+            }
+        });
+
+        let excess = rotated.len().saturating_sub(self.keep);
+        for path in &rotated[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::HOST_COLORS;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// Collects what reaches it in a buffer shared with the test.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn sample() -> PingResult {
+        PingResult::new("example.com".to_string(), 12.5, Utc::now())
+    }
+
+    /// A sample of `host` taken `seconds` into 2024.
+    fn sample_at(host: &str, average: f64, seconds: i64) -> PingResult {
+        let at = DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap();
+        PingResult::new(host.to_string(), average, at)
+    }
+
+    /// A path in the temp directory unique to this test run, with nothing
+    /// at it yet.
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{name}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    fn summarize(samples: &[PingResult]) -> Vec<(String, f64, DateTime<Utc>)> {
+        samples
+            .iter()
+            .map(|sample| (sample.host.clone(), sample.average, sample.datetime_recv))
+            .collect()
+    }
+
+    #[test]
+    fn baseline_export_loads_back() {
+        let path = temp_path("baseline.csv");
+        let samples = [
+            sample_at("a", 10.5, 0),
+            sample_at("b", 20.25, 1),
+            sample_at("a", 30.0, 2),
+        ];
+        export_to_csv(&path, &samples, &CsvOptions::default()).unwrap();
+        let baseline = import_samples(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(summarize(&baseline), summarize(&samples));
+    }
+
+    #[test]
+    fn rolling_columns_cover_each_host_separately() {
+        let samples = [
+            sample_at("a", 10.0, 0),
+            sample_at("b", 100.0, 0),
+            sample_at("a", 20.0, 1),
+            sample_at("a", 60.0, 2),
+        ];
+        let options = CsvOptions {
+            rolling_window: Some(2),
+            ..CsvOptions::default()
+        };
+        let csv = format_csv(&samples, &options);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "timestamp,host,average,roll_avg,roll_min,roll_max"
+        );
+        assert_eq!(lines[1], "2024-01-01T00:00:00.000Z,a,10,10,10,10");
+        assert_eq!(lines[2], "2024-01-01T00:00:00.000Z,b,100,100,100,100");
+        assert_eq!(lines[3], "2024-01-01T00:00:01.000Z,a,20,15,10,20");
+        assert_eq!(lines[4], "2024-01-01T00:00:02.000Z,a,60,40,20,60");
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_the_samples() {
+        let points = [(0.0, 10.0), (1.0, 30.0), (2.0, 5.0), (3.0, 20.0)];
+        let curve = catmull_rom(&points, SPLINE_STEPS);
+        assert_eq!(curve.len(), (points.len() - 1) * SPLINE_STEPS + 1);
+        for (i, point) in points.iter().enumerate() {
+            assert_eq!(curve[i * SPLINE_STEPS], *point);
+        }
+        assert!(curve.iter().all(|&(_, y)| y >= 0.0));
+    }
+
+    #[test]
+    fn smoothed_chart_is_a_valid_png() {
+        let path = std::env::temp_dir().join(format!("smoothed-{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        let series = [(
+            "example.com".to_string(),
+            vec![12.0, 48.0, 3.0, 25.0, 19.0],
+            HOST_COLORS[0],
+        )];
+        export_chart(path, &series, true).unwrap();
+        let png = fs::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn streams_one_json_line_per_sample() {
+        let mut out = vec![];
+        for (i, average) in [12.5, 30.0, 7.25].into_iter().enumerate() {
+            write_ndjson(&mut out, &sample_at("example.com", average, i as i64)).unwrap();
+        }
+        let lines: Vec<PingResult> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines
+                .iter()
+                .map(|sample| sample.average)
+                .collect::<Vec<_>>(),
+            [12.5, 30.0, 7.25]
+        );
+        assert_eq!(lines[2].datetime_recv, sample_at("", 0.0, 2).datetime_recv);
+    }
+
+    #[test]
+    fn sample_writer_writes_each_sample_as_it_comes() {
+        let out = Shared::default();
+        let writer = SampleWriter::spawn(out.clone());
+        writer.write(&sample());
+        let started = Instant::now();
+        while out.0.lock().unwrap().is_empty() && started.elapsed() < Duration::from_secs(1) {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(written.lines().count(), 1);
+        assert!(written.contains("\"example.com\""));
+        drop(writer);
+    }
+
+    #[test]
+    fn csv_uses_the_configured_delimiter() {
+        let samples = [sample_at("a", 10.5, 0), sample_at("b;c", 20.0, 1)];
+        let comma = format_csv(&samples, &CsvOptions::default());
+        assert_eq!(
+            comma,
+            "timestamp,host,average\n\
+             2024-01-01T00:00:00.000Z,a,10.5\n\
+             2024-01-01T00:00:01.000Z,b;c,20"
+        );
+        let semicolon = CsvOptions {
+            delimiter: ';',
+            ..CsvOptions::default()
+        };
+        let csv = format_csv(&samples, &semicolon);
+        assert_eq!(
+            csv,
+            "timestamp;host;average\n\
+             2024-01-01T00:00:00.000Z;a;10.5\n\
+             2024-01-01T00:00:01.000Z;\"b;c\";20"
+        );
+        let hosts: Vec<String> = parse_csv(&csv)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.host)
+            .collect();
+        assert_eq!(hosts, ["a", "b;c"]);
+    }
+
+    #[test]
+    fn csv_ends_lines_with_crlf_when_asked() {
+        let samples = [sample_at("a", 10.5, 0), sample_at("b", 20.0, 1)];
+        let options = CsvOptions {
+            crlf: true,
+            ..CsvOptions::default()
+        };
+        let csv = format_csv(&samples, &options);
+        assert_eq!(csv.matches("\r\n").count(), 2);
+        assert_eq!(csv.matches('\n').count(), 2);
+        assert_eq!(parse_csv(&csv).unwrap().len(), 2);
+    }
+
+    /// An empty directory in the temp directory unique to this test run.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn rotating_writer_opens_a_new_file_each_day() {
+        let dir = temp_dir("rotating-days");
+        let mut writer =
+            RotatingWriter::new(&dir, ExportFormat::Ndjson, CsvOptions::default(), 7).unwrap();
+        writer.write(&sample_at("a", 10.0, 86_398)).unwrap();
+        writer.write(&sample_at("a", 11.0, 86_399)).unwrap();
+        writer.write(&sample_at("a", 12.0, 86_400)).unwrap();
+
+        let first = writer.path_for(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let second = writer.path_for(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+        let lines = |path: &Path| fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!((lines(&first), lines(&second)), (2, 1));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn binary_export_round_trips() {
+        let mut samples = vec![
+            sample_at("a", 10.5, 0),
+            sample_at("example.com", 20.25, 1),
+            sample_at("a", 30.0, 2),
+        ];
+        samples[1].datetime_recv += chrono::Duration::microseconds(123_456);
+        let encoded = encode_binary(&samples).unwrap();
+        assert!(encoded.len() < format_csv(&samples, &CsvOptions::default()).len());
+        assert_eq!(
+            summarize(&decode_binary(&encoded).unwrap()),
+            summarize(&samples)
+        );
+
+        assert!(decode_binary(b"NTB2").is_err());
+        assert!(decode_binary(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn compressed_export_round_trips() {
+        let path = temp_path("compressed.csv.gz");
+        let samples: Vec<PingResult> = (0..100).map(|i| sample_at("a", i as f64, i)).collect();
+        export_samples(&path, &samples, &CsvOptions::default()).unwrap();
+        let compressed = fs::read(&path).unwrap();
+        assert!(compressed.starts_with(&[0x1f, 0x8b]));
+        assert!(compressed.len() < format_csv(&samples, &CsvOptions::default()).len());
+        let read = import_samples(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(summarize(&read), summarize(&samples));
+    }
+
+    #[test]
+    fn notes_round_trip() {
+        let path = temp_path("notes.csv");
+        let notes = vec![
+            Note {
+                at: sample_at("", 0.0, 90).datetime_recv,
+                text: "started VPN".to_string(),
+            },
+            Note {
+                at: sample_at("", 0.0, 300).datetime_recv,
+                text: "switched to \"5 GHz\", then back".to_string(),
+            },
+        ];
+        export_notes(&notes_path(&path), &notes).unwrap();
+        let read = import_notes(&notes_path(&path)).unwrap();
+        fs::remove_file(notes_path(&path)).unwrap();
+        assert_eq!(read, notes);
+    }
+
+    #[test]
+    fn csv_round_trips_milliseconds() {
+        let at = DateTime::from_timestamp(1_704_067_200, 123_456_789).unwrap();
+        let csv = format_csv(
+            &[PingResult::new("example.com".to_string(), 12.5, at)],
+            &CsvOptions::default(),
+        );
+        assert!(csv.contains("2024-01-01T00:00:00.123Z"));
+        let read = parse_csv(&csv).unwrap();
+        assert_eq!(
+            read[0].datetime_recv,
+            DateTime::from_timestamp(1_704_067_200, 123_000_000).unwrap()
+        );
+    }
+
+    #[test]
+    fn metadata_round_trips() {
+        let path = temp_path("metadata.csv");
+        let metadata = RunMetadata {
+            run_name: Some("office wifi".to_string()),
+            notes: vec!["2nd floor".to_string(), "after the router swap".to_string()],
+            hosts: vec!["a".to_string(), "b".to_string()],
+            started: sample_at("", 0.0, 60).datetime_recv,
+            version: "1.2.3".to_string(),
+            invocation: None,
+        };
+        export_metadata(&metadata_path(&path), &metadata).unwrap();
+        let read = import_metadata(&metadata_path(&path)).unwrap();
+        fs::remove_file(metadata_path(&path)).unwrap();
+        assert_eq!(read, metadata);
+        assert_eq!(
+            read.to_string(),
+            "office wifi: a, b from 2024-01-01T00:01:00.000Z (network-test 1.2.3)\n\
+             2nd floor\nafter the router swap"
+        );
+
+        // Only the start and version are required
+        let minimal: RunMetadata = serde_json::from_str(
+            r#"{"run_name":null,"started":"2024-01-01T00:00:00.000Z","version":"1.0.0"}"#,
+        )
+        .unwrap();
+        assert!(minimal.notes.is_empty() && minimal.hosts.is_empty());
+    }
+
+    /// The element names of `xml` in document order, checking that every
+    /// tag is closed in the right order. Declarations and comments are
+    /// skipped.
+    fn xml_elements(xml: &str) -> Result<Vec<String>, String> {
+        let mut open: Vec<String> = vec![];
+        let mut elements = vec![];
+        let mut rest = xml;
+        while let Some(start) = rest.find('<') {
+            let end = rest[start..].find('>').ok_or("unclosed tag")? + start;
+            let tag = &rest[start + 1..end];
+            rest = &rest[end + 1..];
+            if tag.starts_with('?') || tag.starts_with('!') {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                if open.pop().as_deref() != Some(name.trim()) {
+                    return Err(format!("unexpected </{name}>"));
+                }
+                continue;
+            }
+            let name = tag
+                .split(|c: char| c.is_whitespace() || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            if name.is_empty() || !tag.matches('"').count().is_multiple_of(2) {
+                return Err(format!("malformed <{tag}>"));
+            }
+            elements.push(name.clone());
+            if !tag.ends_with('/') {
+                open.push(name);
+            }
+        }
+        match open.pop() {
+            Some(name) => Err(format!("<{name}> never closed")),
+            None => Ok(elements),
+        }
+    }
+
+    #[test]
+    fn svg_charts_are_well_formed() {
+        let path = temp_path("chart.svg");
+        let series = [
+            (
+                "a.example".to_string(),
+                vec![12.0, 48.0, 3.0],
+                HOST_COLORS[0],
+            ),
+            ("b & c".to_string(), vec![5.0, 6.0, 7.0], HOST_COLORS[1]),
+        ];
+        export_chart(&path, &series, false).unwrap();
+        let svg = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let elements = xml_elements(&svg).unwrap();
+        assert_eq!(elements[0], "svg");
+        assert_eq!(elements.iter().filter(|name| *name == "svg").count(), 1);
+        assert!(svg.contains(r#"width="800" height="600""#));
+        assert!(svg.contains("a.example"));
+        assert!(svg.contains("b &amp; c"));
+        assert!(!svg.contains("b & c"));
+        assert!(xml_elements("<svg><g></svg></g>").is_err());
+    }
+
+    #[test]
+    fn sample_writer_writes_every_sample_under_load() {
+        let out = Shared::default();
+        let writer = SampleWriter::spawn(out.clone());
+        for i in 0..2500 {
+            for host in ["a", "b", "c", "d"] {
+                writer.write(&sample_at(host, i as f64, i));
+            }
+        }
+        assert!(writer.error().is_none());
+        drop(writer);
+
+        let started = Instant::now();
+        let lines = || String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        while lines().lines().count() < 10_000 && started.elapsed() < Duration::from_secs(10) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let written: Vec<PingResult> = lines()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(written.len(), 10_000);
+        for host in ["a", "b", "c", "d"] {
+            let averages: Vec<f64> = written
+                .iter()
+                .filter(|sample| sample.host == host)
+                .map(|sample| sample.average)
+                .collect();
+            assert_eq!(averages, (0..2500).map(|i| i as f64).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn sample_writer_reports_write_errors() {
+        struct Full;
+
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let writer = SampleWriter::spawn(Full);
+        writer.write(&sample());
+        let started = Instant::now();
+        let mut error = None;
+        while error.is_none() && started.elapsed() < Duration::from_secs(5) {
+            error = writer.error();
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(error.unwrap().kind(), io::ErrorKind::StorageFull);
+        // Later samples are dropped rather than panicking
+        writer.write(&sample());
+        assert!(writer.error().is_none());
+    }
+
+    #[test]
+    fn merges_overlapping_exports_in_order_once() {
+        let first_path = temp_path("merge-first.csv");
+        let second_path = temp_path(&format!("merge-second.{BINARY_EXTENSION}"));
+        // The second session overlaps the end of the first, then a gap
+        let first = [
+            sample_at("a", 10.0, 0),
+            sample_at("b", 20.0, 0),
+            sample_at("a", 11.0, 10),
+            sample_at("a", 12.0, 20),
+        ];
+        let second = [
+            sample_at("a", 99.0, 10),
+            sample_at("a", 98.0, 20),
+            sample_at("b", 21.0, 20),
+            sample_at("a", 13.0, 3600),
+        ];
+        export_samples(&first_path, &first, &CsvOptions::default()).unwrap();
+        export_samples(&second_path, &second, &CsvOptions::default()).unwrap();
+
+        let exports = vec![
+            import_samples(&first_path).unwrap(),
+            import_samples(&second_path).unwrap(),
+        ];
+        let merged = merge_samples(exports);
+        let at = |seconds| sample_at("", 0.0, seconds).datetime_recv;
+        assert_eq!(
+            summarize(&merged),
+            [
+                ("a".to_string(), 10.0, at(0)),
+                ("b".to_string(), 20.0, at(0)),
+                ("a".to_string(), 11.0, at(10)),
+                ("a".to_string(), 12.0, at(20)),
+                ("b".to_string(), 21.0, at(20)),
+                ("a".to_string(), 13.0, at(3600)),
+            ]
+        );
+        fs::remove_file(first_path).unwrap();
+        fs::remove_file(second_path).unwrap();
+    }
+
+    #[test]
+    fn rotating_writer_opens_a_new_part_past_the_size_limit() {
+        let dir = temp_dir("rotating-size");
+        let line = serde_json::to_vec(&sample_at("a", 10.0, 0)).unwrap().len() as u64 + 1;
+        let open = || {
+            RotatingWriter::new(&dir, ExportFormat::Ndjson, CsvOptions::default(), 7)
+                .unwrap()
+                .with_max_file_size(2 * line + 1)
+        };
+        let mut writer = open();
+        for i in 0..5 {
+            writer.write(&sample_at("a", 10.0, i)).unwrap();
+        }
+
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let part = |part| writer.part_path(day, part);
+        let lines = |path: PathBuf| fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!([lines(part(0)), lines(part(1)), lines(part(2))], [2, 2, 1]);
+        assert!(part(1).ends_with(format!("{ROTATING_PREFIX}2024-01-01.1.ndjson")));
+        assert!((0..3).all(|i| fs::metadata(part(i)).unwrap().len() <= 2 * line + 1));
+
+        // A restart carries on in the last part, and a new day starts over
+        let mut writer = open();
+        writer.write(&sample_at("a", 10.0, 5)).unwrap();
+        writer.write(&sample_at("a", 10.0, 86_400)).unwrap();
+        assert_eq!(lines(part(2)), 2);
+        assert!(!part(3).exists());
+        let next_day = writer.path_for(day.succ_opt().unwrap());
+        assert_eq!(lines(next_day), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotating_writer_reopens_a_file_moved_away() {
+        let dir = temp_dir("rotating-moved");
+        let mut writer =
+            RotatingWriter::new(&dir, ExportFormat::Ndjson, CsvOptions::default(), 7).unwrap();
+        writer.write(&sample_at("a", 10.0, 0)).unwrap();
+        let path = writer.path_for(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let moved = dir.with_extension("old");
+        let _ = fs::remove_dir_all(&moved);
+        fs::rename(&dir, &moved).unwrap();
+
+        writer.write(&sample_at("a", 11.0, 1)).unwrap();
+        let lines = |path: &Path| fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!(lines(&path), 1);
+        assert_eq!(lines(&moved.join(path.file_name().unwrap())), 1);
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&moved).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+//! HTTP(S) latency probing with a per-phase timing breakdown.
+
+use crate::ping::PingResult;
+use crate::proxy::Proxy;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time spent in each phase of an HTTP request, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HttpTimings {
+    pub dns: f64,
+    pub connect: f64,
+    /// Only set for `https` targets.
+    pub tls: Option<f64>,
+    /// From the request being sent to the first response byte.
+    pub ttfb: f64,
+    /// From the start of resolution to the end of the response.
+    pub total: f64,
+}
+
+/// The parts of an `http://` or `https://` URL needed to issue a request.
+#[derive(Debug, PartialEq)]
+pub struct HttpTarget {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+/// Parses `target` as a URL, defaulting to `https://` when no scheme is given.
+pub fn parse_url(target: &str) -> Result<HttpTarget, std::io::Error> {
+    let invalid =
+        |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("{msg}: {target}"));
+
+    let (tls, rest) = match target.split_once("://") {
+        Some(("https", rest)) => (true, rest),
+        Some(("http", rest)) => (false, rest),
+        Some(_) => return Err(invalid("Unsupported URL scheme")),
+        None => (true, target),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(':') => (
+            host,
+            port.parse().map_err(|_| invalid("Invalid port in URL"))?,
+        ),
+        _ => (authority, if tls { 443 } else { 80 }),
+    };
+    if host.is_empty() {
+        return Err(invalid("Missing host in URL"));
+    }
+
+    Ok(HttpTarget {
+        tls,
+        host: host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string(),
+        port,
+        path: path.to_string(),
+    })
+}
+
+fn tls_config() -> Arc<rustls::ClientConfig> {
+    static CONFIG: OnceLock<Arc<rustls::ClientConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let roots = rustls::RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+                rustls::crypto::ring::default_provider(),
+            ))
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// Times a `HEAD` request to `url`, recording DNS, connect, TLS and
+/// time-to-first-byte separately.
+///
+/// Through a `proxy` the proxy resolves the host, so DNS is left at zero and
+/// connect covers reaching the proxy and opening the tunnel.
+pub fn get_http(url: &str, proxy: Option<&Proxy>) -> Result<PingResult, std::io::Error> {
+    let target = parse_url(url)?;
+
+    let started = Instant::now();
+    let (mut tcp, resolved) = match proxy {
+        Some(proxy) => (
+            proxy.connect(&target.host, target.port, HTTP_TIMEOUT)?,
+            Duration::ZERO,
+        ),
+        None => {
+            let addr = (target.host.as_str(), target.port)
+                .to_socket_addrs()
+                .map_err(|err| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Could not resolve {}: {err}", target.host),
+                    )
+                })?
+                .next()
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("No address for {url}"))
+                })?;
+            let resolved = started.elapsed();
+            let tcp = TcpStream::connect_timeout(&addr, HTTP_TIMEOUT)?;
+            tcp.set_read_timeout(Some(HTTP_TIMEOUT))?;
+            tcp.set_write_timeout(Some(HTTP_TIMEOUT))?;
+            (tcp, resolved)
+        }
+    };
+    let connected = started.elapsed();
+
+    let request = format!(
+        "HEAD {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: network-test\r\nConnection: close\r\n\r\n",
+        target.path, target.host
+    );
+
+    let (handshaken, first_byte) = if target.tls {
+        let server_name = rustls::pki_types::ServerName::try_from(target.host.clone())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let mut conn =
+            rustls::ClientConnection::new(tls_config(), server_name).map_err(io::Error::other)?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut tcp)?;
+        }
+        let handshaken = started.elapsed();
+
+        let mut stream = rustls::Stream::new(&mut conn, &mut tcp);
+        let first_byte = send_request(&mut stream, &request, started)?;
+        (Some(handshaken), first_byte)
+    } else {
+        (None, send_request(&mut tcp, &request, started)?)
+    };
+    let finished = started.elapsed();
+
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let request_sent = handshaken.unwrap_or(connected);
+    let timings = HttpTimings {
+        dns: ms(resolved),
+        connect: ms(connected - resolved),
+        tls: handshaken.map(|h| ms(h - connected)),
+        ttfb: ms(first_byte - request_sent),
+        total: ms(finished),
+    };
+
+    let mut result = PingResult::new(url.to_string(), timings.total, Utc::now());
+    result.http_timings = Some(timings);
+    Ok(result)
+}
+
+/// POSTs `body` as JSON to `url` without a proxy, failing unless the
+/// response status is 2xx.
+pub fn post_json(url: &str, body: &str) -> Result<(), std::io::Error> {
+    let target = parse_url(url)?;
+    let addr = (target.host.as_str(), target.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No address for {url}")))?;
+    let mut tcp = TcpStream::connect_timeout(&addr, HTTP_TIMEOUT)?;
+    tcp.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    tcp.set_write_timeout(Some(HTTP_TIMEOUT))?;
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: network-test\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        target.path,
+        target.host,
+        body.len()
+    );
+    let mut response = vec![];
+    if target.tls {
+        let server_name = rustls::pki_types::ServerName::try_from(target.host.clone())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let mut conn =
+            rustls::ClientConnection::new(tls_config(), server_name).map_err(io::Error::other)?;
+        let mut stream = rustls::Stream::new(&mut conn, &mut tcp);
+        read_response(&mut stream, &request, &mut response)?;
+    } else {
+        read_response(&mut tcp, &request, &mut response)?;
+    }
+
+    let status_line = response
+        .split(|&byte| byte == b'\n')
+        .next()
+        .unwrap_or_default();
+    let status_line = String::from_utf8_lossy(status_line);
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "{url} answered {}",
+            status_line.trim_end()
+        ))),
+    }
+}
+
+/// Writes `request` and reads the whole response into `response`.
+fn read_response(
+    stream: &mut (impl Read + Write),
+    request: &str,
+    response: &mut Vec<u8>,
+) -> Result<(), std::io::Error> {
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+    match stream.read_to_end(response) {
+        // Plenty of servers close without a TLS close_notify
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(()),
+        other => other.map(|_| ()),
+    }
+}
+
+/// Writes `request` and drains the response, returning when the first byte
+/// arrived relative to `started`.
+fn send_request(
+    stream: &mut (impl Read + Write),
+    request: &str,
+    started: Instant,
+) -> Result<Duration, std::io::Error> {
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+
+    let mut buf = [0u8; 4096];
+    let mut first_byte = None;
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(_) => {
+                first_byte.get_or_insert_with(|| started.elapsed());
+            }
+            // Plenty of servers close without a TLS close_notify
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    first_byte.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Empty HTTP response"))
+}
@@ -0,0 +1,99 @@
+//! Throughput measurement by running `iperf3` against a server.
+
+use crate::ping::{PingResult, RawLog};
+use chrono::Utc;
+use serde_json::Value;
+use std::io;
+use std::process::Command;
+
+/// Length of each `iperf3` run, in seconds. The sampling interval is waited
+/// out on top of this.
+const IPERF_SECONDS: u32 = 2;
+
+/// Runs one `iperf3 -c` against `server` and returns the received bandwidth,
+/// along with the TCP round trips it saw.
+pub fn get_iperf(server: &str, raw_log: Option<&RawLog>) -> Result<PingResult, io::Error> {
+    let mut command = Command::new("iperf3");
+    command.args(["-c", server, "-J", "-t", &IPERF_SECONDS.to_string()]);
+    let output = command.output()?;
+    if let Some(raw_log) = raw_log {
+        raw_log.record(&format!("{command:?}"), &output.stdout, &output.stderr);
+    }
+    // iperf3 still prints its JSON, with an "error" field, when it fails
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_iperf(server, &stdout)
+}
+
+/// Reads the result of `iperf3 -J`. `average` is the mean TCP round trip when
+/// iperf3 reports one, which not every platform does, and 0 otherwise.
+pub fn parse_iperf(server: &str, json: &str) -> Result<PingResult, io::Error> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let report: Value = serde_json::from_str(json)
+        .map_err(|err| invalid(format!("Invalid iperf3 output for {server}: {err}")))?;
+    if let Some(error) = report["error"].as_str() {
+        return Err(io::Error::other(format!(
+            "iperf3 to {server} failed: {error}"
+        )));
+    }
+    let end = &report["end"];
+    let bits_per_second = end["sum_received"]["bits_per_second"]
+        .as_f64()
+        .or_else(|| end["sum"]["bits_per_second"].as_f64())
+        .ok_or_else(|| invalid(format!("No bandwidth in iperf3 output for {server}")))?;
+
+    // Round trips are reported per stream in microseconds
+    let sender = &end["streams"][0]["sender"];
+    let rtt = |field: &str| sender[field].as_f64().map(|micros| micros / 1000.0);
+
+    let mut result = PingResult::new(
+        server.to_string(),
+        rtt("mean_rtt").unwrap_or(0.0),
+        Utc::now(),
+    );
+    result.min = rtt("min_rtt");
+    result.avg = rtt("mean_rtt");
+    result.max = rtt("max_rtt");
+    result.bandwidth = Some(bits_per_second / 1_000_000.0);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trimmed from an `iperf3 -c ... -J` run on Linux.
+    const RESULT: &str = r#"{
+        "start": {"connected": [{"socket": 5, "remote_host": "10.0.0.2", "remote_port": 5201}]},
+        "intervals": [],
+        "end": {
+            "streams": [{
+                "sender": {"bytes": 235929600, "bits_per_second": 943718400.5,
+                           "max_rtt": 1520, "min_rtt": 380, "mean_rtt": 812},
+                "receiver": {"bytes": 235536384, "bits_per_second": 942145536.0}
+            }],
+            "sum_sent": {"bits_per_second": 943718400.5},
+            "sum_received": {"bits_per_second": 942145536.0}
+        }
+    }"#;
+
+    #[test]
+    fn parses_bandwidth_and_round_trips() {
+        let result = parse_iperf("10.0.0.2", RESULT).unwrap();
+        assert_eq!(result.bandwidth, Some(942.145536));
+        assert_eq!(result.average, 0.812);
+        assert_eq!(
+            (result.min, result.avg, result.max),
+            (Some(0.38), Some(0.812), Some(1.52))
+        );
+    }
+
+    #[test]
+    fn reports_iperf_errors() {
+        let failed = r#"{"start": {}, "intervals": [], "end": {},
+            "error": "unable to connect to server: Connection refused"}"#;
+        let err = parse_iperf("10.0.0.2", failed).unwrap_err();
+        assert!(err.to_string().contains("Connection refused"));
+        assert!(parse_iperf("10.0.0.2", "iperf3: error").is_err());
+    }
+}
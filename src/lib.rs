@@ -0,0 +1,53 @@
+//! Core of the `network-test` latency monitor.
+//!
+//! The binary is a thin wrapper around this crate, so everything it does can
+//! be reused from another program: take samples with a [`Pinger`], feed them
+//! through the [`stats`] helpers, and write them out with [`export`].
+//!
+//! ```no_run
+//! use network_test::{Mode, Pinger};
+//!
+//! let pinger = Pinger::new(Mode::Icmp);
+//! let sample = pinger.ping("8.8.8.8")?;
+//! println!("{} answered in {:.2} ms", sample.host, sample.average);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+pub mod alert;
+pub mod app;
+pub mod ascii;
+pub mod breach;
+pub mod capture;
+pub mod color;
+pub mod config;
+#[cfg(feature = "dns")]
+pub mod dns;
+pub mod doctor;
+pub mod events;
+pub mod export;
+pub mod http;
+pub mod iperf;
+pub mod metrics;
+pub mod netwatch;
+pub mod pidfile;
+pub mod ping;
+pub mod probe;
+pub mod proxy;
+pub mod replay;
+pub mod resolve;
+pub mod sampler;
+pub mod schedule;
+pub mod simulate;
+#[cfg(all(unix, feature = "socket"))]
+pub mod socket;
+pub mod stats;
+pub mod status;
+pub mod summary;
+pub mod suspend;
+pub mod timezone;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod webhook;
+
+pub use config::Config;
+pub use ping::{parse_ping, IpFamily, Metric, Mode, PingResult, Pinger};
@@ -1,228 +1,549 @@
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
-use eframe::egui::{self};
-use egui_plot::{AxisHints, Line, Plot, PlotPoints};
-use plotters::prelude::*;
-use plotters::{
-    chart::ChartBuilder,
-    style::{IntoFont, WHITE},
+use chrono::{DateTime, Local, Utc};
+use network_test::alert::{terminal_bell, Beeper, Snooze};
+use network_test::app::{draw_chart_realtime, PingApp};
+use network_test::ascii::to_ascii;
+use network_test::breach::watch_breaches;
+use network_test::config::{Config, ONCE_ITERATIONS};
+use network_test::doctor::{display_available, print_report, run_checks};
+use network_test::export::{
+    chart_series, export_chart, export_metadata, export_rollups_to_csv, export_samples,
+    import_metadata, import_notes, import_samples, merge_samples, metadata_path, notes_path,
+    write_ndjson, RotatingWriter,
 };
+use network_test::http::parse_url;
+use network_test::metrics::{self, spawn_metrics_writer, Metrics};
+use network_test::netwatch::spawn_network_watcher;
+use network_test::pidfile::{self, PidFile};
+use network_test::ping::is_unreachable;
+use network_test::probe::{self, serve as serve_probes, HealthWatch};
+use network_test::replay::Replay;
+use network_test::resolve::spawn_resolver;
+use network_test::sampler::{
+    sample_channel, spawn_sampler, spawn_streaming_sampler, supervise, Budget,
+};
+#[cfg(all(unix, feature = "socket"))]
+use network_test::socket::{serve, tee, LiveStats};
+use network_test::stats::{
+    assess_health, rollups, Health, RegimeDetector, StreamingSummary, Thresholds, Warmup,
+};
+use network_test::status::StatusLine;
+use network_test::summary::{summary_table, Summary};
+use network_test::suspend::spawn_suspend_watcher;
+use network_test::timezone;
+use network_test::webhook::watch_outages;
+use network_test::{IpFamily, Mode, PingResult};
+use std::env;
+use std::io;
+use std::path::Path;
 use std::sync::mpsc;
-use std::thread;
-use std::{env, fmt::Debug, fs::File, io::Write, path::Path, process::Command, str::from_utf8};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
-    let ping_ip = args[1].clone();
-    let iterations = match args[2].parse::<i32>() {
-        Ok(i) => i,
-        Err(err) => panic!("Error parsing: {err}"),
-    };
-    println!("Running ping to {ping_ip}");
-
-    // Create a channel for sending ping data
-    let (tx, rx) = mpsc::channel();
-
-    thread::spawn(move || {
-        let mut i: i32 = 0;
-        while i < iterations {
-            let ping_value = get_ping(&ping_ip).unwrap();
-            if tx.send(ping_value).is_err() {
-                break; // Exit if receiver is dropped
-            }
-            i += 1;
+    let config = match Config::from_args(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2);
         }
-    });
-
-    // export_to_csv(file_path, ping_average_values);
-
-    draw_chart_realtime(rx)
-}
+    };
+    if let Some(zone) = config.timezone {
+        timezone::set_zone(zone);
+    }
 
-fn get_ping(ping_ip: &str) -> Result<PingResult, std::io::Error> {
-    let output = Command::new("ping")
-        .arg(ping_ip)
-        .arg("-c 1")
-        .output()?;
+    if !config.ping_args.is_empty() {
+        eprintln!(
+            "Passing {:?} to ping unchecked, samples may fail to parse",
+            config.ping_args
+        );
+    }
 
-    let output_as_str = match from_utf8(&output.stdout) {
-        Ok(s) => s,
-        Err(err) => panic!("Invalid UTF-8 sequence: {err}"),
-    };
+    if config.doctor {
+        let failed = print_report(&run_checks(&config));
+        std::process::exit(if failed { 1 } else { 0 });
+    }
 
-    parse_ping(output_as_str)
-}
+    if let Some(file_path) = config
+        .export_path
+        .as_ref()
+        .filter(|_| !config.merge_paths.is_empty())
+    {
+        let exports = config
+            .merge_paths
+            .iter()
+            .map(|file_path| import_samples(file_path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let total: usize = exports.iter().map(Vec::len).sum();
+        let merged = merge_samples(exports);
+        export_samples(file_path, &merged, &config.csv_options)?;
+        eprintln!(
+            "Merged {} samples from {} files into {file_path}, {} duplicates dropped",
+            merged.len(),
+            config.merge_paths.len(),
+            total - merged.len()
+        );
+        return Ok(());
+    }
 
-fn parse_ping(ping_output: &str) -> Result<PingResult, std::io::Error> {
-    let stats_index = match ping_output.rfind("min/avg/max/stddev") {
-        Some(i) => i,
-        None => panic!("Sequence not found"),
+    let pid_file = match &config.pid_file {
+        Some(path) => match PidFile::create(path) {
+            Ok(pid_file) => Some(pid_file),
+            Err(err) => {
+                eprintln!("Could not write {}: {err}", path.display());
+                std::process::exit(1);
+            }
+        },
+        None => None,
     };
+    if let Some(pid_file) = &pid_file {
+        pid_file.remove_on_signal()?;
+    }
 
-    let stats_output = &ping_output[stats_index..];
-
-    let mut stats_splitted = stats_output.split(" = ");
+    if config.fail_fast {
+        // One up-front sample per host, so hosts that can't be resolved or
+        // reached show before anything starts. Other failures are left to
+        // the regular sampling.
+        for (host, _, pinger) in config.samplers() {
+            match pinger.ping(&host) {
+                Err(err) if is_unreachable(&err) => {
+                    eprintln!("Could not reach {}: {err}", pinger.label(&host));
+                    pidfile::exit(Health::Down as i32);
+                }
+                _ => {}
+            }
+        }
+    }
 
-    stats_splitted.next(); // Headers
+    // Create a channel for sending ping data
+    let (tx, rx) = sample_channel(config.channel_capacity, config.backpressure);
+    let dropped = tx.dropped();
 
-    let stats_values: Vec<&str> = match stats_splitted.next() {
-        Some(values) => values.split("/").collect(),
-        None => panic!("Stats values not found"),
+    // A replay shows recorded samples only
+    let samplers = match config.replay_path {
+        Some(_) => vec![],
+        None => config.samplers(),
     };
-
-    match stats_values[1].parse() {
-        Ok(average) => return Ok(PingResult::new(average, Utc::now())),
-        Err(err) => panic!("Error parsing average value: {err}"),
+    let (restart_tx, restarts) = mpsc::channel();
+    for (host, interval, pinger) in samplers {
+        // Status goes to stderr so stdout stays clean for --stream-stdout
+        eprintln!("Running ping to {} every {interval:?}", pinger.label(&host));
+        let (budget, tx) = (Budget::new(config.iterations), tx.clone());
+        if config.stream_ping {
+            supervise(host.clone(), restart_tx.clone(), move || {
+                spawn_streaming_sampler(
+                    host.clone(),
+                    interval,
+                    pinger.clone(),
+                    budget.clone(),
+                    tx.clone(),
+                )
+            });
+        } else {
+            let schedule = config.schedule.clone();
+            supervise(host.clone(), restart_tx.clone(), move || {
+                spawn_sampler(
+                    host.clone(),
+                    interval,
+                    schedule.clone(),
+                    pinger.clone(),
+                    budget.clone(),
+                    tx.clone(),
+                )
+            });
+        }
     }
-}
+    drop(tx);
+    drop(restart_tx);
 
-fn export_to_csv(file_path: &str, values: Vec<f64>) {
-    let path = Path::new(file_path);
-    if path.exists() {
-        panic!("File {file_path} already exists.")
+    // HTTP targets are looked up by the host part of their URL
+    let names = config
+        .hosts
+        .iter()
+        .map(|host| match config.mode {
+            Mode::Http => parse_url(&host.host).map(|target| target.host),
+            _ => Ok(host.host.clone()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let (resolve_tx, resolutions) = mpsc::channel();
+    if let Some(interval) = config.reresolve.filter(|_| config.replay_path.is_none()) {
+        for name in &names {
+            spawn_resolver(name.clone(), interval, resolve_tx.clone());
+        }
+    }
+    let (network_tx, network_changes) = mpsc::channel();
+    if config.watch_network && config.replay_path.is_none() {
+        spawn_network_watcher(names, network_tx, resolve_tx.clone());
+    }
+    drop(resolve_tx);
+    let (suspend_tx, suspensions) = mpsc::channel();
+    if config.replay_path.is_none() {
+        spawn_suspend_watcher(suspend_tx);
     }
 
-    let to_write = values
-        .iter()
-        .map(|v| v.to_string())
-        .collect::<Vec<String>>()
-        .join("\n");
+    #[cfg(all(unix, feature = "socket"))]
+    let rx = match &config.stats_socket {
+        Some(path) => {
+            let stats = Arc::new(Mutex::new(LiveStats::new()));
+            serve(path, Arc::clone(&stats))?;
+            eprintln!("Answering stats on {}", path.display());
+            tee(rx, stats)
+        }
+        None => rx,
+    };
 
-    let mut file = match File::create(file_path) {
-        Ok(f) => f,
-        Err(err) => panic!("Error creating file: {err}"),
+    let rx = match config
+        .probe_listen
+        .as_ref()
+        .filter(|_| config.replay_path.is_none())
+    {
+        Some(addr) => {
+            let pinger = config.pinger();
+            let hosts = config
+                .hosts
+                .iter()
+                .map(|host| (pinger.label(&host.host), config.interval_for(&host.host)))
+                .collect();
+            let health = Arc::new(Mutex::new(HealthWatch::new(
+                hosts,
+                config.thresholds,
+                config.healthz_loss,
+                Utc::now(),
+            )));
+            serve_probes(addr, pinger, Arc::clone(&health))?;
+            eprintln!("Answering POST /probe and GET /healthz on {addr}");
+            probe::tee(rx, health)
+        }
+        None => rx,
     };
 
-    match file.write_all(to_write.as_bytes()) {
-        Ok(it) => it,
-        Err(err) => panic!("Error creating file: {err}"),
+    // Muted from the GUI, so only ever active there
+    let snooze = Snooze::default();
+    let rx = match &config.webhook_url {
+        Some(url) if config.replay_path.is_none() => {
+            eprintln!(
+                "Calling {url} on outages over {:?}",
+                config.outage_alert_after
+            );
+            watch_outages(
+                rx,
+                url.clone(),
+                config.outage_alert_after,
+                config.schedule.clone(),
+                snooze.clone(),
+            )
+        }
+        _ => rx,
     };
-}
 
-fn draw_chart_png(ping_data: Vec<f64>) -> Result<(), Box<dyn std::error::Error>> {
-    // Prepare the drawing area
-    let root = BitMapBackend::new("ping_graph.png", (800, 600)).into_drawing_area();
-    root.fill(&WHITE)?;
+    let rx = match &config.on_breach {
+        Some(command) if config.replay_path.is_none() => {
+            let limits: Vec<(String, Option<f64>)> = config
+                .hosts
+                .iter()
+                .map(|host| (host.host.clone(), config.host_threshold(&host.host)))
+                .collect();
+            let (schedule, default) = (config.schedule.clone(), config.thresholds.latency);
+            eprintln!("Running {command} when a host goes over its threshold");
+            watch_breaches(
+                rx,
+                command.clone(),
+                config.schedule.clone(),
+                snooze.clone(),
+                move |host, time| {
+                    let host = IpFamily::host_of(host);
+                    limits
+                        .iter()
+                        .find(|(name, _)| name == host)
+                        .and_then(|(_, limit)| *limit)
+                        .or(schedule.threshold_at(time))
+                        .or(default)
+                },
+            )
+        }
+        _ => rx,
+    };
 
-    // Prepare a chart context
-    let mut chart = ChartBuilder::on(&root)
-        .caption("Network Ping Monitoring", ("Arial", 30).into_font())
-        .margin(5)
-        .x_label_area_size(30)
-        .y_label_area_size(50)
-        .build_cartesian_2d(0..100, 0.0..200.0)?;
+    let rx = match &config.metrics_file {
+        Some(path) => {
+            let metrics = Arc::new(Mutex::new(Metrics::new()));
+            let intervals: Vec<(String, _)> = config
+                .hosts
+                .iter()
+                .map(|host| (host.host.clone(), config.interval_for(&host.host)))
+                .collect();
+            let default = config.interval;
+            spawn_metrics_writer(path.clone(), Arc::clone(&metrics), move |host| {
+                let host = IpFamily::host_of(host);
+                intervals
+                    .iter()
+                    .find(|(name, _)| name == host)
+                    .map_or(default, |(_, interval)| *interval)
+            });
+            eprintln!("Writing metrics to {}", path.display());
+            metrics::tee(rx, metrics)
+        }
+        None => rx,
+    };
+
+    #[cfg(feature = "tui")]
+    if config.tui {
+        let started = Utc::now();
+        let ping_data = network_test::tui::run_tui(rx, &config)?;
+        return export(&ping_data, &config, started);
+    }
 
-    chart.configure_mesh().draw()?;
+    let headless = config.headless.unwrap_or_else(|| {
+        let available = display_available();
+        if !available {
+            eprintln!("No display found, running headless (pass --gui to force the GUI)");
+        }
+        !available
+    });
+    if headless {
+        return headless_main(rx, &config);
+    }
 
-    chart.draw_series(LineSeries::new(
-        ping_data.iter().enumerate().map(|(x, &y)| (x as i32, y)),
-        &RED,
-    ))?;
+    let baseline = match &config.baseline_path {
+        Some(file_path) => import_samples(file_path)?,
+        None => vec![],
+    };
+    let baseline_notes = match &config.baseline_path {
+        Some(file_path) if Path::new(&notes_path(file_path)).exists() => {
+            import_notes(&notes_path(file_path))?
+        }
+        _ => vec![],
+    };
 
-    chart
-        .configure_series_labels()
-        .background_style(&WHITE.mix(0.8))
-        .border_style(&BLACK)
-        .draw()?;
+    let history = config
+        .history_paths
+        .iter()
+        .map(|file_path| import_samples(file_path))
+        .collect::<Result<_, _>>()?;
+
+    let replay = match &config.replay_path {
+        Some(file_path) => {
+            let mut replay = Replay::new(import_samples(file_path)?);
+            if Path::new(&metadata_path(file_path)).exists() {
+                let metadata = import_metadata(&metadata_path(file_path))?;
+                eprintln!("Replaying {metadata}");
+                replay = replay.with_metadata(metadata);
+            }
+            Some(replay)
+        }
+        None => None,
+    };
 
-    root.present()?;
+    let forced_gui = config.headless == Some(false);
+    let mut app = PingApp::new(rx, config, baseline)
+        .with_baseline_notes(baseline_notes)
+        .with_history(history)
+        .with_dropped(dropped)
+        .with_resolutions(resolutions)
+        .with_network_changes(network_changes)
+        .with_suspensions(suspensions)
+        .with_restarts(restarts)
+        .with_snooze(snooze);
+    if let Some(replay) = replay {
+        app = app.with_replay(replay);
+    }
+    let mut app = Some(app);
+    if let Err(err) = draw_chart_realtime(&mut app) {
+        // The app is only still here if the backend failed before using it
+        match app {
+            Some(app) if !forced_gui => {
+                eprintln!("Could not start the GUI ({err}), falling back to headless mode");
+                let (ping_receiver, config) = app.into_parts();
+                return headless_main(ping_receiver, &config);
+            }
+            _ => return Err(err),
+        }
+    }
 
     Ok(())
 }
 
-fn draw_chart_realtime(
+fn headless_main(
     ping_receiver: mpsc::Receiver<PingResult>,
+    config: &Config,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Set up eframe options
-    let options = eframe::NativeOptions {
-        ..Default::default()
-    };
-
-    // Run the application
-    eframe::run_native(
-        "Network Ping Monitor",
-        options,
-        Box::new(|_cc| Ok(Box::new(PingApp::new(ping_receiver)))),
-    )?;
+    let ping_data = run_headless(ping_receiver, config)?;
+    if config.once {
+        let expected = config
+            .iterations
+            .unwrap_or(ONCE_ITERATIONS)
+            .saturating_sub(config.warmup as u32);
+        let health = config
+            .samplers()
+            .iter()
+            .map(|(host, _, pinger)| {
+                let thresholds = Thresholds {
+                    latency: config.host_threshold(host).or(config.thresholds.latency),
+                    ..config.thresholds
+                };
+                assess_health(&ping_data, &pinger.label(host), expected, &thresholds)
+            })
+            .max()
+            .unwrap_or(Health::Healthy);
+        eprintln!("Health: {health:?}");
+        pidfile::exit(health as i32);
+    }
 
     Ok(())
 }
 
-struct PingResult {
-    average: f64,
-    datetime_recv: DateTime<Utc>
-}
+/// Collects samples without a GUI until every sampler has finished, printing
+/// or logging each one and exporting at the end.
+fn run_headless(
+    ping_receiver: mpsc::Receiver<PingResult>,
+    config: &Config,
+) -> Result<Vec<PingResult>, Box<dyn std::error::Error>> {
+    let started = Utc::now();
+    let mut ping_data = vec![];
+    let mut beeper = Beeper::new();
+    let mut warmup = Warmup::new(config.warmup);
+    let mut regimes = RegimeDetector::new(config.regime_sensitivity);
+    let mut daemon_log = match &config.daemon_dir {
+        Some(dir) => {
+            eprintln!("Logging samples to {dir}");
+            let log = RotatingWriter::new(
+                dir,
+                config.daemon_format,
+                config.csv_options.clone(),
+                config.daemon_keep,
+            )?;
+            Some(match config.max_file_size {
+                Some(size) => log.with_max_file_size(size as u64),
+                None => log,
+            })
+        }
+        None => None,
+    };
+    let mut log_failing = false;
+    let mut summaries: Vec<(String, StreamingSummary)> = vec![];
+    let mut status = StatusLine::new();
+    let ascii = config.ascii_terminal();
+    let plain = |text: String| if ascii { to_ascii(&text) } else { text };
+    for ping_value in ping_receiver {
+        if config.beep
+            && !config.schedule.in_maintenance(Local::now().time())
+            && beeper.should_beep(
+                &ping_value,
+                &config.thresholds_for(&ping_value.host, Local::now().time()),
+                Instant::now(),
+            )
+        {
+            terminal_bell();
+        }
+        if let Some(log) = &mut daemon_log {
+            // A daemon keeps sampling through a full disk or a moved
+            // directory, saying so once per stretch of failures
+            match log.write(&ping_value) {
+                Ok(()) if log_failing => {
+                    log_failing = false;
+                    eprintln!("Logging samples again");
+                }
+                Ok(()) => {}
+                Err(err) if !log_failing => {
+                    log_failing = true;
+                    eprintln!("Error logging samples, retrying with the next one: {err}");
+                }
+                Err(_) => {}
+            }
+        }
+        if config.stream_stdout {
+            write_ndjson(&mut io::stdout().lock(), &ping_value)?;
+        } else if daemon_log.is_none() && !config.stats_only && !config.status_line {
+            println!(
+                "{}",
+                plain(format!("{}: {:.2} ms", ping_value.host, ping_value.average))
+            );
+        }
 
-impl PingResult {
-    fn new(average: f64, datetime_recv: DateTime<Utc>) -> PingResult {
-        PingResult { average, datetime_recv }
+        if warmup.is_warming_up(&ping_value) {
+            continue;
+        }
+        if let Some(change) = regimes.observe(&ping_value) {
+            eprintln!("{}", plain(change.event().to_string()));
+        }
+        if config.status_line {
+            status.record(&ping_value);
+            if !config.once {
+                println!("{}", status.render(|host| config.interval_for(host), ascii));
+            }
+        }
+        if config.stats_only || config.summary_template.is_some() {
+            let index = match summaries
+                .iter()
+                .position(|(host, _)| *host == ping_value.host)
+            {
+                Some(index) => index,
+                None => {
+                    summaries.push((
+                        ping_value.host.clone(),
+                        StreamingSummary::new().with_loss_cutoff(config.thresholds.loss_cutoff),
+                    ));
+                    summaries.len() - 1
+                }
+            };
+            summaries[index].1.record(&ping_value);
+        }
+        if config.export_path.is_some()
+            || config.rollup_path.is_some()
+            || config.chart_path.is_some()
+            || config.once
+        {
+            ping_data.push(ping_value);
+        }
     }
-}
 
-impl Debug for PingResult {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("PingResult")
-            .field("average", &self.average)
-            .finish()
+    if let Some(template) = &config.summary_template {
+        let expected = config
+            .iterations
+            .unwrap_or(ONCE_ITERATIONS)
+            .saturating_sub(config.warmup as u32);
+        for (host, summary) in &summaries {
+            let interval = config.interval_for(host);
+            if let Some(summary) = Summary::from_streaming(host, summary, expected, interval) {
+                println!("{}", plain(template.render(&summary)));
+            }
+        }
+    } else if config.stats_only {
+        print!(
+            "{}",
+            summary_table(&summaries, |host| config.interval_for(host), ascii)
+        );
+    } else if config.status_line && config.once {
+        println!("{}", status.render(|host| config.interval_for(host), ascii));
     }
-}
+    export(&ping_data, config, started)?;
 
-struct PingApp {
-    ping_receiver: mpsc::Receiver<PingResult>,
-    ping_data: Vec<PingResult>,
-    max_points: usize
+    Ok(ping_data)
 }
 
-impl PingApp {
-    fn new(ping_receiver: mpsc::Receiver<PingResult>) -> Self {
-        PingApp {
-            ping_receiver,
-            ping_data: vec![],
-            max_points: 1000
-        }
+/// Writes the raw and rollup exports requested on the command line, with the
+/// metadata of the run that `started`.
+fn export(
+    ping_data: &[PingResult],
+    config: &Config,
+    started: DateTime<Utc>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(forensics) = &config.forensics {
+        forensics.flush();
     }
-}
-
-impl eframe::App for PingApp {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        // Try to receive new ping data without blocking
-        while let Ok(ping_value) = self.ping_receiver.try_recv() {
-            self.ping_data.push(ping_value);
-        }
-
-        // Limit the number of points
-        if self.ping_data.len() > self.max_points {
-            self.ping_data.remove(0);
-        }
-
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let plot = Plot::new("ping_plot")
-                .view_aspect(2.0)
-                .x_axis_label("Time")
-                .y_axis_label("Ping (ms)");
-                // .custom_x_axes(vec![AxisHints::new_x().formatter(|x, _range| {
-                //     // Convert the x value (seconds since epoch) back to DateTime
-                //     let dt = Utc.timestamp_opt(x.value as i64, 0).unwrap();
-                //     // Format the time as desired
-                //     dt.format("%H:%M:%S").to_string()
-                // }).label_spacing(10.0..=20.0)]);
-
-            plot.show(ui, |plot_ui| {
-                // Convert ping data to plot points
-                let points = PlotPoints::from_ys_f64(&self.ping_data.iter().map(|pr| { pr.average} ).collect::<Vec<f64>>().clone());
-                // let points = PlotPoints::new(self.ping_data.iter()
-                // .map(|data| {
-                //     [
-                //         data.datetime_recv.timestamp() as f64,
-                //         data.average
-                //     ]
-                // }).collect());
-
-                // Add the line to the plot
-                plot_ui.line(Line::new(points));
-            });
+    if let Some(file_path) = &config.export_path {
+        export_samples(file_path, ping_data, &config.csv_options)?;
+        export_metadata(&metadata_path(file_path), &config.run_metadata(started))?;
+    }
+    if let Some(file_path) = &config.rollup_path {
+        let rollups = rollups(ping_data, config.rollup_bucket, |host| {
+            config.interval_for(host)
         });
-
-        // Request a repaint to ensure continuous updates
-        ctx.request_repaint();
+        export_rollups_to_csv(file_path, &rollups, &config.csv_options)?;
+    }
+    if let Some(file_path) = &config.chart_path {
+        let series = chart_series(ping_data, |index| config.host_color(index));
+        export_chart(file_path, &series, config.chart_smooth)?;
     }
+
+    Ok(())
 }
@@ -0,0 +1,229 @@
+//! `--metrics-file`: rewrites a Prometheus text file with the current gauges
+//! of every host, for node_exporter's textfile collector.
+
+use crate::ping::PingResult;
+use crate::sampler;
+use crate::stats::{estimated_loss, mean_jitter};
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the metrics file is rewritten.
+pub const METRICS_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Recent samples per host the loss and jitter gauges are taken over.
+pub const METRICS_SAMPLES: usize = 60;
+
+/// Escapes a label value as the text format requires.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The latest samples of each host, in the order hosts were first seen.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    hosts: Vec<(String, VecDeque<PingResult>)>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn record(&mut self, ping: &PingResult) {
+        let index = match self.hosts.iter().position(|(host, _)| *host == ping.host) {
+            Some(index) => index,
+            None => {
+                self.hosts.push((ping.host.clone(), VecDeque::new()));
+                self.hosts.len() - 1
+            }
+        };
+        let recent = &mut self.hosts[index].1;
+        if recent.len() == METRICS_SAMPLES {
+            recent.pop_front();
+        }
+        recent.push_back(ping.clone());
+    }
+
+    /// The gauges in Prometheus text format, with loss estimated from the
+    /// gaps between samples taken every `interval_for(host)`.
+    pub fn render(&self, interval_for: impl Fn(&str) -> Duration) -> String {
+        let gauges = [
+            (
+                "network_test_latency_ms",
+                "Latency of the latest sample in ms.",
+            ),
+            (
+                "network_test_loss_percent",
+                "Estimated loss over the recent samples in percent.",
+            ),
+            (
+                "network_test_jitter_ms",
+                "Mean difference between consecutive recent samples in ms.",
+            ),
+        ];
+        let values: Vec<(String, [Option<f64>; 3])> = self
+            .hosts
+            .iter()
+            .map(|(host, recent)| {
+                let latencies: Vec<f64> = recent.iter().map(|ping| ping.average).collect();
+                let times: Vec<DateTime<Utc>> =
+                    recent.iter().map(|ping| ping.datetime_recv).collect();
+                let values = [
+                    latencies.last().copied(),
+                    Some(estimated_loss(&times, interval_for(host))),
+                    mean_jitter(&latencies),
+                ];
+                (escape_label(host), values)
+            })
+            .collect();
+        let mut text = String::new();
+        for (index, (name, help)) in gauges.iter().enumerate() {
+            let _ = writeln!(text, "# HELP {name} {help}");
+            let _ = writeln!(text, "# TYPE {name} gauge");
+            for (host, values) in &values {
+                if let Some(value) = values[index] {
+                    let _ = writeln!(text, "{name}{{host=\"{host}\"}} {value}");
+                }
+            }
+        }
+        text
+    }
+}
+
+/// Replaces `path` with `contents` by writing a temporary file next to it
+/// and renaming that over it, so readers never see a partial file.
+pub fn write_atomically(path: &Path, contents: &str) -> Result<(), io::Error> {
+    let mut temporary = path.as_os_str().to_os_string();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+/// Passes every sample from `rx` on to the returned receiver, recording it in
+/// `metrics` on the way.
+pub fn tee(
+    rx: mpsc::Receiver<PingResult>,
+    metrics: Arc<Mutex<Metrics>>,
+) -> mpsc::Receiver<PingResult> {
+    sampler::tee(rx, move |ping_value| {
+        metrics
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .record(ping_value);
+    })
+}
+
+/// Rewrites `path` with `metrics` every [`METRICS_INTERVAL`] on its own
+/// thread, logging failures to stderr and trying again next time.
+pub fn spawn_metrics_writer(
+    path: PathBuf,
+    metrics: Arc<Mutex<Metrics>>,
+    interval_for: impl Fn(&str) -> Duration + Send + 'static,
+) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(METRICS_INTERVAL);
+        let text = metrics
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .render(&interval_for);
+        if let Err(err) = write_atomically(&path, &text) {
+            eprintln!("Error writing metrics to {}: {err}", path.display());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks `text` against the Prometheus text format as written here:
+    /// every series a gauge declared just before it, with one `host` label.
+    /// Returns the `(name, host, value)` of each series.
+    fn parse_metrics(text: &str) -> Result<Vec<(String, String, f64)>, String> {
+        let valid_name = |name: &str| {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+        };
+        let mut declared = None;
+        let mut series = vec![];
+        if !text.ends_with('\n') {
+            return Err("no newline at the end".to_string());
+        }
+        for line in text.lines() {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                let (name, _) = help.split_once(' ').ok_or(line)?;
+                if !valid_name(name) {
+                    return Err(line.to_string());
+                }
+            } else if let Some(kind) = line.strip_prefix("# TYPE ") {
+                match kind.split_once(' ') {
+                    Some((name, "gauge")) if valid_name(name) => declared = Some(name),
+                    _ => return Err(line.to_string()),
+                }
+            } else {
+                let (name, rest) = line.split_once("{host=\"").ok_or(line)?;
+                let (host, value) = rest.rsplit_once("\"} ").ok_or(line)?;
+                if declared != Some(name)
+                    || host.replace("\\\\", "").replace("\\\"", "").contains('"')
+                {
+                    return Err(line.to_string());
+                }
+                let value = value.parse().map_err(|_| line.to_string())?;
+                series.push((name.to_string(), host.to_string(), value));
+            }
+        }
+        Ok(series)
+    }
+
+    #[test]
+    fn writes_valid_metrics_text() {
+        let mut metrics = Metrics::new();
+        for (seconds, average) in [(0, 10.0), (1, 14.0), (3, 12.0)] {
+            let at = DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap();
+            metrics.record(&PingResult::new("a".to_string(), average, at));
+            metrics.record(&PingResult::new(
+                r#"b "quoted" \ host"#.to_string(),
+                5.0,
+                at,
+            ));
+        }
+        let text = metrics.render(|_| Duration::from_secs(1));
+
+        let path = std::env::temp_dir().join(format!("metrics-{}.prom", std::process::id()));
+        write_atomically(&path, &text).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(!path.with_extension("prom.tmp").exists());
+
+        let series = parse_metrics(&written).unwrap();
+        let value = |name: &str, host: &str| {
+            series
+                .iter()
+                .find(|(n, h, _)| n == name && h == host)
+                .map(|(_, _, value)| *value)
+        };
+        assert_eq!(series.len(), 6);
+        assert_eq!(value("network_test_latency_ms", "a"), Some(12.0));
+        assert_eq!(value("network_test_loss_percent", "a"), Some(25.0));
+        assert_eq!(value("network_test_jitter_ms", "a"), Some(3.0));
+        assert_eq!(
+            value("network_test_latency_ms", r#"b \"quoted\" \\ host"#),
+            Some(5.0)
+        );
+        assert!(parse_metrics("network_test_latency_ms{host=\"a\"} 1\n").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! Noticing the network changing under a run, like switching Wi-Fi or a VPN
+//! coming up, by polling the default routes.
+
+use crate::events::Event;
+use crate::resolve::{resolve, ResolutionChange, ResolveTracker};
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the routing table is read with `--watch-network`.
+pub const NETWORK_POLL: Duration = Duration::from_secs(2);
+
+/// A route covering the whole address space or half of it, which is how
+/// VPNs take over without replacing the default route.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DefaultRoute {
+    pub interface: String,
+    pub gateway: Ipv4Addr,
+}
+
+impl fmt::Display for DefaultRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.gateway.is_unspecified() {
+            write!(f, "{}", self.interface)
+        } else {
+            write!(f, "{} via {}", self.interface, self.gateway)
+        }
+    }
+}
+
+/// The default routes in a `/proc/net/route` table, sorted and without
+/// duplicates.
+pub fn parse_proc_route(contents: &str) -> Vec<DefaultRoute> {
+    // Addresses are hex in the kernel's byte order
+    let address = |hex: &str| {
+        u32::from_str_radix(hex, 16)
+            .ok()
+            .map(|raw| Ipv4Addr::from(raw.to_ne_bytes()))
+    };
+    let mut routes: Vec<DefaultRoute> = contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (interface, gateway, mask) = (fields.first()?, fields.get(2)?, fields.get(7)?);
+            if address(mask)?.to_bits().count_ones() > 1 {
+                return None;
+            }
+            Some(DefaultRoute {
+                interface: interface.to_string(),
+                gateway: address(gateway)?,
+            })
+        })
+        .collect();
+    routes.sort();
+    routes.dedup();
+    routes
+}
+
+/// The current default routes. Only Linux is supported.
+pub fn default_routes() -> Result<Vec<DefaultRoute>, io::Error> {
+    Ok(parse_proc_route(&fs::read_to_string("/proc/net/route")?))
+}
+
+/// The default routes changing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkChange {
+    pub at: DateTime<Utc>,
+    pub before: Vec<DefaultRoute>,
+    pub after: Vec<DefaultRoute>,
+}
+
+impl NetworkChange {
+    /// Short description, like `wlan0 via 192.168.1.1 -> tun0`.
+    pub fn summary(&self) -> String {
+        let list = |routes: &[DefaultRoute]| match routes {
+            [] => "no route".to_string(),
+            routes => routes
+                .iter()
+                .map(DefaultRoute::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        format!("{} -> {}", list(&self.before), list(&self.after))
+    }
+
+    pub fn event(&self) -> Event {
+        Event {
+            at: self.at,
+            host: "network".to_string(),
+            message: format!("changed from {}", self.summary()),
+        }
+    }
+}
+
+/// Remembers the last routes seen.
+#[derive(Debug, Default)]
+pub struct RouteTracker {
+    last: Option<Vec<DefaultRoute>>,
+}
+
+impl RouteTracker {
+    pub fn new() -> RouteTracker {
+        RouteTracker::default()
+    }
+
+    /// Records the routes seen at `at`, returning the change if they differ
+    /// from last time. The first snapshot is never a change.
+    pub fn observe(
+        &mut self,
+        routes: Vec<DefaultRoute>,
+        at: DateTime<Utc>,
+    ) -> Option<NetworkChange> {
+        let before = self.last.replace(routes.clone())?;
+        if before == routes {
+            return None;
+        }
+        Some(NetworkChange {
+            at,
+            before,
+            after: routes,
+        })
+    }
+}
+
+/// Polls the routes every [`NETWORK_POLL`] on its own thread. Each change is
+/// logged to stderr and sent on `tx`, and then `hosts` are looked up again
+/// with any new addresses sent on `resolve_tx`. Send errors are ignored, as
+/// headless runs only log.
+pub fn spawn_network_watcher(
+    hosts: Vec<String>,
+    tx: mpsc::Sender<NetworkChange>,
+    resolve_tx: mpsc::Sender<ResolutionChange>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut routes = RouteTracker::new();
+        let mut addresses = ResolveTracker::new();
+        let lookup = |addresses: &mut ResolveTracker| {
+            for host in &hosts {
+                match resolve(host) {
+                    Ok(addrs) => {
+                        if let Some(change) = addresses.observe(host, addrs, Utc::now()) {
+                            eprintln!("{}", change.event());
+                            let _ = resolve_tx.send(change);
+                        }
+                    }
+                    Err(err) => eprintln!("Could not resolve {host}: {err}"),
+                }
+            }
+        };
+        lookup(&mut addresses);
+        loop {
+            match default_routes() {
+                Ok(current) => {
+                    if let Some(change) = routes.observe(current, Utc::now()) {
+                        eprintln!("{}", change.event());
+                        let _ = tx.send(change);
+                        lookup(&mut addresses);
+                    }
+                }
+                Err(err) => eprintln!("Could not read the routing table: {err}"),
+            }
+            thread::sleep(NETWORK_POLL);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `/proc/net/route` line, with addresses in the kernel's byte order.
+    fn route(interface: &str, destination: &str, gateway: &str, mask: &str) -> String {
+        let hex = |addr: &str| {
+            let addr: Ipv4Addr = addr.parse().unwrap();
+            format!("{:08X}", u32::from_ne_bytes(addr.octets()))
+        };
+        format!(
+            "{interface}\t{}\t{}\t0003\t0\t0\t0\t{}\t0\t0\t0\n",
+            hex(destination),
+            hex(gateway),
+            hex(mask)
+        )
+    }
+
+    fn table(routes: &[String]) -> String {
+        let header =
+            "Iface\tDestination\tGateway\tFlags\tRefCnt\tUse\tMetric\tMask\tMTU\tWindow\tIRTT\n";
+        format!("{header}{}", routes.concat())
+    }
+
+    #[test]
+    fn detects_default_route_changes() {
+        let wifi = table(&[
+            route("wlan0", "0.0.0.0", "192.168.1.1", "0.0.0.0"),
+            route("wlan0", "192.168.1.0", "0.0.0.0", "255.255.255.0"),
+        ]);
+        // A VPN taking over with two half routes rather than a default one
+        let vpn = format!(
+            "{wifi}{}{}",
+            route("tun0", "0.0.0.0", "0.0.0.0", "128.0.0.0"),
+            route("tun0", "128.0.0.0", "0.0.0.0", "128.0.0.0")
+        );
+        let at = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+
+        let mut tracker = RouteTracker::new();
+        assert_eq!(tracker.observe(parse_proc_route(&wifi), at), None);
+        assert_eq!(tracker.observe(parse_proc_route(&wifi), at), None);
+        let change = tracker.observe(parse_proc_route(&vpn), at).unwrap();
+        assert_eq!(
+            change.summary(),
+            "wlan0 via 192.168.1.1 -> tun0, wlan0 via 192.168.1.1"
+        );
+        let change = tracker.observe(parse_proc_route(&table(&[])), at).unwrap();
+        assert_eq!(
+            change.event().message,
+            "changed from tun0, wlan0 via 192.168.1.1 -> no route"
+        );
+    }
+}
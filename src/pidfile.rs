@@ -0,0 +1,166 @@
+//! `--pid-file`: the process ID written at startup for service managers such
+//! as systemd and supervisord, and removed again on exit or when the process
+//! is told to stop.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often a stop signal is checked for.
+const SIGNAL_POLL: Duration = Duration::from_millis(100);
+
+/// Exit code after a stop signal, as shells report a process killed by
+/// SIGINT.
+const SIGNAL_EXIT: i32 = 130;
+
+/// The PID file this process wrote and hasn't removed yet.
+static CURRENT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Removes the PID file when dropped.
+#[derive(Debug)]
+pub struct PidFile(());
+
+impl PidFile {
+    /// Writes this process's ID to `path`. A file left there by a process
+    /// that is still running is an [`io::ErrorKind::AlreadyExists`] error;
+    /// one of a process that is gone is replaced.
+    pub fn create(path: impl Into<PathBuf>) -> Result<PidFile, io::Error> {
+        let path = path.into();
+        if let Some(pid) = running_pid(&path)? {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Already running as process {pid}, see {}", path.display()),
+            ));
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        writeln!(file, "{}", std::process::id())?;
+        *CURRENT.lock().unwrap_or_else(|err| err.into_inner()) = Some(path);
+        Ok(PidFile(()))
+    }
+
+    /// Removes the PID file and exits on SIGINT or SIGTERM, which would
+    /// otherwise end the process without cleaning up.
+    pub fn remove_on_signal(&self) -> Result<(), io::Error> {
+        let stop = Arc::new(AtomicBool::new(false));
+        for &signal in signal_hook::consts::TERM_SIGNALS {
+            signal_hook::flag::register(signal, Arc::clone(&stop))?;
+        }
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                thread::sleep(SIGNAL_POLL);
+            }
+            exit(SIGNAL_EXIT);
+        });
+        Ok(())
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        remove_current();
+    }
+}
+
+fn remove_current() {
+    let path = CURRENT.lock().unwrap_or_else(|err| err.into_inner()).take();
+    if let Some(path) = path {
+        if let Err(err) = fs::remove_file(&path) {
+            eprintln!("Error removing {}: {err}", path.display());
+        }
+    }
+}
+
+/// Removes the PID file, if one was written, and exits with `code`.
+/// [`std::process::exit`] skips destructors, so this takes its place once
+/// the PID file may exist.
+pub fn exit(code: i32) -> ! {
+    remove_current();
+    std::process::exit(code)
+}
+
+/// The ID in the PID file at `path` if that process is still running. A
+/// stale or unreadable file is removed.
+fn running_pid(path: &Path) -> Result<Option<u32>, io::Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    match contents.trim().parse() {
+        Ok(pid) if pid != std::process::id() && process_alive(pid) => Ok(Some(pid)),
+        _ => {
+            eprintln!("Replacing stale PID file {}", path.display());
+            fs::remove_file(path)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Whether a process with `pid` exists, as far as can be told without
+/// platform APIs.
+fn process_alive(pid: u32) -> bool {
+    if cfg!(windows) {
+        Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {pid}"), "/NH"])
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+    } else if Path::new("/proc/self").exists() {
+        // Unlike `kill -0`, this sees processes of other users
+        Path::new("/proc").join(pid.to_string()).exists()
+    } else {
+        Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One test, as a process holds a single PID file at a time
+    #[cfg(unix)]
+    #[test]
+    fn writes_and_removes_the_pid_file_unless_another_process_runs() {
+        let path = std::env::temp_dir().join(format!("pidfile-{}.pid", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let pid_file = PidFile::create(&path).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(written, format!("{}\n", std::process::id()));
+        drop(pid_file);
+        assert!(!path.exists());
+
+        // A file left by a process that is still running is kept
+        let mut running = Command::new("sleep").arg("10").spawn().unwrap();
+        fs::write(&path, format!("{}\n", running.id())).unwrap();
+        let err = PidFile::create(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", running.id())
+        );
+        running.kill().unwrap();
+        running.wait().unwrap();
+
+        // Once it's gone, or the file is unreadable, the file is stale
+        for stale in [format!("{}\n", running.id()), "not a pid\n".to_string()] {
+            fs::write(&path, stale).unwrap();
+            let pid_file = PidFile::create(&path).unwrap();
+            let written = fs::read_to_string(&path).unwrap();
+            assert_eq!(written, format!("{}\n", std::process::id()));
+            drop(pid_file);
+        }
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,1018 @@
+//! Taking a single latency sample and the [`PingResult`] it produces.
+
+use crate::capture::{Forensics, RawCaptures, RawRecorder};
+#[cfg(feature = "dns")]
+use crate::dns::{get_dns, DnsQuery};
+use crate::export::TIMESTAMP_FORMAT;
+use crate::http::{get_http, HttpTimings};
+use crate::iperf::get_iperf;
+use crate::proxy::Proxy;
+use crate::simulate::Simulator;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Lines, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{fmt, fmt::Debug, io, str::from_utf8};
+
+/// One latency measurement of a host.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PingResult {
+    pub host: String,
+    /// Round-trip time in milliseconds, as picked by the [`Metric`] in use.
+    /// Plots, stats and alerts all go by this.
+    pub average: f64,
+    /// The min/avg/max round trips `ping` reported, when it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(with = "crate::export::timestamp")]
+    pub datetime_recv: DateTime<Utc>,
+    /// When the probe was started, before spawning `ping`, so the time it
+    /// takes to start doesn't shift the sample. Unknown when streaming and
+    /// for imported samples.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::export::optional_timestamp"
+    )]
+    pub datetime_sent: Option<DateTime<Utc>>,
+    /// Phase breakdown, only present in HTTP mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_timings: Option<HttpTimings>,
+    /// `icmp_seq` of the reply, only known when streaming.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u32>,
+    /// TTL of the reply, when `ping` printed its reply line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u8>,
+    /// Received throughput in Mbit/s, only present in iperf mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<f64>,
+}
+
+impl PingResult {
+    pub fn new(host: String, average: f64, datetime_recv: DateTime<Utc>) -> PingResult {
+        PingResult {
+            host,
+            average,
+            min: None,
+            avg: None,
+            max: None,
+            datetime_recv,
+            datetime_sent: None,
+            http_timings: None,
+            seq: None,
+            ttl: None,
+            bandwidth: None,
+        }
+    }
+
+    /// When the probe was sent if known, and otherwise when its reply came
+    /// in. Samples are plotted at this time.
+    pub fn sent_at(&self) -> DateTime<Utc> {
+        self.datetime_sent.unwrap_or(self.datetime_recv)
+    }
+
+    /// Rough number of bytes this sample takes up in memory, including the
+    /// host name it owns.
+    pub fn estimated_size(&self) -> usize {
+        std::mem::size_of::<PingResult>() + self.host.capacity()
+    }
+}
+
+impl Debug for PingResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PingResult")
+            .field("host", &self.host)
+            .field("average", &self.average)
+            .field("min", &self.min)
+            .field("avg", &self.avg)
+            .field("max", &self.max)
+            .field("http_timings", &self.http_timings)
+            .field("seq", &self.seq)
+            .field("ttl", &self.ttl)
+            .field("bandwidth", &self.bandwidth)
+            .finish()
+    }
+}
+
+/// How latency to a host is measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// One `ping -c 1` per sample.
+    Icmp,
+    /// One HTTP(S) `HEAD` request per sample, timing each phase.
+    Http,
+    /// One short `iperf3` run per sample, measuring bandwidth as well as the
+    /// TCP round trip.
+    Iperf,
+    /// One DNS query per sample to a resolver, timing the lookup. Only with
+    /// the `dns` feature.
+    Dns,
+    /// Made-up samples, no network involved.
+    Simulate,
+}
+
+impl Mode {
+    pub fn parse(value: &str) -> Result<Mode, String> {
+        match value {
+            "icmp" => Ok(Mode::Icmp),
+            "http" => Ok(Mode::Http),
+            "iperf" => Ok(Mode::Iperf),
+            "dns" if cfg!(feature = "dns") => Ok(Mode::Dns),
+            "dns" => Err("--mode dns needs a build with the dns feature".to_string()),
+            "simulate" => Ok(Mode::Simulate),
+            _ => Err(format!(
+                "Unknown mode {value}, expected icmp, http, iperf, dns or simulate"
+            )),
+        }
+    }
+}
+
+/// Which of the round trips `ping` reports becomes a sample's
+/// [`average`](PingResult::average).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Min,
+    Avg,
+    /// The worst case.
+    Max,
+}
+
+impl Metric {
+    pub fn parse(value: &str) -> Result<Metric, String> {
+        match value {
+            "min" => Ok(Metric::Min),
+            "avg" => Ok(Metric::Avg),
+            "max" => Ok(Metric::Max),
+            _ => Err(format!("Unknown metric {value}, expected min, avg or max")),
+        }
+    }
+
+    /// Makes this metric the primary value of `result`, leaving it unchanged
+    /// when the metric wasn't reported.
+    pub fn apply(self, result: &mut PingResult) {
+        let value = match self {
+            Metric::Min => result.min,
+            Metric::Avg => result.avg,
+            Metric::Max => result.max,
+        };
+        if let Some(value) = value {
+            result.average = value;
+        }
+    }
+}
+
+/// The IP version to ping over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    pub const BOTH: [IpFamily; 2] = [IpFamily::V4, IpFamily::V6];
+
+    fn flag(self) -> &'static str {
+        match self {
+            IpFamily::V4 => "-4",
+            IpFamily::V6 => "-6",
+        }
+    }
+
+    /// The name samples of `host` taken over this family are reported under.
+    pub fn label(self, host: &str) -> String {
+        format!("{host} ({self})")
+    }
+
+    /// The host a [`label`](IpFamily::label) was made for, or `label` itself
+    /// if it isn't one.
+    pub fn host_of(label: &str) -> &str {
+        IpFamily::BOTH
+            .iter()
+            .find_map(|family| label.strip_suffix(&format!(" ({family})")))
+            .unwrap_or(label)
+    }
+}
+
+impl fmt::Display for IpFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpFamily::V4 => write!(f, "IPv4"),
+            IpFamily::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// A file every probe's raw output is appended to, parsed or not, for
+/// reproducing parse failures. Clones share the file.
+#[derive(Debug, Clone)]
+pub struct RawLog {
+    file: Arc<Mutex<File>>,
+}
+
+impl RawLog {
+    pub fn open(path: impl AsRef<Path>) -> Result<RawLog, io::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(RawLog {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Appends the output of one run of `command` under a timestamped header.
+    /// Failing to write is reported on stderr rather than failing the sample.
+    pub fn record(&self, command: &str, stdout: &[u8], stderr: &[u8]) {
+        let entry = raw_entry(command, stdout, stderr);
+
+        // A sampler that panicked mid-write shouldn't stop the others logging
+        let mut file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+        if let Err(err) = file.write_all(&entry) {
+            eprintln!("Error writing the raw log: {err}");
+        }
+    }
+}
+
+/// The output of one run of `command` under a timestamped header, as the
+/// raw log and forensics dumps hold it.
+pub fn raw_entry(command: &str, stdout: &[u8], stderr: &[u8]) -> Vec<u8> {
+    let mut entry = format!("=== {} {command}\n", Utc::now().format(TIMESTAMP_FORMAT)).into_bytes();
+    for (name, output) in [("stdout", stdout), ("stderr", stderr)] {
+        if !output.is_empty() {
+            entry.extend_from_slice(format!("[{name}]\n").as_bytes());
+            entry.extend_from_slice(output);
+            if !output.ends_with(b"\n") {
+                entry.push(b'\n');
+            }
+        }
+    }
+    entry
+}
+
+/// Most output a single `ping -c 1` may print to either stream before it
+/// is killed; a real one prints well under a kilobyte.
+pub const PING_OUTPUT_LIMIT: usize = 64 * 1024;
+
+/// How long a single `ping` may run when `--sample-timeout` isn't set.
+pub const DEFAULT_SAMPLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often a running `ping` is checked for having finished.
+const RUN_POLL: Duration = Duration::from_millis(5);
+
+/// The `ping` flag setting the ToS byte: `-Q` in Linux's iputils and `-z` in
+/// the BSD `ping` macOS ships. Windows' `ping -v` is ignored by current
+/// versions, so `--tos` isn't offered there.
+pub const TOS_FLAG: &str = if cfg!(target_os = "linux") {
+    "-Q"
+} else {
+    "-z"
+};
+
+/// Takes latency samples using a [`Mode`].
+#[derive(Debug, Clone)]
+pub struct Pinger {
+    mode: Mode,
+    ping_binary: PathBuf,
+    metric: Metric,
+    family: Option<IpFamily>,
+    raw_log: Option<RawLog>,
+    recorder: Option<RawRecorder>,
+    captures: Option<RawCaptures>,
+    forensics: Option<Forensics>,
+    simulator: Simulator,
+    proxy: Option<Proxy>,
+    tos: Option<u8>,
+    extra_args: Vec<String>,
+    #[cfg(feature = "dns")]
+    dns_query: Option<DnsQuery>,
+    timeout: Duration,
+}
+
+impl Pinger {
+    pub fn new(mode: Mode) -> Pinger {
+        Pinger {
+            mode,
+            ping_binary: PathBuf::from("ping"),
+            metric: Metric::Avg,
+            family: None,
+            raw_log: None,
+            recorder: None,
+            captures: None,
+            forensics: None,
+            simulator: Simulator::new(None),
+            proxy: None,
+            tos: None,
+            extra_args: vec![],
+            #[cfg(feature = "dns")]
+            dns_query: None,
+            timeout: DEFAULT_SAMPLE_TIMEOUT,
+        }
+    }
+
+    /// Sends HTTP probes through `proxy`.
+    pub fn with_proxy(mut self, proxy: Proxy) -> Pinger {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Appends the raw output of every `ping` and `iperf3` run to `raw_log`.
+    pub fn with_raw_log(mut self, raw_log: RawLog) -> Pinger {
+        self.raw_log = Some(raw_log);
+        self
+    }
+
+    /// Saves the output of every `ping` run to its own file in `recorder`.
+    /// ICMP mode only.
+    pub fn with_raw_recorder(mut self, recorder: RawRecorder) -> Pinger {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Parses the next capture of the host from `captures` instead of
+    /// running `ping`. ICMP mode only.
+    pub fn with_raw_captures(mut self, captures: RawCaptures) -> Pinger {
+        self.captures = Some(captures);
+        self
+    }
+
+    /// Pings over only `family`, passing `ping` its `-4` or `-6` flag, and
+    /// reports samples under the host's [`IpFamily::label`]. ICMP mode only.
+    pub fn with_family(mut self, family: IpFamily) -> Pinger {
+        self.family = Some(family);
+        self
+    }
+
+    /// The name samples of `host` are reported under.
+    pub fn label(&self, host: &str) -> String {
+        match self.family {
+            Some(family) => family.label(host),
+            None => host.to_string(),
+        }
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Pinger {
+        self.metric = metric;
+        self
+    }
+
+    /// Sets the ToS byte of echo requests, DSCP in its upper six bits. Only
+    /// takes effect in ICMP mode, and only with a `ping` taking [`TOS_FLAG`].
+    pub fn with_tos(mut self, tos: u8) -> Pinger {
+        self.tos = Some(tos);
+        self
+    }
+
+    /// Appends `args` to every `ping` command line, after the ones set here.
+    /// They aren't checked, so they may change the output beyond parsing.
+    pub fn with_extra_args(mut self, args: Vec<String>) -> Pinger {
+        self.extra_args = args;
+        self
+    }
+
+    /// Keeps the latest raw output of every host for `forensics` to save
+    /// around anomalies.
+    pub fn with_forensics(mut self, forensics: Forensics) -> Pinger {
+        self.forensics = Some(forensics);
+        self
+    }
+
+    /// Makes up the same samples on every run in simulate mode.
+    pub fn with_seed(mut self, seed: u64) -> Pinger {
+        self.simulator = Simulator::new(Some(seed));
+        self
+    }
+
+    /// Kills a `ping` still running after `timeout`, failing its sample.
+    pub fn with_timeout(mut self, timeout: Duration) -> Pinger {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Looks up `query` in DNS mode, where hosts are the resolvers asked.
+    #[cfg(feature = "dns")]
+    pub fn with_dns_query(mut self, query: DnsQuery) -> Pinger {
+        self.dns_query = Some(query);
+        self
+    }
+
+    /// Runs `path` instead of the `ping` found on `PATH` in ICMP mode.
+    pub fn with_ping_binary(mut self, path: impl Into<PathBuf>) -> Pinger {
+        self.ping_binary = path.into();
+        self
+    }
+
+    /// Takes a single sample of `host`, which is a URL in HTTP mode, an
+    /// `iperf3 -s` server in iperf mode and a resolver in DNS mode.
+    pub fn ping(&self, host: &str) -> Result<PingResult, std::io::Error> {
+        let sent = Utc::now();
+        let result = match self.mode {
+            Mode::Icmp => match &self.captures {
+                Some(captures) => parse_ping(host, &captures.next(host)?),
+                None => self.get_ping(host),
+            },
+            Mode::Http => get_http(host, self.proxy.as_ref()),
+            Mode::Iperf => get_iperf(host, self.raw_log.as_ref()),
+            #[cfg(feature = "dns")]
+            Mode::Dns => match &self.dns_query {
+                Some(query) => get_dns(host, query),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "DNS mode needs a query",
+                )),
+            },
+            #[cfg(not(feature = "dns"))]
+            Mode::Dns => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "DNS mode needs a build with the dns feature",
+            )),
+            Mode::Simulate => self.simulator.sample(host),
+        }
+        .map(|mut result| {
+            self.metric.apply(&mut result);
+            result
+        });
+        if let Some(forensics) = &self.forensics {
+            forensics.observe(host, &result);
+        }
+        let mut result = result?;
+        result.host = self.label(host);
+        result.datetime_sent = Some(sent);
+        Ok(result)
+    }
+
+    /// Starts one `ping` that keeps sending a request per `interval`, stopping
+    /// after `count` if given, and yields its replies as they're printed. Only
+    /// ICMP mode can stream.
+    pub fn stream(
+        &self,
+        host: &str,
+        interval: Duration,
+        count: Option<u32>,
+    ) -> Result<PingStream, std::io::Error> {
+        if self.mode != Mode::Icmp {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Only ICMP pings can be streamed",
+            ));
+        }
+
+        let mut command = Command::new(&self.ping_binary);
+        command
+            .arg(host)
+            .args(["-i", &interval.as_secs_f64().to_string()]);
+        if let Some(family) = self.family {
+            command.arg(family.flag());
+        }
+        if let Some(tos) = self.tos {
+            command.args([TOS_FLAG, &tos.to_string()]);
+        }
+        if let Some(count) = count {
+            command.args(["-c", &count.to_string()]);
+        }
+        command.args(&self.extra_args);
+        let mut child = command.stdout(Stdio::piped()).spawn()?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        Ok(PingStream {
+            host: self.label(host),
+            raw_log: self.raw_log.clone(),
+            child,
+            lines: BufReader::new(stdout).lines(),
+        })
+    }
+}
+
+/// Replies read from a running `ping`, see [`Pinger::stream`]. The process is
+/// killed when this is dropped.
+pub struct PingStream {
+    host: String,
+    raw_log: Option<RawLog>,
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+}
+
+impl Iterator for PingStream {
+    type Item = PingResult;
+
+    /// Waits for the next reply, ending when `ping` exits.
+    fn next(&mut self) -> Option<PingResult> {
+        loop {
+            let line = self.lines.next()?.ok()?;
+            if let Some(raw_log) = &self.raw_log {
+                raw_log.record(
+                    &format!("ping {} (streaming)", self.host),
+                    line.as_bytes(),
+                    &[],
+                );
+            }
+            if let Some(result) = parse_reply_line(&self.host, &line) {
+                return Some(result);
+            }
+        }
+    }
+}
+
+impl Drop for PingStream {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Pinger {
+    /// Runs one `ping -c 1` against `ping_ip`.
+    fn get_ping(&self, ping_ip: &str) -> Result<PingResult, std::io::Error> {
+        let mut command = Command::new(&self.ping_binary);
+        command.arg(ping_ip).args(["-c", "1"]);
+        if let Some(family) = self.family {
+            command.arg(family.flag());
+        }
+        if let Some(tos) = self.tos {
+            command.args([TOS_FLAG, &tos.to_string()]);
+        }
+        command.args(&self.extra_args);
+        let output = run_bounded(&mut command, self.timeout)?;
+        if let Some(raw_log) = &self.raw_log {
+            raw_log.record(&format!("{command:?}"), &output.stdout, &output.stderr);
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.record(ping_ip, &output.stdout);
+        }
+        if let Some(forensics) = &self.forensics {
+            let entry = raw_entry(&format!("{command:?}"), &output.stdout, &output.stderr);
+            forensics.capture(ping_ip, entry);
+        }
+
+        let output_as_str = from_utf8(&output.stdout)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        parse_ping(ping_ip, output_as_str).map_err(|err| {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            unreachable_error(ping_ip, &format!("{output_as_str}{stderr}")).unwrap_or(err)
+        })
+    }
+}
+
+/// What `ping` prints when it can't resolve a host or the host can't be
+/// reached, lowercased, with the error kind each means.
+const UNREACHABLE_MESSAGES: [(&str, io::ErrorKind); 7] = [
+    ("unknown host", io::ErrorKind::NotFound),
+    ("cannot resolve", io::ErrorKind::NotFound),
+    ("name or service not known", io::ErrorKind::NotFound),
+    (
+        "temporary failure in name resolution",
+        io::ErrorKind::NotFound,
+    ),
+    ("no address associated", io::ErrorKind::NotFound),
+    ("network is unreachable", io::ErrorKind::NetworkUnreachable),
+    ("host unreachable", io::ErrorKind::HostUnreachable),
+];
+
+/// The error for `ping` output saying `host` couldn't be resolved or
+/// reached, if it does.
+fn unreachable_error(host: &str, output: &str) -> Option<io::Error> {
+    let output = output.to_lowercase();
+    UNREACHABLE_MESSAGES
+        .iter()
+        .find(|(message, _)| output.contains(message))
+        .map(|(message, kind)| io::Error::new(*kind, format!("{host}: {message}")))
+}
+
+/// Whether `err` means the host couldn't be resolved or reached at all, as
+/// opposed to a sample that just didn't come back.
+pub fn is_unreachable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::NotFound
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::ConnectionRefused
+    )
+}
+
+/// Runs `command` to completion like [`Command::output`], but kills it once
+/// it has run for `timeout`, giving an [`io::ErrorKind::TimedOut`] error, or
+/// printed more than [`PING_OUTPUT_LIMIT`] to either stream, giving an
+/// [`io::ErrorKind::InvalidData`] one. A runaway `ping` can't hang its
+/// sampler either way.
+pub fn run_bounded(command: &mut Command, timeout: Duration) -> Result<Output, std::io::Error> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let overflowed = Arc::new(AtomicBool::new(false));
+    let read = |pipe: Box<dyn Read + Send>| {
+        let overflowed = Arc::clone(&overflowed);
+        thread::spawn(move || {
+            let mut output = vec![];
+            pipe.take(PING_OUTPUT_LIMIT as u64 + 1)
+                .read_to_end(&mut output)?;
+            if output.len() > PING_OUTPUT_LIMIT {
+                overflowed.store(true, Ordering::Relaxed);
+            }
+            Ok::<_, io::Error>(output)
+        })
+    };
+    let stdout = read(Box::new(child.stdout.take().expect("stdout is piped")));
+    let stderr = read(Box::new(child.stderr.take().expect("stderr is piped")));
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let error = if overflowed.load(Ordering::Relaxed) {
+            Some(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Output over {PING_OUTPUT_LIMIT} bytes"),
+            ))
+        } else if started.elapsed() >= timeout {
+            Some(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("No answer within {timeout:?}"),
+            ))
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(error);
+        }
+        thread::sleep(RUN_POLL);
+    };
+
+    let join = |reader: thread::JoinHandle<Result<Vec<u8>, io::Error>>| {
+        reader
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("Output reader panicked")))
+    };
+    let (stdout, stderr) = (join(stdout)?, join(stderr)?);
+    if overflowed.load(Ordering::Relaxed) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Output over {PING_OUTPUT_LIMIT} bytes"),
+        ));
+    }
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Extracts the round-trip time from a single reply line such as
+/// `64 bytes from 1.1.1.1: icmp_seq=0 ttl=57 time=12.3 ms`, along with its
+/// `icmp_seq` and TTL. Any other line, like the header or a timeout notice, gives
+/// `None`.
+pub fn parse_reply_line(host: &str, line: &str) -> Option<PingResult> {
+    let field = |name: &str| {
+        line.split_whitespace()
+            .find_map(|field| field.strip_prefix(name)?.strip_prefix('='))
+    };
+    let average = field("time")?.trim_end_matches("ms").parse().ok()?;
+
+    let mut result = PingResult::new(host.to_string(), average, Utc::now());
+    result.seq = field("icmp_seq").and_then(|seq| seq.parse().ok());
+    result.ttl = field("ttl").and_then(|ttl| ttl.parse().ok());
+    Some(result)
+}
+
+/// Extracts the min/avg/max round-trip times from the summary that `ping`
+/// prints.
+/// Output without a summary, e.g. when no reply came back, is an
+/// [`io::ErrorKind::InvalidData`] error. The TTL comes from the reply line.
+/// The `icmp_seq` is left out since every `ping -c 1` starts again from the
+/// same number.
+pub fn parse_ping(host: &str, ping_output: &str) -> Result<PingResult, std::io::Error> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let stats_index = ping_output
+        .rfind("min/avg/max/stddev")
+        .ok_or_else(|| invalid(format!("No round-trip summary for {host}")))?;
+
+    let stats_output = &ping_output[stats_index..];
+
+    let mut stats_splitted = stats_output.split(" = ");
+
+    stats_splitted.next(); // Headers
+
+    let stats_values: Vec<&str> = match stats_splitted.next() {
+        Some(values) => values.split("/").collect(),
+        None => return Err(invalid("Stats values not found".to_string())),
+    };
+
+    let mut result = match stats_values.get(1).map(|average| average.parse()) {
+        Some(Ok(average)) => PingResult::new(host.to_string(), average, Utc::now()),
+        Some(Err(err)) => return Err(invalid(format!("Error parsing average value: {err}"))),
+        None => return Err(invalid("Average value not found".to_string())),
+    };
+    result.min = stats_values.first().and_then(|min| min.parse().ok());
+    result.avg = Some(result.average);
+    result.max = stats_values.get(2).and_then(|max| max.parse().ok());
+    result.ttl = ping_output
+        .lines()
+        .find_map(|line| parse_reply_line(host, line))
+        .and_then(|reply| reply.ttl);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution_failures_are_not_found() {
+        let linux = unreachable_error(
+            "nosuch.invalid",
+            "ping: nosuch.invalid: Name or service not known\n",
+        );
+        let macos = unreachable_error(
+            "nosuch.invalid",
+            "ping: cannot resolve nosuch.invalid: Unknown host\n",
+        );
+        assert_eq!(linux.map(|err| err.kind()), Some(io::ErrorKind::NotFound));
+        assert_eq!(macos.map(|err| err.kind()), Some(io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn unreachable_hosts_and_networks_are_told_apart() {
+        let host = unreachable_error(
+            "10.0.0.9",
+            "From 10.0.0.1 icmp_seq=1 Destination Host Unreachable\n",
+        );
+        let network = unreachable_error("10.0.0.9", "ping: connect: Network is unreachable\n");
+        assert_eq!(
+            host.map(|err| err.kind()),
+            Some(io::ErrorKind::HostUnreachable)
+        );
+        assert_eq!(
+            network.map(|err| err.kind()),
+            Some(io::ErrorKind::NetworkUnreachable)
+        );
+    }
+
+    #[test]
+    fn timeouts_are_not_unreachable() {
+        assert!(unreachable_error("example.com", "Request timeout for icmp_seq 0\n").is_none());
+        let timeout = io::Error::new(io::ErrorKind::TimedOut, "No answer");
+        let no_summary = io::Error::new(io::ErrorKind::InvalidData, "No round-trip summary");
+        assert!(!is_unreachable(&timeout));
+        assert!(!is_unreachable(&no_summary));
+        assert!(is_unreachable(&io::Error::from(
+            io::ErrorKind::ConnectionRefused
+        )));
+    }
+
+    /// A stand-in `ping` in its own temp directory that saves its arguments
+    /// one per line to `args` next to it and answers in `average` ms.
+    #[cfg(unix)]
+    fn stub_ping(name: &str, average: f64) -> PathBuf {
+        stub_script(
+            name,
+            &format!(
+                "echo 'round-trip min/avg/max/stddev = {average}/{average}/{average}/0.000 ms'"
+            ),
+        )
+    }
+
+    /// A stand-in `ping` like [`stub_ping`] that runs `body` after saving
+    /// its arguments.
+    #[cfg(unix)]
+    fn stub_script(name: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ping");
+        let args = dir.join("args");
+        let script = format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$@\" > {}\n{body}\n",
+            args.display()
+        );
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    /// The arguments the [`stub_ping`] at `path` last ran with.
+    #[cfg(unix)]
+    fn stub_args(path: &Path) -> Vec<String> {
+        let args = std::fs::read_to_string(path.with_file_name("args")).unwrap();
+        args.lines().map(String::from).collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_the_given_ping_binary() {
+        let ping = stub_ping("ping-binary", 7.25);
+        let sample = Pinger::new(Mode::Icmp)
+            .with_ping_binary(&ping)
+            .ping("example.com")
+            .unwrap();
+        assert_eq!(sample.average, 7.25);
+        assert_eq!(stub_args(&ping), ["example.com", "-c", "1"]);
+        std::fs::remove_dir_all(ping.parent().unwrap()).unwrap();
+    }
+
+    const STREAM: &str = "\
+PING 1.1.1.1 (1.1.1.1): 56 data bytes
+64 bytes from 1.1.1.1: icmp_seq=0 ttl=57 time=12.3 ms
+Request timeout for icmp_seq 1
+64 bytes from 1.1.1.1: icmp_seq=2 ttl=58 time=9.87 ms
+
+--- 1.1.1.1 ping statistics ---
+3 packets transmitted, 2 packets received, 33.3% packet loss
+round-trip min/avg/max/stddev = 9.870/11.085/12.300/1.215 ms
+";
+
+    #[test]
+    fn parses_only_reply_lines_of_a_stream() {
+        let replies: Vec<PingResult> = STREAM
+            .lines()
+            .filter_map(|line| parse_reply_line("1.1.1.1", line))
+            .collect();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(
+            replies
+                .iter()
+                .map(|reply| reply.average)
+                .collect::<Vec<_>>(),
+            [12.3, 9.87]
+        );
+        assert_eq!(replies[1].seq, Some(2));
+        assert_eq!(replies[1].ttl, Some(58));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn streams_each_reply_as_it_is_printed() {
+        let ping = stub_script("ping-stream", &format!("cat <<'EOF'\n{STREAM}EOF"));
+        let replies: Vec<PingResult> = Pinger::new(Mode::Icmp)
+            .with_ping_binary(&ping)
+            .stream("1.1.1.1", Duration::from_millis(200), Some(3))
+            .unwrap()
+            .collect();
+        assert_eq!(replies.len(), 2);
+        assert_eq!(stub_args(&ping), ["1.1.1.1", "-i", "0.2", "-c", "3"]);
+        std::fs::remove_dir_all(ping.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reads_the_sequence_number_of_a_reply() {
+        let reply = parse_reply_line(
+            "1.1.1.1",
+            "64 bytes from 1.1.1.1: icmp_seq=41 ttl=57 time=12.3 ms",
+        )
+        .unwrap();
+        assert_eq!(reply.seq, Some(41));
+        let unnumbered = parse_reply_line("1.1.1.1", "64 bytes from 1.1.1.1: time=1.5 ms").unwrap();
+        assert_eq!(unnumbered.seq, None);
+        assert!(parse_reply_line("1.1.1.1", "Request timeout for icmp_seq 3").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn samples_go_by_the_selected_metric() {
+        let ping = stub_script(
+            "ping-metric",
+            "echo 'round-trip min/avg/max/stddev = 5.0/7.5/12.0/2.9 ms'",
+        );
+        let average = |metric: Metric| {
+            Pinger::new(Mode::Icmp)
+                .with_ping_binary(&ping)
+                .with_metric(metric)
+                .ping("example.com")
+                .unwrap()
+                .average
+        };
+        assert_eq!(
+            [Metric::Min, Metric::Avg, Metric::Max].map(average),
+            [5.0, 7.5, 12.0]
+        );
+        std::fs::remove_dir_all(ping.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn raw_log_holds_every_run_parsed_or_not() {
+        let ping = stub_script("ping-raw-log", "echo 'garbled reply'\necho 'oops' >&2");
+        let log = ping.with_file_name("raw.log");
+        let pinger = Pinger::new(Mode::Icmp)
+            .with_ping_binary(&ping)
+            .with_raw_log(RawLog::open(&log).unwrap());
+        assert!(pinger.ping("example.com").is_err());
+        assert!(pinger.ping("example.com").is_err());
+
+        let raw = std::fs::read_to_string(&log).unwrap();
+        assert_eq!(raw.matches("=== ").count(), 2);
+        assert!(raw.contains("[stdout]\ngarbled reply\n[stderr]\noops\n"));
+        std::fs::remove_dir_all(ping.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn passes_the_tos_byte_to_ping() {
+        let ping = stub_ping("ping-tos", 7.25);
+        let args = [
+            "example.com",
+            "--tos",
+            "0xb8",
+            "--ping-binary",
+            ping.to_str().unwrap(),
+        ];
+        let config = crate::config::Config::from_args(args.map(String::from)).unwrap();
+        config.pinger().ping("example.com").unwrap();
+        let expected_flag = if cfg!(target_os = "linux") {
+            "-Q"
+        } else {
+            "-z"
+        };
+        assert_eq!(
+            stub_args(&ping),
+            ["example.com", "-c", "1", expected_flag, "184"]
+        );
+        std::fs::remove_dir_all(ping.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn appends_extra_ping_args_in_order() {
+        let ping = stub_ping("ping-extra-args", 7.25);
+        let args = [
+            "example.com",
+            "--ping-args",
+            "-s 1200 -p 'ff 00' -W 2",
+            "--ping-binary",
+            ping.to_str().unwrap(),
+        ];
+        let config = crate::config::Config::from_args(args.map(String::from)).unwrap();
+        config.pinger().ping("example.com").unwrap();
+        assert_eq!(
+            stub_args(&ping),
+            [
+                "example.com",
+                "-c",
+                "1",
+                "-s",
+                "1200",
+                "-p",
+                "ff 00",
+                "-W",
+                "2"
+            ]
+        );
+        std::fs::remove_dir_all(ping.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn records_when_the_probe_was_sent() {
+        let ping = stub_script(
+            "ping-sent",
+            "sleep 0.2\necho 'round-trip min/avg/max/stddev = 1.0/1.0/1.0/0.000 ms'",
+        );
+        let before = Utc::now();
+        let sample = Pinger::new(Mode::Icmp)
+            .with_ping_binary(&ping)
+            .ping("example.com")
+            .unwrap();
+        let sent = sample.datetime_sent.unwrap();
+        assert!(before <= sent);
+        assert!(sample.datetime_recv - sent >= chrono::Duration::milliseconds(200));
+        assert_eq!(sample.sent_at(), sent);
+        std::fs::remove_dir_all(ping.parent().unwrap()).unwrap();
+
+        let unsent = PingResult::new("example.com".to_string(), 1.0, before);
+        assert_eq!(unsent.sent_at(), before);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kills_a_ping_that_runs_past_the_timeout() {
+        let ping = stub_script("ping-slow", "sleep 1\ntouch \"$(dirname \"$0\")/finished\"");
+        let started = Instant::now();
+        let err = Pinger::new(Mode::Icmp)
+            .with_ping_binary(&ping)
+            .with_timeout(Duration::from_millis(100))
+            .ping("example.com")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_millis(900));
+
+        // Killed, it never gets to finish
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!ping.with_file_name("finished").exists());
+        std::fs::remove_dir_all(ping.parent().unwrap()).unwrap();
+    }
+}
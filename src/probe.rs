@@ -0,0 +1,418 @@
+//! `--probe-listen`: a small HTTP server for integration tests and load
+//! balancers to drive and check the monitor.
+//!
+//! `POST /probe` answers with the aggregate of an extra burst of samples of a
+//! host, taken on top of the regular ones. The request body is JSON like
+//! `{"host": "example.com", "count": 5}`, with `count` defaulting to
+//! [`DEFAULT_PROBE_COUNT`]. The answer is a [`ProbeResult`].
+//!
+//! `GET /healthz` answers 200 while every host is up and 503 once one of
+//! them looks down, with each host's [`HostHealth`].
+
+use crate::ping::{PingResult, Pinger};
+use crate::sampler;
+use crate::stats::Thresholds;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Samples taken when a request doesn't say.
+pub const DEFAULT_PROBE_COUNT: usize = 5;
+
+/// Most samples one request may ask for, so a typo can't keep a host busy.
+pub const MAX_PROBE_COUNT: usize = 100;
+
+/// Largest request body read.
+const MAX_REQUEST_BODY: usize = 64 * 1024;
+
+/// How long a client gets to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Intervals of each host `/healthz` judges its loss over.
+pub const HEALTH_SAMPLES: usize = 20;
+
+fn default_count() -> usize {
+    DEFAULT_PROBE_COUNT
+}
+
+/// What `POST /probe` asks for.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProbeRequest {
+    pub host: String,
+    #[serde(default = "default_count")]
+    pub count: usize,
+}
+
+/// The aggregate of a burst, with latencies in ms over the samples that
+/// answered and loss as a fraction of `count`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub host: String,
+    pub count: usize,
+    pub received: usize,
+    pub min: Option<f64>,
+    pub avg: Option<f64>,
+    pub max: Option<f64>,
+    pub loss: f64,
+}
+
+/// Takes `request.count` samples of `request.host` one after the other.
+pub fn probe(pinger: &Pinger, request: &ProbeRequest) -> ProbeResult {
+    let latencies: Vec<f64> = (0..request.count)
+        .filter_map(|_| pinger.ping(&request.host).ok())
+        .map(|result| result.average)
+        .collect();
+    let received = latencies.len();
+    ProbeResult {
+        host: request.host.clone(),
+        count: request.count,
+        received,
+        min: latencies.iter().copied().reduce(f64::min),
+        avg: match received {
+            0 => None,
+            received => Some(latencies.iter().sum::<f64>() / received as f64),
+        },
+        max: latencies.iter().copied().reduce(f64::max),
+        loss: 1.0 - received as f64 / request.count as f64,
+    }
+}
+
+/// What `/healthz` reports of one host, with loss in percent.
+#[derive(Debug, Clone, Serialize)]
+pub struct HostHealth {
+    pub host: String,
+    pub up: bool,
+    pub loss: f64,
+    #[serde(with = "crate::export::optional_timestamp")]
+    pub last_at: Option<DateTime<Utc>>,
+}
+
+/// The latest samples of each host, judged by `/healthz`.
+#[derive(Debug)]
+pub struct HealthWatch {
+    started: DateTime<Utc>,
+    /// Loss in percent from which a host counts as down.
+    down_loss: f64,
+    thresholds: Thresholds,
+    hosts: Vec<(String, Duration, VecDeque<PingResult>)>,
+}
+
+impl HealthWatch {
+    /// Watches `hosts`, each labelled as its samples are and sampled every
+    /// given interval, from `started` on. Hosts that never answer count as
+    /// down once they should have.
+    pub fn new(
+        hosts: Vec<(String, Duration)>,
+        thresholds: Thresholds,
+        down_loss: f64,
+        started: DateTime<Utc>,
+    ) -> HealthWatch {
+        HealthWatch {
+            started,
+            down_loss,
+            thresholds,
+            hosts: hosts
+                .into_iter()
+                .map(|(host, interval)| (host, interval, VecDeque::new()))
+                .collect(),
+        }
+    }
+
+    pub fn record(&mut self, sample: &PingResult) {
+        if let Some((_, _, recent)) = self
+            .hosts
+            .iter_mut()
+            .find(|(host, _, _)| *host == sample.host)
+        {
+            if recent.len() == HEALTH_SAMPLES {
+                recent.pop_front();
+            }
+            recent.push_back(sample.clone());
+        }
+    }
+
+    /// Each host's health at `now`, its loss taken over the last
+    /// [`HEALTH_SAMPLES`] intervals, or the time since the start if that's
+    /// shorter. Samples over the loss cutoff count as lost.
+    pub fn health(&self, now: DateTime<Utc>) -> Vec<HostHealth> {
+        self.hosts
+            .iter()
+            .map(|(host, interval, recent)| {
+                let window = chrono::Duration::from_std(*interval * HEALTH_SAMPLES as u32)
+                    .unwrap_or_default();
+                let since = self.started.max(now - window);
+                let elapsed = (now - since).to_std().unwrap_or_default();
+                let expected = (elapsed.as_secs_f64() / interval.as_secs_f64()) as usize;
+                let received = recent
+                    .iter()
+                    .filter(|sample| sample.datetime_recv > since)
+                    .filter(|sample| !self.thresholds.counts_as_lost(sample.average))
+                    .count()
+                    .min(expected);
+                let loss = match expected {
+                    0 => 0.0,
+                    expected => 100.0 * (expected - received) as f64 / expected as f64,
+                };
+                HostHealth {
+                    host: host.clone(),
+                    up: loss < self.down_loss,
+                    loss,
+                    last_at: recent.back().map(|sample| sample.datetime_recv),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Passes every sample from `rx` on to the returned receiver, recording it in
+/// `health` on the way.
+pub fn tee(
+    rx: mpsc::Receiver<PingResult>,
+    health: Arc<Mutex<HealthWatch>>,
+) -> mpsc::Receiver<PingResult> {
+    sampler::tee(rx, move |ping_value| {
+        health
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .record(ping_value);
+    })
+}
+
+/// Listens on `addr`, such as `127.0.0.1:9000`, and answers each connection
+/// on its own thread, sampling with `pinger` and judging with `health`.
+pub fn serve(
+    addr: &str,
+    pinger: Pinger,
+    health: Arc<Mutex<HealthWatch>>,
+) -> Result<JoinHandle<()>, io::Error> {
+    let listener = TcpListener::bind(addr)?;
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let pinger = pinger.clone();
+                    let health = Arc::clone(&health);
+                    thread::spawn(move || {
+                        if let Err(err) = answer(stream, &pinger, &health) {
+                            eprintln!("Probe client failed: {err}");
+                        }
+                    });
+                }
+                Err(err) => eprintln!("Probe server failed to accept: {err}"),
+            }
+        }
+    }))
+}
+
+fn answer(
+    stream: TcpStream,
+    pinger: &Pinger,
+    health: &Mutex<HealthWatch>,
+) -> Result<(), io::Error> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("POST"), Some("/probe")) if content_length <= MAX_REQUEST_BODY => {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            match serde_json::from_slice::<ProbeRequest>(&body) {
+                Ok(request) if (1..=MAX_PROBE_COUNT).contains(&request.count) => {
+                    ("200 OK", serde_json::to_value(probe(pinger, &request))?)
+                }
+                Ok(_) => (
+                    "400 Bad Request",
+                    error(format!("count must be 1 to {MAX_PROBE_COUNT}")),
+                ),
+                Err(err) => ("400 Bad Request", error(format!("Invalid request: {err}"))),
+            }
+        }
+        (Some("POST"), Some("/probe")) => (
+            "413 Payload Too Large",
+            error(format!("Request body over {MAX_REQUEST_BODY} bytes")),
+        ),
+        (Some(_), Some("/probe")) => (
+            "405 Method Not Allowed",
+            error("Use POST /probe".to_string()),
+        ),
+        (Some("GET"), Some("/healthz")) => {
+            let hosts = health
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .health(Utc::now());
+            let status = if hosts.iter().all(|host| host.up) {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            (status, serde_json::json!({ "hosts": hosts }))
+        }
+        (Some(_), Some("/healthz")) => (
+            "405 Method Not Allowed",
+            error("Use GET /healthz".to_string()),
+        ),
+        _ => (
+            "404 Not Found",
+            error("Only POST /probe and GET /healthz are served".to_string()),
+        ),
+    };
+
+    let body = body.to_string();
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn error(message: String) -> serde_json::Value {
+    serde_json::json!({ "error": message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ping::Mode;
+
+    fn thresholds() -> Thresholds {
+        Thresholds {
+            latency: None,
+            loss: 5.0,
+            loss_cutoff: None,
+        }
+    }
+
+    /// Serves on a free loopback port, returning its address.
+    fn serve_locally(health: Arc<Mutex<HealthWatch>>) -> String {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let pinger = Pinger::new(Mode::Simulate).with_seed(5);
+        serve(&addr, pinger, health).unwrap();
+        addr
+    }
+
+    /// The status code and JSON body of the answer to `request`.
+    fn request(addr: &str, request: &str) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).unwrap();
+        let (head, body) = answer.split_once("\r\n\r\n").unwrap();
+        let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn post_probe(addr: &str, body: &str) -> (u16, serde_json::Value) {
+        request(
+            addr,
+            &format!(
+                "POST /probe HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            ),
+        )
+    }
+
+    #[test]
+    fn answers_probes_over_loopback() {
+        let hosts = vec![("example.com".to_string(), Duration::from_secs(1))];
+        let health = HealthWatch::new(hosts, thresholds(), 50.0, Utc::now());
+        let addr = serve_locally(Arc::new(Mutex::new(health)));
+
+        let (status, result) = post_probe(&addr, r#"{"host": "example.com", "count": 20}"#);
+        assert_eq!(status, 200);
+        assert_eq!(result["host"], "example.com");
+        assert_eq!(result["count"], 20);
+        let received = result["received"].as_u64().unwrap();
+        // Simulated dropouts are rare but count as loss
+        assert!(received > 0 && received <= 20);
+        let loss = result["loss"].as_f64().unwrap();
+        assert!((loss - (1.0 - received as f64 / 20.0)).abs() < 1e-9);
+        let [min, avg, max] = ["min", "avg", "max"].map(|key| result[key].as_f64().unwrap());
+        assert!(0.0 < min && min <= avg && avg <= max);
+
+        let (status, result) = post_probe(&addr, r#"{"host": "example.com"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(result["count"], DEFAULT_PROBE_COUNT);
+
+        assert_eq!(
+            post_probe(&addr, r#"{"host": "example.com", "count": 0}"#).0,
+            400
+        );
+        assert_eq!(post_probe(&addr, "not json").0, 400);
+        let get = "GET /probe HTTP/1.1\r\n\r\n";
+        assert_eq!(request(&addr, get).0, 405);
+
+        let (status, health) = request(&addr, "GET /healthz HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert_eq!(health["hosts"][0]["host"], "example.com");
+        assert_eq!(request(&addr, "GET / HTTP/1.1\r\n\r\n").0, 404);
+    }
+
+    #[test]
+    fn healthz_flips_with_loss() {
+        // Sampled every second for the last minute
+        let started = Utc::now() - chrono::Duration::seconds(60);
+        let watch = || {
+            let hosts = vec![("example.com".to_string(), Duration::from_secs(1))];
+            HealthWatch::new(hosts, thresholds(), 50.0, started)
+        };
+        let health = Arc::new(Mutex::new(watch()));
+        let addr = serve_locally(Arc::clone(&health));
+        let healthz = || request(&addr, "GET /healthz HTTP/1.1\r\n\r\n");
+        // Only the samples of the last `HEALTH_SAMPLES` seconds that `kept`
+        // lets answer
+        let answered = |kept: fn(usize) -> bool| {
+            let now = Utc::now();
+            let mut health = health.lock().unwrap();
+            *health = watch();
+            for i in (0..HEALTH_SAMPLES).rev().filter(|&i| kept(i)) {
+                let at = now - chrono::Duration::milliseconds(500 + 1000 * i as i64);
+                health.record(&PingResult::new("example.com".to_string(), 10.0, at));
+            }
+        };
+
+        let (status, body) = healthz();
+        assert_eq!(status, 503);
+        assert_eq!(body["hosts"][0]["up"], false);
+        assert_eq!(body["hosts"][0]["loss"], 100.0);
+
+        answered(|_| true);
+        let (status, body) = healthz();
+        assert_eq!(status, 200);
+        assert_eq!(body["hosts"][0]["up"], true);
+        assert_eq!(body["hosts"][0]["loss"], 0.0);
+
+        // 12 of 20 lost
+        answered(|i| i % 5 < 2);
+        let (status, body) = healthz();
+        assert_eq!(status, 503);
+        assert_eq!(body["hosts"][0]["loss"], 60.0);
+
+        answered(|i| i != 0);
+        assert_eq!(healthz().0, 200);
+    }
+}
@@ -0,0 +1,319 @@
+//! Tunnelling probe connections through a SOCKS5 or HTTP `CONNECT` proxy,
+//! for `--proxy`.
+//!
+//! Errors say whether the proxy itself failed or it couldn't reach the
+//! target, so a broken proxy isn't taken for a host being down.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProxyKind {
+    Socks5,
+    Http,
+}
+
+/// A proxy given as `socks5://host:port` or `http://host:port`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Proxy {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Proxy {
+    pub fn parse(url: &str) -> Result<Proxy, String> {
+        let (kind, authority) = match url.split_once("://") {
+            Some(("socks5" | "socks5h", rest)) => (ProxyKind::Socks5, rest),
+            Some(("http", rest)) => (ProxyKind::Http, rest),
+            _ => {
+                return Err(format!(
+                    "Invalid proxy {url}, expected socks5://host:port or http://host:port"
+                ))
+            }
+        };
+        let authority = authority.trim_end_matches('/');
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !host.ends_with(':') => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("Invalid port in proxy {url}"))?,
+            ),
+            _ => (
+                authority,
+                match kind {
+                    ProxyKind::Socks5 => 1080,
+                    ProxyKind::Http => 8080,
+                },
+            ),
+        };
+        if host.is_empty() {
+            return Err(format!("Missing host in proxy {url}"));
+        }
+
+        Ok(Proxy {
+            kind,
+            host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port,
+        })
+    }
+
+    /// Opens a connection to `host:port` through the proxy. The proxy looks
+    /// `host` up itself.
+    pub fn connect(
+        &self,
+        host: &str,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<TcpStream, io::Error> {
+        let proxy_failed =
+            |err: io::Error| io::Error::new(err.kind(), format!("Proxy {self} failed: {err}"));
+
+        let addr = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(proxy_failed)?
+            .next()
+            .ok_or_else(|| proxy_failed(io::ErrorKind::NotFound.into()))?;
+        let mut tcp = TcpStream::connect_timeout(&addr, timeout).map_err(proxy_failed)?;
+        tcp.set_read_timeout(Some(timeout))?;
+        tcp.set_write_timeout(Some(timeout))?;
+
+        match self.kind {
+            ProxyKind::Socks5 => self.socks5_handshake(&mut tcp, host, port)?,
+            ProxyKind::Http => self.http_connect(&mut tcp, host, port)?,
+        }
+        Ok(tcp)
+    }
+
+    fn socks5_handshake(
+        &self,
+        tcp: &mut TcpStream,
+        host: &str,
+        port: u16,
+    ) -> Result<(), io::Error> {
+        let protocol = |msg: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Proxy {self} failed: {msg}"),
+            )
+        };
+
+        // No authentication
+        tcp.write_all(&[5, 1, 0])?;
+        let mut choice = [0; 2];
+        tcp.read_exact(&mut choice)?;
+        if choice != [5, 0] {
+            return Err(protocol("it requires authentication".to_string()));
+        }
+
+        let name = host.as_bytes();
+        let len = u8::try_from(name.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Host name too long"))?;
+        let mut request = vec![5, 1, 0, 3, len];
+        request.extend_from_slice(name);
+        request.extend_from_slice(&port.to_be_bytes());
+        tcp.write_all(&request)?;
+
+        let mut reply = [0; 4];
+        tcp.read_exact(&mut reply)?;
+        let target_failed = |reason: &str| {
+            io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("{host}:{port} could not be reached through proxy {self}: {reason}"),
+            )
+        };
+        match reply[1] {
+            0 => {}
+            3 => return Err(target_failed("network unreachable")),
+            4 => return Err(target_failed("host unreachable")),
+            5 => return Err(target_failed("connection refused")),
+            6 => return Err(target_failed("timed out")),
+            code => return Err(protocol(format!("it answered with error {code}"))),
+        }
+
+        // Skip the address the proxy bound, IPv4, a name or IPv6
+        let bound = match reply[3] {
+            1 => 4,
+            3 => {
+                let mut len = [0; 1];
+                tcp.read_exact(&mut len)?;
+                len[0] as usize
+            }
+            4 => 16,
+            atyp => return Err(protocol(format!("unknown address type {atyp}"))),
+        };
+        let mut skipped = vec![0; bound + 2];
+        tcp.read_exact(&mut skipped)?;
+        Ok(())
+    }
+
+    fn http_connect(&self, tcp: &mut TcpStream, host: &str, port: u16) -> Result<(), io::Error> {
+        let authority = if host.contains(':') {
+            format!("[{host}]:{port}")
+        } else {
+            format!("{host}:{port}")
+        };
+        write!(
+            tcp,
+            "CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\nUser-Agent: network-test\r\n\r\n"
+        )?;
+        tcp.flush()?;
+
+        // Read byte by byte so nothing past the headers is taken off the tunnel
+        let mut response = vec![];
+        let mut byte = [0; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if tcp.read(&mut byte)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("Proxy {self} failed: it closed the connection"),
+                ));
+            }
+            response.push(byte[0]);
+        }
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            // Bad gateway and gateway timeout mean the proxy is fine
+            Some("502" | "504") => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("{authority} could not be reached through proxy {self}: {status_line}"),
+            )),
+            _ => Err(io::Error::other(format!(
+                "Proxy {self} failed: {status_line}"
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for Proxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scheme = match self.kind {
+            ProxyKind::Socks5 => "socks5",
+            ProxyKind::Http => "http",
+        };
+        write!(f, "{scheme}://{}:{}", self.host, self.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ping::{Mode, Pinger};
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Reads one request's head, returning its first line.
+    fn read_head(reader: &mut impl BufRead) -> String {
+        let mut first = String::new();
+        reader.read_line(&mut first).unwrap();
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+            line.clear();
+        }
+        first.trim_end().to_string()
+    }
+
+    /// An HTTP proxy on loopback answering one `CONNECT` with `status` and,
+    /// if that lets the tunnel open, the HTTP request sent through it.
+    /// Yields the request lines it got.
+    fn http_proxy(status: &'static str) -> (Proxy, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = Proxy::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut requests = vec![read_head(&mut reader)];
+            write!(&stream, "HTTP/1.1 {status}\r\n\r\n").unwrap();
+            if status.starts_with('2') {
+                requests.push(read_head(&mut reader));
+                write!(&stream, "HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            }
+            requests
+        });
+        (proxy, handle)
+    }
+
+    #[test]
+    fn parses_proxy_urls() {
+        let proxy = Proxy::parse("socks5://[::1]:9050").unwrap();
+        assert_eq!(
+            (proxy.kind, proxy.host.as_str(), proxy.port),
+            (ProxyKind::Socks5, "::1", 9050)
+        );
+        assert_eq!(Proxy::parse("http://proxy.lan").unwrap().port, 8080);
+        assert!(Proxy::parse("ftp://proxy.lan").is_err());
+        assert!(Proxy::parse("http://:8080").is_err());
+    }
+
+    #[test]
+    fn http_probes_go_through_the_proxy() {
+        let (proxy, handle) = http_proxy("200 Connection established");
+        let sample = Pinger::new(Mode::Http)
+            .with_proxy(proxy)
+            .ping("http://example.com/health")
+            .unwrap();
+        assert_eq!(
+            handle.join().unwrap(),
+            ["CONNECT example.com:80 HTTP/1.1", "HEAD /health HTTP/1.1"]
+        );
+        assert!(sample.average >= 0.0);
+    }
+
+    #[test]
+    fn tells_proxy_failures_from_unreachable_targets() {
+        let (proxy, handle) = http_proxy("502 Bad Gateway");
+        let err = proxy
+            .connect("example.com", 80, Duration::from_secs(5))
+            .unwrap_err();
+        handle.join().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert!(
+            err.to_string()
+                .starts_with("example.com:80 could not be reached"),
+            "{err}"
+        );
+
+        let (proxy, handle) = http_proxy("407 Proxy Authentication Required");
+        let err = proxy
+            .connect("example.com", 80, Duration::from_secs(5))
+            .unwrap_err();
+        handle.join().unwrap();
+        assert!(
+            err.to_string().starts_with("Proxy http://127.0.0.1:"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn socks5_proxies_are_asked_for_the_host_name() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy = Proxy::parse(&format!("socks5://{}", listener.local_addr().unwrap())).unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            stream.write_all(&[5, 0]).unwrap();
+            let mut request = [0; 5 + 11 + 2];
+            stream.read_exact(&mut request).unwrap();
+            stream
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80])
+                .unwrap();
+            (greeting, request)
+        });
+        proxy
+            .connect("example.com", 443, Duration::from_secs(5))
+            .unwrap();
+        let (greeting, request) = handle.join().unwrap();
+        assert_eq!(greeting, [5, 1, 0]);
+        assert_eq!(&request[..5], [5, 1, 0, 3, 11]);
+        assert_eq!(&request[5..16], b"example.com");
+        assert_eq!(&request[16..], 443u16.to_be_bytes());
+    }
+}
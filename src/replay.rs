@@ -0,0 +1,141 @@
+//! Playing back an export at an adjustable speed, for stepping through a
+//! captured incident.
+
+use crate::export::RunMetadata;
+use crate::ping::PingResult;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+/// Playback speeds offered, as multiples of real time.
+pub const REPLAY_SPEEDS: [f64; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 16.0, 64.0];
+
+/// A playback cursor over recorded samples. Samples received up to the
+/// cursor are revealed.
+pub struct Replay {
+    samples: Vec<PingResult>,
+    cursor: DateTime<Utc>,
+    pub playing: bool,
+    /// Multiple of real time the cursor advances at.
+    pub speed: f64,
+    /// What the recording is of, when its export came with metadata.
+    pub metadata: Option<RunMetadata>,
+}
+
+impl Replay {
+    /// Starts paused before the first sample.
+    pub fn new(mut samples: Vec<PingResult>) -> Replay {
+        samples.sort_by_key(|sample| sample.datetime_recv);
+        let cursor = samples
+            .first()
+            .map_or(DateTime::UNIX_EPOCH, |sample| sample.datetime_recv);
+        Replay {
+            samples,
+            cursor,
+            playing: false,
+            speed: 1.0,
+            metadata: None,
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: RunMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn start(&self) -> DateTime<Utc> {
+        self.samples
+            .first()
+            .map_or(self.cursor, |sample| sample.datetime_recv)
+    }
+
+    pub fn end(&self) -> DateTime<Utc> {
+        self.samples
+            .last()
+            .map_or(self.cursor, |sample| sample.datetime_recv)
+    }
+
+    pub fn cursor(&self) -> DateTime<Utc> {
+        self.cursor
+    }
+
+    /// Moves the cursor to `at`, kept within the recording.
+    pub fn seek(&mut self, at: DateTime<Utc>) {
+        self.cursor = at.clamp(self.start(), self.end());
+    }
+
+    /// Moves the cursor on by `elapsed` real time at the playback speed while
+    /// playing, pausing at the end.
+    pub fn advance(&mut self, elapsed: Duration) {
+        if !self.playing {
+            return;
+        }
+        let step = chrono::Duration::from_std(elapsed.mul_f64(self.speed)).unwrap_or_default();
+        self.seek(self.cursor + step);
+        if self.cursor == self.end() {
+            self.playing = false;
+        }
+    }
+
+    /// Every host in the recording, in the order they first report.
+    pub fn hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = vec![];
+        for sample in &self.samples {
+            if !hosts.contains(&sample.host) {
+                hosts.push(sample.host.clone());
+            }
+        }
+        hosts
+    }
+
+    /// The samples received up to and including the cursor, oldest first.
+    pub fn revealed(&self) -> &[PingResult] {
+        let count = self
+            .samples
+            .partition_point(|sample| sample.datetime_recv <= self.cursor);
+        &self.samples[..count]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn seeking_reveals_the_samples_up_to_the_cursor() {
+        let samples = (0..10)
+            .rev()
+            .map(|i| PingResult::new("example.com".to_string(), 10.0, at(i * 10)))
+            .collect();
+        let mut replay = Replay::new(samples);
+        assert_eq!(replay.revealed().len(), 1);
+        replay.seek(at(45));
+        assert_eq!(replay.revealed().len(), 5);
+        replay.seek(at(50));
+        assert_eq!(replay.revealed().len(), 6);
+        replay.seek(at(-100));
+        assert_eq!(replay.cursor(), at(0));
+        replay.seek(at(1000));
+        assert_eq!(replay.revealed().len(), 10);
+    }
+
+    #[test]
+    fn plays_at_its_speed_and_pauses_at_the_end() {
+        let samples = (0..10)
+            .map(|i| PingResult::new("example.com".to_string(), 10.0, at(i * 10)))
+            .collect();
+        let mut replay = Replay::new(samples);
+        replay.advance(Duration::from_secs(30));
+        assert_eq!(replay.cursor(), at(0));
+        replay.playing = true;
+        replay.speed = 4.0;
+        replay.advance(Duration::from_secs(5));
+        assert_eq!(replay.revealed().len(), 3);
+        replay.advance(Duration::from_secs(60));
+        assert_eq!(replay.cursor(), at(90));
+        assert!(!replay.playing);
+    }
+}
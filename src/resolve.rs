@@ -0,0 +1,137 @@
+//! Re-resolving host names during a run to notice DNS changes, e.g. behind
+//! round-robin DNS or a failover.
+
+use crate::events::Event;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The addresses `host` currently resolves to, sorted and without duplicates.
+pub fn resolve(host: &str) -> Result<Vec<IpAddr>, io::Error> {
+    let mut addrs: Vec<IpAddr> = (host, 0).to_socket_addrs()?.map(|a| a.ip()).collect();
+    addrs.sort();
+    addrs.dedup();
+    Ok(addrs)
+}
+
+/// A host name starting to resolve to different addresses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolutionChange {
+    pub at: DateTime<Utc>,
+    pub host: String,
+    pub before: Vec<IpAddr>,
+    pub after: Vec<IpAddr>,
+}
+
+impl ResolutionChange {
+    pub fn event(&self) -> Event {
+        let list = |addrs: &[IpAddr]| {
+            addrs
+                .iter()
+                .map(IpAddr::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        Event {
+            at: self.at,
+            host: self.host.clone(),
+            message: format!(
+                "now resolves to {} (was {})",
+                list(&self.after),
+                list(&self.before)
+            ),
+        }
+    }
+}
+
+/// Remembers what each host last resolved to.
+#[derive(Debug, Default)]
+pub struct ResolveTracker {
+    last: HashMap<String, Vec<IpAddr>>,
+}
+
+impl ResolveTracker {
+    pub fn new() -> ResolveTracker {
+        ResolveTracker::default()
+    }
+
+    /// Records that `host` resolved to `addrs` at `at`, returning the change
+    /// if they differ from last time. The first resolution of a host is
+    /// never a change.
+    pub fn observe(
+        &mut self,
+        host: &str,
+        addrs: Vec<IpAddr>,
+        at: DateTime<Utc>,
+    ) -> Option<ResolutionChange> {
+        let before = self.last.insert(host.to_string(), addrs.clone())?;
+        (before != addrs).then(|| ResolutionChange {
+            at,
+            host: host.to_string(),
+            before,
+            after: addrs,
+        })
+    }
+}
+
+/// Resolves `host` every `interval` on its own thread, logging each change to
+/// stderr and sending it on `tx` if anything still listens. Failed lookups
+/// are logged and retried at the next interval.
+pub fn spawn_resolver(
+    host: String,
+    interval: Duration,
+    tx: mpsc::Sender<ResolutionChange>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut tracker = ResolveTracker::new();
+        loop {
+            match resolve(&host) {
+                Ok(addrs) => {
+                    if let Some(change) = tracker.observe(&host, addrs, Utc::now()) {
+                        eprintln!("{}", change.event());
+                        // Headless runs only log the change
+                        let _ = tx.send(change);
+                    }
+                }
+                Err(err) => eprintln!("Could not resolve {host}: {err}"),
+            }
+            thread::sleep(interval);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notices_an_address_change_between_resolutions() {
+        let at = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+        let first: IpAddr = "192.0.2.1".parse().unwrap();
+        let second: IpAddr = "192.0.2.2".parse().unwrap();
+        let mut tracker = ResolveTracker::new();
+        assert_eq!(tracker.observe("example.com", vec![first], at), None);
+        assert_eq!(tracker.observe("example.com", vec![first], at), None);
+        let change = tracker.observe("example.com", vec![second], at).unwrap();
+        assert_eq!(change.before, [first]);
+        assert_eq!(change.after, [second]);
+        assert_eq!(
+            change.event().message,
+            "now resolves to 192.0.2.2 (was 192.0.2.1)"
+        );
+        // Other hosts are tracked on their own
+        assert_eq!(tracker.observe("example.org", vec![first], at), None);
+    }
+
+    #[test]
+    fn resolves_addresses_sorted_and_deduplicated() {
+        assert_eq!(
+            resolve("127.0.0.1").unwrap(),
+            ["127.0.0.1".parse::<IpAddr>().unwrap()]
+        );
+    }
+}
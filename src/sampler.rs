@@ -0,0 +1,453 @@
+//! Background threads that sample hosts on a schedule.
+
+use crate::events::Event;
+use crate::ping::{PingResult, Pinger};
+use crate::schedule::Schedule;
+use chrono::{DateTime, Local, Utc};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What samplers do when the channel to the display is full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backpressure {
+    /// Wait for room, delaying the next sample. Nothing is lost but sampling
+    /// slows down to the rate samples are consumed.
+    Block,
+    /// Discard the oldest sample waiting and count it, keeping the sampling
+    /// rate, so a display that falls behind catches up on fresh samples
+    /// rather than stale ones.
+    Drop,
+}
+
+impl Backpressure {
+    pub fn parse(value: &str) -> Result<Backpressure, String> {
+        match value {
+            "block" => Ok(Backpressure::Block),
+            "drop" => Ok(Backpressure::Drop),
+            _ => Err(format!(
+                "Unknown backpressure {value}, expected block or drop"
+            )),
+        }
+    }
+}
+
+/// Samples sent but not yet handed to the receiver.
+struct Queue {
+    samples: VecDeque<PingResult>,
+    senders: usize,
+    receiver_gone: bool,
+}
+
+/// The queue shared by the senders and the thread handing samples on.
+struct SampleQueue {
+    queue: Mutex<Queue>,
+    /// Signalled whenever a sample is queued or taken, or a side goes away.
+    changed: Condvar,
+    capacity: usize,
+}
+
+impl SampleQueue {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        // A sampler that panicked mid-send shouldn't stop the others
+        self.queue.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn wait<'a>(&self, queue: MutexGuard<'a, Queue>) -> MutexGuard<'a, Queue> {
+        self.changed
+            .wait(queue)
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Sending half of the bounded channel samplers report through.
+pub struct SampleSender {
+    queue: Arc<SampleQueue>,
+    backpressure: Backpressure,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl SampleSender {
+    /// Sends `sample` according to the [`Backpressure`] policy. Fails only
+    /// once the receiver is gone.
+    pub fn send(&self, sample: PingResult) -> Result<(), mpsc::SendError<()>> {
+        let mut queue = self.queue.lock();
+        loop {
+            if queue.receiver_gone {
+                return Err(mpsc::SendError(()));
+            }
+            if queue.samples.len() < self.queue.capacity {
+                break;
+            }
+            match self.backpressure {
+                Backpressure::Block => queue = self.queue.wait(queue),
+                Backpressure::Drop => {
+                    queue.samples.pop_front();
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+            }
+        }
+        queue.samples.push_back(sample);
+        self.queue.changed.notify_all();
+        Ok(())
+    }
+
+    /// Count of samples dropped because the channel was full, shared by every
+    /// clone of this sender.
+    pub fn dropped(&self) -> Arc<AtomicUsize> {
+        Arc::clone(&self.dropped)
+    }
+}
+
+impl Clone for SampleSender {
+    fn clone(&self) -> Self {
+        self.queue.lock().senders += 1;
+        SampleSender {
+            queue: Arc::clone(&self.queue),
+            backpressure: self.backpressure,
+            dropped: Arc::clone(&self.dropped),
+        }
+    }
+}
+
+impl Drop for SampleSender {
+    fn drop(&mut self) {
+        self.queue.lock().senders -= 1;
+        self.queue.changed.notify_all();
+    }
+}
+
+/// Creates a channel holding at most `capacity` samples not yet received,
+/// besides the one being handed over. The channel's queue lets
+/// [`Backpressure::Drop`] evict the oldest sample, which a plain
+/// [`mpsc::sync_channel`] can't from the sending side. A thread moves
+/// samples on to the returned receiver, ending once every sender is gone.
+pub fn sample_channel(
+    capacity: usize,
+    backpressure: Backpressure,
+) -> (SampleSender, mpsc::Receiver<PingResult>) {
+    let queue = Arc::new(SampleQueue {
+        queue: Mutex::new(Queue {
+            samples: VecDeque::with_capacity(capacity.min(1024)),
+            senders: 1,
+            receiver_gone: false,
+        }),
+        changed: Condvar::new(),
+        capacity: capacity.max(1),
+    });
+    let (tx, rx) = mpsc::sync_channel(0);
+    let relay = Arc::clone(&queue);
+    thread::spawn(move || loop {
+        let sample = {
+            let mut queue = relay.lock();
+            loop {
+                if let Some(sample) = queue.samples.pop_front() {
+                    break sample;
+                }
+                if queue.senders == 0 {
+                    return;
+                }
+                queue = relay.wait(queue);
+            }
+        };
+        relay.changed.notify_all();
+        if tx.send(sample).is_err() {
+            relay.lock().receiver_gone = true;
+            relay.changed.notify_all();
+            return;
+        }
+    });
+    let sender = SampleSender {
+        queue,
+        backpressure,
+        dropped: Arc::new(AtomicUsize::new(0)),
+    };
+    (sender, rx)
+}
+
+/// Passes every sample from `rx` on to the returned receiver, showing it to
+/// `observe` on the way, for the consumers that watch samples go by on
+/// their way to the display. The hand-over is unbuffered so the channel's
+/// backpressure still reaches the samplers.
+pub fn tee(
+    rx: mpsc::Receiver<PingResult>,
+    mut observe: impl FnMut(&PingResult) + Send + 'static,
+) -> mpsc::Receiver<PingResult> {
+    let (tx, teed) = mpsc::sync_channel(0);
+    thread::spawn(move || {
+        for ping_value in rx {
+            observe(&ping_value);
+            if tx.send(ping_value).is_err() {
+                break;
+            }
+        }
+    });
+    teed
+}
+
+/// Iterations a sampler has left, or no limit. Clones share the count, so a
+/// sampler [`supervise`] restarts carries on where the last one stopped.
+#[derive(Debug, Clone)]
+pub struct Budget(Option<Arc<AtomicU32>>);
+
+impl Budget {
+    pub fn new(iterations: Option<u32>) -> Budget {
+        Budget(iterations.map(|n| Arc::new(AtomicU32::new(n))))
+    }
+
+    /// Uses up one iteration, or gives false once none are left.
+    pub fn take(&self) -> bool {
+        match &self.0 {
+            Some(left) => left
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok(),
+            None => true,
+        }
+    }
+
+    /// Iterations left, if there's a limit.
+    pub fn remaining(&self) -> Option<u32> {
+        self.0.as_ref().map(|left| left.load(Ordering::Relaxed))
+    }
+}
+
+/// Pings `host` once per `interval` on a background thread, sending each
+/// result down `tx`. Failed pings are logged and count towards `budget`.
+/// The thread gives true once the budget is used up or the receiver is gone.
+///
+/// `schedule` overrides `interval` during its time ranges, checked against the
+/// local time before every sleep.
+pub fn spawn_sampler(
+    host: String,
+    interval: Duration,
+    schedule: Schedule,
+    pinger: Pinger,
+    budget: Budget,
+    tx: SampleSender,
+) -> thread::JoinHandle<bool> {
+    thread::spawn(move || {
+        while budget.take() {
+            let started = Instant::now();
+            match pinger.ping(&host) {
+                Ok(ping_value) => {
+                    if tx.send(ping_value).is_err() {
+                        break; // Exit if receiver is dropped
+                    }
+                }
+                // Failures are usually transient, so keep sampling
+                Err(err) => eprintln!("Error pinging {host}: {err}"),
+            }
+
+            let interval = schedule
+                .interval_at(Local::now().time())
+                .unwrap_or(interval);
+            if let Some(remaining) = interval.checked_sub(started.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+        true
+    })
+}
+
+/// Like [`spawn_sampler`], but keeps a single `ping` running and sends a
+/// result for every reply line it prints.
+///
+/// This saves starting a process per sample and delivers each reply as soon
+/// as `ping` prints it rather than when it exits. In exchange the interval is
+/// left to `ping -i`, so schedules don't apply and intervals under 200ms need
+/// root on Linux; lost replies just print nothing, so they don't show up as
+/// errors; and the sampler stops if `ping` itself exits. That only counts as
+/// finishing normally when `ping` was told how many probes to send.
+pub fn spawn_streaming_sampler(
+    host: String,
+    interval: Duration,
+    pinger: Pinger,
+    budget: Budget,
+    tx: SampleSender,
+) -> thread::JoinHandle<bool> {
+    thread::spawn(move || {
+        let stream = match pinger.stream(&host, interval, budget.remaining()) {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Error starting ping to {host}: {err}");
+                return false;
+            }
+        };
+        for ping_value in stream {
+            if !budget.take() || tx.send(ping_value).is_err() {
+                return true; // Exit if receiver is dropped
+            }
+        }
+        budget.remaining().is_some()
+    })
+}
+
+/// Pause before a sampler that stopped unexpectedly is started again.
+pub const SAMPLER_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// A sampler [`supervise`] had to start again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SamplerRestart {
+    pub at: DateTime<Utc>,
+    pub host: String,
+}
+
+impl SamplerRestart {
+    pub fn event(&self) -> Event {
+        Event {
+            at: self.at,
+            host: self.host.clone(),
+            message: "sampler stopped unexpectedly and was restarted".to_string(),
+        }
+    }
+}
+
+/// Watches the sampler thread `spawn` starts and starts a new one whenever it
+/// panics or stops without saying it finished, so a bug in one probe doesn't
+/// silently stop a host's data. Each restart is sent on `restarts`. Samplers
+/// sharing a [`Budget`] carry on its count. The watchdog ends when the
+/// sampler finishes normally.
+pub fn supervise(
+    host: String,
+    restarts: mpsc::Sender<SamplerRestart>,
+    spawn: impl Fn() -> thread::JoinHandle<bool> + Send + 'static,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while !spawn().join().unwrap_or(false) {
+            eprintln!("Sampler for {host} stopped unexpectedly, restarting it");
+            let _ = restarts.send(SamplerRestart {
+                at: Utc::now(),
+                host: host.clone(),
+            });
+            thread::sleep(SAMPLER_RESTART_DELAY);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_is_shared_between_clones() {
+        let budget = Budget::new(Some(3));
+        let restarted = budget.clone();
+        assert!(budget.take());
+        assert!(restarted.take());
+        assert_eq!(budget.remaining(), Some(1));
+        assert!(restarted.take());
+        assert!(!budget.take());
+        assert_eq!(restarted.remaining(), Some(0));
+        assert!(Budget::new(None).take());
+    }
+
+    #[test]
+    fn supervise_restarts_samplers_that_exit_early_or_panic() {
+        let exits = Arc::new(Mutex::new(vec![Ok(false), Err("panicked")]));
+        let spawned = Arc::new(AtomicUsize::new(0));
+        let (restarts, restarted) = mpsc::channel();
+        let watchdog = supervise("example.com".to_string(), restarts, {
+            let spawned = Arc::clone(&spawned);
+            move || {
+                spawned.fetch_add(1, Ordering::Relaxed);
+                let exit = exits.lock().unwrap().pop().unwrap_or(Ok(true));
+                thread::spawn(move || exit.expect("sampler panicked"))
+            }
+        });
+        watchdog.join().unwrap();
+        assert_eq!(spawned.load(Ordering::Relaxed), 3);
+        let restarts: Vec<_> = restarted.try_iter().collect();
+        assert_eq!(restarts.len(), 2);
+        assert!(restarts.iter().all(|restart| restart.host == "example.com"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sampler_keeps_going_after_a_failed_ping() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Fails the first time it runs, then answers
+        let dir = std::env::temp_dir().join(format!("sampler-recovers-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let ping = dir.join("ping");
+        let script = format!(
+            "#!/bin/sh\n\
+             if [ ! -e {marker} ]; then touch {marker}; exit 2; fi\n\
+             echo 'round-trip min/avg/max/stddev = 5.0/5.0/5.0/0.000 ms'\n",
+            marker = dir.join("failed").display()
+        );
+        std::fs::write(&ping, script).unwrap();
+        std::fs::set_permissions(&ping, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let (tx, rx) = sample_channel(4, Backpressure::Block);
+        let sampler = spawn_sampler(
+            "example.com".to_string(),
+            Duration::from_millis(1),
+            Schedule::default(),
+            Pinger::new(crate::Mode::Icmp).with_ping_binary(&ping),
+            Budget::new(Some(2)),
+            tx,
+        );
+        assert!(sampler.join().unwrap());
+        let samples: Vec<PingResult> = rx.iter().collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].average, 5.0);
+    }
+
+    #[test]
+    fn dropping_channel_keeps_the_newest_samples() {
+        let (tx, rx) = sample_channel(3, Backpressure::Drop);
+        let dropped = tx.dropped();
+        for i in 0..10 {
+            let sample = PingResult::new("example.com".to_string(), i as f64, Utc::now());
+            tx.send(sample).unwrap();
+        }
+        drop(tx);
+        let kept: Vec<f64> = rx.iter().map(|sample| sample.average).collect();
+        // At most one sample was already on its way before the others came
+        assert!(kept.ends_with(&[7.0, 8.0, 9.0]), "{kept:?}");
+        assert!(kept.len() <= 4, "{kept:?}");
+        assert_eq!(dropped.load(Ordering::Relaxed), 10 - kept.len());
+    }
+
+    #[test]
+    fn senders_fail_once_the_receiver_is_gone() {
+        let (tx, rx) = sample_channel(2, Backpressure::Block);
+        drop(rx);
+        let started = Instant::now();
+        let mut sent = 0;
+        while tx
+            .send(PingResult::new("example.com".to_string(), 1.0, Utc::now()))
+            .is_ok()
+        {
+            sent += 1;
+            assert!(started.elapsed() < Duration::from_secs(5));
+        }
+        // The first is handed on and found undeliverable, waking the rest
+        assert!(sent <= 3, "{sent}");
+    }
+
+    #[test]
+    fn tee_shows_every_sample_and_passes_it_on() {
+        let (tx, rx) = mpsc::channel();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        let teed = tee(rx, move |sample| {
+            recorded.lock().unwrap().push(sample.average)
+        });
+        let at = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+        for latency in [10.0, 20.0] {
+            tx.send(PingResult::new("a".to_string(), latency, at))
+                .unwrap();
+        }
+        drop(tx);
+        let passed: Vec<_> = teed.iter().map(|sample| sample.average).collect();
+        assert_eq!(passed, [10.0, 20.0]);
+        assert_eq!(*seen.lock().unwrap(), [10.0, 20.0]);
+    }
+}
@@ -0,0 +1,201 @@
+//! Time-of-day overrides for the sampling interval and latency threshold,
+//! and maintenance windows during which alerts stay quiet.
+
+use crate::config::parse_interval;
+use chrono::{DateTime, Days, Local, NaiveTime, TimeZone, Utc};
+use std::time::Duration;
+
+/// Overrides that apply between `start` and `end` local time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleEntry {
+    pub start: NaiveTime,
+    /// Exclusive. An `end` before `start` wraps past midnight.
+    pub end: NaiveTime,
+    pub interval: Option<Duration>,
+    /// Latency threshold in ms.
+    pub threshold: Option<f64>,
+    /// Expected downtime: alerts are held back, the plot is shaded and CSV
+    /// exports flag the samples.
+    pub maintenance: bool,
+}
+
+impl ScheduleEntry {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// Time ranges with overrides. Outside every range the defaults apply.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schedule {
+    pub entries: Vec<ScheduleEntry>,
+}
+
+impl Schedule {
+    /// The first entry covering `time`.
+    pub fn active(&self, time: NaiveTime) -> Option<&ScheduleEntry> {
+        self.entries.iter().find(|entry| entry.contains(time))
+    }
+
+    pub fn interval_at(&self, time: NaiveTime) -> Option<Duration> {
+        self.active(time).and_then(|entry| entry.interval)
+    }
+
+    pub fn threshold_at(&self, time: NaiveTime) -> Option<f64> {
+        self.active(time).and_then(|entry| entry.threshold)
+    }
+
+    /// Whether any maintenance window covers `time`, not only the first
+    /// entry that does.
+    pub fn in_maintenance(&self, time: NaiveTime) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.maintenance && entry.contains(time))
+    }
+
+    pub fn has_maintenance(&self) -> bool {
+        self.entries.iter().any(|entry| entry.maintenance)
+    }
+
+    /// Every maintenance window between `start` and `end`, clipped to them,
+    /// as it fell on each local day.
+    pub fn maintenance_spans(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        let (first, last) = (
+            start.with_timezone(&Local).date_naive(),
+            end.with_timezone(&Local).date_naive(),
+        );
+        let at = |day: chrono::NaiveDate, time: NaiveTime| {
+            Local
+                .from_local_datetime(&day.and_time(time))
+                .earliest()
+                .map(|at| at.with_timezone(&Utc))
+        };
+        let mut spans = vec![];
+        // From the day before, for windows wrapping past midnight into `first`
+        let mut day = first - Days::new(1);
+        while day <= last {
+            for entry in self.entries.iter().filter(|entry| entry.maintenance) {
+                let end_day = if entry.end <= entry.start {
+                    day + Days::new(1)
+                } else {
+                    day
+                };
+                let (Some(from), Some(to)) = (at(day, entry.start), at(end_day, entry.end)) else {
+                    continue;
+                };
+                let (from, to) = (from.max(start), to.min(end));
+                if from < to {
+                    spans.push((from, to));
+                }
+            }
+            day = day + Days::new(1);
+        }
+        spans
+    }
+}
+
+/// Parses a schedule file with one
+/// `HH:MM-HH:MM [interval=d] [threshold=ms] [maintenance]` range per line,
+/// e.g. `09:00-17:00 interval=5s threshold=100` or `02:00-03:00 maintenance`.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn parse_schedule(contents: &str) -> Result<Schedule, String> {
+    let entries = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_schedule_entry)
+        .collect::<Result<_, _>>()?;
+
+    Ok(Schedule { entries })
+}
+
+fn parse_schedule_entry(line: &str) -> Result<ScheduleEntry, String> {
+    let mut fields = line.split_whitespace();
+    let range = fields.next().unwrap_or_default();
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| format!("Invalid time range {range}, expected HH:MM-HH:MM"))?;
+    let parse_time = |time: &str| {
+        NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|err| format!("Invalid time {time}: {err}"))
+    };
+
+    let mut entry = ScheduleEntry {
+        start: parse_time(start)?,
+        end: parse_time(end)?,
+        interval: None,
+        threshold: None,
+        maintenance: false,
+    };
+    for field in fields {
+        if field == "maintenance" {
+            entry.maintenance = true;
+            continue;
+        }
+        match field.split_once('=') {
+            Some(("interval", value)) => entry.interval = Some(parse_interval(value)?),
+            Some(("threshold", value)) => {
+                entry.threshold = Some(
+                    value
+                        .parse()
+                        .map_err(|err| format!("Invalid threshold {value}: {err}"))?,
+                )
+            }
+            _ => return Err(format!("Unknown schedule override {field}")),
+        }
+    }
+
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn overrides_switch_at_range_boundaries() {
+        let schedule = parse_schedule(
+            "# Office hours\n\
+             09:00-17:00 interval=5s threshold=100\n\
+             \n\
+             22:00-02:00 interval=1m\n",
+        )
+        .unwrap();
+        assert_eq!(schedule.interval_at(time(8, 59)), None);
+        assert_eq!(
+            schedule.interval_at(time(9, 0)),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(schedule.threshold_at(time(16, 59)), Some(100.0));
+        assert_eq!(schedule.interval_at(time(17, 0)), None);
+        // Wrapping past midnight
+        assert_eq!(
+            schedule.interval_at(time(23, 30)),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            schedule.interval_at(time(1, 59)),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(schedule.interval_at(time(2, 0)), None);
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        assert!(parse_schedule("9-17 interval=5s").is_err());
+        assert!(parse_schedule("09:00-17:00 interval=0s").is_err());
+        assert!(parse_schedule("09:00-17:00 speed=fast").is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! `--mode simulate`: made-up samples with spikes and dropouts for demos and
+//! trying out the display without a network. The same `--seed` always gives
+//! every host the same series.
+
+use crate::ping::PingResult;
+use chrono::Utc;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Chance of a sample being a spike of several times the usual latency.
+const SPIKE_CHANCE: f64 = 0.02;
+
+/// Chance of a sample being dropped.
+const DROPOUT_CHANCE: f64 = 0.01;
+
+/// Generates each host's samples from its own random number generator, so
+/// the series don't depend on the order hosts are sampled in. Clones share
+/// the generators.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    seed: Option<u64>,
+    hosts: Arc<Mutex<HashMap<String, StdRng>>>,
+}
+
+impl Simulator {
+    /// Seeds the hosts' generators from `seed`, or from entropy when `None`.
+    pub fn new(seed: Option<u64>) -> Simulator {
+        Simulator {
+            seed,
+            hosts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The next made-up sample of `host`: a latency around a base that
+    /// depends on the host, an occasional spike, or a dropout error.
+    pub fn sample(&self, host: &str) -> Result<PingResult, io::Error> {
+        let mut hosts = self.hosts.lock().unwrap_or_else(|err| err.into_inner());
+        let rng = hosts
+            .entry(host.to_string())
+            .or_insert_with(|| match self.seed {
+                Some(seed) => StdRng::seed_from_u64(seed ^ fnv1a(host)),
+                None => StdRng::from_entropy(),
+            });
+
+        if rng.gen_bool(DROPOUT_CHANCE) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("Simulated dropout of {host}"),
+            ));
+        }
+        let base = 10.0 + (fnv1a(host) % 40) as f64;
+        let mut latency = base * rng.gen_range(0.8..1.3);
+        if rng.gen_bool(SPIKE_CHANCE) {
+            latency *= rng.gen_range(4.0..10.0);
+        }
+        Ok(PingResult::new(host.to_string(), latency, Utc::now()))
+    }
+}
+
+/// FNV-1a hash of `host`, stable across runs and Rust versions unlike the
+/// standard hasher.
+fn fnv1a(host: &str) -> u64 {
+    host.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{format_csv, CsvOptions};
+    use chrono::DateTime;
+
+    /// The CSV export of a run of `seed`, with the samples timestamped by
+    /// their place in the run rather than the clock.
+    fn export_run(seed: u64) -> String {
+        let simulator = Simulator::new(Some(seed));
+        let mut samples = vec![];
+        for i in 0..500 {
+            for host in ["a", "b"] {
+                if let Ok(mut sample) = simulator.sample(host) {
+                    sample.datetime_recv = DateTime::from_timestamp(1_704_067_200 + i, 0).unwrap();
+                    samples.push(sample);
+                }
+            }
+        }
+        // Dropouts and spikes are part of what must repeat
+        assert!(samples.len() < 1000);
+        assert!(samples.iter().any(|sample| sample.average > 100.0));
+        format_csv(&samples, &CsvOptions::default())
+    }
+
+    #[test]
+    fn the_same_seed_exports_the_same_bytes() {
+        assert_eq!(export_run(42).as_bytes(), export_run(42).as_bytes());
+        assert_ne!(export_run(42), export_run(43));
+    }
+}
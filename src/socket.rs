@@ -0,0 +1,213 @@
+//! `--stats-socket`: answers with the current stats over a Unix domain socket,
+//! for status bars and other local tools that poll.
+//!
+//! A client writes one request line and gets one JSON line back. `stats` (or
+//! an empty line) returns a [`Snapshot`].
+
+use crate::ping::PingResult;
+use crate::sampler;
+use crate::stats::StreamingSummary;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long a client gets to send its request line.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Current stats of one host.
+#[derive(Debug, Clone, Serialize)]
+pub struct HostSnapshot {
+    pub host: String,
+    /// Latency of the latest sample in ms.
+    pub last: f64,
+    #[serde(with = "crate::export::timestamp")]
+    pub last_at: DateTime<Utc>,
+    pub count: usize,
+    pub avg: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub p95: Option<f64>,
+}
+
+/// What the socket answers with, one entry per host in the order they first
+/// reported.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Snapshot {
+    pub hosts: Vec<HostSnapshot>,
+}
+
+/// Running stats behind the snapshot, shared between the tee and the socket.
+#[derive(Default)]
+pub struct LiveStats {
+    hosts: Vec<(StreamingSummary, PingResult)>,
+}
+
+impl LiveStats {
+    pub fn new() -> LiveStats {
+        LiveStats::default()
+    }
+
+    pub fn record(&mut self, ping_value: &PingResult) {
+        match self
+            .hosts
+            .iter_mut()
+            .find(|(_, last)| last.host == ping_value.host)
+        {
+            Some((summary, last)) => {
+                summary.push(ping_value.average);
+                *last = ping_value.clone();
+            }
+            None => {
+                let mut summary = StreamingSummary::new();
+                summary.push(ping_value.average);
+                self.hosts.push((summary, ping_value.clone()));
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            hosts: self
+                .hosts
+                .iter()
+                .map(|(summary, last)| HostSnapshot {
+                    host: last.host.clone(),
+                    last: last.average,
+                    last_at: last.datetime_recv,
+                    count: summary.stats.count(),
+                    avg: summary.stats.mean(),
+                    min: summary.stats.min(),
+                    max: summary.stats.max(),
+                    p95: summary.p95.estimate(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Passes every sample from `rx` on to the returned receiver, recording it in
+/// `stats` on the way.
+pub fn tee(
+    rx: mpsc::Receiver<PingResult>,
+    stats: Arc<Mutex<LiveStats>>,
+) -> mpsc::Receiver<PingResult> {
+    sampler::tee(rx, move |ping_value| {
+        stats
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .record(ping_value);
+    })
+}
+
+/// Listens on `path`, replacing a socket left behind by an earlier run, and
+/// answers each connection on its own thread. Anything else at `path` is
+/// left alone and gives an [`io::ErrorKind::AlreadyExists`] error.
+pub fn serve(path: &Path, stats: Arc<Mutex<LiveStats>>) -> Result<JoinHandle<()>, io::Error> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let listener = UnixListener::bind(path)?;
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let stats = Arc::clone(&stats);
+                    thread::spawn(move || {
+                        if let Err(err) = answer(stream, &stats) {
+                            eprintln!("Stats socket client failed: {err}");
+                        }
+                    });
+                }
+                Err(err) => eprintln!("Stats socket failed to accept: {err}"),
+            }
+        }
+    }))
+}
+
+fn answer(stream: UnixStream, stats: &Mutex<LiveStats>) -> Result<(), io::Error> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let response = match request.trim() {
+        "" | "stats" => serde_json::to_string(
+            &stats
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .snapshot(),
+        )?,
+        other => serde_json::json!({ "error": format!("Unknown request {other}, expected stats") })
+            .to_string(),
+    };
+    let mut stream = stream;
+    writeln!(stream, "{response}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_with_the_current_snapshot() {
+        let stats = Arc::new(Mutex::new(LiveStats::new()));
+        let at = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+        for average in [10.0, 30.0] {
+            let sample = PingResult::new("example.com".to_string(), average, at);
+            stats.lock().unwrap().record(&sample);
+        }
+        let path = std::env::temp_dir().join(format!("stats-socket-{}", std::process::id()));
+        serve(&path, stats).unwrap();
+
+        let query = |request: &str| {
+            let mut stream = UnixStream::connect(&path).unwrap();
+            writeln!(stream, "{request}").unwrap();
+            let mut response = String::new();
+            BufReader::new(stream).read_line(&mut response).unwrap();
+            serde_json::from_str::<serde_json::Value>(&response).unwrap()
+        };
+        let snapshot = query("stats");
+        let host = &snapshot["hosts"][0];
+        assert_eq!(host["host"], "example.com");
+        assert_eq!(host["last"], 30.0);
+        assert_eq!(host["count"], 2);
+        assert_eq!(host["avg"], 20.0);
+        assert_eq!(query("")["hosts"][0]["min"], 10.0);
+        assert!(query("latest")["error"].is_string());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replaces_only_stale_sockets() {
+        let path = std::env::temp_dir().join(format!("stats-socket-kept-{}", std::process::id()));
+        fs::write(&path, "not a socket").unwrap();
+        let stats = Arc::new(Mutex::new(LiveStats::new()));
+        let err = serve(&path, Arc::clone(&stats)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not a socket");
+        fs::remove_file(&path).unwrap();
+
+        // Left behind by a run that didn't clean up
+        drop(UnixListener::bind(&path).unwrap());
+        serve(&path, stats).unwrap();
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(stream, "stats").unwrap();
+        let mut response = String::new();
+        BufReader::new(stream).read_line(&mut response).unwrap();
+        assert_eq!(response, "{\"hosts\":[]}\n");
+        fs::remove_file(&path).unwrap();
+    }
+}
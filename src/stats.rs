@@ -0,0 +1,1991 @@
+//! Statistics computed over collected samples.
+
+use crate::events::Event;
+use crate::ping::PingResult;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::Duration;
+
+/// Limits beyond which a host is considered degraded.
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    /// Average latency in ms.
+    pub latency: Option<f64>,
+    /// Packet loss in percent.
+    pub loss: f64,
+    /// Latency in ms above which a sample counts as lost, as a reply that
+    /// late is no use to real-time applications.
+    pub loss_cutoff: Option<f64>,
+}
+
+impl Thresholds {
+    /// Whether a sample of `latency` ms counts as lost.
+    pub fn counts_as_lost(&self, latency: f64) -> bool {
+        self.loss_cutoff.is_some_and(|cutoff| latency > cutoff)
+    }
+}
+
+/// A service level such as "95% of samples under 100 ms".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyBudget {
+    /// Latency in ms a sample must not exceed.
+    pub latency: f64,
+    /// Percentage of samples that must stay within `latency`.
+    pub target: f64,
+}
+
+impl LatencyBudget {
+    /// Percentage of `values` within the budget, `None` when there are none.
+    pub fn met_percent(&self, values: &[f64]) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        let met = values
+            .iter()
+            .filter(|&&value| value <= self.latency)
+            .count();
+        Some(met as f64 * 100.0 / values.len() as f64)
+    }
+
+    pub fn is_met(&self, met_percent: f64) -> bool {
+        met_percent >= self.target
+    }
+}
+
+/// Min/avg/max over a trailing window of samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RollingStats {
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Computes rolling stats for every value using the `window` values ending at
+/// it. The first rows use however many values are available.
+pub fn rolling_stats(values: &[f64], window: usize) -> Vec<RollingStats> {
+    (0..values.len())
+        .map(|i| {
+            let slice = &values[(i + 1).saturating_sub(window)..=i];
+            RollingStats {
+                avg: slice.iter().sum::<f64>() / slice.len() as f64,
+                min: slice.iter().copied().fold(f64::INFINITY, f64::min),
+                max: slice.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            }
+        })
+        .collect()
+}
+
+/// How the moving overlay combines the samples in its window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Aggregate {
+    #[default]
+    Mean,
+    /// Unlike the mean, barely moved by a single spike.
+    Median,
+    Max,
+    Min,
+}
+
+impl Aggregate {
+    pub const ALL: [Aggregate; 4] = [
+        Aggregate::Mean,
+        Aggregate::Median,
+        Aggregate::Max,
+        Aggregate::Min,
+    ];
+
+    pub fn parse(value: &str) -> Result<Aggregate, String> {
+        match value {
+            "mean" => Ok(Aggregate::Mean),
+            "median" => Ok(Aggregate::Median),
+            "max" => Ok(Aggregate::Max),
+            "min" => Ok(Aggregate::Min),
+            _ => Err(format!(
+                "Unknown aggregate {value}, expected mean, median, max or min"
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aggregate::Mean => "mean",
+            Aggregate::Median => "median",
+            Aggregate::Max => "max",
+            Aggregate::Min => "min",
+        }
+    }
+
+    /// `values` combined into one, which mustn't be empty.
+    fn apply(self, values: &[f64]) -> f64 {
+        match self {
+            Aggregate::Mean => values.iter().sum::<f64>() / values.len() as f64,
+            Aggregate::Median => median(values).unwrap_or(f64::NAN),
+            Aggregate::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Aggregate::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+        }
+    }
+}
+
+/// `aggregate` of the `window` values ending at each value. The first ones
+/// use however many values are available.
+pub fn moving(values: &[f64], window: usize, aggregate: Aggregate) -> Vec<f64> {
+    (0..values.len())
+        .map(|i| aggregate.apply(&values[(i + 1).saturating_sub(window.max(1))..=i]))
+        .collect()
+}
+
+/// Median of `values`, `None` when there are none.
+fn median(values: &[f64]) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((sorted[middle - 1] + sorted[middle]) / 2.0),
+        _ => Some(sorted[middle]),
+    }
+}
+
+/// The range of `values` within `mads` median absolute deviations of their
+/// median, outside which a value is rejected as an outlier. `None` when
+/// there are no values or at least half of them are equal, leaving the
+/// deviation at 0 and nothing to scale by.
+pub fn outlier_bounds(values: &[f64], mads: f64) -> Option<(f64, f64)> {
+    let middle = median(values)?;
+    let deviations: Vec<f64> = values.iter().map(|value| (value - middle).abs()).collect();
+    let mad = median(&deviations)?;
+    if mad == 0.0 {
+        return None;
+    }
+    Some((middle - mads * mad, middle + mads * mad))
+}
+
+/// Outcome of a `--once` run, used as the process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Health {
+    Healthy = 0,
+    Degraded = 1,
+    Down = 2,
+}
+
+/// Judges `host` from its samples, counting any of the `expected` samples
+/// that never arrived or came in over the loss cutoff as lost.
+pub fn assess_health(
+    samples: &[PingResult],
+    host: &str,
+    expected: u32,
+    thresholds: &Thresholds,
+) -> Health {
+    let averages: Vec<f64> = samples
+        .iter()
+        .filter(|sample| sample.host == host && !thresholds.counts_as_lost(sample.average))
+        .map(|sample| sample.average)
+        .collect();
+    if averages.is_empty() {
+        return Health::Down;
+    }
+
+    let received = averages.len().min(expected as usize);
+    let loss = 100.0 * (expected as usize - received) as f64 / expected.max(1) as f64;
+    let average = averages.iter().sum::<f64>() / averages.len() as f64;
+
+    if loss > thresholds.loss || thresholds.latency.is_some_and(|limit| average > limit) {
+        Health::Degraded
+    } else {
+        Health::Healthy
+    }
+}
+
+/// How many samples taken every `interval` should have arrived between the
+/// first and last of `times`, and at least as many as did.
+pub fn expected_samples(times: &[DateTime<Utc>], interval: Duration) -> usize {
+    let (Some(first), Some(last)) = (times.iter().min(), times.iter().max()) else {
+        return 0;
+    };
+    let span = (*last - *first).num_milliseconds() as f64 / 1000.0;
+    ((span / interval.as_secs_f64()).round() as usize + 1).max(times.len())
+}
+
+/// Loss in percent estimated from the [`expected_samples`] of `times`.
+pub fn estimated_loss(times: &[DateTime<Utc>], interval: Duration) -> f64 {
+    let expected = expected_samples(times, interval);
+    if expected == 0 {
+        return 0.0;
+    }
+    100.0 * (expected - times.len()) as f64 / expected as f64
+}
+
+/// How far the measured inter-arrival time may stray from the configured
+/// interval, as a fraction of it, before the stats panel warns.
+pub const RATE_DEVIATION: f64 = 0.5;
+
+/// Time between consecutive samples, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterArrival {
+    pub last: f64,
+    pub average: f64,
+}
+
+impl InterArrival {
+    /// Whether the average gap differs from `interval` by more than
+    /// [`RATE_DEVIATION`].
+    pub fn deviates_from(&self, interval: Duration) -> bool {
+        let expected = interval.as_secs_f64();
+        (self.average - expected).abs() > expected * RATE_DEVIATION
+    }
+}
+
+/// Computes inter-arrival stats from receive times in order. Needs at least two
+/// samples.
+pub fn inter_arrival(times: &[DateTime<Utc>]) -> Option<InterArrival> {
+    let gaps: Vec<f64> = times
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).num_microseconds().unwrap_or(i64::MAX) as f64 / 1e6)
+        .collect();
+
+    Some(InterArrival {
+        last: *gaps.last()?,
+        average: gaps.iter().sum::<f64>() / gaps.len() as f64,
+    })
+}
+
+/// How fast latency changed between consecutive `(time, latency)` samples,
+/// in ms per second and placed at the later sample, so a climb shows before
+/// the latency itself gets high. Samples are divided by however far apart
+/// they actually were, and pairs at the same instant are skipped rather than
+/// divided by zero. The first sample has nothing to compare with.
+pub fn latency_rate(samples: &[(DateTime<Utc>, f64)]) -> Vec<(DateTime<Utc>, f64)> {
+    samples
+        .windows(2)
+        .filter_map(|pair| {
+            let ((before, from), (at, to)) = (pair[0], pair[1]);
+            let dt = (at - before).num_microseconds()? as f64 / 1e6;
+            if dt <= 0.0 {
+                return None;
+            }
+            Some((at, (to - from) / dt))
+        })
+        .collect()
+}
+
+/// Samples before each one that its deviation is measured against.
+pub const DEVIATION_WINDOW: usize = 30;
+
+/// Each value's z-score against the mean and standard deviation of up to
+/// `window` values before it, so a value is judged by what was normal just
+/// then. `None` for the first two values and wherever those before didn't
+/// vary.
+pub fn deviation_scores(values: &[f64], window: usize) -> Vec<Option<f64>> {
+    (0..values.len())
+        .map(|i| {
+            let recent = &values[i.saturating_sub(window)..i];
+            let stats = recent
+                .iter()
+                .fold(RunningStats::new(), |mut stats, &value| {
+                    stats.push(value);
+                    stats
+                });
+            let std_dev = stats.std_dev().filter(|&std_dev| std_dev > 0.0)?;
+            Some((values[i] - stats.mean()?) / std_dev)
+        })
+        .collect()
+}
+
+/// Consecutive differences averaged into each sample's local jitter.
+pub const JITTER_WINDOW: usize = 10;
+
+/// Mean absolute difference between consecutive latencies, `None` with
+/// fewer than two.
+pub fn mean_jitter(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let total: f64 = values
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .sum();
+    Some(total / (values.len() - 1) as f64)
+}
+
+/// One host's row of the comparison table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostComparison {
+    pub host: String,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Estimated loss in percent.
+    pub loss: f64,
+    pub jitter: Option<f64>,
+}
+
+impl HostComparison {
+    /// Compares the `samples` of `host`, taken every `interval`. `None` when
+    /// there are none.
+    pub fn of(host: &str, samples: &[&PingResult], interval: Duration) -> Option<HostComparison> {
+        let values: Vec<f64> = samples.iter().map(|sample| sample.average).collect();
+        let times: Vec<DateTime<Utc>> = samples.iter().map(|sample| sample.datetime_recv).collect();
+        let stats = values
+            .iter()
+            .fold(RunningStats::new(), |mut stats, &value| {
+                stats.push(value);
+                stats
+            });
+        Some(HostComparison {
+            host: host.to_string(),
+            avg: stats.mean()?,
+            min: stats.min()?,
+            max: stats.max()?,
+            loss: estimated_loss(&times, interval),
+            jitter: mean_jitter(&values),
+        })
+    }
+}
+
+/// Pairs each latency in `values` with its local jitter: the mean absolute
+/// change between consecutive samples over the last `window` changes up to
+/// it. The first sample has no change to go by and is left out.
+pub fn jitter_scatter(values: &[f64], window: usize) -> Vec<(f64, f64)> {
+    let changes: Vec<f64> = values
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .collect();
+    let window = window.max(1);
+    (0..changes.len())
+        .map(|index| {
+            let recent = &changes[(index + 1).saturating_sub(window)..=index];
+            let jitter = recent.iter().sum::<f64>() / recent.len() as f64;
+            (values[index + 1], jitter)
+        })
+        .collect()
+}
+
+/// How long ago a host last answered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LastSeen {
+    pub elapsed: Duration,
+    /// Whether `elapsed` is past the grace period.
+    pub overdue: bool,
+}
+
+impl LastSeen {
+    pub fn new(last: DateTime<Utc>, now: DateTime<Utc>, grace: Duration) -> LastSeen {
+        // A sample stamped slightly in the future counts as just seen
+        let elapsed = (now - last).to_std().unwrap_or_default();
+        LastSeen {
+            elapsed,
+            overdue: elapsed > grace,
+        }
+    }
+}
+
+/// A host staying silent for longer than its grace period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outage {
+    pub host: String,
+    /// When the last sample before the silence came in.
+    pub start: DateTime<Utc>,
+    /// When the first sample after it came in.
+    pub end: DateTime<Utc>,
+}
+
+impl Outage {
+    /// The outage between consecutive samples of `host` received at `last`
+    /// and `next`, if they are more than `grace` apart.
+    pub fn between(
+        host: &str,
+        last: DateTime<Utc>,
+        next: DateTime<Utc>,
+        grace: Duration,
+    ) -> Option<Outage> {
+        LastSeen::new(last, next, grace).overdue.then(|| Outage {
+            host: host.to_string(),
+            start: last,
+            end: next,
+        })
+    }
+
+    pub fn event(&self) -> Event {
+        Event {
+            at: self.end,
+            host: self.host.clone(),
+            message: format!("unreachable for {}s", (self.end - self.start).num_seconds()),
+        }
+    }
+}
+
+/// Samples of one host that never came, found from the gaps between those
+/// that did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Losses {
+    /// Runs of consecutive missed samples, each as one outage from the
+    /// sample before it to the one after.
+    pub runs: Vec<Outage>,
+    /// When each sample missed on its own was due.
+    pub single: Vec<DateTime<Utc>>,
+}
+
+/// The samples missing between consecutive `times` of `host`, taken every
+/// `interval`, with a sample counted as missed once a gap is closer to two
+/// intervals than to one.
+pub fn losses(host: &str, times: &[DateTime<Utc>], interval: Duration) -> Losses {
+    let mut losses = Losses::default();
+    let due = chrono::Duration::from_std(interval).unwrap_or_default();
+    for pair in times.windows(2) {
+        let gap = (pair[1] - pair[0]).num_milliseconds() as f64 / 1000.0;
+        match (gap / interval.as_secs_f64()).round() as usize {
+            0..=1 => {}
+            2 => losses.single.push(pair[0] + due),
+            _ => losses.runs.push(Outage {
+                host: host.to_string(),
+                start: pair[0],
+                end: pair[1],
+            }),
+        }
+    }
+    losses
+}
+
+/// The number of outages starting in each `bucket` long slot from `start` to
+/// `end`, as `(slot start, count)` including the empty slots.
+pub fn outage_rate(
+    outages: &[Outage],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Duration,
+) -> Vec<(DateTime<Utc>, usize)> {
+    let Ok(bucket) = chrono::Duration::from_std(bucket) else {
+        return vec![];
+    };
+    if bucket.num_milliseconds() <= 0 || end < start {
+        return vec![];
+    }
+    let slot =
+        |at: DateTime<Utc>| ((at - start).num_milliseconds() / bucket.num_milliseconds()) as usize;
+
+    let mut counts = vec![0; slot(end) + 1];
+    for outage in outages {
+        if (start..=end).contains(&outage.start) {
+            counts[slot(outage.start)] += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(index, count)| (start + bucket * index as i32, count))
+        .collect()
+}
+
+/// The number of times a host's latency rose above its threshold in each
+/// `bucket` long slot from `start` to `end`, as `(slot start, count)`
+/// including the empty slots. A crossing is a sample over
+/// `threshold(sample)` right after one of the same host at or under it, so
+/// a long stretch over the threshold counts once. Samples without a
+/// threshold end any stretch over one.
+pub fn crossing_rate(
+    samples: &[PingResult],
+    threshold: impl Fn(&PingResult) -> Option<f64>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Duration,
+) -> Vec<(DateTime<Utc>, usize)> {
+    let Ok(bucket) = chrono::Duration::from_std(bucket) else {
+        return vec![];
+    };
+    if bucket.num_milliseconds() <= 0 || end < start {
+        return vec![];
+    }
+    let slot =
+        |at: DateTime<Utc>| ((at - start).num_milliseconds() / bucket.num_milliseconds()) as usize;
+
+    let mut counts = vec![0; slot(end) + 1];
+    let mut above: HashMap<&str, bool> = HashMap::new();
+    for sample in samples {
+        let is_above = threshold(sample).is_some_and(|limit| sample.average > limit);
+        let was_above = above.insert(&sample.host, is_above).unwrap_or(false);
+        if is_above && !was_above && (start..=end).contains(&sample.datetime_recv) {
+            counts[slot(sample.datetime_recv)] += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(index, count)| (start + bucket * index as i32, count))
+        .collect()
+}
+
+/// Samples older than `after` are averaged into one per `bucket`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DownsampleTier {
+    pub after: Duration,
+    pub bucket: Duration,
+}
+
+/// The tiers `--downsample` uses: full resolution for 10 minutes, then 10
+/// second averages for two hours and minute averages after that.
+pub const DOWNSAMPLE_TIERS: [DownsampleTier; 2] = [
+    DownsampleTier {
+        after: Duration::from_secs(10 * 60),
+        bucket: Duration::from_secs(10),
+    },
+    DownsampleTier {
+        after: Duration::from_secs(2 * 60 * 60),
+        bucket: Duration::from_secs(60),
+    },
+];
+
+/// Averages each host's samples into the buckets of every tier they are old
+/// enough for, keeping history in fewer points the older it gets. Buckets are
+/// aligned to the epoch and only merged once wholly past the tier's age, so a
+/// merged sample stays put on later passes. Returns how many samples were
+/// merged away.
+pub fn downsample(
+    data: &mut Vec<PingResult>,
+    now: DateTime<Utc>,
+    tiers: &[DownsampleTier],
+) -> usize {
+    let before = data.len();
+    for tier in tiers {
+        let bucket = tier.bucket.as_millis() as i64;
+        let Some(cutoff) = chrono::Duration::from_std(tier.after)
+            .ok()
+            .and_then(|after| now.checked_sub_signed(after))
+        else {
+            continue;
+        };
+        if bucket == 0 {
+            continue;
+        }
+
+        let mut merged: Vec<(PingResult, usize)> = Vec::with_capacity(data.len());
+        let mut slots: HashMap<(String, i64), usize> = HashMap::new();
+        for sample in data.drain(..) {
+            let slot = sample.datetime_recv.timestamp_millis().div_euclid(bucket);
+            if (slot + 1) * bucket > cutoff.timestamp_millis() {
+                merged.push((sample, 1));
+                continue;
+            }
+            match slots.get(&(sample.host.clone(), slot)) {
+                Some(&index) => {
+                    let (into, count) = &mut merged[index];
+                    *count += 1;
+                    merge_sample(into, &sample, *count);
+                }
+                None => {
+                    slots.insert((sample.host.clone(), slot), merged.len());
+                    let mut sample = sample;
+                    sample.datetime_recv =
+                        DateTime::from_timestamp_millis(slot * bucket + bucket / 2)
+                            .unwrap_or(sample.datetime_recv);
+                    sample.seq = None;
+                    merged.push((sample, 1));
+                }
+            }
+        }
+        data.extend(merged.into_iter().map(|(sample, _)| sample));
+    }
+    before - data.len()
+}
+
+/// Folds `sample` into `into`, the running average of `count` samples.
+fn merge_sample(into: &mut PingResult, sample: &PingResult, count: usize) {
+    let mean = |into: f64, value: f64| into + (value - into) / count as f64;
+    let mean_of = |into: Option<f64>, value: Option<f64>| match (into, value) {
+        (Some(into), Some(value)) => Some(mean(into, value)),
+        (into, value) => into.or(value),
+    };
+    into.average = mean(into.average, sample.average);
+    into.avg = mean_of(into.avg, sample.avg);
+    into.bandwidth = mean_of(into.bandwidth, sample.bandwidth);
+    into.min = match (into.min, sample.min) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    into.max = match (into.max, sample.max) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+}
+
+/// Drops samples received more than `retain` before `now`.
+pub fn trim_older_than(data: &mut Vec<PingResult>, now: DateTime<Utc>, retain: Duration) {
+    // A window reaching past the representable range keeps everything
+    let Some(cutoff) = chrono::Duration::from_std(retain)
+        .ok()
+        .and_then(|retain| now.checked_sub_signed(retain))
+    else {
+        return;
+    };
+    data.retain(|sample| sample.datetime_recv >= cutoff);
+}
+
+/// Drops the oldest samples, whichever host they belong to, until their
+/// [estimated size](PingResult::estimated_size) fits in `budget` bytes, and
+/// returns the estimate for the samples left.
+pub fn trim_to_memory(data: &mut Vec<PingResult>, budget: usize) -> usize {
+    let mut used: usize = data.iter().map(PingResult::estimated_size).sum();
+    let excess = data
+        .iter()
+        .take_while(|sample| {
+            let over = used > budget;
+            if over {
+                used -= sample.estimated_size();
+            }
+            over
+        })
+        .count();
+    data.drain(..excess);
+    used
+}
+
+/// Moves each sample to the same time of day in `tz` on `day`, so earlier days
+/// can be drawn over the same hours of today. Times that `day` skips for a
+/// DST change are dropped.
+pub fn align_time_of_day<'a, Tz: TimeZone>(
+    samples: impl IntoIterator<Item = &'a PingResult>,
+    day: NaiveDate,
+    tz: &Tz,
+) -> Vec<(DateTime<Utc>, f64)> {
+    samples
+        .into_iter()
+        .filter_map(|sample| {
+            let time = sample.datetime_recv.with_timezone(tz).time();
+            let aligned = tz.from_local_datetime(&day.and_time(time)).earliest()?;
+            Some((aligned.with_timezone(&Utc), sample.average))
+        })
+        .collect()
+}
+
+/// Sets aside the first samples of each host, which tend to include ARP and
+/// DNS resolution overhead.
+pub struct Warmup {
+    samples: usize,
+    seen: HashMap<String, usize>,
+}
+
+impl Warmup {
+    /// Skips `samples` samples per host.
+    pub fn new(samples: usize) -> Warmup {
+        Warmup {
+            samples,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Counts `sample` and reports whether it falls within its host's warm-up.
+    pub fn is_warming_up(&mut self, sample: &PingResult) -> bool {
+        let seen = self.seen.entry(sample.host.clone()).or_default();
+        *seen += 1;
+        *seen <= self.samples
+    }
+}
+
+/// Aggregate of one host's samples over a fixed time bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rollup {
+    /// Start of the bucket.
+    pub start: DateTime<Utc>,
+    pub host: String,
+    pub count: usize,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p95: f64,
+    /// Estimated packet loss in percent.
+    pub loss: f64,
+}
+
+/// Groups samples into `bucket`-long windows aligned to the epoch and
+/// aggregates each host's samples per window, sorted by start then host.
+///
+/// Loss is estimated from how many samples `interval_for` says should have
+/// arrived during the part of the bucket the host was being sampled, so the
+/// partial buckets at the start and end of a run don't count as lossy.
+pub fn rollups(
+    samples: &[PingResult],
+    bucket: Duration,
+    interval_for: impl Fn(&str) -> Duration,
+) -> Vec<Rollup> {
+    let bucket_ms = (bucket.as_millis() as i64).max(1);
+    let mut buckets: BTreeMap<(i64, &str), Vec<f64>> = BTreeMap::new();
+    let mut spans: HashMap<&str, (DateTime<Utc>, DateTime<Utc>)> = HashMap::new();
+    for sample in samples {
+        let index = sample
+            .datetime_recv
+            .timestamp_millis()
+            .div_euclid(bucket_ms);
+        buckets
+            .entry((index, &sample.host))
+            .or_default()
+            .push(sample.average);
+
+        let time = sample.datetime_recv;
+        let span = spans.entry(&sample.host).or_insert((time, time));
+        span.0 = span.0.min(time);
+        span.1 = span.1.max(time);
+    }
+
+    buckets
+        .into_iter()
+        .filter_map(|((index, host), mut values)| {
+            let start = DateTime::from_timestamp_millis(index * bucket_ms)?;
+            let end = DateTime::from_timestamp_millis((index + 1) * bucket_ms)?;
+            values.sort_by(f64::total_cmp);
+
+            let count = values.len();
+            let (first, last) = spans[host];
+            let sampled = (end.min(last) - start.max(first)).num_milliseconds() as f64 / 1000.0;
+            let expected =
+                ((sampled / interval_for(host).as_secs_f64()).round() as usize).max(count);
+
+            Some(Rollup {
+                start,
+                host: host.to_string(),
+                count,
+                avg: values.iter().sum::<f64>() / count as f64,
+                min: values[0],
+                max: values[count - 1],
+                p95: percentile(&values, 95.0),
+                loss: 100.0 * (expected - count) as f64 / expected as f64,
+            })
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of already sorted, non-empty `values`.
+fn percentile(sorted: &[f64], percent: f64) -> f64 {
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Samples a shift has to persist for before [`RegimeDetector`] reports it.
+pub const REGIME_HOLD: usize = 10;
+
+/// Smoothing factor of the EWMA [`RegimeDetector`] tracks.
+const REGIME_ALPHA: f64 = 0.3;
+
+/// A sustained step in a host's latency, typically a routing change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegimeChange {
+    /// When the shift started.
+    pub at: DateTime<Utc>,
+    pub host: String,
+    /// Mean latency in ms before and after the shift.
+    pub before: f64,
+    pub after: f64,
+}
+
+impl RegimeChange {
+    pub fn event(&self) -> Event {
+        Event {
+            at: self.at,
+            host: self.host.clone(),
+            message: format!(
+                "latency shifted from {:.1} ms to {:.1} ms",
+                self.before, self.after
+            ),
+        }
+    }
+}
+
+/// Detects latency regime changes per host.
+///
+/// Each host's regime is the mean of its samples since the last change. A
+/// change is reported once the EWMA of the samples has stayed more than
+/// `sensitivity`, as a fraction of that mean, away from it for
+/// [`REGIME_HOLD`] samples in a row; the EWMA then becomes the new regime.
+pub struct RegimeDetector {
+    sensitivity: f64,
+    hosts: HashMap<String, Regime>,
+}
+
+struct Regime {
+    mean: f64,
+    count: usize,
+    ewma: f64,
+    /// Consecutive deviating samples and when the first of them arrived.
+    deviating: usize,
+    since: DateTime<Utc>,
+}
+
+impl RegimeDetector {
+    pub fn new(sensitivity: f64) -> RegimeDetector {
+        RegimeDetector {
+            sensitivity,
+            hosts: HashMap::new(),
+        }
+    }
+
+    /// Feeds in the next sample of its host, returning a change if this
+    /// sample confirms one.
+    pub fn observe(&mut self, sample: &PingResult) -> Option<RegimeChange> {
+        let value = sample.average;
+        let regime = self
+            .hosts
+            .entry(sample.host.clone())
+            .or_insert_with(|| Regime {
+                mean: value,
+                count: 0,
+                ewma: value,
+                deviating: 0,
+                since: sample.datetime_recv,
+            });
+        regime.ewma = REGIME_ALPHA * value + (1.0 - REGIME_ALPHA) * regime.ewma;
+
+        // The first samples only establish the regime
+        let deviates = regime.count >= REGIME_HOLD
+            && (regime.ewma - regime.mean).abs() > self.sensitivity * regime.mean;
+        if !deviates {
+            regime.deviating = 0;
+            regime.count += 1;
+            regime.mean += (value - regime.mean) / regime.count as f64;
+            return None;
+        }
+
+        if regime.deviating == 0 {
+            regime.since = sample.datetime_recv;
+        }
+        regime.deviating += 1;
+        if regime.deviating < REGIME_HOLD {
+            return None;
+        }
+
+        let change = RegimeChange {
+            at: regime.since,
+            host: sample.host.clone(),
+            before: regime.mean,
+            after: regime.ewma,
+        };
+        regime.mean = regime.ewma;
+        regime.count = REGIME_HOLD;
+        regime.deviating = 0;
+        Some(change)
+    }
+}
+
+/// How many `icmp_seq` numbers older than the highest are remembered to tell
+/// late replies from duplicates.
+const SEQUENCE_MEMORY: u32 = 1024;
+
+/// Reply anomalies found from `icmp_seq` numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SequenceCounts {
+    /// Sequence numbers skipped and never seen since.
+    pub dropped: usize,
+    /// Replies that arrived after a later one.
+    pub out_of_order: usize,
+    pub duplicates: usize,
+}
+
+/// Tracks the `icmp_seq` of each host's replies to count drops, reordering
+/// and duplicates exactly, rather than inferring loss from counts.
+#[derive(Default)]
+pub struct SequenceTracker {
+    hosts: HashMap<String, SequenceState>,
+}
+
+struct SequenceState {
+    highest: u32,
+    /// Recently seen numbers, down to [`SEQUENCE_MEMORY`] below `highest`.
+    seen: BTreeSet<u32>,
+    counts: SequenceCounts,
+}
+
+impl SequenceTracker {
+    pub fn new() -> SequenceTracker {
+        SequenceTracker::default()
+    }
+
+    /// Records the sequence number of `sample`, if it has one.
+    pub fn observe(&mut self, sample: &PingResult) {
+        let Some(seq) = sample.seq else {
+            return;
+        };
+        let Some(state) = self.hosts.get_mut(&sample.host) else {
+            self.hosts.insert(
+                sample.host.clone(),
+                SequenceState {
+                    highest: seq,
+                    seen: BTreeSet::from([seq]),
+                    counts: SequenceCounts::default(),
+                },
+            );
+            return;
+        };
+
+        if !state.seen.insert(seq) {
+            state.counts.duplicates += 1;
+        } else if seq > state.highest {
+            state.counts.dropped += (seq - state.highest - 1) as usize;
+            state.highest = seq;
+            let oldest = seq.saturating_sub(SEQUENCE_MEMORY);
+            state.seen = state.seen.split_off(&oldest);
+        } else {
+            // A reply counted as dropped turned up late
+            state.counts.out_of_order += 1;
+            state.counts.dropped = state.counts.dropped.saturating_sub(1);
+        }
+    }
+
+    pub fn counts(&self, host: &str) -> Option<SequenceCounts> {
+        self.hosts.get(host).map(|state| state.counts)
+    }
+}
+
+/// Replies per host whose TTLs are compared by [`TtlTracker`].
+pub const TTL_WINDOW: usize = 20;
+
+/// TTL changes within [`TTL_WINDOW`] replies from which the path is flagged
+/// as unstable. One change is just a reroute.
+pub const TTL_UNSTABLE_CHANGES: usize = 3;
+
+/// How a host's reply TTLs varied over the last [`TTL_WINDOW`] replies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtlStats {
+    /// The most common TTL, the highest of any tie.
+    pub mode: u8,
+    pub min: u8,
+    pub max: u8,
+    pub variance: f64,
+    /// Replies whose TTL differed from the one before.
+    pub changes: usize,
+}
+
+impl TtlStats {
+    /// Whether the TTL changed often enough to suggest the path keeps
+    /// changing, or that replies come back different ways.
+    pub fn unstable(&self) -> bool {
+        self.changes >= TTL_UNSTABLE_CHANGES
+    }
+}
+
+/// Keeps the latest reply TTLs of each host, since a swinging hop count
+/// hints at route changes or asymmetric routing.
+#[derive(Default)]
+pub struct TtlTracker {
+    hosts: HashMap<String, VecDeque<u8>>,
+}
+
+impl TtlTracker {
+    pub fn new() -> TtlTracker {
+        TtlTracker::default()
+    }
+
+    /// Records the TTL of `sample`, if it has one.
+    pub fn observe(&mut self, sample: &PingResult) {
+        let Some(ttl) = sample.ttl else {
+            return;
+        };
+        let ttls = self.hosts.entry(sample.host.clone()).or_default();
+        if ttls.len() == TTL_WINDOW {
+            ttls.pop_front();
+        }
+        ttls.push_back(ttl);
+    }
+
+    pub fn stats(&self, host: &str) -> Option<TtlStats> {
+        let ttls = self.hosts.get(host)?;
+        let mut counts: BTreeMap<u8, usize> = BTreeMap::new();
+        for &ttl in ttls {
+            *counts.entry(ttl).or_default() += 1;
+        }
+        let (&mode, _) = counts.iter().max_by_key(|&(_, count)| count)?;
+        let mut stats = RunningStats::new();
+        for &ttl in ttls {
+            stats.push(ttl as f64);
+        }
+        Some(TtlStats {
+            mode,
+            min: *counts.keys().next()?,
+            max: *counts.keys().next_back()?,
+            variance: stats.variance().unwrap_or(0.0),
+            changes: ttls
+                .iter()
+                .zip(ttls.iter().skip(1))
+                .filter(|(a, b)| a != b)
+                .count(),
+        })
+    }
+}
+
+/// Smallest latency in ms [`LogHistogram`] tells apart; anything faster
+/// lands in the first bucket.
+const HISTOGRAM_MIN: f64 = 0.001;
+
+/// Histogram with buckets growing geometrically, so sub-millisecond and
+/// multi-second latencies are both resolved to within the same relative
+/// error: half of `growth - 1`, e.g. 2.5% for 1.05.
+#[derive(Debug, Clone)]
+pub struct LogHistogram {
+    growth: f64,
+    /// Samples per bucket index; bucket `i` holds values up to
+    /// `HISTOGRAM_MIN * growth^i`.
+    counts: BTreeMap<u32, u64>,
+    total: u64,
+}
+
+impl LogHistogram {
+    pub fn new(growth: f64) -> LogHistogram {
+        LogHistogram {
+            growth,
+            counts: BTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    pub fn from_values(growth: f64, values: impl IntoIterator<Item = f64>) -> LogHistogram {
+        let mut histogram = LogHistogram::new(growth);
+        for value in values {
+            histogram.record(value);
+        }
+        histogram
+    }
+
+    pub fn record(&mut self, value: f64) {
+        let index = ((value / HISTOGRAM_MIN).ln() / self.growth.ln()).ceil();
+        *self.counts.entry(index.max(0.0) as u32).or_default() += 1;
+        self.total += 1;
+    }
+
+    pub fn len(&self) -> u64 {
+        self.total
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    /// Non-empty buckets as `(low, high, count)`, lowest first.
+    pub fn buckets(&self) -> impl Iterator<Item = (f64, f64, u64)> + '_ {
+        self.counts.iter().map(|(&index, &count)| {
+            let (low, high) = self.bounds(index);
+            (low, high, count)
+        })
+    }
+
+    /// The latency `percent` of samples are at or below, `None` when empty.
+    /// Taken as the geometric middle of the bucket holding that rank.
+    pub fn percentile(&self, percent: f64) -> Option<f64> {
+        if self.is_empty() {
+            return None;
+        }
+        let rank = ((percent / 100.0 * self.total as f64).ceil() as u64).clamp(1, self.total);
+        let mut seen = 0;
+        for (&index, &count) in &self.counts {
+            seen += count;
+            if seen >= rank {
+                let (low, high) = self.bounds(index);
+                return Some((low * high).sqrt());
+            }
+        }
+        None
+    }
+
+    fn bounds(&self, index: u32) -> (f64, f64) {
+        let high = HISTOGRAM_MIN * self.growth.powi(index as i32);
+        (high / self.growth, high)
+    }
+}
+
+/// Count, mean, variance and extremes kept up to date one value at a time
+/// with Welford's algorithm, without holding on to the values.
+#[derive(Debug, Clone, Default)]
+pub struct RunningStats {
+    count: usize,
+    mean: f64,
+    /// Sum of squared differences from the mean.
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl RunningStats {
+    pub fn new() -> RunningStats {
+        RunningStats::default()
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.count == 0 {
+            (self.min, self.max) = (value, value);
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// Sample variance, `None` below two values.
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 1).then(|| self.m2 / (self.count - 1) as f64)
+    }
+
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+}
+
+/// Estimates one percentile of a stream in constant memory with the P²
+/// algorithm of Jain and Chlamtac, which moves five markers towards the
+/// minimum, the maximum, the percentile and the points halfway to it.
+#[derive(Debug, Clone)]
+pub struct P2Quantile {
+    /// The percentile as a fraction.
+    p: f64,
+    count: usize,
+    /// Marker heights, which hold the first five values as they arrive.
+    heights: [f64; 5],
+    /// Marker positions, 1-based.
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    pub fn new(percent: f64) -> P2Quantile {
+        let p = percent / 100.0;
+        P2Quantile {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        let q = &mut self.heights;
+        let k = if value < q[0] {
+            q[0] = value;
+            0
+        } else if value >= q[4] {
+            q[4] = value;
+            3
+        } else {
+            (0..4).find(|&i| value < q[i + 1]).unwrap_or(3)
+        };
+        for position in &mut self.positions[k + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+
+        let n = &mut self.positions;
+        for i in 1..4 {
+            let d = self.desired[i] - n[i];
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let parabolic = q[i]
+                    + d / (n[i + 1] - n[i - 1])
+                        * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                            + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]));
+                q[i] = if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                    parabolic
+                } else {
+                    // Linear towards the neighbour in the direction of travel
+                    let j = if d > 0.0 { i + 1 } else { i - 1 };
+                    q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+                };
+                n[i] += d;
+            }
+        }
+    }
+
+    /// The current estimate, exact until more than five values came in and
+    /// `None` before any did.
+    pub fn estimate(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            count if count <= 5 => {
+                let mut values = self.heights[..count].to_vec();
+                values.sort_by(f64::total_cmp);
+                Some(percentile(&values, self.p * 100.0))
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+}
+
+/// A latency and when it was measured.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extreme {
+    pub value: f64,
+    pub at: DateTime<Utc>,
+}
+
+/// The best and worst sample of a whole session.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extremes {
+    pub min: Extreme,
+    pub max: Extreme,
+}
+
+impl Extremes {
+    fn new(sample: &PingResult) -> Extremes {
+        let extreme = Extreme {
+            value: sample.average,
+            at: sample.datetime_recv,
+        };
+        Extremes {
+            min: extreme,
+            max: extreme,
+        }
+    }
+
+    /// Takes in `sample`. Ties keep the earlier one.
+    pub fn record(&mut self, sample: &PingResult) {
+        let extreme = Extreme {
+            value: sample.average,
+            at: sample.datetime_recv,
+        };
+        if extreme.value < self.min.value {
+            self.min = extreme;
+        }
+        if extreme.value > self.max.value {
+            self.max = extreme;
+        }
+    }
+}
+
+/// Each host's all-time [`Extremes`], kept apart from the samples so that
+/// trimming them loses nothing.
+#[derive(Debug, Default)]
+pub struct ExtremesTracker {
+    hosts: HashMap<String, Extremes>,
+}
+
+impl ExtremesTracker {
+    pub fn new() -> ExtremesTracker {
+        ExtremesTracker::default()
+    }
+
+    pub fn observe(&mut self, sample: &PingResult) {
+        match self.hosts.get_mut(&sample.host) {
+            Some(extremes) => extremes.record(sample),
+            None => {
+                self.hosts
+                    .insert(sample.host.clone(), Extremes::new(sample));
+            }
+        }
+    }
+
+    pub fn get(&self, host: &str) -> Option<Extremes> {
+        self.hosts.get(host).copied()
+    }
+}
+
+/// Length of the periods availability is judged over.
+pub const AVAILABILITY_PERIOD: Duration = Duration::from_secs(60);
+
+/// Share of time a host was available, in percent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Availability {
+    /// Periods in which any sample arrived, however many were lost.
+    pub strict: f64,
+    /// Periods weighted by the share of their samples that arrived, so a
+    /// period that lost half counts as half available.
+    pub weighted: f64,
+}
+
+/// Counts a host's samples per [`AVAILABILITY_PERIOD`], aligned to the
+/// epoch, to judge its availability without keeping the samples.
+#[derive(Debug, Clone, Default)]
+pub struct AvailabilityCounter {
+    periods: BTreeMap<i64, usize>,
+    span: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl AvailabilityCounter {
+    pub fn new() -> AvailabilityCounter {
+        AvailabilityCounter::default()
+    }
+
+    pub fn record(&mut self, at: DateTime<Utc>) {
+        let period_ms = AVAILABILITY_PERIOD.as_millis() as i64;
+        *self
+            .periods
+            .entry(at.timestamp_millis().div_euclid(period_ms))
+            .or_default() += 1;
+        self.record_lost(at);
+    }
+
+    /// Notes a sample at `at` that counts as lost, so the time is judged
+    /// without it counting as available.
+    pub fn record_lost(&mut self, at: DateTime<Utc>) {
+        self.span = Some(match self.span {
+            Some((first, last)) => (first.min(at), last.max(at)),
+            None => (at, at),
+        });
+    }
+
+    /// Availability over every period from the first sample to the last,
+    /// expecting one sample per `interval`. As in [`rollups`] only the part
+    /// of the first and last period the host was sampled for counts, and
+    /// periods without any sample are fully unavailable.
+    pub fn availability(&self, interval: Duration) -> Option<Availability> {
+        let (first, last) = self.span?;
+        let period_ms = AVAILABILITY_PERIOD.as_millis() as i64;
+        let (from, to) = (
+            first.timestamp_millis().div_euclid(period_ms),
+            last.timestamp_millis().div_euclid(period_ms),
+        );
+        let (mut up, mut weighted) = (0, 0.0);
+        for index in from..=to {
+            let count = self.periods.get(&index).copied().unwrap_or(0);
+            let start = DateTime::from_timestamp_millis(index * period_ms)?;
+            let end = DateTime::from_timestamp_millis((index + 1) * period_ms)?;
+            let sampled = (end.min(last) - start.max(first)).num_milliseconds() as f64 / 1000.0;
+            let expected = ((sampled / interval.as_secs_f64()).round() as usize)
+                .max(count)
+                .max(1);
+            if count > 0 {
+                up += 1;
+            }
+            weighted += count as f64 / expected as f64;
+        }
+        let periods = (to - from + 1) as f64;
+        Some(Availability {
+            strict: 100.0 * up as f64 / periods,
+            weighted: 100.0 * weighted / periods,
+        })
+    }
+}
+
+/// Bounded-memory summary of one host's samples, for `--stats-only`.
+#[derive(Debug, Clone)]
+pub struct StreamingSummary {
+    pub stats: RunningStats,
+    pub p50: P2Quantile,
+    pub p95: P2Quantile,
+    pub p99: P2Quantile,
+    pub availability: AvailabilityCounter,
+    /// Only known for samples taken in with [`StreamingSummary::record`].
+    pub extremes: Option<Extremes>,
+    /// Latency in ms above which recorded samples count as lost.
+    pub loss_cutoff: Option<f64>,
+    /// Samples left out for coming in over `loss_cutoff`.
+    pub late: usize,
+}
+
+impl StreamingSummary {
+    pub fn new() -> StreamingSummary {
+        StreamingSummary {
+            stats: RunningStats::new(),
+            p50: P2Quantile::new(50.0),
+            p95: P2Quantile::new(95.0),
+            p99: P2Quantile::new(99.0),
+            availability: AvailabilityCounter::new(),
+            extremes: None,
+            loss_cutoff: None,
+            late: 0,
+        }
+    }
+
+    /// Counts recorded samples over `cutoff` ms as lost rather than taking
+    /// them in.
+    pub fn with_loss_cutoff(mut self, cutoff: Option<f64>) -> StreamingSummary {
+        self.loss_cutoff = cutoff;
+        self
+    }
+
+    /// Takes in `sample`'s latency and when it arrived, unless it's over the
+    /// loss cutoff.
+    pub fn record(&mut self, sample: &PingResult) {
+        if self
+            .loss_cutoff
+            .is_some_and(|cutoff| sample.average > cutoff)
+        {
+            self.late += 1;
+            self.availability.record_lost(sample.datetime_recv);
+            return;
+        }
+        self.push(sample.average);
+        self.availability.record(sample.datetime_recv);
+        match &mut self.extremes {
+            Some(extremes) => extremes.record(sample),
+            None => self.extremes = Some(Extremes::new(sample)),
+        }
+    }
+
+    /// Takes in a latency only, leaving availability and extremes out.
+    pub fn push(&mut self, value: f64) {
+        self.stats.push(value);
+        for quantile in [&mut self.p50, &mut self.p95, &mut self.p99] {
+            quantile.push(value);
+        }
+    }
+}
+
+impl Default for StreamingSummary {
+    fn default() -> Self {
+        StreamingSummary::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An instant `seconds` into 2024.
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap()
+    }
+
+    fn sample(host: &str, average: f64, seconds: i64) -> PingResult {
+        PingResult::new(host.to_string(), average, at(seconds))
+    }
+
+    #[test]
+    fn retention_drops_samples_older_than_the_window() {
+        let mut data: Vec<PingResult> = (0..10).map(|i| sample("a", 10.0, i * 10)).collect();
+        trim_older_than(&mut data, at(100), Duration::from_secs(30));
+        let kept: Vec<DateTime<Utc>> = data.iter().map(|sample| sample.datetime_recv).collect();
+        assert_eq!(kept, [at(70), at(80), at(90)]);
+
+        trim_older_than(&mut data, at(100), Duration::MAX);
+        assert_eq!(data.len(), 3);
+    }
+
+    #[test]
+    fn inter_arrival_averages_the_gaps() {
+        let times = [at(0), at(1), at(3), at(6)];
+        let gaps = inter_arrival(&times).unwrap();
+        assert_eq!(gaps.last, 3.0);
+        assert_eq!(gaps.average, 2.0);
+        assert!(gaps.deviates_from(Duration::from_secs(1)));
+        assert!(!gaps.deviates_from(Duration::from_secs(2)));
+        assert_eq!(inter_arrival(&[at(0)]), None);
+    }
+
+    #[test]
+    fn warmup_samples_are_kept_out_of_the_average() {
+        let mut warmup = Warmup::new(2);
+        let samples = [
+            sample("a", 300.0, 0),
+            sample("b", 250.0, 0),
+            sample("a", 120.0, 1),
+            sample("a", 10.0, 2),
+            sample("b", 20.0, 1),
+            sample("a", 20.0, 3),
+            sample("b", 30.0, 2),
+        ];
+        let kept: Vec<&PingResult> = samples
+            .iter()
+            .filter(|sample| !warmup.is_warming_up(sample))
+            .collect();
+        let average = |host: &str| {
+            let values: Vec<f64> = kept
+                .iter()
+                .filter(|sample| sample.host == host)
+                .map(|sample| sample.average)
+                .collect();
+            values.iter().sum::<f64>() / values.len() as f64
+        };
+        assert_eq!(kept.len(), 3);
+        assert_eq!(average("a"), 15.0);
+        assert_eq!(average("b"), 30.0);
+    }
+
+    #[test]
+    fn latency_budget_counts_samples_within_it() {
+        let budget = LatencyBudget {
+            latency: 100.0,
+            target: 75.0,
+        };
+        let met = budget
+            .met_percent(&[20.0, 100.0, 140.0, 80.0, 300.0])
+            .unwrap();
+        assert_eq!(met, 60.0);
+        assert!(!budget.is_met(met));
+        assert!(budget.is_met(75.0));
+        assert_eq!(budget.met_percent(&[]), None);
+    }
+
+    #[test]
+    fn two_minutes_of_samples_roll_up_into_two_rows() {
+        let samples: Vec<PingResult> = (0..120)
+            .map(|i| sample("a", if i < 60 { 10.0 } else { 30.0 }, i))
+            .collect();
+        let rows = rollups(&samples, Duration::from_secs(60), |_| {
+            Duration::from_secs(1)
+        });
+        assert_eq!(rows.len(), 2);
+        assert_eq!((rows[0].start, rows[1].start), (at(0), at(60)));
+        assert_eq!((rows[0].count, rows[1].count), (60, 60));
+        assert_eq!((rows[0].avg, rows[1].avg), (10.0, 30.0));
+        assert!(rows.iter().all(|row| row.loss == 0.0));
+    }
+
+    #[test]
+    fn last_seen_is_overdue_past_the_grace_period() {
+        let grace = Duration::from_secs(3);
+        let within = LastSeen::new(at(0), at(3), grace);
+        assert_eq!(within.elapsed, Duration::from_secs(3));
+        assert!(!within.overdue);
+        assert!(LastSeen::new(at(0), at(4), grace).overdue);
+        // A sample stamped after `now` was just seen
+        assert_eq!(LastSeen::new(at(5), at(4), grace).elapsed, Duration::ZERO);
+
+        let outage = Outage::between("a", at(0), at(10), grace).unwrap();
+        assert_eq!((outage.start, outage.end), (at(0), at(10)));
+        assert_eq!(Outage::between("a", at(0), at(2), grace), None);
+    }
+
+    #[test]
+    fn detects_a_step_change_once() {
+        let mut detector = RegimeDetector::new(0.2);
+        let changes: Vec<RegimeChange> = (0..60)
+            .map(|i| sample("a", if i < 20 { 10.0 } else { 50.0 }, i))
+            .filter_map(|sample| detector.observe(&sample))
+            .collect();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].at, at(20));
+        assert_eq!(changes[0].before, 10.0);
+        assert!((changes[0].after - 50.0).abs() < 2.0);
+    }
+
+    #[test]
+    fn steady_latency_has_no_regime_changes() {
+        let mut detector = RegimeDetector::new(0.2);
+        let noisy = [10.0, 11.0, 9.0, 10.5, 9.5];
+        assert!((0..100)
+            .map(|i| sample("a", noisy[i % noisy.len()], i as i64))
+            .all(|sample| detector.observe(&sample).is_none()));
+    }
+
+    #[test]
+    fn counts_gaps_reordering_and_duplicates() {
+        let mut tracker = SequenceTracker::new();
+        for (i, seq) in [0, 1, 2, 5, 3, 3, 6, 7].into_iter().enumerate() {
+            let mut sample = sample("a", 10.0, i as i64);
+            sample.seq = Some(seq);
+            tracker.observe(&sample);
+        }
+        // 3 and 4 were skipped, 3 turned up late and then again
+        assert_eq!(
+            tracker.counts("a"),
+            Some(SequenceCounts {
+                dropped: 1,
+                out_of_order: 1,
+                duplicates: 1,
+            })
+        );
+        assert_eq!(tracker.counts("b"), None);
+    }
+
+    #[test]
+    fn histogram_percentiles_match_exact_ones() {
+        // Spread over four orders of magnitude
+        let values: Vec<f64> = (1..=2000).map(|i| 0.5 * 1.004_f64.powi(i)).collect();
+        let histogram = LogHistogram::from_values(1.05, values.iter().copied());
+        assert_eq!(histogram.len(), 2000);
+        for percent in [1.0, 50.0, 90.0, 99.0, 99.9] {
+            let exact = percentile(&values, percent);
+            let estimate = histogram.percentile(percent).unwrap();
+            assert!(
+                (estimate - exact).abs() <= exact * 0.05,
+                "p{percent}: {estimate} vs {exact}"
+            );
+        }
+        assert_eq!(LogHistogram::new(1.05).percentile(50.0), None);
+    }
+
+    #[test]
+    fn aligns_days_by_time_of_day() {
+        let day = |d: i64, hours: i64, minutes: i64| d * 86_400 + hours * 3600 + minutes * 60;
+        let yesterday = [
+            sample("a", 10.0, day(0, 9, 0)),
+            sample("a", 20.0, day(0, 17, 30)),
+        ];
+        let today = [
+            sample("a", 15.0, day(1, 9, 0)),
+            sample("a", 25.0, day(1, 17, 30)),
+        ];
+        let on = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let aligned = align_time_of_day(&yesterday, on, &Utc);
+        assert_eq!(
+            aligned,
+            [(at(day(1, 9, 0)), 10.0), (at(day(1, 17, 30)), 20.0)]
+        );
+        let times =
+            |series: &[(DateTime<Utc>, f64)]| series.iter().map(|(at, _)| *at).collect::<Vec<_>>();
+        assert_eq!(times(&aligned), times(&align_time_of_day(&today, on, &Utc)));
+
+        // Local 02:30 doesn't exist on the day clocks spring forward
+        let zone: chrono_tz::Tz = "America/New_York".parse().unwrap();
+        let night = PingResult::new(
+            "a".to_string(),
+            10.0,
+            DateTime::parse_from_rfc3339("2024-03-09T07:30:00Z")
+                .unwrap()
+                .to_utc(),
+        );
+        let spring = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        assert!(align_time_of_day([&night], spring, &zone).is_empty());
+    }
+
+    #[test]
+    fn trimming_keeps_memory_under_the_budget() {
+        let mut data: Vec<PingResult> = (0..100)
+            .map(|i| sample(if i % 2 == 0 { "a" } else { "b" }, i as f64, i))
+            .collect();
+        let size = data[0].estimated_size();
+        let budget = size * 30 + size / 2;
+        let used = trim_to_memory(&mut data, budget);
+        assert!(used <= budget);
+        assert_eq!(
+            used,
+            data.iter().map(PingResult::estimated_size).sum::<usize>()
+        );
+        assert_eq!(data.len(), 30);
+        // The oldest go first, whichever host they're from
+        assert_eq!(data[0].average, 70.0);
+        assert_eq!(trim_to_memory(&mut data, usize::MAX), used);
+        assert_eq!(data.len(), 30);
+    }
+
+    #[test]
+    fn streaming_stats_match_exact_ones() {
+        // 0 to 999 ms in a scrambled order
+        let values: Vec<f64> = (0..1000).map(|i| (i * 7919 % 1000) as f64).collect();
+        let mut summary = StreamingSummary::new();
+        for &value in &values {
+            summary.push(value);
+        }
+
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance = values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / (values.len() - 1) as f64;
+        assert!((summary.stats.mean().unwrap() - mean).abs() < 1e-9);
+        assert!((summary.stats.variance().unwrap() - variance).abs() < 1e-6);
+        assert_eq!(summary.stats.min(), Some(0.0));
+        assert_eq!(summary.stats.max(), Some(999.0));
+
+        let mut sorted = values.clone();
+        sorted.sort_by(f64::total_cmp);
+        for (quantile, percent) in [
+            (&summary.p50, 50.0),
+            (&summary.p95, 95.0),
+            (&summary.p99, 99.0),
+        ] {
+            let exact = percentile(&sorted, percent);
+            let estimate = quantile.estimate().unwrap();
+            assert!(
+                (estimate - exact).abs() < 20.0,
+                "p{percent}: {estimate} vs {exact}"
+            );
+        }
+    }
+
+    #[test]
+    fn counts_outages_per_bucket() {
+        let outage = |start: i64| Outage {
+            host: "a".to_string(),
+            start: at(start),
+            end: at(start + 30),
+        };
+        let outages = [
+            outage(10),
+            outage(200),
+            outage(290),
+            outage(700),
+            outage(2000),
+        ];
+        let rate = outage_rate(&outages, at(0), at(900), Duration::from_secs(300));
+        assert_eq!(rate, [(at(0), 3), (at(300), 0), (at(600), 1), (at(900), 0)]);
+        assert!(outage_rate(&outages, at(900), at(0), Duration::from_secs(300)).is_empty());
+        assert!(outage_rate(&outages, at(0), at(900), Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn downsampling_keeps_recent_data_at_full_resolution() {
+        let mut data: Vec<PingResult> = (0..3600)
+            .flat_map(|i| [sample("a", i as f64, i), sample("b", 5.0, i)])
+            .collect();
+        let merged = downsample(&mut data, at(3600), &DOWNSAMPLE_TIERS);
+        // The first 50 minutes go into 10 second buckets per host
+        assert_eq!(merged, 2 * (3000 - 300));
+        assert_eq!(data.len(), 2 * (300 + 600));
+        let a: Vec<&PingResult> = data.iter().filter(|sample| sample.host == "a").collect();
+        assert_eq!((a[0].datetime_recv, a[0].average), (at(5), 4.5));
+        assert_eq!((a[299].datetime_recv, a[299].average), (at(2995), 2994.5));
+        let recent: Vec<f64> = a[300..].iter().map(|sample| sample.average).collect();
+        assert_eq!(recent, (3000..3600).map(|i| i as f64).collect::<Vec<_>>());
+        assert!(data
+            .iter()
+            .filter(|sample| sample.host == "b")
+            .all(|sample| sample.average == 5.0));
+
+        // Nothing changes on another pass at the same time
+        assert_eq!(downsample(&mut data, at(3600), &DOWNSAMPLE_TIERS), 0);
+    }
+
+    #[test]
+    fn scatters_latency_against_local_jitter() {
+        let values = [10.0, 12.0, 8.0, 8.0, 20.0];
+        assert_eq!(
+            jitter_scatter(&values, 2),
+            [(12.0, 2.0), (8.0, 3.0), (8.0, 2.0), (20.0, 6.0)]
+        );
+        assert_eq!(jitter_scatter(&values, 0)[1], (8.0, 4.0));
+        assert!(jitter_scatter(&[10.0], 2).is_empty());
+    }
+
+    #[test]
+    fn frequent_ttl_changes_flag_the_path_unstable() {
+        let mut tracker = TtlTracker::new();
+        let observe = |tracker: &mut TtlTracker, ttls: &[u8]| {
+            for &ttl in ttls {
+                let mut sample = sample("a", 10.0, 0);
+                sample.ttl = Some(ttl);
+                tracker.observe(&sample);
+            }
+        };
+        // One reroute is not instability
+        observe(&mut tracker, &[57, 57, 57, 57, 55, 55]);
+        let stats = tracker.stats("a").unwrap();
+        assert_eq!(
+            (stats.mode, stats.min, stats.max, stats.changes),
+            (57, 55, 57, 1)
+        );
+        assert!(!stats.unstable());
+
+        observe(&mut tracker, &[57, 55, 57]);
+        let stats = tracker.stats("a").unwrap();
+        assert_eq!((stats.mode, stats.changes), (57, 4));
+        assert!(stats.unstable());
+
+        // The swings age out of the window
+        observe(&mut tracker, &[57; TTL_WINDOW]);
+        assert_eq!(tracker.stats("a").unwrap().changes, 0);
+        assert_eq!(tracker.stats("a").unwrap().variance, 0.0);
+        assert!(tracker.stats("b").is_none());
+    }
+
+    #[test]
+    fn weighted_availability_counts_partial_loss() {
+        let mut counter = AvailabilityCounter::new();
+        assert_eq!(counter.availability(Duration::from_secs(10)), None);
+        // A full minute, one at half loss, a silent one and another full one
+        for seconds in (0..60)
+            .step_by(10)
+            .chain([60, 70, 80])
+            .chain((180..240).step_by(10))
+        {
+            counter.record(at(seconds));
+        }
+        assert_eq!(
+            counter.availability(Duration::from_secs(10)),
+            Some(Availability {
+                strict: 75.0,
+                weighted: 62.5
+            })
+        );
+    }
+
+    #[test]
+    fn samples_over_the_loss_cutoff_count_as_lost() {
+        let thresholds = Thresholds {
+            latency: None,
+            loss: 10.0,
+            loss_cutoff: Some(1000.0),
+        };
+        assert!(!thresholds.counts_as_lost(1000.0));
+        assert!(thresholds.counts_as_lost(3000.0));
+
+        // Every other sample of the second minute is 3 s late
+        let samples: Vec<PingResult> = (0..12)
+            .map(|i| {
+                sample(
+                    "a",
+                    if i >= 6 && i % 2 == 0 { 3000.0 } else { 20.0 },
+                    i * 10,
+                )
+            })
+            .collect();
+        let mut summary = StreamingSummary::new().with_loss_cutoff(thresholds.loss_cutoff);
+        for sample in &samples {
+            summary.record(sample);
+        }
+        assert_eq!((summary.stats.count(), summary.late), (9, 3));
+        assert_eq!(summary.stats.max(), Some(20.0));
+        let availability = summary
+            .availability
+            .availability(Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(availability.strict, 100.0);
+        // Three of the five due in the last 50 s arrived in time
+        assert_eq!(availability.weighted, 80.0);
+        assert_eq!(
+            assess_health(&samples, "a", 12, &thresholds),
+            Health::Degraded
+        );
+        let lenient = Thresholds {
+            loss_cutoff: None,
+            ..thresholds
+        };
+        assert_eq!(assess_health(&samples, "a", 12, &lenient), Health::Healthy);
+    }
+
+    #[test]
+    fn injected_outliers_fall_outside_the_bounds() {
+        let mut values = vec![20.0, 21.0, 19.0, 22.0, 20.0, 18.0, 21.0, 20.0];
+        values.insert(4, 10_000.0);
+        // Median 20 and median absolute deviation 1
+        assert_eq!(outlier_bounds(&values, 3.0), Some((17.0, 23.0)));
+        let (low, high) = outlier_bounds(&values, 3.0).unwrap();
+        let kept: Vec<f64> = values
+            .iter()
+            .copied()
+            .filter(|value| (low..=high).contains(value))
+            .collect();
+        assert_eq!(kept.len(), values.len() - 1);
+        assert_eq!(kept.iter().sum::<f64>() / kept.len() as f64, 20.125);
+
+        assert_eq!(outlier_bounds(&[], 3.0), None);
+        assert_eq!(outlier_bounds(&[5.0, 5.0, 5.0, 80.0], 3.0), None);
+    }
+
+    #[test]
+    fn counts_threshold_crossings_per_bucket() {
+        // a goes over 50 ms at 10s, 40s, 70s and 110s
+        let latencies = [
+            10.0, 60.0, 70.0, 10.0, 80.0, 10.0, 10.0, 90.0, 90.0, 90.0, 10.0, 60.0,
+        ];
+        let mut samples: Vec<PingResult> = latencies
+            .iter()
+            .enumerate()
+            .map(|(i, &latency)| sample("a", latency, i as i64 * 10))
+            .collect();
+        // b's samples neither end nor start a's stretches, but cross on their own
+        samples.insert(2, sample("b", 10.0, 15));
+        samples.insert(9, sample("b", 70.0, 75));
+        let crossings = crossing_rate(
+            &samples,
+            |_| Some(50.0),
+            at(0),
+            at(119),
+            Duration::from_secs(60),
+        );
+        assert_eq!(crossings, [(at(0), 2), (at(60), 3)]);
+
+        // Without a threshold nothing crosses, and the stretch starts over
+        let limit = |sample: &PingResult| (sample.datetime_recv != at(80)).then_some(50.0);
+        let crossings = crossing_rate(&samples, limit, at(0), at(119), Duration::from_secs(60));
+        assert_eq!(crossings, [(at(0), 2), (at(60), 4)]);
+
+        let windowed = crossing_rate(
+            &samples,
+            |_| Some(50.0),
+            at(60),
+            at(119),
+            Duration::from_secs(30),
+        );
+        assert_eq!(windowed, [(at(60), 2), (at(90), 1)]);
+        assert!(crossing_rate(&samples, |_| Some(50.0), at(0), at(119), Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn moving_aggregates_cover_their_window() {
+        let values = [10.0, 50.0, 20.0, 30.0, 100.0];
+        let expected: [(Aggregate, [f64; 5]); 4] = [
+            (Aggregate::Mean, [10.0, 30.0, 80.0 / 3.0, 100.0 / 3.0, 50.0]),
+            (Aggregate::Median, [10.0, 30.0, 20.0, 30.0, 30.0]),
+            (Aggregate::Max, [10.0, 50.0, 50.0, 50.0, 100.0]),
+            (Aggregate::Min, [10.0, 10.0, 10.0, 20.0, 20.0]),
+        ];
+        for (aggregate, series) in expected {
+            assert_eq!(moving(&values, 3, aggregate), series, "{aggregate:?}");
+            assert_eq!(Aggregate::parse(aggregate.name()), Ok(aggregate));
+        }
+        // A window of 0 or 1 leaves the series as it is
+        assert_eq!(moving(&values, 0, Aggregate::Median), values);
+        assert_eq!(moving(&values, 1, Aggregate::Mean), values);
+        assert!(moving(&[], 3, Aggregate::Max).is_empty());
+        assert!(Aggregate::parse("mode").is_err());
+    }
+
+    #[test]
+    fn latency_rate_divides_by_the_actual_gap() {
+        let series = [
+            (at(0), 10.0),
+            (at(1), 20.0),
+            (at(3), 10.0),
+            // Same instant as the one before, skipped
+            (at(3), 50.0),
+            (at(7), 90.0),
+        ];
+        assert_eq!(
+            latency_rate(&series),
+            [(at(1), 10.0), (at(3), -5.0), (at(7), 10.0)]
+        );
+        let half_second = at(7) + chrono::Duration::milliseconds(500);
+        assert_eq!(
+            latency_rate(&[(at(7), 90.0), (half_second, 95.0)]),
+            [(half_second, 10.0)]
+        );
+        assert!(latency_rate(&series[..1]).is_empty());
+    }
+
+    #[test]
+    fn consecutive_losses_are_one_region() {
+        // Every second, but 30 missed from 10s and one on its own at 50s
+        let times: Vec<DateTime<Utc>> = (0..=60)
+            .filter(|&i| !(11..=40).contains(&i) && i != 50)
+            .map(at)
+            .collect();
+        let missed = losses("a", &times, Duration::from_secs(1));
+        assert_eq!(
+            missed.runs,
+            [Outage {
+                host: "a".to_string(),
+                start: at(10),
+                end: at(41),
+            }]
+        );
+        assert_eq!(missed.single, [at(50)]);
+        assert_eq!(missed.runs[0].event().message, "unreachable for 31s");
+
+        // Jitter short of a whole missed interval isn't a loss
+        let late = |seconds, ms| at(seconds) + chrono::Duration::milliseconds(ms);
+        let jittery = [at(0), late(1, 400), late(2, 300), at(3)];
+        assert_eq!(
+            losses("a", &jittery, Duration::from_secs(1)),
+            Losses::default()
+        );
+    }
+}
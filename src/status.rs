@@ -0,0 +1,138 @@
+//! The compact `--status-line` output, such as `8.8.8.8 23ms ▁▂▃▅▂▁ 0%`, for
+//! status bars like tmux or polybar.
+
+use crate::ascii::to_ascii;
+use crate::ping::PingResult;
+use crate::stats::estimated_loss;
+use chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Recent samples per host drawn in the sparkline and counted for loss.
+pub const STATUS_SAMPLES: usize = 8;
+
+/// The sparkline characters from lowest to highest.
+pub const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Stand-ins for [`SPARK_BLOCKS`] on terminals limited to ASCII.
+pub const ASCII_SPARK_BLOCKS: [char; 4] = ['_', '.', '-', '^'];
+
+/// One character per value, scaled between the lowest and highest of them.
+/// A flat series is drawn with the lowest character.
+pub fn sparkline(values: &[f64], blocks: &[char]) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = blocks.len() - 1;
+    values
+        .iter()
+        .map(|value| {
+            let level = if max > min {
+                ((value - min) / (max - min) * top as f64).round() as usize
+            } else {
+                0
+            };
+            blocks[level.min(top)]
+        })
+        .collect()
+}
+
+/// The latest samples of each host, in the order hosts were first seen.
+#[derive(Debug, Default)]
+pub struct StatusLine {
+    hosts: Vec<(String, VecDeque<PingResult>)>,
+}
+
+impl StatusLine {
+    pub fn new() -> StatusLine {
+        StatusLine::default()
+    }
+
+    pub fn record(&mut self, ping: &PingResult) {
+        let index = match self.hosts.iter().position(|(host, _)| *host == ping.host) {
+            Some(index) => index,
+            None => {
+                self.hosts.push((ping.host.clone(), VecDeque::new()));
+                self.hosts.len() - 1
+            }
+        };
+        let recent = &mut self.hosts[index].1;
+        if recent.len() == STATUS_SAMPLES {
+            recent.pop_front();
+        }
+        recent.push_back(ping.clone());
+    }
+
+    /// The line for every host seen, with loss estimated from the gaps
+    /// between samples taken every `interval_for(host)`.
+    pub fn render(&self, interval_for: impl Fn(&str) -> Duration, ascii: bool) -> String {
+        let blocks: &[char] = if ascii {
+            &ASCII_SPARK_BLOCKS
+        } else {
+            &SPARK_BLOCKS
+        };
+        self.hosts
+            .iter()
+            .filter_map(|(host, recent)| {
+                let latest = recent.back()?.average;
+                let values: Vec<f64> = recent.iter().map(|ping| ping.average).collect();
+                let times: Vec<DateTime<Utc>> =
+                    recent.iter().map(|ping| ping.datetime_recv).collect();
+                let label = if ascii { to_ascii(host) } else { host.clone() };
+                Some(format!(
+                    "{label} {latest:.0}ms {} {:.0}%",
+                    sparkline(&values, blocks),
+                    estimated_loss(&times, interval_for(host))
+                ))
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_status_lines_have_only_ascii_bytes() {
+        let mut status = StatusLine::new();
+        for (seconds, average) in [(0, 10.0), (1, 30.0), (2, 20.0)] {
+            let at = DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap();
+            status.record(&PingResult::new("bücher.example".to_string(), average, at));
+        }
+        let interval = |_: &str| Duration::from_secs(1);
+        assert_eq!(status.render(interval, true), "b?cher.example 20ms _^- 0%");
+        assert_eq!(status.render(interval, false), "bücher.example 20ms ▁█▅ 0%");
+    }
+
+    #[test]
+    fn sparkline_scales_between_the_lowest_and_highest() {
+        let values = [10.0, 20.0, 30.0, 40.0, 50.0, 60.0, 70.0, 80.0];
+        assert_eq!(sparkline(&values, &SPARK_BLOCKS), "▁▂▃▄▅▆▇█");
+        assert_eq!(sparkline(&[5.0, 80.0, 5.0, 42.5], &SPARK_BLOCKS), "▁█▁▅");
+        assert_eq!(sparkline(&[7.0, 7.0, 7.0], &SPARK_BLOCKS), "▁▁▁");
+        assert_eq!(sparkline(&[], &SPARK_BLOCKS), "");
+    }
+
+    #[test]
+    fn status_lines_keep_the_latest_samples_of_each_host() {
+        let mut status = StatusLine::new();
+        for seconds in 0..20 {
+            let at = DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap();
+            status.record(&PingResult::new("a".to_string(), seconds as f64, at));
+            if seconds % 2 == 0 {
+                status.record(&PingResult::new("b".to_string(), 5.0, at));
+            }
+        }
+        let flat = "▁".repeat(STATUS_SAMPLES);
+        // b's last 8 samples span 14s, in which 15 were due
+        assert_eq!(
+            status.render(|_| Duration::from_secs(1), false),
+            format!("a 19ms ▁▂▃▄▅▆▇█ 0%  b 5ms {flat} 47%")
+        );
+        let interval_for = |host: &str| Duration::from_secs(if host == "b" { 2 } else { 1 });
+        assert!(status
+            .render(interval_for, false)
+            .ends_with(&format!("b 5ms {flat} 0%")));
+    }
+}
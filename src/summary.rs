@@ -0,0 +1,261 @@
+//! The per-host summary printed at the end of a headless run, and the
+//! `--summary-template` it can be formatted with.
+
+use crate::ascii::to_ascii;
+use crate::export::format_timestamp;
+use crate::stats::StreamingSummary;
+use crate::timezone::format_in;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+
+/// Placeholders a [`SummaryTemplate`] may use.
+pub const PLACEHOLDERS: [&str; 10] = [
+    "host",
+    "avg",
+    "min",
+    "max",
+    "loss",
+    "p95",
+    "availability",
+    "weighted",
+    "min_at",
+    "max_at",
+];
+
+/// Headline numbers of one host's run. Latencies are in ms, loss and
+/// availability in percent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub host: String,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p95: f64,
+    pub loss: f64,
+    /// See [`Availability`](crate::stats::Availability).
+    pub availability: f64,
+    pub weighted_availability: f64,
+    /// When the `min` and `max` samples came in.
+    pub min_at: DateTime<Utc>,
+    pub max_at: DateTime<Utc>,
+}
+
+impl Summary {
+    /// Summarizes `summary`, counting loss against `expected` samples and
+    /// availability against one per `interval`. `None` when no sample came
+    /// in.
+    pub fn from_streaming(
+        host: &str,
+        summary: &StreamingSummary,
+        expected: u32,
+        interval: Duration,
+    ) -> Option<Summary> {
+        let stats = &summary.stats;
+        let received = stats.count().min(expected as usize);
+        let availability = summary.availability.availability(interval)?;
+        let extremes = summary.extremes?;
+        Some(Summary {
+            host: host.to_string(),
+            avg: stats.mean()?,
+            min: extremes.min.value,
+            max: extremes.max.value,
+            p95: summary.p95.estimate()?,
+            loss: 100.0 * (expected as usize - received) as f64 / expected.max(1) as f64,
+            availability: availability.strict,
+            weighted_availability: availability.weighted,
+            min_at: extremes.min.at,
+            max_at: extremes.max.at,
+        })
+    }
+
+    fn field(&self, placeholder: &str) -> String {
+        match placeholder {
+            "host" => self.host.clone(),
+            "avg" => format!("{:.2}", self.avg),
+            "min" => format!("{:.2}", self.min),
+            "max" => format!("{:.2}", self.max),
+            "p95" => format!("{:.2}", self.p95),
+            "loss" => format!("{:.1}", self.loss),
+            "availability" => format!("{:.2}", self.availability),
+            "weighted" => format!("{:.2}", self.weighted_availability),
+            "min_at" => format_timestamp(self.min_at),
+            "max_at" => format_timestamp(self.max_at),
+            _ => unreachable!("templates are checked when parsed"),
+        }
+    }
+}
+
+/// The `--stats-only` table, one row per host, with the strict and
+/// loss-weighted availability in percent against one sample per
+/// `interval_for(host)`. Under `ascii` host names are cut down to ASCII.
+pub fn summary_table(
+    summaries: &[(String, StreamingSummary)],
+    interval_for: impl Fn(&str) -> Duration,
+    ascii: bool,
+) -> String {
+    let mut table = format!(
+        "{:<30} {:>7} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}\n",
+        "host",
+        "count",
+        "avg",
+        "stddev",
+        "min",
+        "p50",
+        "p95",
+        "p99",
+        "max",
+        "avail",
+        "w.avail",
+        "min at",
+        "max at"
+    );
+    let stat = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{v:.2}"));
+    let time = |at: Option<DateTime<Utc>>| {
+        at.map_or("-".to_string(), |at| format_in(at, &Utc, "%H:%M:%S"))
+    };
+    for (host, summary) in summaries {
+        let availability = summary.availability.availability(interval_for(host));
+        let host = if ascii { to_ascii(host) } else { host.clone() };
+        table.push_str(&format!(
+            "{:<30} {:>7} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}\n",
+            host,
+            summary.stats.count(),
+            stat(summary.stats.mean()),
+            stat(summary.stats.std_dev()),
+            stat(summary.stats.min()),
+            stat(summary.p50.estimate()),
+            stat(summary.p95.estimate()),
+            stat(summary.p99.estimate()),
+            stat(summary.stats.max()),
+            stat(availability.map(|a| a.strict)),
+            stat(availability.map(|a| a.weighted)),
+            time(summary.extremes.map(|e| e.min.at)),
+            time(summary.extremes.map(|e| e.max.at)),
+        ));
+    }
+    table
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder(String),
+}
+
+/// A line such as `{host}: {avg} ms avg, {loss}% loss` to print per host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryTemplate {
+    parts: Vec<Part>,
+}
+
+impl SummaryTemplate {
+    /// Splits `template` into text and placeholders, rejecting placeholders
+    /// not in [`PLACEHOLDERS`] and unclosed braces.
+    pub fn parse(template: &str) -> Result<SummaryTemplate, String> {
+        let mut parts = vec![];
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed {{ in summary template {template}"))?;
+            let name = &rest[open + 1..open + close];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "Unknown placeholder {{{name}}} in summary template, expected one of {}",
+                    PLACEHOLDERS.map(|p| format!("{{{p}}}")).join(", ")
+                ));
+            }
+            parts.push(Part::Placeholder(name.to_string()));
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        Ok(SummaryTemplate { parts })
+    }
+
+    pub fn render(&self, summary: &Summary) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Placeholder(name) => summary.field(name),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> Summary {
+        let at = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+        Summary {
+            host: "example.com".to_string(),
+            avg: 12.345,
+            min: 8.0,
+            max: 40.5,
+            p95: 30.0,
+            loss: 2.5,
+            availability: 99.5,
+            weighted_availability: 98.25,
+            min_at: at,
+            max_at: at + chrono::Duration::seconds(90),
+        }
+    }
+
+    #[test]
+    fn renders_placeholders_into_the_template() {
+        let template =
+            SummaryTemplate::parse("{host}: {avg} ms avg ({min}-{max}, p95 {p95}), {loss}% loss")
+                .unwrap();
+        assert_eq!(
+            template.render(&summary()),
+            "example.com: 12.35 ms avg (8.00-40.50, p95 30.00), 2.5% loss"
+        );
+        let template =
+            SummaryTemplate::parse("{availability}/{weighted} worst at {max_at}").unwrap();
+        assert_eq!(
+            template.render(&summary()),
+            "99.50/98.25 worst at 2024-01-01T00:01:30.000Z"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders_and_unclosed_braces() {
+        let err = SummaryTemplate::parse("{host} {median}").unwrap_err();
+        assert!(err.starts_with("Unknown placeholder {median}"), "{err}");
+        assert!(SummaryTemplate::parse("{host").is_err());
+        assert_eq!(
+            SummaryTemplate::parse("no placeholders")
+                .unwrap()
+                .render(&summary()),
+            "no placeholders"
+        );
+    }
+
+    #[test]
+    fn ascii_tables_have_only_ascii_bytes() {
+        let mut streaming = StreamingSummary::new();
+        for seconds in [0, 1, 2] {
+            let at = DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap();
+            streaming.record(&crate::ping::PingResult::new(
+                "bücher.example".to_string(),
+                9.5,
+                at,
+            ));
+        }
+        let summaries = [("bücher.example".to_string(), streaming)];
+        let interval = |_: &str| Duration::from_secs(1);
+
+        let table = summary_table(&summaries, interval, true);
+        assert!(table.is_ascii(), "{table}");
+        assert!(table.contains("b?cher.example"));
+        assert!(!summary_table(&summaries, interval, false).is_ascii());
+    }
+}
@@ -0,0 +1,127 @@
+//! Noticing the machine sleeping, so the gap it leaves in the samples is
+//! annotated rather than drawn across as if the network had been measured.
+
+use crate::events::Event;
+use chrono::{DateTime, Utc};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the clocks are compared.
+pub const SUSPEND_POLL: Duration = Duration::from_secs(1);
+
+/// How far the wall clock has to get ahead of the monotonic clock between
+/// two polls to count as a suspend, well above any scheduling delay.
+pub const SUSPEND_MIN: Duration = Duration::from_secs(5);
+
+/// A stretch of wall-clock time the machine spent asleep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suspension {
+    /// The last poll before going to sleep.
+    pub start: DateTime<Utc>,
+    /// The first poll after waking up.
+    pub end: DateTime<Utc>,
+}
+
+impl Suspension {
+    /// Whether the suspension overlaps the time from `start` to `end`.
+    pub fn overlaps(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+        self.start < end && start < self.end
+    }
+
+    pub fn event(&self) -> Event {
+        Event {
+            at: self.end,
+            host: "system".to_string(),
+            message: format!("suspended for {}s", (self.end - self.start).num_seconds()),
+        }
+    }
+}
+
+/// Compares successive readings of the wall clock and the monotonic clock.
+/// The monotonic clock stops while the machine sleeps on Linux and macOS, so
+/// the wall clock pulling ahead of it gives the sleep away. Windows counts
+/// sleep in its monotonic clock, so nothing is detected there.
+#[derive(Debug, Default)]
+pub struct SuspendDetector {
+    last: Option<(Instant, DateTime<Utc>)>,
+}
+
+impl SuspendDetector {
+    pub fn new() -> SuspendDetector {
+        SuspendDetector::default()
+    }
+
+    /// Records the clocks read at the same moment, returning the suspension
+    /// since the previous reading if there was one.
+    pub fn observe(&mut self, monotonic: Instant, wall: DateTime<Utc>) -> Option<Suspension> {
+        let (last_monotonic, last_wall) = self.last.replace((monotonic, wall))?;
+        let slept = (wall - last_wall)
+            .to_std()
+            .ok()?
+            .checked_sub(monotonic - last_monotonic)?;
+        if slept < SUSPEND_MIN {
+            return None;
+        }
+        Some(Suspension {
+            start: last_wall,
+            end: wall,
+        })
+    }
+}
+
+/// Compares the clocks every [`SUSPEND_POLL`] on its own thread, logging
+/// each suspension to stderr and sending it on `tx`. Send errors are
+/// ignored, as headless runs only log.
+pub fn spawn_suspend_watcher(tx: mpsc::Sender<Suspension>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut detector = SuspendDetector::new();
+        loop {
+            if let Some(suspension) = detector.observe(Instant::now(), Utc::now()) {
+                eprintln!("{}", suspension.event());
+                let _ = tx.send(suspension);
+            }
+            thread::sleep(SUSPEND_POLL);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_wall_clock_jump_is_a_suspension() {
+        let monotonic = Instant::now();
+        let wall = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+        let later = |seconds| wall + chrono::Duration::seconds(seconds);
+        let mut detector = SuspendDetector::new();
+        assert_eq!(detector.observe(monotonic, wall), None);
+        // Polls a little late are just scheduling
+        assert_eq!(
+            detector.observe(monotonic + Duration::from_secs(1), later(3)),
+            None
+        );
+
+        // The lid closed for ten minutes while only a second went by awake
+        let suspension = detector
+            .observe(monotonic + Duration::from_secs(2), later(603))
+            .unwrap();
+        assert_eq!(
+            suspension,
+            Suspension {
+                start: later(3),
+                end: later(603)
+            }
+        );
+        assert_eq!(suspension.event().message, "suspended for 600s");
+        assert!(suspension.overlaps(later(0), later(10)));
+        assert!(!suspension.overlaps(later(603), later(610)));
+
+        // A wall clock set back isn't one
+        assert_eq!(
+            detector.observe(monotonic + Duration::from_secs(3), later(0)),
+            None
+        );
+    }
+}
@@ -0,0 +1,75 @@
+//! `--timezone`: the zone timestamps are displayed and exported in. Samples
+//! are always kept in UTC.
+
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Set once at startup, before anything is formatted.
+static ZONE: OnceLock<Tz> = OnceLock::new();
+
+/// Parses an IANA zone name such as `America/New_York`.
+pub fn parse_zone(name: &str) -> Result<Tz, String> {
+    name.parse().map_err(|_| {
+        format!("Unknown timezone {name}, expected an IANA name like America/New_York")
+    })
+}
+
+/// Displays and exports timestamps in `zone` from now on. Only the first
+/// call has an effect.
+pub fn set_zone(zone: Tz) {
+    let _ = ZONE.set(zone);
+}
+
+/// The `--timezone` zone, if one was given.
+pub fn zone() -> Option<Tz> {
+    ZONE.get().copied()
+}
+
+/// `at` formatted with `format` in the `--timezone` zone, or in `default`
+/// without one.
+pub fn format_in<Z: TimeZone>(at: DateTime<Utc>, default: &Z, format: &str) -> String
+where
+    Z::Offset: Display,
+{
+    format_in_zone(at, zone(), default, format)
+}
+
+/// `at` formatted with `format` in `zone`, or in `default` without one.
+fn format_in_zone<Z: TimeZone>(
+    at: DateTime<Utc>,
+    zone: Option<Tz>,
+    default: &Z,
+    format: &str,
+) -> String
+where
+    Z::Offset: Display,
+{
+    match zone {
+        Some(zone) => at.with_timezone(&zone).format(format).to_string(),
+        None => at.with_timezone(default).format(format).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_an_instant_in_named_zones() {
+        // Summer, so both zones are on daylight saving time
+        let at = DateTime::from_timestamp(1_719_835_200, 0).unwrap();
+        let format = "%Y-%m-%d %H:%M %Z";
+        let in_zone = |name| format_in_zone(at, Some(parse_zone(name).unwrap()), &Utc, format);
+        assert_eq!(in_zone("America/New_York"), "2024-07-01 08:00 EDT");
+        assert_eq!(in_zone("Asia/Tokyo"), "2024-07-01 21:00 JST");
+        assert_eq!(
+            format_in_zone(at, None, &Utc, format),
+            "2024-07-01 12:00 UTC"
+        );
+
+        let err = parse_zone("Mars/Olympus_Mons").unwrap_err();
+        assert!(err.contains("Unknown timezone Mars/Olympus_Mons"), "{err}");
+    }
+}
@@ -0,0 +1,275 @@
+//! Terminal dashboard for `--tui`, built on ratatui.
+
+use crate::alert::{terminal_bell, Beeper};
+use crate::app::{format_time, plot_x};
+use crate::ascii::ascii_char;
+use crate::color::Rgb;
+use crate::config::Config;
+use crate::events::EventLog;
+use crate::ping::PingResult;
+use crate::stats::{trim_older_than, trim_to_memory, LastSeen, RegimeDetector, Warmup};
+use crate::timezone::format_in;
+use chrono::{DateTime, Local, Utc};
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::symbols::Marker;
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType, List, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Samples kept per host when `--retain` is not set, as in the GUI.
+const MAX_POINTS: usize = 1000;
+
+/// How many of the latest samples are listed below the stats.
+const RECENT_SAMPLES: usize = 50;
+
+/// Shows the live chart, per-host stats, the latest samples and events until
+/// `q` or Esc is pressed, then hands back the samples still held for exporting.
+pub fn run_tui(
+    ping_receiver: mpsc::Receiver<PingResult>,
+    config: &Config,
+) -> Result<Vec<PingResult>, Box<dyn std::error::Error>> {
+    let mut terminal = ratatui::init();
+    let result = tui_loop(&mut terminal, ping_receiver, config);
+    ratatui::restore();
+    result
+}
+
+fn tui_loop(
+    terminal: &mut DefaultTerminal,
+    ping_receiver: mpsc::Receiver<PingResult>,
+    config: &Config,
+) -> Result<Vec<PingResult>, Box<dyn std::error::Error>> {
+    let mut ping_data: Vec<PingResult> = vec![];
+    let mut hosts: Vec<String> = vec![];
+    let mut last_seen: HashMap<String, DateTime<Utc>> = HashMap::new();
+    let mut warmup = Warmup::new(config.warmup);
+    let mut beeper = Beeper::new();
+    let mut regimes = RegimeDetector::new(config.regime_sensitivity);
+    let mut events = EventLog::new();
+    let ascii = config.ascii_terminal();
+
+    loop {
+        while let Ok(ping_value) = ping_receiver.try_recv() {
+            if config.beep
+                && !config.schedule.in_maintenance(Local::now().time())
+                && beeper.should_beep(
+                    &ping_value,
+                    &config.thresholds_for(&ping_value.host, Local::now().time()),
+                    Instant::now(),
+                )
+            {
+                terminal_bell();
+            }
+            if !hosts.contains(&ping_value.host) {
+                hosts.push(ping_value.host.clone());
+            }
+            last_seen.insert(ping_value.host.clone(), ping_value.datetime_recv);
+            if warmup.is_warming_up(&ping_value) {
+                continue;
+            }
+            if let Some(change) = regimes.observe(&ping_value) {
+                events.push(change.event());
+            }
+            ping_data.push(ping_value);
+        }
+
+        if let Some(retain) = config.retain {
+            trim_older_than(&mut ping_data, Utc::now(), retain);
+        } else {
+            let excess = ping_data
+                .len()
+                .saturating_sub(MAX_POINTS * hosts.len().max(1));
+            ping_data.drain(..excess);
+        }
+        if let Some(budget) = config.max_memory {
+            trim_to_memory(&mut ping_data, budget);
+        }
+
+        // Resizes are picked up by the next draw
+        terminal.draw(|frame| {
+            draw(frame, &ping_data, &hosts, &last_seen, &events, config);
+            if ascii {
+                replace_non_ascii(frame.buffer_mut());
+            }
+        })?;
+
+        if event::poll(Duration::from_millis(100))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press
+                    && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                {
+                    return Ok(ping_data);
+                }
+            }
+        }
+    }
+}
+
+fn draw(
+    frame: &mut Frame,
+    ping_data: &[PingResult],
+    hosts: &[String],
+    last_seen: &HashMap<String, DateTime<Utc>>,
+    events: &EventLog,
+    config: &Config,
+) {
+    let [chart_area, stats_area, recent_area] = Layout::vertical([
+        Constraint::Min(8),
+        Constraint::Length(hosts.len() as u16 + 3),
+        Constraint::Length(8),
+    ])
+    .areas(frame.area());
+
+    let color = |index: usize| {
+        let Rgb(r, g, b) = config.host_color(index);
+        Color::Rgb(r, g, b)
+    };
+
+    // One line per host, positioned by the time each sample arrived
+    let series: Vec<Vec<(f64, f64)>> = hosts
+        .iter()
+        .map(|host| {
+            let mut points: Vec<(f64, f64)> = ping_data
+                .iter()
+                .filter(|data| &data.host == host)
+                .map(|data| (plot_x(data.sent_at()), data.average))
+                .collect();
+            points.drain(..config.unplotted(points.len()));
+            points
+        })
+        .collect();
+    let datasets = hosts
+        .iter()
+        .zip(&series)
+        .enumerate()
+        .map(|(index, (host, points))| {
+            Dataset::default()
+                .name(host.as_str())
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(color(index)))
+                .data(points)
+        })
+        .collect();
+
+    let x_min = series
+        .iter()
+        .filter_map(|points| points.first())
+        .map(|point| point.0)
+        .reduce(f64::min)
+        .unwrap_or(0.0);
+    let x_max = ping_data
+        .last()
+        .map_or(1.0, |data| plot_x(data.sent_at()))
+        .max(x_min + 1.0);
+    let y_max = ping_data
+        .iter()
+        .map(|data| data.average)
+        .fold(1.0, f64::max)
+        * 1.1;
+    let chart = Chart::new(datasets)
+        .block(Block::bordered().title(" Ping (ms) - q to quit "))
+        .x_axis(
+            Axis::default()
+                .bounds([x_min, x_max])
+                .labels([format_time(x_min), format_time(x_max)]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, y_max])
+                .labels(["0".to_string(), format!("{y_max:.0}")]),
+        );
+    frame.render_widget(chart, chart_area);
+
+    let now = Utc::now();
+    let rows = hosts.iter().enumerate().map(|(index, host)| {
+        let averages: Vec<f64> = ping_data
+            .iter()
+            .filter(|data| &data.host == host)
+            .map(|data| data.average)
+            .collect();
+        let stat = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{v:.2}"));
+        let average =
+            (!averages.is_empty()).then(|| averages.iter().sum::<f64>() / averages.len() as f64);
+        let min = averages.iter().copied().reduce(f64::min);
+        let max = averages.iter().copied().reduce(f64::max);
+
+        let mut style = Style::default().fg(color(index));
+        let seen = last_seen.get(host).map(|&last| {
+            let seen = LastSeen::new(last, now, config.grace_for(host));
+            if seen.overdue {
+                let Rgb(r, g, b) = config.palette.unwrap_or_default().bad();
+                style = style.fg(Color::Rgb(r, g, b));
+            }
+            let secs = seen.elapsed.as_secs();
+            format!("{:02}:{:02} ago", secs / 60, secs % 60)
+        });
+
+        Row::new([
+            host.clone(),
+            stat(averages.last().copied()),
+            stat(average),
+            stat(min),
+            stat(max),
+            seen.unwrap_or_default(),
+        ])
+        .style(style)
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Fill(2),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+        ],
+    )
+    .header(Row::new([
+        "Host",
+        "Last",
+        "Average",
+        "Min",
+        "Max",
+        "Last seen",
+    ]))
+    .block(Block::bordered().title(" Stats (ms) "));
+    frame.render_widget(table, stats_area);
+
+    let recent = ping_data.iter().rev().take(RECENT_SAMPLES).map(|data| {
+        format!(
+            "{}  {}  {:.2} ms",
+            format_in(data.datetime_recv, &Utc, "%H:%M:%S"),
+            data.host,
+            data.average
+        )
+    });
+    let [samples_area, events_area]: [Rect; 2] =
+        Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(recent_area);
+    frame.render_widget(
+        List::new(recent).block(Block::bordered().title(" Recent samples ")),
+        samples_area,
+    );
+    frame.render_widget(
+        List::new(events.iter().rev().map(|event| event.to_string()))
+            .block(Block::bordered().title(" Events ")),
+        events_area,
+    );
+}
+
+/// Swaps every glyph ratatui drew, such as borders and plot markers, for an
+/// ASCII stand-in.
+fn replace_non_ascii(buffer: &mut Buffer) {
+    for cell in &mut buffer.content {
+        if !cell.symbol().is_ascii() {
+            let plain: String = cell.symbol().chars().map(ascii_char).take(1).collect();
+            cell.set_symbol(&plain);
+        }
+    }
+}
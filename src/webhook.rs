@@ -0,0 +1,213 @@
+//! `--webhook-url`: POSTs a JSON payload when a host has been silent for
+//! `--outage-alert-after`, and again once it answers, for alerting into chat
+//! or paging services.
+
+use crate::alert::Snooze;
+use crate::http::post_json;
+use crate::ping::PingResult;
+use crate::sampler;
+use crate::schedule::Schedule;
+use chrono::{DateTime, Local, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often hosts are checked for having gone silent.
+pub const WEBHOOK_POLL: Duration = Duration::from_secs(1);
+
+/// How long after an outage alert another one of the same host is held
+/// back, so a flapping host doesn't page over and over.
+pub const WEBHOOK_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+/// The JSON body of a webhook call.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WebhookPayload {
+    /// `outage` while the host is silent, `recovery` once it answers again.
+    pub event: &'static str,
+    pub host: String,
+    /// The last answer before the outage.
+    #[serde(with = "crate::export::timestamp")]
+    pub start: DateTime<Utc>,
+    /// How long the outage has lasted so far, or lasted in all on recovery.
+    pub duration_secs: f64,
+}
+
+impl WebhookPayload {
+    fn new(event: &'static str, host: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        WebhookPayload {
+            event,
+            host: host.to_string(),
+            start,
+            duration_secs: (end - start).num_milliseconds() as f64 / 1000.0,
+        }
+    }
+}
+
+/// Tracks when each host last answered to tell when an outage is worth an
+/// alert and when it is over.
+#[derive(Debug)]
+pub struct OutageAlerts {
+    after: Duration,
+    last_seen: HashMap<String, DateTime<Utc>>,
+    /// Start of each host's outage that was alerted and hasn't recovered.
+    alerted: HashMap<String, DateTime<Utc>>,
+    /// When each host was last alerted, for [`WEBHOOK_COOLDOWN`].
+    last_alert: HashMap<String, DateTime<Utc>>,
+}
+
+impl OutageAlerts {
+    /// Alerts on hosts silent for longer than `after`.
+    pub fn new(after: Duration) -> OutageAlerts {
+        OutageAlerts {
+            after,
+            last_seen: HashMap::new(),
+            alerted: HashMap::new(),
+            last_alert: HashMap::new(),
+        }
+    }
+
+    /// Records an answer, returning the recovery payload if it ends an
+    /// alerted outage.
+    pub fn observe(&mut self, sample: &PingResult) -> Option<WebhookPayload> {
+        self.last_seen
+            .insert(sample.host.clone(), sample.datetime_recv);
+        let start = self.alerted.remove(&sample.host)?;
+        Some(WebhookPayload::new(
+            "recovery",
+            &sample.host,
+            start,
+            sample.datetime_recv,
+        ))
+    }
+
+    /// The outage payloads of hosts that have been silent past the limit at
+    /// `now` and weren't alerted yet, leaving out hosts still cooling down.
+    pub fn check(&mut self, now: DateTime<Utc>) -> Vec<WebhookPayload> {
+        let mut payloads = vec![];
+        for (host, &last) in &self.last_seen {
+            let silent = (now - last).to_std().unwrap_or_default();
+            let cooling_down = self.last_alert.get(host).is_some_and(|&alerted| {
+                (now - alerted).to_std().unwrap_or_default() < WEBHOOK_COOLDOWN
+            });
+            if silent <= self.after || self.alerted.contains_key(host) || cooling_down {
+                continue;
+            }
+            self.alerted.insert(host.clone(), last);
+            self.last_alert.insert(host.clone(), now);
+            payloads.push(WebhookPayload::new("outage", host, last, now));
+        }
+        payloads
+    }
+}
+
+/// POSTs `payload` to `url` on its own thread so a slow endpoint doesn't
+/// hold up the samples, logging failures to stderr.
+fn send(url: &str, payload: &WebhookPayload) {
+    let url = url.to_string();
+    let body = serde_json::to_string(payload).expect("payloads serialize");
+    thread::spawn(move || {
+        if let Err(err) = post_json(&url, &body) {
+            eprintln!("Webhook to {url} failed: {err}");
+        }
+    });
+}
+
+/// Passes every sample from `rx` on to the returned receiver, calling the
+/// webhook at `url` as hosts go silent for longer than `after` and come
+/// back. Outages during a maintenance window of `schedule` or while `snooze`
+/// is active aren't alerted. Silence is checked for every [`WEBHOOK_POLL`]
+/// on a thread of its own, until the samples stop.
+pub fn watch_outages(
+    rx: mpsc::Receiver<PingResult>,
+    url: String,
+    after: Duration,
+    schedule: Schedule,
+    snooze: Snooze,
+) -> mpsc::Receiver<PingResult> {
+    let alerts = Arc::new(Mutex::new(OutageAlerts::new(after)));
+    let checked = Arc::downgrade(&alerts);
+    let checker_url = url.clone();
+    thread::spawn(move || loop {
+        thread::sleep(WEBHOOK_POLL);
+        let Some(alerts) = checked.upgrade() else {
+            break;
+        };
+        if schedule.in_maintenance(Local::now().time()) || snooze.is_active(Instant::now()) {
+            continue;
+        }
+        let payloads = alerts
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .check(Utc::now());
+        for payload in payloads {
+            send(&checker_url, &payload);
+        }
+    });
+    sampler::tee(rx, move |ping_value| {
+        let payload = alerts
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .observe(ping_value);
+        if let Some(payload) = payload {
+            send(&url, &payload);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_704_067_200 + seconds, 0).unwrap()
+    }
+
+    fn sample(seconds: i64) -> PingResult {
+        PingResult::new("example.com".to_string(), 10.0, at(seconds))
+    }
+
+    #[test]
+    fn builds_outage_and_recovery_payloads() {
+        let mut alerts = OutageAlerts::new(Duration::from_secs(30));
+        assert_eq!(alerts.observe(&sample(0)), None);
+        assert!(alerts.check(at(30)).is_empty());
+
+        let outage = alerts.check(at(45)).pop().unwrap();
+        assert_eq!(
+            serde_json::to_value(&outage).unwrap(),
+            serde_json::json!({
+                "event": "outage",
+                "host": "example.com",
+                "start": "2024-01-01T00:00:00.000Z",
+                "duration_secs": 45.0,
+            })
+        );
+        // Alerted once per outage
+        assert!(alerts.check(at(60)).is_empty());
+
+        let recovery = alerts.observe(&sample(90)).unwrap();
+        assert_eq!(
+            recovery,
+            WebhookPayload {
+                event: "recovery",
+                host: "example.com".to_string(),
+                start: at(0),
+                duration_secs: 90.0,
+            }
+        );
+        assert_eq!(alerts.observe(&sample(91)), None);
+    }
+
+    #[test]
+    fn flapping_hosts_are_held_back_by_the_cooldown() {
+        let mut alerts = OutageAlerts::new(Duration::from_secs(30));
+        alerts.observe(&sample(0));
+        assert_eq!(alerts.check(at(31)).len(), 1);
+        alerts.observe(&sample(40));
+        assert!(alerts.check(at(100)).is_empty());
+        let cooled = 31 + WEBHOOK_COOLDOWN.as_secs() as i64;
+        assert_eq!(alerts.check(at(cooled)).len(), 1);
+    }
+}
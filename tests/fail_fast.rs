@@ -0,0 +1,53 @@
+//! `--fail-fast` against a stand-in `ping` that fails its first sample.
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Writes an executable `ping` running `script` and returns its path.
+fn stub_ping(name: &str, script: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("network-test-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("ping");
+    fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+fn run_once(ping: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_network-test"))
+        .args([
+            "--fail-fast",
+            "--once",
+            "--headless",
+            "--sample-timeout",
+            "2s",
+        ])
+        .arg("--ping-binary")
+        .arg(ping)
+        .args(["nosuch.invalid", "2"])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn exits_down_when_the_first_sample_cannot_resolve() {
+    let ping = stub_ping(
+        "unresolved",
+        "echo \"ping: $1: Name or service not known\" >&2; exit 2",
+    );
+    let output = run_once(&ping);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{stderr}");
+    assert!(stderr.contains("Could not reach"), "{stderr}");
+}
+
+#[test]
+fn carries_on_when_the_first_sample_times_out() {
+    let ping = stub_ping("timeout", "echo 'Request timeout for icmp_seq 0'; exit 2");
+    let output = run_once(&ping);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Could not reach"), "{stderr}");
+}
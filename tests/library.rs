@@ -0,0 +1,105 @@
+//! The library surface as another program would use it, without the GUI or
+//! a real `ping`.
+
+use network_test::export::{format_csv, parse_csv, CsvOptions};
+use network_test::sampler::{sample_channel, spawn_sampler, Backpressure, Budget};
+use network_test::schedule::Schedule;
+use network_test::stats::{rolling_stats, RunningStats};
+use network_test::{parse_ping, Config, Mode, Pinger};
+use std::time::Duration;
+
+const PING_OUTPUT: &str = "\
+PING 1.1.1.1 (1.1.1.1): 56 data bytes
+64 bytes from 1.1.1.1: icmp_seq=0 ttl=57 time=12.345 ms
+
+--- 1.1.1.1 ping statistics ---
+1 packets transmitted, 1 packets received, 0.0% packet loss
+round-trip min/avg/max/stddev = 12.345/12.345/12.345/0.000 ms
+";
+
+#[test]
+fn parses_ping_output() {
+    let sample = parse_ping("1.1.1.1", PING_OUTPUT).unwrap();
+    assert_eq!(sample.host, "1.1.1.1");
+    assert_eq!(sample.average, 12.345);
+    assert_eq!(sample.ttl, Some(57));
+    assert!(parse_ping("1.1.1.1", "Request timeout for icmp_seq 0\n").is_err());
+}
+
+#[test]
+fn seeded_pingers_make_up_the_same_samples() {
+    let latencies = || {
+        let pinger = Pinger::new(Mode::Simulate).with_seed(7);
+        (0..20)
+            .map(|_| pinger.ping("example.com").map(|sample| sample.average).ok())
+            .collect::<Vec<_>>()
+    };
+    let first = latencies();
+    assert_eq!(first, latencies());
+    assert!(first.iter().flatten().all(|&latency| latency > 0.0));
+}
+
+#[test]
+fn samplers_send_their_budget_of_samples() {
+    let (tx, rx) = sample_channel(16, Backpressure::Block);
+    let pinger = Pinger::new(Mode::Simulate).with_seed(1);
+    let sampler = spawn_sampler(
+        "example.com".to_string(),
+        Duration::from_millis(1),
+        Schedule::default(),
+        pinger,
+        Budget::new(Some(5)),
+        tx,
+    );
+    assert!(sampler.join().unwrap());
+    let samples: Vec<_> = rx.iter().collect();
+    // Simulated dropouts count towards the budget without sending anything
+    assert!(!samples.is_empty() && samples.len() <= 5);
+    assert!(samples.iter().all(|sample| sample.host == "example.com"));
+}
+
+#[test]
+fn stats_and_exports_work_on_samples() {
+    let pinger = Pinger::new(Mode::Simulate).with_seed(3);
+    let samples: Vec<_> = (0..50)
+        .filter_map(|_| pinger.ping("example.com").ok())
+        .collect();
+
+    let mut stats = RunningStats::new();
+    for sample in &samples {
+        stats.push(sample.average);
+    }
+    assert_eq!(stats.count(), samples.len());
+    assert!(stats.min() <= stats.mean() && stats.mean() <= stats.max());
+    let rolling = rolling_stats(&[10.0, 20.0, 30.0], 2);
+    assert_eq!(rolling[2].avg, 25.0);
+
+    let csv = format_csv(&samples, &CsvOptions::default());
+    let read: Vec<f64> = parse_csv(&csv)
+        .unwrap()
+        .iter()
+        .map(|sample| sample.average)
+        .collect();
+    let written: Vec<f64> = samples.iter().map(|sample| sample.average).collect();
+    assert_eq!(read, written);
+}
+
+#[test]
+fn configs_parse_from_arguments() {
+    let config = Config::from_args(
+        [
+            "example.com",
+            "--host",
+            "1.1.1.1=250ms",
+            "--mode",
+            "simulate",
+        ]
+        .map(String::from),
+    )
+    .unwrap();
+    let samplers = config.samplers();
+    assert_eq!(samplers.len(), 2);
+    assert_eq!(samplers[1].0, "1.1.1.1");
+    assert_eq!(samplers[1].1, Duration::from_millis(250));
+    assert!(Config::from_args(["--interval", "soon"].map(String::from)).is_err());
+}